@@ -1,6 +1,6 @@
 use std::{
     os::unix::fs::PermissionsExt,
-    path::Path,
+    path::{Path, PathBuf},
     time::{Duration, Instant},
 };
 
@@ -16,39 +16,176 @@ use snops_common::{
     state::{TransferId, TransferStatusUpdate},
     util::sha256_file,
 };
-use tokio::{fs::File, io::AsyncWriteExt};
-use tracing::info;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use tracing::{error, info};
 
 use crate::transfers::{self, TransferTx};
 
 const TRANSFER_UPDATE_RATE: Duration = Duration::from_secs(2);
+/// Delay used when the control plane is busy but gives no `Retry-After`
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+/// Number of times a binary download is retried while the server is busy
+const MAX_BUSY_RETRIES: u32 = 10;
+
+/// The server is throttling downloads and asked to retry after a delay
+#[derive(Debug)]
+pub struct DownloadBusy(pub Duration);
+
+impl std::fmt::Display for DownloadBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "server busy, retry after {}s", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for DownloadBusy {}
+
+/// Get the delay requested by a response when the server is busy
+pub fn retry_after(res: &reqwest::Response) -> Option<Duration> {
+    if res.status() != StatusCode::SERVICE_UNAVAILABLE {
+        return None;
+    }
+
+    Some(
+        res.headers()
+            .get(http::header::RETRY_AFTER)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_RETRY_AFTER),
+    )
+}
+
+/// Get the path a download is written to until it completes
+fn partial_path(to: &Path) -> PathBuf {
+    let mut name = to.file_name().unwrap_or_default().to_owned();
+    name.push(".part");
+    to.with_file_name(name)
+}
+
+/// Get the path of the file storing the `If-Range` validator of a partial
+/// download's source
+fn validator_path(part: &Path) -> PathBuf {
+    let mut name = part.file_name().unwrap_or_default().to_owned();
+    name.push(".etag");
+    part.with_file_name(name)
+}
+
+/// Get the value a resumed download sends in its `If-Range` header: the
+/// response's strong ETag, or its `Last-Modified` date. Weak ETags cannot be
+/// used in an `If-Range` header.
+fn range_validator(res: &reqwest::Response) -> Option<&str> {
+    let header = |name| res.headers().get(name).and_then(|h| h.to_str().ok());
+    header(http::header::ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(http::header::LAST_MODIFIED))
+}
+
+/// Get the total length from a `Content-Range: bytes <start>-<end>/<total>`
+/// header
+fn content_range_total(res: &reqwest::Response) -> Option<u64> {
+    res.headers()
+        .get(http::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .rsplit_once('/')?
+        .1
+        .parse()
+        .ok()
+}
 
 /// Download a file. Returns a None if 404.
+///
+/// The file is downloaded to a `.part` file next to the destination, which is
+/// resumed with a range request if a previous download was interrupted. The
+/// range request carries the ETag (or modification date) of the interrupted
+/// download in an `If-Range` header, so a changed file is downloaded from the
+/// start. Partial files without a known validator are not resumed. The partial
+/// file is only moved into place once its length and sha256 (when provided) are
+/// verified.
 pub async fn download_file(
     tx_id: TransferId,
     client: &reqwest::Client,
     url: impl IntoUrl,
     to: impl AsRef<Path>,
+    sha256: Option<&str>,
     transfer_tx: TransferTx,
 ) -> anyhow::Result<Option<(File, String, u64)>> {
     let desc = url.as_str().to_owned();
-    let req = client.get(url).send().await?;
+    let url = url.into_url()?;
+    let to = to.as_ref();
+    let part = partial_path(to);
+    let part_validator = validator_path(&part);
+
+    // resume from the end of a previously interrupted download, as long as the
+    // remote file is unchanged
+    let mut offset = tokio::fs::metadata(&part)
+        .await
+        .map(|meta| meta.len())
+        .unwrap_or_default();
+    let mut req = client.get(url.clone());
+    match tokio::fs::read_to_string(&part_validator).await {
+        Ok(validator) if offset > 0 => {
+            req = req
+                .header(http::header::RANGE, format!("bytes={offset}-"))
+                .header(http::header::IF_RANGE, validator.trim());
+        }
+        _ => offset = 0,
+    }
+    let mut req = req.send().await?;
+
+    // the partial file is not a prefix of the remote file, so start over
+    if req.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        offset = 0;
+        req = client.get(url).send().await?;
+    }
     if req.status() == StatusCode::NOT_FOUND {
         return Ok(None);
     }
+    if let Some(delay) = retry_after(&req) {
+        return Err(DownloadBusy(delay).into());
+    }
+
+    // servers that do not support ranges, or whose file changed since the
+    // partial download, respond with the whole file
+    if req.status() != StatusCode::PARTIAL_CONTENT {
+        offset = 0;
+    }
+
+    // remember the validator of a new download so it can be resumed
+    if offset == 0 {
+        match range_validator(&req) {
+            Some(validator) => tokio::fs::write(&part_validator, validator).await?,
+            None => {
+                let _ = tokio::fs::remove_file(&part_validator).await;
+            }
+        }
+    }
+
+    let total = match offset {
+        0 => req.content_length(),
+        _ => content_range_total(&req),
+    };
 
     // start a new transfer
     transfer_tx.send((
         tx_id,
         TransferStatusUpdate::Start {
-            desc,
+            desc: desc.clone(),
             time: Utc::now(),
-            total: req.content_length().unwrap_or_default(),
+            total: total.unwrap_or_default(),
         },
     ))?;
 
-    let mut stream = req.bytes_stream();
-    let mut file = File::create(to).await.inspect_err(|_| {
+    let mut digest = Sha256::new();
+    let file = if offset > 0 {
+        open_partial(&part, &mut digest).await
+    } else {
+        File::create(&part).await
+    };
+    let mut file = file.inspect_err(|_| {
         let _ = transfer_tx.send((
             tx_id,
             TransferStatusUpdate::End {
@@ -57,10 +194,15 @@ pub async fn download_file(
         ));
     })?;
 
-    let mut downloaded = 0;
-    let mut digest = Sha256::new();
+    let mut stream = req.bytes_stream();
+    let mut downloaded = offset;
     let mut update_next = Instant::now() + TRANSFER_UPDATE_RATE;
 
+    if offset > 0 {
+        info!("resuming download of {desc} from byte {offset}");
+        let _ = transfer_tx.send((tx_id, TransferStatusUpdate::Progress { downloaded }));
+    }
+
     while let Some(chunk) = stream.next().await {
         let chunk = chunk.inspect_err(|e| {
             let _ = transfer_tx.send((
@@ -90,23 +232,76 @@ pub async fn download_file(
             ));
         })?;
     }
+    file.flush().await?;
+
+    // the partial file is kept so the download can be resumed
+    if let Some(total) = total.filter(|total| *total != downloaded) {
+        let _ = transfer_tx.send((
+            tx_id,
+            TransferStatusUpdate::End {
+                interruption: Some(format!("incomplete download: {downloaded}/{total} bytes")),
+            },
+        ));
+        bail!("incomplete download of {desc}: {downloaded} of {total} bytes");
+    }
+
+    let actual_sha256 = format!("{:x}", digest.finalize());
+
+    if let Some(sha256) = sha256 {
+        if actual_sha256 != sha256.to_ascii_lowercase() {
+            // the partial file cannot be resumed from. failing to remove it must
+            // not hide the mismatch
+            drop(file);
+            if let Err(e) = tokio::fs::remove_file(&part).await {
+                error!("failed to remove {}: {e}", part.display());
+            }
+            let _ = tokio::fs::remove_file(&part_validator).await;
+            let _ = transfer_tx.send((
+                tx_id,
+                TransferStatusUpdate::End {
+                    interruption: Some("sha256 mismatch".to_string()),
+                },
+            ));
+            bail!("sha256 mismatch for {desc}: expected {sha256}, found {actual_sha256}");
+        }
+    }
 
-    let sha256 = format!("{:x}", digest.finalize());
+    tokio::fs::rename(&part, to).await?;
+    let _ = tokio::fs::remove_file(&part_validator).await;
 
     // mark the transfer as ended
     transfer_tx.send((tx_id, TransferStatusUpdate::End { interruption: None }))?;
 
-    Ok(Some((file, sha256, downloaded)))
+    Ok(Some((file, actual_sha256, downloaded)))
+}
+
+/// Open a partial download for appending, hashing its existing contents
+async fn open_partial(part: &Path, digest: &mut Sha256) -> std::io::Result<File> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .open(part)
+        .await?;
+
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        digest.update(&buf[..n]);
+    }
+
+    Ok(file)
 }
 
 pub async fn check_binary(
+    client: &reqwest::Client,
     binary: &BinaryEntry,
     base_url: &str,
     path: &Path,
     transfer_tx: TransferTx,
 ) -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
-
     // check if we already have an up-to-date binary
     let source_url = match &binary.source {
         BinarySource::Url(url) => url.to_string(),
@@ -118,7 +313,7 @@ pub async fn check_binary(
     // this also checks for sha256 differences, along with last modified time
     // against the target
     let file_issues = get_file_issues(
-        &client,
+        client,
         &source_url,
         path,
         binary.size,
@@ -138,26 +333,41 @@ pub async fn check_binary(
     }
     info!("downloading binary update to {}: {binary}", path.display());
 
-    let tx_id = transfers::next_id();
-    let Some((file, sha256, size)) =
-        download_file(tx_id, &client, &source_url, path, transfer_tx).await?
-    else {
+    let mut attempts = 0;
+    let download = loop {
+        let tx_id = transfers::next_id();
+        match download_file(
+            tx_id,
+            client,
+            &source_url,
+            path,
+            binary.sha256.as_deref(),
+            transfer_tx.clone(),
+        )
+        .await
+        {
+            Err(e) if attempts < MAX_BUSY_RETRIES => {
+                let Some(DownloadBusy(delay)) = e.downcast_ref() else {
+                    return Err(e);
+                };
+                attempts += 1;
+                info!("{e} while downloading {}", path.display());
+                tokio::time::sleep(*delay).await;
+            }
+            res => break res?,
+        }
+    };
+    // the sha256 is verified before the download is moved into place
+    let Some((file, _sha256, size)) = download else {
         bail!("downloading binary returned 404");
     };
 
-    if let Some(bin_sha256) = &binary.sha256 {
-        if sha256 != bin_sha256.to_ascii_lowercase() {
-            bail!(
-                "binary sha256 mismatch for {}: expected {}, found {}",
-                path.display(),
-                bin_sha256,
-                sha256
-            );
-        }
-    }
-
     if let Some(bin_size) = binary.size {
         if size != bin_size {
+            drop(file);
+            if let Err(e) = tokio::fs::remove_file(path).await {
+                error!("failed to remove {}: {e}", path.display());
+            }
             bail!(
                 "binary size mismatch for {}: expected {}, found {}",
                 path.display(),
@@ -211,9 +421,7 @@ pub async fn get_file_issues(
 
     // if sha256 is present, only download if the sha256 is different
     if let Some(sha256) = sha256 {
-        let bad_sha256 = sha256_file(&dst.to_path_buf())
-            .map_err(|e| ReconcileError::FileReadError(dst.to_path_buf(), e.to_string()))?
-            != sha256.to_ascii_lowercase();
+        let bad_sha256 = hash_file(dst).await? != sha256.to_ascii_lowercase();
         return Ok(bad_sha256.then_some(BadFileReason::Sha256));
     }
 
@@ -233,6 +441,11 @@ pub async fn get_file_issues(
             error: e.to_string(),
         })?;
 
+    // keep the local file while the server is too busy to compare against
+    if retry_after(&res).is_some() {
+        return Ok(None);
+    }
+
     let Some(last_modified_header) = res
         .headers()
         .get(http::header::LAST_MODIFIED)
@@ -256,10 +469,54 @@ pub async fn get_file_issues(
         .modified()
         .map_err(|e| ReconcileError::FileStatError(dst.to_path_buf(), e.to_string()))?;
 
+    if remote_content_length != local_content_length {
+        return Ok(Some(BadFileReason::Size));
+    }
+
     let is_stale = remote_last_modified
-        .map(|res| res > local_last_modified)
+        .as_ref()
+        .map(|res| *res > local_last_modified)
         .unwrap_or(true);
-    Ok(is_stale
-        .then_some(BadFileReason::Stale)
-        .or_else(|| (remote_content_length != local_content_length).then_some(BadFileReason::Size)))
+    if !is_stale {
+        return Ok(None);
+    }
+
+    // the remote file is newer, but its content may not have changed
+    let Some(remote_sha256) = res
+        .headers()
+        .get(http::header::ETAG)
+        .and_then(|e| e.to_str().ok())
+        .map(|e| e.trim_matches('"').to_ascii_lowercase())
+    else {
+        return Ok(Some(BadFileReason::Stale));
+    };
+
+    let local_sha256 = hash_file(dst).await?;
+    if local_sha256 != remote_sha256 {
+        return Ok(Some(BadFileReason::Stale));
+    }
+
+    // bump the local mtime so the file is not re-hashed on the next check
+    if let Ok(remote_last_modified) = remote_last_modified {
+        if let Err(e) = std::fs::File::options()
+            .write(true)
+            .open(dst)
+            .and_then(|f| f.set_modified(remote_last_modified))
+        {
+            tracing::warn!("failed to update mtime of {}: {e}", dst.display());
+        }
+    }
+
+    Ok(None)
+}
+
+/// Hash a file on the blocking thread pool, as large binaries take a while to
+/// read
+async fn hash_file(path: &Path) -> Result<String, ReconcileError> {
+    let read_err = |e: String| ReconcileError::FileReadError(path.to_path_buf(), e);
+    let owned = path.to_path_buf();
+    tokio::task::spawn_blocking(move || sha256_file(&owned))
+        .await
+        .map_err(|e| read_err(e.to_string()))?
+        .map_err(|e| read_err(e.to_string()))
 }
@@ -2,7 +2,7 @@
 use std::env;
 use std::{
     fs,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::PathBuf,
 };
 
@@ -10,8 +10,13 @@ use std::{
 use clap::CommandFactory;
 use clap::Parser;
 use http::Uri;
-use snops_common::state::{AgentId, AgentModeOptions, NetworkId, PortConfig, StorageId};
+use snops_common::{
+    db::DbOptions,
+    rpc::PING_INTERVAL_SEC,
+    state::{AgentId, AgentModeOptions, NetworkId, PortConfig, StorageId},
+};
 use tracing::{info, warn};
+use url::Url;
 
 use crate::net;
 
@@ -56,12 +61,60 @@ pub struct Cli {
     #[clap(long = "bind", default_value_t = IpAddr::V4(Ipv4Addr::UNSPECIFIED))]
     pub bind_addr: IpAddr,
 
+    /// Bind nodes to `::` instead of `0.0.0.0` when the bind address is left
+    /// unspecified, to accept IPv6 peers
+    #[arg(long)]
+    pub enable_ipv6: bool,
+
     #[clap(flatten)]
     pub ports: PortConfig,
 
     #[clap(flatten)]
     pub modes: AgentModeOptions,
 
+    #[clap(flatten)]
+    pub db: DbOptions,
+
+    /// SOCKS5 proxy (i.e `socks5://host:port`) the agent's own outbound HTTP,
+    /// such as binary and ledger downloads, is dialed through. The launched
+    /// node's p2p connections are not proxied
+    #[arg(long, env = "SNOPS_PEER_PROXY", value_parser = net::parse_socks5_url)]
+    pub peer_proxy: Option<Url>,
+
+    /// Seconds between pings sent to the control plane
+    #[arg(long, default_value_t = PING_INTERVAL_SEC, value_parser = clap::value_parser!(u64).range(1..))]
+    pub ping_interval: u64,
+
+    /// Seconds a message to the control plane may take to send before the
+    /// connection is considered interrupted
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub send_timeout: u64,
+
+    /// Exit with an error after this many consecutive failed attempts to
+    /// reconnect to the control plane. Retries forever when absent
+    #[arg(long)]
+    pub max_reconnect_attempts: Option<u32>,
+
+    /// Exit with an error as soon as the control plane connection fails or is
+    /// lost, instead of reconnecting
+    #[arg(long, conflicts_with = "max_reconnect_attempts")]
+    pub fail_fast: bool,
+
+    /// Address of a listener that only serves the `/healthz` and `/readyz`
+    /// probes. Probes are not served when absent
+    #[arg(long)]
+    pub health_bind: Option<SocketAddr>,
+
+    /// A cgroup v2 directory delegated to the agent, used to enforce the CPU
+    /// limits of nodes. The agent must not be a member of this cgroup
+    #[arg(long)]
+    pub cgroup: Option<PathBuf>,
+
+    /// Maximum number of the agent's log lines buffered on disk while the
+    /// loki server is unreachable. The oldest lines are dropped when full
+    #[arg(long, default_value_t = 100_000)]
+    pub loki_buffer: usize,
+
     #[clap(short, long, default_value_t = false)]
     /// Run the agent in quiet mode, suppressing most node output
     pub quiet: bool,
@@ -103,11 +156,29 @@ impl Cli {
         std::process::exit(0);
     }
 
-    pub fn get_local_ip(&self) -> IpAddr {
-        if self.bind_addr.is_unspecified() {
-            IpAddr::V4(Ipv4Addr::LOCALHOST)
+    /// The number of reconnect attempts before the agent exits, or `None` to
+    /// retry forever
+    pub fn reconnect_limit(&self) -> Option<u32> {
+        if self.fail_fast {
+            Some(0)
         } else {
-            self.bind_addr
+            self.max_reconnect_attempts
+        }
+    }
+
+    pub fn get_local_ip(&self) -> IpAddr {
+        match self.bind_addr {
+            IpAddr::V4(addr) if addr.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(addr) if addr.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            addr => addr,
+        }
+    }
+
+    /// Bind to all IPv6 addresses instead of all IPv4 addresses when IPv6 is
+    /// enabled and no specific bind address was given
+    pub fn resolve_bind_addr(&mut self) {
+        if self.enable_ipv6 && self.bind_addr == IpAddr::V4(Ipv4Addr::UNSPECIFIED) {
+            self.bind_addr = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
         }
     }
 
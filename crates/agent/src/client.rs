@@ -1,5 +1,11 @@
 use std::{
-    sync::Arc,
+    fmt::Display,
+    io,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     time::{Duration, Instant},
 };
 
@@ -8,14 +14,15 @@ use http::{HeaderValue, StatusCode, Uri};
 use snops_common::{
     constant::{ENV_AGENT_KEY, HEADER_AGENT_KEY},
     rpc::{
-        PING_INTERVAL_SEC, PING_LENGTH, RpcTransport,
+        PING_LENGTH, RpcTransport,
+        codec::{CodecError, PROTOCOL_VERSION},
         control::{ControlServiceClient, PING_HEADER, agent::AgentService},
     },
 };
 use tarpc::server::Channel;
-use tokio::select;
+use tokio::{net::TcpStream, select};
 use tokio_tungstenite::{
-    connect_async,
+    client_async_tls,
     tungstenite::{self, client::IntoClientRequest, handshake::client::Request},
 };
 use tracing::{error, info, warn};
@@ -25,6 +32,39 @@ use crate::{
     state::GlobalState,
 };
 
+/// Set once a control plane protocol mismatch has been reported, so skewed
+/// messages don't log an error each
+static PROTOCOL_MISMATCH_LOGGED: AtomicBool = AtomicBool::new(false);
+
+/// Minimum time between logs of an error that repeats for every message
+const REPEATED_ERROR_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Rate limits the logs of an error that can occur for every message, so a
+/// stream of bad messages does not flood the logs
+#[derive(Default)]
+struct RepeatedError {
+    last_logged: Option<Instant>,
+    /// Number of errors that were not logged since the last log
+    suppressed: u32,
+}
+
+impl RepeatedError {
+    /// Log the error unless one was logged recently
+    fn warn(&mut self, context: &str, e: impl Display) {
+        if self
+            .last_logged
+            .is_some_and(|last| last.elapsed() < REPEATED_ERROR_INTERVAL)
+        {
+            self.suppressed += 1;
+            return;
+        }
+
+        warn!(suppressed = self.suppressed, "{context}: {e}");
+        self.last_logged = Some(Instant::now());
+        self.suppressed = 0;
+    }
+}
+
 pub fn new_ws_request(ws_uri: &Uri, jwt: Option<String>) -> Request {
     let mut req = ws_uri.to_owned().into_client_request().unwrap();
 
@@ -48,25 +88,53 @@ pub fn new_ws_request(ws_uri: &Uri, jwt: Option<String>) -> Request {
     req
 }
 
-pub async fn ws_connection(ws_req: Request, state: Arc<GlobalState>) {
-    let (mut stream, _response) = match connect_async(ws_req).await {
+/// Resolve the control plane host in the websocket URI. This is done before
+/// every connection attempt so agents follow DNS changes across reconnects.
+pub async fn resolve_endpoint(ws_uri: &Uri) -> io::Result<SocketAddr> {
+    let authority = ws_uri
+        .authority()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing endpoint host"))?;
+
+    tokio::net::lookup_host(authority.as_str())
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "endpoint resolved to no addresses"))
+}
+
+/// Connect to the control plane and serve its requests until the connection
+/// is lost. Returns whether the connection was established.
+pub async fn ws_connection(ws_req: Request, addr: SocketAddr, state: Arc<GlobalState>) -> bool {
+    // Connect to the freshly resolved address. The request still carries the
+    // original host for the Host header and TLS server name.
+    let socket = match TcpStream::connect(addr).await {
+        Ok(socket) => socket,
+        // Ignore connection refused errors, we only care if something interesting is
+        // causing the connection to fail.
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => return false,
+        Err(e) => {
+            error!("failed to connect to control plane at {addr}: {e}");
+            return false;
+        }
+    };
+
+    let (mut stream, _response) = match client_async_tls(ws_req, socket).await {
         Ok(res) => res,
         Err(e) => {
             match e {
                 // Ignore connection refused errors, we only care if something interesting is
                 // causing the connection to fail.
                 tungstenite::Error::Io(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
-                    return;
+                    return false;
                 }
                 // Shutdown the agent if the control plane requires an upgrade
                 tungstenite::Error::Http(e) if e.status() == StatusCode::UPGRADE_REQUIRED => {
                     error!("The control plane requires an agent upgrade. Shutting down...");
                     state.shutdown().await;
-                    return;
+                    return false;
                 }
                 _ => error!("failed to connect to websocket: {e}"),
             }
-            return;
+            return false;
         }
     };
 
@@ -82,8 +150,12 @@ pub async fn ws_connection(ws_req: Request, state: Arc<GlobalState>) {
     state.client.write().await.replace(client.clone());
 
     let start_time = Instant::now();
-    let mut interval = tokio::time::interval(Duration::from_secs(PING_INTERVAL_SEC));
+    let ping_interval = Duration::from_secs(state.cli.ping_interval);
+    let send_timeout = Duration::from_secs(state.cli.send_timeout);
+    let mut interval = tokio::time::interval(ping_interval);
     let mut num_pings: u32 = 0;
+    let mut encode_errors = RepeatedError::default();
+    let mut decode_errors = RepeatedError::default();
 
     // initialize and start the rpc server
     let mut server_handle = Box::pin(
@@ -106,7 +178,7 @@ pub async fn ws_connection(ws_req: Request, state: Arc<GlobalState>) {
                 payload.extend_from_slice(&start_time.elapsed().as_micros().to_le_bytes());
 
                 let send = stream.send(tungstenite::Message::Ping(payload));
-                if tokio::time::timeout(Duration::from_secs(10), send).await.is_err() {
+                if tokio::time::timeout(send_timeout, send).await.is_err() {
                     error!("The connection to the control plane was interrupted while sending ping");
                     break
                 }
@@ -121,13 +193,13 @@ pub async fn ws_connection(ws_req: Request, state: Arc<GlobalState>) {
                 let bin = match snops_common::rpc::codec::encode(&control::MuxedMessageOutgoing::Child(msg)) {
                     Ok(bin) => bin,
                     Err(e) => {
-                        error!("failed to serialize response: {e}");
+                        encode_errors.warn("failed to serialize response", e);
                         continue;
                     }
                 };
 
                 let send = stream.send(tungstenite::Message::Binary(bin));
-                if tokio::time::timeout(Duration::from_secs(10), send).await.is_err() {
+                if tokio::time::timeout(send_timeout, send).await.is_err() {
                     error!("The connection to the control plane was interrupted while sending agent message");
                     break;
                 }
@@ -142,12 +214,12 @@ pub async fn ws_connection(ws_req: Request, state: Arc<GlobalState>) {
                 let bin = match snops_common::rpc::codec::encode(&control::MuxedMessageOutgoing::Parent(msg)) {
                     Ok(bin) => bin,
                     Err(e) => {
-                        error!("failed to serialize request: {e}");
+                        encode_errors.warn("failed to serialize request", e);
                         continue;
                     }
                 };
                 let send = stream.send(tungstenite::Message::Binary(bin));
-                if tokio::time::timeout(Duration::from_secs(10), send).await.is_err() {
+                if tokio::time::timeout(send_timeout, send).await.is_err() {
                     error!("The connection to the control plane was interrupted while sending control message");
                     break;
                 }
@@ -178,7 +250,7 @@ pub async fn ws_connection(ws_req: Request, state: Arc<GlobalState>) {
                     }
                     let (left, right) = payload.split_at(size_of::<u32>());
                     let ping_index = u32::from_le_bytes(left.try_into().unwrap());
-                    let _uptime_start = u128::from_le_bytes(right.try_into().unwrap());
+                    let uptime_start = u128::from_le_bytes(right.try_into().unwrap());
 
                     if ping_index != num_pings {
                         warn!("Received a pong payload with an invalid index {ping_index}, expected {num_pings}");
@@ -187,16 +259,22 @@ pub async fn ws_connection(ws_req: Request, state: Arc<GlobalState>) {
 
                     num_pings += 1;
 
-                    // when desired, we can add this as a metric
-                    // let uptime_now = start_time.elapsed().as_micros();
-                    // let uptime_diff = uptime_now - uptime_start;
+                    let uptime_diff = start_time.elapsed().as_micros().saturating_sub(uptime_start);
+                    state.metrics.write().await.ping_rtt =
+                        Some(Duration::from_micros(uptime_diff as u64));
                 }
 
                 Some(Ok(tungstenite::Message::Binary(bin))) => {
                     let msg = match snops_common::rpc::codec::decode(&bin) {
                         Ok(msg) => msg,
+                        Err(CodecError::VersionMismatch { received }) => {
+                            if !PROTOCOL_MISMATCH_LOGGED.swap(true, Ordering::Relaxed) {
+                                error!("control plane protocol v{received}, agent expects v{PROTOCOL_VERSION} - please update");
+                            }
+                            continue;
+                        }
                         Err(e) => {
-                            error!("failed to deserialize a message from the control plane: {e}");
+                            decode_errors.warn("failed to deserialize a message from the control plane", e);
                             continue;
                         }
                     };
@@ -231,4 +309,11 @@ pub async fn ws_connection(ws_req: Request, state: Arc<GlobalState>) {
             }
         }
     }
+
+    // only reset the reconnect backoff if the connection was stable
+    if start_time.elapsed() > ping_interval {
+        state.reset_reconnect_backoff();
+    }
+
+    true
 }
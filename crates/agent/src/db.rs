@@ -9,7 +9,7 @@ use indexmap::IndexMap;
 use snops_common::{
     api::AgentEnvInfo,
     db::{
-        Database as DatabaseTrait,
+        Database as DatabaseTrait, DbOptions,
         error::DatabaseError,
         tree::{DbRecords, DbTree},
     },
@@ -82,8 +82,8 @@ pub struct Database {
 }
 
 impl DatabaseTrait for Database {
-    fn open(path: &Path) -> Result<Self, DatabaseError> {
-        let db = sled::open(path)?;
+    fn open_with(path: &Path, options: &DbOptions) -> Result<Self, DatabaseError> {
+        let db = options.open(path)?;
         let strings = DbTree::new(db.open_tree(b"v1/strings")?);
         let documents = DbRecords::new(db.open_tree(b"v1/documents")?);
         let jwt_mutex = Mutex::new(strings.restore(&AgentDbString::Jwt)?);
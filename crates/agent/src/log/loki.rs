@@ -0,0 +1,516 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt},
+    process::ChildStdout,
+    sync::mpsc,
+};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+    info, warn,
+};
+use tracing_subscriber::{Layer, layer::Context};
+use url::Url;
+
+use crate::state::GlobalState;
+
+/// Number of log lines waiting to be shipped before new lines are dropped
+const CHANNEL_CAPACITY: usize = 10_000;
+/// Maximum number of log lines in a single push
+const BATCH_SIZE: usize = 1000;
+/// Time between pushes to loki
+const PUSH_INTERVAL: Duration = Duration::from_secs(2);
+/// Time a push may take before loki is considered unreachable
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Name of the file in the agent's data directory that buffers log lines
+const BUFFER_FILE: &str = "loki-buffer.jsonl";
+
+/// Receives the log lines of the agent and its node
+pub type LokiReceiver = mpsc::Receiver<LokiLine>;
+/// Sends log lines to the loki shipping task
+pub type LokiSender = mpsc::Sender<LokiLine>;
+
+/// A log line waiting to be shipped to loki
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LokiLine {
+    /// Labels of the line's stream, or `None` for the agent's own logs
+    stream: Option<Arc<str>>,
+    /// The line serialized as a loki `[timestamp, line]` value
+    value: String,
+}
+
+impl LokiLine {
+    fn new(stream: Option<Arc<str>>, line: &str) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Self {
+            stream,
+            value: json!([timestamp.to_string(), line]).to_string(),
+        }
+    }
+
+    /// Serialize the line for the buffer file. The agent's own lines are
+    /// stored as bare values.
+    fn to_buffer_line(&self) -> String {
+        match &self.stream {
+            Some(stream) => format!(r#"{{"stream":{stream},"value":{}}}"#, self.value),
+            None => self.value.clone(),
+        }
+    }
+
+    fn from_buffer_line(line: String) -> Option<Self> {
+        if line.starts_with('[') {
+            return Some(Self {
+                stream: None,
+                value: line,
+            });
+        }
+
+        let Value::Object(mut entry) = serde_json::from_str(&line).ok()? else {
+            return None;
+        };
+        Some(Self {
+            stream: Some(entry.remove("stream")?.to_string().into()),
+            value: entry.remove("value")?.to_string(),
+        })
+    }
+}
+
+/// Sends the agent's own logs to the loki shipping task
+pub struct LokiLayer {
+    tx: LokiSender,
+}
+
+impl LokiLayer {
+    pub fn channel() -> (Self, LokiReceiver) {
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        (Self { tx }, rx)
+    }
+
+    /// A sender for shipping lines that are not the agent's own logs
+    pub fn sender(&self) -> LokiSender {
+        self.tx.clone()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LokiLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        // the shipping task's own logs would be shipped in a loop while loki is
+        // unreachable
+        if meta.target() == module_path!() {
+            return;
+        }
+
+        let mut visitor = LineVisitor(format!("{} {}:", meta.level(), meta.target()));
+        event.record(&mut visitor);
+
+        // lines are dropped when the shipping task falls behind
+        let _ = self.tx.try_send(LokiLine::new(None, &visitor.0));
+    }
+}
+
+/// Labels of the stream a node's log lines are shipped in
+pub fn node_labels(agent: &str, env_id: &str, node_key: &str) -> Arc<str> {
+    json!({
+        "source": "node",
+        "agent": agent,
+        "env_id": env_id,
+        "node_key": node_key,
+    })
+    .to_string()
+    .into()
+}
+
+/// Ship a node's stdout to loki with the node's labels, echoing it to the
+/// agent's stdout unless `quiet` is set. The node's lines are buffered with
+/// the agent's own while loki is unreachable.
+pub async fn forward_node_stdout(
+    stdout: ChildStdout,
+    quiet: bool,
+    tx: LokiSender,
+    labels: Arc<str>,
+) {
+    let mut reader = tokio::io::BufReader::new(stdout);
+    let mut out = tokio::io::stdout();
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if !quiet {
+            let _ = out.write_all(&line).await;
+        }
+
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end();
+        if text.is_empty() {
+            continue;
+        }
+        // the shipping task is waited on rather than dropping node lines; the
+        // node drops its own output while the pipe is full
+        if tx
+            .send(LokiLine::new(Some(Arc::clone(&labels)), text))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Formats an event's fields after its level and target
+struct LineVisitor(String);
+
+impl Visit for LineVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let _ = match field.name() {
+            "message" => write!(self.0, " {value:?}"),
+            name => write!(self.0, " {name}={value:?}"),
+        };
+    }
+}
+
+/// Log lines that could not be pushed to loki, kept on disk so they survive
+/// agent restarts. The oldest lines are dropped once the buffer is full.
+///
+/// The buffer's file is written with blocking IO, so the shipping task moves
+/// the buffer to a blocking thread for each write.
+struct LokiBuffer {
+    path: PathBuf,
+    capacity: usize,
+    lines: VecDeque<LokiLine>,
+}
+
+impl LokiBuffer {
+    /// Open the buffer, reading the lines left over from a previous run
+    fn open(path: PathBuf, capacity: usize) -> Self {
+        let mut lines = VecDeque::new();
+        if let Ok(file) = fs::File::open(&path) {
+            lines.extend(
+                BufReader::new(file)
+                    .lines()
+                    .map_while(Result::ok)
+                    .filter_map(LokiLine::from_buffer_line),
+            );
+        }
+
+        let mut buffer = Self {
+            path,
+            capacity,
+            lines,
+        };
+        if buffer.lines.len() > capacity {
+            buffer.lines.drain(..buffer.lines.len() - capacity);
+            if let Err(e) = buffer.rewrite() {
+                warn!("failed to write loki buffer: {e}");
+            }
+        }
+        buffer
+    }
+
+    fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Queue lines after the buffered lines, dropping the oldest lines over
+    /// the buffer's capacity
+    fn push(&mut self, lines: Vec<LokiLine>) -> io::Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let overflow = (self.lines.len() + lines.len()).saturating_sub(self.capacity);
+        if overflow == 0 {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            for line in &lines {
+                writeln!(file, "{}", line.to_buffer_line())?;
+            }
+            self.lines.extend(lines);
+            return Ok(());
+        }
+
+        self.lines.extend(lines);
+        self.lines.drain(..overflow);
+        self.rewrite()
+    }
+
+    /// Remove the `n` oldest lines
+    fn pop_front(&mut self, n: usize) -> io::Result<()> {
+        if n == 0 {
+            return Ok(());
+        }
+        self.lines.drain(..n.min(self.lines.len()));
+        self.rewrite()
+    }
+
+    fn rewrite(&self) -> io::Result<()> {
+        if self.lines.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+        for line in &self.lines {
+            writeln!(file, "{}", line.to_buffer_line())?;
+        }
+        file.flush()?;
+        drop(file);
+        fs::rename(tmp, &self.path)
+    }
+}
+
+/// Run a write to the buffer on a blocking thread. The buffer is re-read
+/// from disk if the write panics.
+async fn write_buffer(
+    buffer: LokiBuffer,
+    write: impl FnOnce(&mut LokiBuffer) -> io::Result<()> + Send + 'static,
+) -> LokiBuffer {
+    let (path, capacity) = (buffer.path.clone(), buffer.capacity);
+    let res = tokio::task::spawn_blocking(move || {
+        let mut buffer = buffer;
+        if let Err(e) = write(&mut buffer) {
+            warn!("failed to write loki buffer: {e}");
+        }
+        buffer
+    })
+    .await;
+
+    match res {
+        Ok(buffer) => buffer,
+        Err(e) => {
+            warn!("failed to write loki buffer: {e}");
+            tokio::task::spawn_blocking(move || LokiBuffer::open(path, capacity))
+                .await
+                .expect("failed to open loki buffer")
+        }
+    }
+}
+
+/// Serialize a batch of log lines as a loki push body, grouping the lines by
+/// stream. `agent_labels` are the labels of the agent's own lines.
+fn push_body(agent_labels: &str, batch: &[LokiLine]) -> String {
+    let mut streams: Vec<(&str, Vec<&str>)> = vec![];
+    for line in batch {
+        let labels = line.stream.as_deref().unwrap_or(agent_labels);
+        match streams.iter_mut().find(|(l, _)| *l == labels) {
+            Some((_, values)) => values.push(&line.value),
+            None => streams.push((labels, vec![&line.value])),
+        }
+    }
+
+    // the labels and lines are already serialized
+    let streams = streams
+        .into_iter()
+        .map(|(labels, values)| format!(r#"{{"stream":{labels},"values":[{}]}}"#, values.join(",")))
+        .collect::<Vec<_>>();
+    format!(r#"{{"streams":[{}]}}"#, streams.join(","))
+}
+
+/// Push a set of log lines in batches, returning the number of lines that
+/// were pushed before a push failed
+async fn push_all(
+    client: &reqwest::Client,
+    url: &Url,
+    labels: &str,
+    lines: &[LokiLine],
+) -> (usize, Option<String>) {
+    let mut pushed = 0;
+    for batch in lines.chunks(BATCH_SIZE) {
+        let body = push_body(labels, batch);
+
+        let res = client
+            .post(url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .timeout(PUSH_TIMEOUT)
+            .body(body)
+            .send()
+            .await;
+        match res {
+            Ok(res) if res.status().is_success() => pushed += batch.len(),
+            Ok(res) => return (pushed, Some(format!("status {}", res.status()))),
+            Err(e) => return (pushed, Some(e.to_string())),
+        }
+    }
+    (pushed, None)
+}
+
+/// Ship the log lines of the agent and its node to the loki server from the
+/// control plane's handshake. Lines are buffered on disk while loki is
+/// unreachable, and shipped before any new lines once it is reachable again.
+/// Lines are dropped when no loki server is configured.
+pub async fn ship(state: Arc<GlobalState>, mut rx: LokiReceiver) {
+    let (path, capacity) = (state.cli.path.join(BUFFER_FILE), state.cli.loki_buffer);
+    let mut buffer = tokio::task::spawn_blocking(move || LokiBuffer::open(path, capacity))
+        .await
+        .expect("failed to open loki buffer");
+    let client = reqwest::Client::new();
+    let labels = json!({ "source": "agent", "agent": state.cli.id.to_string() }).to_string();
+    let mut reachable = true;
+
+    let mut interval = tokio::time::interval(PUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let mut lines = vec![];
+        while let Ok(line) = rx.try_recv() {
+            lines.push(line);
+        }
+
+        let loki = state.loki.lock().ok().and_then(|loki| loki.clone());
+        let Some(url) = loki.and_then(|loki| loki.join("loki/api/v1/push").ok()) else {
+            continue;
+        };
+
+        let mut error = None;
+        // buffered lines are shipped first to keep the lines in order
+        if !buffer.is_empty() {
+            let (pushed, e) =
+                push_all(&client, &url, &labels, buffer.lines.make_contiguous()).await;
+            if pushed > 0 {
+                buffer = write_buffer(buffer, move |buffer| buffer.pop_front(pushed)).await;
+            }
+            error = e;
+        }
+        if error.is_none() {
+            let (pushed, e) = push_all(&client, &url, &labels, &lines).await;
+            lines.drain(..pushed);
+            error = e;
+        }
+
+        match error {
+            Some(e) => {
+                if reachable {
+                    warn!("failed to push logs to loki, buffering: {e}");
+                }
+                reachable = false;
+                if !lines.is_empty() {
+                    buffer = write_buffer(buffer, move |buffer| buffer.push(lines)).await;
+                }
+            }
+            None if !reachable => {
+                info!("loki is reachable again, buffered logs were shipped");
+                reachable = true;
+            }
+            None => {}
+        }
+
+        state.metrics.write().await.loki_buffered = buffer.len();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn agent_line(i: usize) -> LokiLine {
+        LokiLine {
+            stream: None,
+            value: json!([i.to_string(), "agent"]).to_string(),
+        }
+    }
+
+    #[test]
+    fn test_buffer_drops_oldest() {
+        let path =
+            std::env::temp_dir().join(format!("snops-loki-buffer-{}.jsonl", std::process::id()));
+        let lines = |range: std::ops::Range<usize>| range.map(agent_line).collect();
+        let expected = |range: std::ops::Range<usize>| range.map(agent_line).collect::<Vec<_>>();
+        let _ = fs::remove_file(&path);
+
+        let mut buffer = LokiBuffer::open(path.clone(), 5);
+        buffer.push(lines(0..3)).unwrap();
+        buffer.push(lines(3..7)).unwrap();
+        assert_eq!(buffer.lines, expected(2..7));
+
+        // the buffer survives restarts
+        let mut buffer = LokiBuffer::open(path.clone(), 5);
+        assert_eq!(buffer.len(), 5);
+        buffer.pop_front(2).unwrap();
+        assert_eq!(LokiBuffer::open(path.clone(), 5).lines, expected(4..7));
+
+        buffer.pop_front(3).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_buffer_keeps_node_streams() {
+        let path = std::env::temp_dir().join(format!(
+            "snops-loki-buffer-node-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let labels = node_labels("agent-1", "env", "validator/0");
+        let node = LokiLine::new(Some(Arc::clone(&labels)), "node \"line\"");
+        let lines = vec![agent_line(0), node.clone(), agent_line(1)];
+
+        let mut buffer = LokiBuffer::open(path.clone(), 5);
+        buffer.push(lines.clone()).unwrap();
+        assert_eq!(LokiBuffer::open(path.clone(), 5).lines, lines);
+
+        buffer.pop_front(3).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_write_buffer_off_runtime() {
+        let path = std::env::temp_dir().join(format!(
+            "snops-loki-buffer-blocking-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let buffer = LokiBuffer::open(path.clone(), 5);
+        let buffer = write_buffer(buffer, |buffer| buffer.push(vec![agent_line(0)])).await;
+        assert_eq!(buffer.len(), 1);
+
+        let buffer = write_buffer(buffer, |buffer| buffer.pop_front(1)).await;
+        assert!(buffer.is_empty());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_push_body_groups_streams() {
+        let labels = node_labels("agent-1", "env", "validator/0");
+        let node = LokiLine::new(Some(labels), "node");
+        let body = push_body(
+            r#"{"source":"agent"}"#,
+            &[agent_line(0), node.clone(), agent_line(1)],
+        );
+
+        let body: Value = serde_json::from_str(&body).unwrap();
+        let streams = body["streams"].as_array().unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0]["stream"]["source"], "agent");
+        assert_eq!(streams[0]["values"].as_array().unwrap().len(), 2);
+        assert_eq!(streams[1]["stream"]["node_key"], "validator/0");
+        assert_eq!(streams[1]["values"][0][1], "node");
+    }
+}
@@ -1,6 +1,15 @@
 use tracing::level_filters::LevelFilter;
 use tracing_appender::non_blocking::WorkerGuard;
-use tracing_subscriber::{EnvFilter, layer::SubscriberExt, reload, util::SubscriberInitExt};
+use tracing_subscriber::{
+    EnvFilter,
+    filter::{Directive, ParseError},
+    layer::SubscriberExt,
+    reload,
+    util::SubscriberInitExt,
+};
+
+mod loki;
+pub use loki::{LokiReceiver, LokiSender, forward_node_stdout, node_labels, ship as ship_loki};
 
 pub type ReloadHandler = reload::Handle<EnvFilter, tracing_subscriber::Registry>;
 
@@ -19,7 +28,23 @@ pub fn make_env_filter(level: LevelFilter) -> EnvFilter {
         .add_directive("tarpc::server=ERROR".parse().unwrap())
 }
 
-pub fn init_logging() -> (WorkerGuard, ReloadHandler) {
+/// Build an env filter from either a bare level (`trace`) or a comma separated
+/// list of directives (`info,snops_agent::reconcile=trace`).
+pub fn parse_env_filter(filter: &str) -> Result<EnvFilter, ParseError> {
+    if let Ok(level) = filter.parse::<LevelFilter>() {
+        return Ok(make_env_filter(level));
+    }
+
+    filter
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .try_fold(make_env_filter(LevelFilter::INFO), |env, d| {
+            Ok(env.add_directive(d.parse::<Directive>()?))
+        })
+}
+
+pub fn init_logging() -> (WorkerGuard, ReloadHandler, LokiSender, LokiReceiver) {
     let (stdout, guard) = tracing_appender::non_blocking(std::io::stdout());
 
     let output: tracing_subscriber::fmt::Layer<
@@ -42,12 +67,15 @@ pub fn init_logging() -> (WorkerGuard, ReloadHandler) {
     };
 
     let (env_filter, reload_handler) = reload::Layer::new(make_env_filter(filter_level));
+    let (loki, loki_rx) = loki::LokiLayer::channel();
+    let loki_tx = loki.sender();
 
     tracing_subscriber::registry()
         .with(env_filter)
         .with(output)
+        .with(loki)
         .try_init()
         .unwrap();
 
-    (guard, reload_handler)
+    (guard, reload_handler, loki_tx, loki_rx)
 }
@@ -13,7 +13,7 @@ mod transfers;
 use std::{
     net::Ipv4Addr,
     sync::{Arc, Mutex},
-    time::{Duration, Instant},
+    time::Instant,
 };
 
 use clap::Parser;
@@ -29,7 +29,7 @@ use tokio::{
 };
 use tracing::{error, info};
 
-use crate::state::GlobalState;
+use crate::state::{GlobalState, RECONNECT_BACKOFF_BASE};
 mod log;
 
 #[tokio::main]
@@ -42,22 +42,27 @@ async fn main() {
     #[cfg(any(feature = "clipages", feature = "mangen"))]
     Cli::parse().run();
 
-    let (_guard, reload_handler) = init_logging();
+    let (_guard, reload_handler, loki_tx, loki_rx) = init_logging();
 
-    let args = Cli::parse();
+    let mut args = Cli::parse();
+    args.resolve_bind_addr();
 
     let (internal_addrs, external_addr) = args.addrs();
 
     let (endpoint, ws_uri) = args.endpoint_and_uri();
     info!("Using endpoint {endpoint}");
 
+    let http_client = net::http_client(args.peer_proxy.as_ref(), &endpoint)
+        .expect("failed to create http client");
+
     // Create the data directory
     tokio::fs::create_dir_all(&args.path)
         .await
         .expect("failed to create data path");
 
     // Open the database
-    let db = db::Database::open(&args.path.join("store")).expect("failed to open database");
+    let db = db::Database::open_with(&args.path.join("store"), &args.db)
+        .expect("failed to open database");
 
     let client = Default::default();
 
@@ -84,9 +89,13 @@ async fn main() {
         internal_addrs,
         cli: args,
         endpoint,
+        http_client,
         queue_reconcile_tx,
         loki: Mutex::new(db.loki_url()),
+        loki_tx,
         last_node_status: RwLock::new(None),
+        last_block_height: Default::default(),
+        agent_state_updates: Default::default(),
         env_info: RwLock::new(
             db.env_info()
                 .inspect_err(|e| {
@@ -113,15 +122,22 @@ async fn main() {
         agent_rpc_port,
         transfer_tx,
         transfers,
+        throttled_transfers: Default::default(),
         node_client: Default::default(),
+        node_command: Default::default(),
+        last_reconcile: Default::default(),
         log_level_handler: reload_handler,
         db: OpaqueDebug(db),
         shutdown: RwLock::new(Some(shutdown_tx)),
+        reconnect_backoff: Mutex::new(RECONNECT_BACKOFF_BASE),
     });
 
     // Start the metrics watcher
     metrics::init(Arc::clone(&state));
 
+    // Start shipping the agent's and node's logs to loki
+    tokio::spawn(log::ship_loki(Arc::clone(&state), loki_rx));
+
     // Start the status server
     let status_state = Arc::clone(&state);
     tokio::spawn(async move {
@@ -132,18 +148,65 @@ async fn main() {
         }
     });
 
+    // Start the health probe server
+    if let Some(health_bind) = state.cli.health_bind {
+        let listener = tokio::net::TcpListener::bind(health_bind)
+            .await
+            .expect("failed to bind health server");
+        let health_state = Arc::clone(&state);
+        tokio::spawn(async move {
+            info!("Starting health server on {health_bind}");
+            if let Err(e) = server::start_health(listener, health_state).await {
+                error!("health server crashed: {e:?}");
+                std::process::exit(1);
+            }
+        });
+    }
+
     // Get the interrupt signals to break the stream connection
     let mut interrupt = Signals::term_or_interrupt();
 
     let state2 = Arc::clone(&state);
-    tokio::spawn(async move {
+    // Completes when the agent runs out of reconnect attempts
+    let mut connection = tokio::spawn(async move {
+        let reconnect_limit = state2.cli.reconnect_limit();
+        let mut reconnect_attempts = 0;
+        let mut last_addr = None;
         loop {
-            let req = client::new_ws_request(&ws_uri, state2.db.jwt());
-            client::ws_connection(req, Arc::clone(&state2)).await;
+            let connected = match client::resolve_endpoint(&ws_uri).await {
+                Ok(addr) => {
+                    if let Some(last) = last_addr.filter(|last| *last != addr) {
+                        info!("Control plane endpoint now resolves to {addr} (was {last})");
+                    }
+                    last_addr = Some(addr);
+
+                    let req = client::new_ws_request(&ws_uri, state2.db.jwt());
+                    client::ws_connection(req, addr, Arc::clone(&state2)).await
+                }
+                Err(e) => {
+                    error!("failed to resolve control plane endpoint {ws_uri}: {e}");
+                    false
+                }
+            };
             // Remove the control client
             state2.client.write().await.take();
-            info!("Attempting to reconnect to the control plane...");
-            tokio::time::sleep(Duration::from_secs(5)).await;
+
+            // Count the reconnect attempts since the last established connection
+            if connected {
+                reconnect_attempts = 0;
+            }
+            if let Some(limit) = reconnect_limit.filter(|limit| reconnect_attempts >= *limit) {
+                error!("Giving up on the control plane after {limit} reconnect attempts");
+                return;
+            }
+            reconnect_attempts += 1;
+
+            let backoff = state2.next_reconnect_backoff();
+            info!(
+                "Attempting to reconnect to the control plane in {}s...",
+                backoff.as_secs()
+            );
+            tokio::time::sleep(backoff).await;
         }
     });
 
@@ -157,10 +220,12 @@ async fn main() {
         context: AgentStateReconcilerContext::hydrate(&state.db),
     };
 
+    let mut exit_code = 0;
     select! {
         _ = root.loop_forever(reconcile_requests) => unreachable!(),
         _ = interrupt.recv_any() => {},
         _ = shutdown_rx => {},
+        _ = &mut connection => exit_code = 1,
     }
 
     info!("Received interrupt signal, shutting down...");
@@ -168,6 +233,9 @@ async fn main() {
         process.graceful_shutdown().await;
         info!("Agent has shut down gracefully");
     }
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
 }
 
 struct Signals {
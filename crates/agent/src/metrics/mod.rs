@@ -10,6 +10,10 @@ pub const UPDATE_RATE: Duration = Duration::from_secs(15);
 #[derive(Default)]
 pub struct Metrics {
     pub tps: TpsMetric,
+    /// Round trip time of the latest ping to the control plane
+    pub ping_rtt: Option<Duration>,
+    /// Number of log lines buffered while loki is unreachable
+    pub loki_buffered: usize,
 }
 
 /// Parsed metrics from the snarkOS Prometheus scraper.
@@ -19,10 +23,6 @@ pub fn init(state: Arc<GlobalState>) {
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(UPDATE_RATE);
         let client = reqwest::Client::new();
-        let route = format!(
-            "http://{}/",
-            SocketAddr::new(state.cli.get_local_ip(), state.cli.ports.metrics)
-        );
 
         loop {
             interval.tick().await;
@@ -31,6 +31,11 @@ pub fn init(state: Arc<GlobalState>) {
                 continue;
             }
 
+            let route = format!(
+                "http://{}/",
+                SocketAddr::new(state.get_node_local_ip().await, state.cli.ports.metrics)
+            );
+
             let metrics_text = 'metrics: {
                 let response = match client.get(&route).send().await {
                     Ok(response) => response,
@@ -1,8 +1,45 @@
 use std::net::IpAddr;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
+use reqwest::{NoProxy, Proxy};
 use tracing::info;
+use url::Url;
 
+/// Parse a `--peer-proxy` url, ensuring it is a SOCKS5 proxy
+pub fn parse_socks5_url(s: &str) -> Result<Url> {
+    let url = Url::parse(s)?;
+    if !matches!(url.scheme(), "socks5" | "socks5h") {
+        bail!(
+            "expected a socks5:// or socks5h:// url, got `{}`",
+            url.scheme()
+        );
+    }
+    if url.host_str().is_none() {
+        bail!("missing proxy host");
+    }
+    Ok(url)
+}
+
+/// Build the client used for the agent's outbound HTTP. When a peer proxy is
+/// set, requests are dialed through it, except for the control plane.
+pub fn http_client(peer_proxy: Option<&Url>, endpoint: &str) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+
+    if let Some(proxy) = peer_proxy {
+        let control_plane = Url::parse(endpoint)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned));
+        builder = builder.proxy(
+            Proxy::all(proxy.as_str())?.no_proxy(control_plane.and_then(NoProxy::from_string)),
+        );
+        info!("Using peer proxy {proxy}");
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Get the addresses of the local network interfaces, excluding loopback and
+/// link-local addresses
 pub fn get_internal_addrs() -> Result<Vec<IpAddr>> {
     let network_interfaces = local_ip_address::list_afinet_netifas()?;
 
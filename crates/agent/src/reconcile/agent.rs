@@ -1,5 +1,5 @@
 use std::{
-    sync::Arc,
+    sync::{Arc, atomic::Ordering},
     time::{Duration, Instant},
 };
 
@@ -8,7 +8,8 @@ use snops_common::{
     binaries::BinaryEntry,
     rpc::error::ReconcileError,
     state::{
-        AgentState, HeightRequest, NodeState, ReconcileCondition, ReconcileOptions, TransferId,
+        AgentState, HeightRequest, NodeState, NodeStatus, ReconcileCondition, ReconcileOptions,
+        TransferId,
     },
 };
 use tarpc::context;
@@ -18,7 +19,7 @@ use tokio::{
     task::AbortHandle,
     time::sleep_until,
 };
-use tracing::{error, info, trace};
+use tracing::{error, info, trace, warn};
 
 use super::{
     Reconcile, ReconcileStatus,
@@ -57,6 +58,9 @@ pub struct AgentStateReconcilerContext {
     /// Information about the node process
     pub process: Option<ProcessContext>,
     pub shutdown_pending: bool,
+    /// When true, changes that require a restart are not applied while the
+    /// node is running and healthy
+    pub restart_deferred: bool,
 }
 
 #[derive(Default)]
@@ -126,7 +130,7 @@ impl AgentStateReconciler {
         // The first reconcile is scheduled for 5 seconds after startup.
         // Connecting to the controlplane will likely trigger a reconcile sooner.
         let mut next_reconcile_at = Instant::now() + Duration::from_secs(5);
-        let mut next_opts = ReconcileOptions::default();
+        let mut next_opts: Option<ReconcileOptions> = None;
 
         // Repeated reconcile loop
         loop {
@@ -136,7 +140,7 @@ impl AgentStateReconciler {
                     // Replace the next_reconcile_at with the soonest reconcile time
                     Some((new_reconcile_at, opts)) = reconcile_requests.recv() => {
                         next_reconcile_at = next_reconcile_at.min(new_reconcile_at);
-                        next_opts = next_opts.union(opts);
+                        next_opts = Some(next_opts.map_or(opts, |prev| prev.union(opts)));
                     },
                     _ = sleep_until(next_reconcile_at.into()) => {
                         break
@@ -152,41 +156,59 @@ impl AgentStateReconciler {
 
             // Update the reconciler with the latest agent state
             // This prevents the agent state from changing during reconciliation
+            let state_updates = self.state.agent_state_updates.load(Ordering::Acquire);
             self.agent_state = self.state.get_agent_state().await;
 
+            // Periodic reconciles keep the deferral from the last requested state
+            let opts = next_opts.take();
+            if let Some(opts) = opts {
+                self.context.restart_deferred = opts.only_if_offline;
+            }
+            let opts = opts.unwrap_or_default();
+
             // Clear the env info if refetch_info is set to force it to be fetched again
-            if next_opts.refetch_info {
+            if opts.refetch_info {
                 self.state.set_env_info(None).await;
             }
 
             // If the agent is forced to shutdown, set the shutdown_pending flag
-            if next_opts.force_shutdown && self.has_process() {
+            if opts.force_shutdown && self.has_process() {
                 self.context.shutdown_pending = true;
             }
 
             // If the agent is forced to clear the last height, clear it
-            if next_opts.clear_last_height {
+            if opts.clear_last_height {
                 self.context.ledger_last_height = None;
                 if let Err(e) = self.state.db.set_last_height(None) {
                     error!("failed to clear last height from db: {e}");
                 }
             }
 
-            next_opts = Default::default();
-
             trace!("Reconciling agent state...");
             let res = self.reconcile().await;
 
+            let node_is_started = self
+                .state
+                .get_node_status()
+                .await
+                .is_some_and(|s| s.is_started());
+            let outcome = res
+                .clone()
+                .map(|s| s.replace_inner(self.is_node_running() && node_is_started));
+            *self.state.last_reconcile.write().await = Some(outcome.clone());
+
+            // The outcome of a reconcile superseded by a new agent state is not
+            // posted, so it is not mistaken for the outcome of the new state. The
+            // new state is reconciled next.
+            let superseded =
+                state_updates != self.state.agent_state_updates.load(Ordering::Acquire);
+            if superseded {
+                trace!("Reconcile superseded by a new agent state");
+            }
+
             // If this reconcile was triggered by a reconcile request, post the status
-            if let Some(client) = self.state.get_ws_client().await {
-                let node_is_started = self
-                    .state
-                    .get_node_status()
-                    .await
-                    .is_some_and(|s| s.is_started());
-                let res = res
-                    .clone()
-                    .map(|s| s.replace_inner(self.is_node_running() && node_is_started));
+            if let Some(client) = self.state.get_ws_client().await.filter(|_| !superseded) {
+                let res = outcome;
 
                 // TODO: throttle this broadcast
                 tokio::spawn(async move {
@@ -229,6 +251,7 @@ impl AgentStateReconciler {
                 if res.inner.is_some() {
                     self.context.process = None;
                     self.state.set_node_status(None).await;
+                    self.state.set_node_command(None).await;
                     self.context.shutdown_pending = false;
                 }
             });
@@ -266,6 +289,29 @@ impl AgentStateReconciler {
             .is_some_and(|p| p.is_running())
     }
 
+    /// Check if a restart should be deferred because the last requested state
+    /// asked to only be applied while the node is offline. Explicit shutdowns
+    /// and offline requests are never deferred.
+    pub async fn should_defer_restart(&mut self, node: &NodeState) -> bool {
+        if !self.context.restart_deferred || self.context.shutdown_pending || !node.online {
+            return false;
+        }
+
+        self.is_node_running()
+            && self.state.is_node_online()
+            && self
+                .state
+                .get_node_status()
+                .await
+                .is_some_and(|s| s.is_started())
+    }
+
+    fn deferred_status() -> ReconcileStatus<()> {
+        ReconcileStatus::default()
+            .add_condition(ReconcileCondition::DeferredRestart)
+            .add_scope("agent_state/deferred")
+    }
+
     pub fn is_shutdown_pending(&self, node: &NodeState, env_info: &AgentEnvInfo) -> bool {
         // Ensure the process is running
         if !self.has_process() {
@@ -336,6 +382,15 @@ impl AgentStateReconciler {
 
 impl Reconcile<(), ReconcileError> for AgentStateReconciler {
     async fn reconcile(&mut self) -> Result<ReconcileStatus<()>, ReconcileError> {
+        // compute-only agents never run a node, so there is nothing to download or
+        // start. authorizations are executed by the rpc server directly
+        if self.state.cli.modes.compute_only {
+            if !matches!(self.agent_state.as_ref(), AgentState::Inventory) {
+                warn!("Ignoring node state on a compute-only agent");
+            }
+            return self.reconcile_inventory().await;
+        }
+
         let (env_id, node) = match self.agent_state.as_ref() {
             AgentState::Inventory => {
                 return self.reconcile_inventory().await;
@@ -348,6 +403,10 @@ impl Reconcile<(), ReconcileError> for AgentStateReconciler {
         // If the node should be torn down because a configuration changed, we need to
         // gracefully shut down the node.
         if self.is_shutdown_pending(node, &env_info) {
+            if self.should_defer_restart(node).await {
+                return Ok(Self::deferred_status());
+            }
+
             self.context.shutdown_pending = true;
             // Unwrap safety - is_shutdown_pending ensures the process exists.
             let process = self.context.process.as_mut().unwrap();
@@ -357,6 +416,7 @@ impl Reconcile<(), ReconcileError> for AgentStateReconciler {
                 if res.inner.is_some() {
                     self.context.process = None;
                     self.state.set_node_status(None).await;
+                    self.state.set_node_command(None).await;
                     self.context.shutdown_pending = false;
                 }
             });
@@ -385,7 +445,14 @@ impl Reconcile<(), ReconcileError> for AgentStateReconciler {
             // If the process has exited, clear the process context
             if !process.is_running() {
                 info!("Node process has exited...");
+                if process.is_out_of_memory().await {
+                    warn!("Node process exceeded its memory limit");
+                    self.state.report_node_status(NodeStatus::OutOfMemory).await;
+                }
                 self.context.process = None;
+                self.state.set_node_command(None).await;
+                // the node went offline on its own, so deferred changes can be applied
+                self.context.restart_deferred = false;
 
                 return Ok(ReconcileStatus::empty()
                     .requeue_after(Duration::ZERO)
@@ -404,6 +471,10 @@ impl Reconcile<(), ReconcileError> for AgentStateReconciler {
 
             // If the command has changed, restart the process
             if process.command != command {
+                if self.should_defer_restart(node).await {
+                    return Ok(Self::deferred_status());
+                }
+
                 info!("Node command has changed, restarting process...");
                 self.context.shutdown_pending = true;
                 return Ok(ReconcileStatus::empty()
@@ -498,6 +569,7 @@ impl Reconcile<(), ReconcileError> for AgentStateReconciler {
             ledger,
             LedgerReconciler {
                 state: Arc::clone(&self.state),
+                env_id: *env_id,
                 env_info: Arc::clone(&env_info),
                 modify_handle: &mut transfers.ledger_modify_handle,
                 target_height: node.height,
@@ -518,9 +590,11 @@ impl Reconcile<(), ReconcileError> for AgentStateReconciler {
         )
         .await?;
 
-        let process = ProcessContext::new(command)?;
+        let command_line = command.redacted_args();
+        let process = ProcessContext::new(command, &self.state)?;
         // Clear the last node running status (it was shut down)
         self.state.set_node_status(None).await;
+        self.state.set_node_command(Some(command_line)).await;
         self.context.process = Some(process);
         self.context.shutdown_pending = false;
         Ok(ReconcileStatus::empty()
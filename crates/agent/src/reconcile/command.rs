@@ -1,4 +1,4 @@
-use std::{net::IpAddr, ops::Deref, path::PathBuf, process::Stdio, sync::Arc};
+use std::{borrow::Cow, net::IpAddr, ops::Deref, path::PathBuf, process::Stdio, sync::Arc};
 
 use indexmap::IndexMap;
 use snops_checkpoint::RetentionPolicy;
@@ -14,29 +14,31 @@ use snops_common::{
 use tokio::process::Command;
 use url::Url;
 
-use crate::state::GlobalState;
+use super::limits;
+use crate::{log, state::GlobalState};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct NodeCommand {
     /// Path to the snarkos binary
     pub command_path: PathBuf,
     /// If true, do not print stdout
-    quiet: bool,
+    pub quiet: bool,
     /// Environment ID (used in loki)
     env_id: EnvId,
     /// Node key (drives NETWORK env)
     network: NetworkId,
     /// Node key (derives node type and loki)
     node_key: NodeKey,
-    /// URL for sending logs to loki
-    loki: Option<Url>,
+    /// URL for sending logs to loki. The agent ships the node's stdout when
+    /// set.
+    pub loki: Option<Url>,
     /// Path to the ledger directory
     ledger_path: PathBuf,
     /// Path to place the log file
     log_path: PathBuf,
     /// Path to genesis block. When absent, use the network's genesis block.
     genesis_path: Option<PathBuf>,
-    /// Env variables to pass to the node
+    /// Env variables to pass to the node, with templates expanded
     env: IndexMap<String, String>,
     /// Port to bind the agent's RPC server for node status
     agent_rpc_port: u16,
@@ -54,6 +56,12 @@ pub struct NodeCommand {
     peers: Vec<String>,
     /// Resolved validator addresses for the node
     validators: Vec<String>,
+    /// CPU limit of the node in millicores
+    pub cpu_limit: Option<u32>,
+    /// Memory limit of the node in MiB
+    pub memory_limit: Option<u64>,
+    /// cgroup the node is placed in to enforce the CPU limit
+    pub cgroup: Option<PathBuf>,
 }
 
 impl NodeCommand {
@@ -63,17 +71,7 @@ impl NodeCommand {
         env_id: EnvId,
         env_info: Arc<AgentEnvInfo>,
     ) -> Result<Self, ReconcileError> {
-        let storage_path = state
-            .cli
-            .storage_path(env_info.network, env_info.storage.id);
-
-        let ledger_path = if env_info.storage.persist {
-            storage_path.join(LEDGER_PERSIST_DIR)
-        } else {
-            let mut dir = state.cli.path.join(NODE_DATA_DIR);
-            dir.push(LEDGER_BASE_DIR);
-            dir
-        };
+        let (ledger_path, genesis_path) = node_ledger_paths(&state, &env_info);
 
         Ok(NodeCommand {
             command_path: state.cli.path.join(SNARKOS_FILE),
@@ -83,12 +81,21 @@ impl NodeCommand {
             loki: state.loki.lock().ok().and_then(|l| l.deref().clone()),
             ledger_path,
             log_path: state.cli.path.join(SNARKOS_LOG_FILE),
-            genesis_path: (!env_info.storage.native_genesis)
-                .then(|| storage_path.join(SNARKOS_GENESIS_FILE)),
+            genesis_path,
             network: env_info.network,
-            env: node.env.clone(),
+            env: expand_env_templates(
+                &node.env,
+                &[
+                    ("node_key", node.node_key.to_string()),
+                    ("node_type", node.node_key.ty.to_string()),
+                    ("node_id", node.node_key.id.clone()),
+                    ("agent_id", state.cli.id.to_string()),
+                    ("env_id", env_id.to_string()),
+                    ("network", env_info.network.to_string()),
+                ],
+            ),
             agent_rpc_port: state.agent_rpc_port,
-            bind_addr: state.cli.bind_addr,
+            bind_addr: node.bind_addr.unwrap_or(state.cli.bind_addr),
             ports: state.cli.ports,
             private_key: match &node.private_key {
                 KeyState::Literal(pk) => Some(pk.clone()),
@@ -109,6 +116,9 @@ impl NodeCommand {
             },
             peers: state.agentpeers_to_cli(&node.peers).await,
             validators: state.agentpeers_to_cli(&node.validators).await,
+            cpu_limit: node.cpu_limit,
+            memory_limit: node.memory_limit,
+            cgroup: state.cli.cgroup.clone(),
             retention_policy: env_info.storage.retention_policy.clone(),
         })
     }
@@ -116,28 +126,25 @@ impl NodeCommand {
     pub fn build(&self) -> Command {
         let mut command = Command::new(&self.command_path);
 
-        // set stdio
-        if self.quiet {
+        // set stdio. The agent ships the node's stdout to loki, buffering it
+        // with its own logs while loki is unreachable
+        if self.loki.is_some() {
+            command.stdout(Stdio::piped());
+        } else if self.quiet {
             command.stdout(Stdio::null());
         } else {
             command.stdout(std::io::stdout());
         }
-        command.stderr(std::io::stderr());
-
-        // add loki URL if one is set
-        if let Some(loki) = &self.loki {
-            command
-                .env(
-                    "SNOPS_LOKI_LABELS",
-                    format!("env_id={},node_key={}", self.env_id, self.node_key),
-                )
-                .arg("--loki")
-                .arg(loki.as_str());
+        // the agent forwards the stderr of a memory limited node to detect
+        // failed allocations
+        if self.memory_limit.is_some() {
+            command.stderr(Stdio::piped());
+        } else {
+            command.stderr(std::io::stderr());
         }
 
         // setup the run command
         command
-            .stderr(std::io::stderr())
             .envs(&self.env)
             .env("NETWORK", self.network.to_string())
             .env("HOME", &self.ledger_path)
@@ -190,6 +197,154 @@ impl NodeCommand {
             command.arg("--validators").arg(self.validators.join(","));
         }
 
+        if let Some(limit) = self.memory_limit {
+            limits::limit_memory(&mut command, limit);
+        }
+
         command
     }
+
+    /// Labels of the loki stream the node's logs are shipped in
+    pub fn loki_labels(&self, agent: &str) -> Arc<str> {
+        log::node_labels(agent, &self.env_id.to_string(), &self.node_key.to_string())
+    }
+
+    /// The command line of the node as environment variables (`NAME=value`)
+    /// followed by the program and its arguments, with the private key and
+    /// the credentials and query parameters of urls (such as loki's)
+    /// redacted.
+    pub fn redacted_args(&self) -> Vec<String> {
+        let command = self.build();
+        let command = command.as_std();
+
+        let envs = command.get_envs().filter_map(|(name, value)| {
+            let name = name.to_string_lossy();
+            let value = match name.as_ref() {
+                "PRIVATE_KEY" => REDACTED.into(),
+                _ => redact_url(value?.to_string_lossy()),
+            };
+            Some(format!("{name}={value}"))
+        });
+
+        let mut redact_next = false;
+        let args = command.get_args().map(|arg| {
+            let arg = arg.to_string_lossy();
+            if std::mem::replace(&mut redact_next, arg == "--private-key") {
+                REDACTED.to_owned()
+            } else {
+                redact_url(arg).into_owned()
+            }
+        });
+
+        envs.chain(std::iter::once(
+            command.get_program().to_string_lossy().into_owned(),
+        ))
+        .chain(args)
+        .collect()
+    }
+}
+
+const REDACTED: &str = "<redacted>";
+
+/// Redact the user info and query parameter values of a value that is a url,
+/// as they commonly carry credentials. Other values are returned unchanged.
+fn redact_url(value: Cow<'_, str>) -> Cow<'_, str> {
+    let Ok(mut url) = Url::parse(&value) else {
+        return value;
+    };
+    if url.username().is_empty() && url.password().is_none() && url.query().is_none() {
+        return value;
+    }
+
+    // `<` and `>` would be percent-encoded in a url
+    const URL_REDACTED: &str = "redacted";
+    if !url.username().is_empty() {
+        let _ = url.set_username(URL_REDACTED);
+    }
+    if url.password().is_some() {
+        let _ = url.set_password(Some(URL_REDACTED));
+    }
+    if url.query().is_some() {
+        let keys = url
+            .query_pairs()
+            .map(|(key, _)| key.into_owned())
+            .collect::<Vec<_>>();
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(keys.iter().map(|key| (key, URL_REDACTED)));
+    }
+    url.to_string().into()
+}
+
+/// Resolve the ledger directory and genesis block used by this agent's node in
+/// the given environment. The genesis path is absent when the network's native
+/// genesis block is used.
+pub fn node_ledger_paths(
+    state: &GlobalState,
+    env_info: &AgentEnvInfo,
+) -> (PathBuf, Option<PathBuf>) {
+    let storage_path = state
+        .cli
+        .storage_path(env_info.network, env_info.storage.id);
+
+    let ledger_path = if env_info.storage.persist {
+        storage_path.join(LEDGER_PERSIST_DIR)
+    } else {
+        let mut dir = state.cli.path.join(NODE_DATA_DIR);
+        dir.push(LEDGER_BASE_DIR);
+        dir
+    };
+
+    let genesis_path =
+        (!env_info.storage.native_genesis).then(|| storage_path.join(SNARKOS_GENESIS_FILE));
+
+    (ledger_path, genesis_path)
+}
+
+/// Expand `{{name}}` placeholders in env var values with node metadata.
+/// Unknown placeholders are left untouched.
+fn expand_env_templates(
+    env: &IndexMap<String, String>,
+    vars: &[(&str, String)],
+) -> IndexMap<String, String> {
+    env.iter()
+        .map(|(key, value)| {
+            let value = vars.iter().fold(value.clone(), |value, (name, var)| {
+                value.replace(&format!("{{{{{name}}}}}"), var)
+            });
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_env_templates() {
+        let env: IndexMap<String, String> = [
+            ("NODE_KEY", "{{node_key}}"),
+            ("LABEL", "{{agent_id}}-{{node_id}}"),
+            ("PLAIN", "bar"),
+            ("UNKNOWN", "{{missing}}"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect();
+
+        let expanded = expand_env_templates(
+            &env,
+            &[
+                ("node_key", "validator/1".to_owned()),
+                ("node_id", "1".to_owned()),
+                ("agent_id", "agent-1".to_owned()),
+            ],
+        );
+
+        assert_eq!(expanded["NODE_KEY"], "validator/1");
+        assert_eq!(expanded["LABEL"], "agent-1-1");
+        assert_eq!(expanded["PLAIN"], "bar");
+        assert_eq!(expanded["UNKNOWN"], "{{missing}}");
+    }
 }
@@ -2,7 +2,7 @@ use std::{
     os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use chrono::{TimeDelta, Utc};
@@ -20,7 +20,7 @@ use url::Url;
 
 use super::Reconcile;
 use crate::{
-    api::{download_file, get_file_issues},
+    api::{DownloadBusy, download_file, get_file_issues},
     state::GlobalState,
     transfers,
 };
@@ -33,6 +33,7 @@ pub fn default_binary(info: &AgentEnvInfo) -> BinaryEntry {
         ))),
         sha256: None,
         size: None,
+        version: None,
     }
 }
 
@@ -123,7 +124,7 @@ impl FileReconciler {
 
 impl Reconcile<bool, ReconcileError> for FileReconciler {
     async fn reconcile(&mut self) -> Result<ReconcileStatus<bool>, ReconcileError> {
-        let client = reqwest::Client::new();
+        let client = self.state.http_client.clone();
 
         // Create a transfer id if one is not provided
         if self.tx_id.is_none() {
@@ -238,16 +239,70 @@ impl Reconcile<bool, ReconcileError> for FileReconciler {
             );
         }
 
+        // back off while the server is throttling this transfer
+        let throttled_until = self.state.throttled_transfers.get(&tx_id).map(|t| *t);
+        if let Some(retry_at) = throttled_until {
+            let delay = retry_at.saturating_duration_since(Instant::now());
+            if !delay.is_zero() {
+                trace!(
+                    "Download of {} throttled for {}s",
+                    self.src,
+                    delay.as_secs()
+                );
+                return Ok(ReconcileStatus::empty()
+                    .add_condition(ReconcileCondition::ThrottledTransfer {
+                        source: self.src.to_string(),
+                        retry_after: delay.as_secs(),
+                    })
+                    .requeue_after(delay));
+            }
+            self.state.throttled_transfers.remove(&tx_id);
+        }
+
+        let head = client.head(self.src.clone()).send().await.ok();
+
+        // refuse downloads that cannot fit on the destination's filesystem
+        let required = self.check_size.or_else(|| {
+            head.as_ref()?
+                .headers()
+                .get(http::header::CONTENT_LENGTH)?
+                .to_str()
+                .ok()?
+                .parse::<u64>()
+                .ok()
+        });
+        let available = self.dst.parent().and_then(available_space);
+        if let (Some(required), Some(available)) = (required, available) {
+            if required > available {
+                return Err(ReconcileError::InsufficientDisk {
+                    path: self.dst.clone(),
+                    required,
+                    available,
+                });
+            }
+        }
+
         let src = self.src.clone();
         let dst = self.dst.clone();
         let transfer_tx = self.state.transfer_tx.clone();
+        let sha256 = self.check_sha256.clone();
+        let state = Arc::clone(&self.state);
 
         // download the file
         let handle = tokio::spawn(async move {
-            download_file(tx_id, &client, src, &dst, transfer_tx)
-                .await
-                // Dropping the File from download_file should close the handle
-                .map(|res| res.is_some())
+            let res =
+                download_file(tx_id, &client, src, &dst, sha256.as_deref(), transfer_tx).await;
+
+            // the next reconcile waits out the server's Retry-After before
+            // downloading again
+            if let Some(DownloadBusy(delay)) = res.as_ref().err().and_then(|e| e.downcast_ref()) {
+                state
+                    .throttled_transfers
+                    .insert(tx_id, Instant::now() + *delay);
+            }
+
+            // Dropping the File from download_file should close the handle
+            res.map(|res| res.is_some())
         })
         .abort_handle();
 
@@ -275,3 +330,10 @@ impl Reconcile<bool, ReconcileError> for FileReconciler {
             .requeue_after(Duration::from_secs(1)))
     }
 }
+
+/// The number of bytes available to the agent on the filesystem containing the
+/// given path
+fn available_space(path: &Path) -> Option<u64> {
+    let stat = nix::sys::statvfs::statvfs(path).ok()?;
+    Some((stat.blocks_available() as u64).saturating_mul(stat.fragment_size() as u64))
+}
@@ -0,0 +1,143 @@
+use std::{
+    io,
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+
+use nix::sys::{
+    resource::{Resource, setrlimit},
+    signal::Signal,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{ChildStderr, Command},
+};
+
+/// Name of the cgroup the node process is placed in, under the agent's cgroup
+const NODE_CGROUP: &str = "node";
+/// Period of the cgroup CPU bandwidth limit, in microseconds
+const CPU_PERIOD_US: u64 = 100_000;
+/// Smallest CPU quota the kernel accepts, in microseconds
+const MIN_CPU_QUOTA_US: u64 = 1_000;
+
+/// Limit the memory the spawned process can allocate.
+///
+/// The data segment is limited rather than the address space, as the ledger
+/// maps files that are far larger than the memory it actually uses.
+pub fn limit_memory(command: &mut Command, memory_limit_mib: u64) {
+    let bytes = memory_limit_mib.saturating_mul(1024 * 1024);
+
+    // SAFETY: setrlimit is a single syscall and is safe to call between fork
+    // and exec
+    unsafe {
+        command.pre_exec(move || {
+            setrlimit(Resource::RLIMIT_DATA, bytes, bytes).map_err(io::Error::from)
+        });
+    }
+}
+
+/// Place a running process in the node cgroup under the given cgroup v2
+/// directory, limiting its CPU bandwidth. The directory must be delegated to
+/// the agent and must not contain the agent process itself.
+pub fn limit_cpu(cgroup: &Path, pid: u32, cpu_limit_millicores: u32) -> io::Result<PathBuf> {
+    // controllers must be enabled in the parent for the child to use them
+    std::fs::write(cgroup.join("cgroup.subtree_control"), "+cpu")?;
+
+    let node_cgroup = cgroup.join(NODE_CGROUP);
+    if !node_cgroup.exists() {
+        std::fs::create_dir(&node_cgroup)?;
+    }
+
+    let quota = cpu_quota(cpu_limit_millicores);
+    std::fs::write(
+        node_cgroup.join("cpu.max"),
+        format!("{quota} {CPU_PERIOD_US}"),
+    )?;
+    std::fs::write(node_cgroup.join("cgroup.procs"), pid.to_string())?;
+
+    Ok(node_cgroup)
+}
+
+/// CPU time a node may use in each period, in microseconds
+fn cpu_quota(cpu_limit_millicores: u32) -> u64 {
+    (u64::from(cpu_limit_millicores) * CPU_PERIOD_US / 1000).max(MIN_CPU_QUOTA_US)
+}
+
+/// Forward a node's stderr to the agent's stderr, returning whether the node
+/// reported a failed allocation before its stderr closed.
+pub async fn forward_stderr(stderr: ChildStderr) -> bool {
+    let mut reader = BufReader::new(stderr);
+    let mut out = tokio::io::stderr();
+    let mut line = Vec::new();
+    let mut alloc_failed = false;
+
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        alloc_failed |= is_alloc_failure(&line);
+        let _ = out.write_all(&line).await;
+    }
+
+    alloc_failed
+}
+
+/// Whether a line of stderr is the message the rust runtime prints before
+/// aborting on a failed allocation
+fn is_alloc_failure(line: &[u8]) -> bool {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end();
+    line.starts_with("memory allocation of") && line.ends_with("failed")
+}
+
+/// Returns true when a process with a memory limit exited because it could
+/// not allocate more memory: the allocation failure was reported on stderr
+/// and the process aborted. Other aborts and kills are not memory related.
+pub fn is_out_of_memory(status: ExitStatus, alloc_failed: bool) -> bool {
+    alloc_failed && status.signal() == Some(Signal::SIGABRT as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_quota() {
+        assert_eq!(cpu_quota(1000), CPU_PERIOD_US);
+        assert_eq!(cpu_quota(1500), 150_000);
+        assert_eq!(cpu_quota(250), 25_000);
+        // a quota below the kernel's minimum would be rejected
+        assert_eq!(cpu_quota(0), MIN_CPU_QUOTA_US);
+        assert_eq!(cpu_quota(5), MIN_CPU_QUOTA_US);
+    }
+
+    #[test]
+    fn test_is_out_of_memory() {
+        let signaled = |sig: Signal| ExitStatus::from_raw(sig as i32);
+        let exited = |code: i32| ExitStatus::from_raw(code << 8);
+
+        assert!(is_out_of_memory(signaled(Signal::SIGABRT), true));
+        // a panic or assertion also aborts, without a failed allocation
+        assert!(!is_out_of_memory(signaled(Signal::SIGABRT), false));
+        // kills are not evidence of a failed allocation
+        assert!(!is_out_of_memory(signaled(Signal::SIGKILL), true));
+        assert!(!is_out_of_memory(exited(1), true));
+        assert!(!is_out_of_memory(exited(0), false));
+    }
+
+    #[test]
+    fn test_is_alloc_failure() {
+        assert!(is_alloc_failure(
+            b"memory allocation of 1073741824 bytes failed\n"
+        ));
+        assert!(!is_alloc_failure(
+            b"thread 'main' panicked at src/main.rs:1:1\n"
+        ));
+        assert!(!is_alloc_failure(
+            b"memory allocation of 64 bytes succeeded\n"
+        ));
+    }
+}
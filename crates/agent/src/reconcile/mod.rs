@@ -4,6 +4,7 @@ mod files;
 pub use files::*;
 use snops_common::state::ReconcileStatus;
 pub mod address;
+pub mod limits;
 pub mod process;
 pub mod state;
 pub mod storage;
@@ -5,11 +5,18 @@ use snops_common::{
     state::{ReconcileCondition, ReconcileStatus},
     util::sha256_file,
 };
-use tokio::{process::Child, select};
-use tracing::{error, info};
+use tokio::{process::Child, select, task::JoinHandle};
+use tracing::{error, info, warn};
 
-use super::{Reconcile, command::NodeCommand};
-use crate::state::NODE_GRACEFUL_SHUTDOWN_TIMEOUT;
+use super::{Reconcile, command::NodeCommand, limits};
+use crate::{
+    log,
+    state::{GlobalState, NODE_GRACEFUL_SHUTDOWN_TIMEOUT},
+};
+
+/// How long the stderr of an exited node is read before its exit is
+/// classified
+const STDERR_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Information about the current process
 pub struct ProcessContext {
@@ -27,28 +34,58 @@ pub struct ProcessContext {
     sigkill_at: Option<Instant>,
     /// The sha256 hash of the running binary
     binary_sha256: String,
+    /// Forwards the stderr of a memory limited node, resolving to whether the
+    /// node reported a failed allocation
+    stderr_task: Option<JoinHandle<bool>>,
 }
 
 impl ProcessContext {
-    pub fn new(command: NodeCommand) -> Result<Self, ReconcileError> {
+    pub fn new(command: NodeCommand, state: &GlobalState) -> Result<Self, ReconcileError> {
+        if !command.command_path.exists() {
+            return Err(ReconcileError::BinaryMissing(command.command_path.clone()));
+        }
+
         let binary_sha256 = sha256_file(&command.command_path).map_err(|e| {
             ReconcileError::FileReadError(command.command_path.clone(), e.to_string())
         })?;
-        command
-            .build()
-            .spawn()
-            .map(|child| Self {
-                command,
-                child,
-                started_at: Instant::now(),
-                sigint_at: None,
-                sigkill_at: None,
-                binary_sha256,
-            })
-            .map_err(|e| {
-                error!("failed to start node process: {e:?}");
-                ReconcileError::SpawnError(e.to_string())
-            })
+        let mut child = command.build().spawn().map_err(|e| {
+            error!("failed to start node process: {e:?}");
+            ReconcileError::SpawnError(e.to_string())
+        })?;
+
+        let stderr_task = child
+            .stderr
+            .take()
+            .map(|stderr| tokio::spawn(limits::forward_stderr(stderr)));
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(log::forward_node_stdout(
+                stdout,
+                command.quiet,
+                state.loki_tx.clone(),
+                command.loki_labels(&state.cli.id.to_string()),
+            ));
+        }
+
+        if let Some(cpu_limit) = command.cpu_limit {
+            match (&command.cgroup, child.id()) {
+                (Some(cgroup), Some(pid)) => match limits::limit_cpu(cgroup, pid, cpu_limit) {
+                    Ok(path) => info!("limited node to {cpu_limit}m cpu in {}", path.display()),
+                    Err(e) => error!("failed to limit node cpu in {}: {e}", cgroup.display()),
+                },
+                (None, _) => warn!("node has a cpu limit, but the agent has no cgroup"),
+                (_, None) => {}
+            }
+        }
+
+        Ok(Self {
+            command,
+            child,
+            started_at: Instant::now(),
+            sigint_at: None,
+            sigkill_at: None,
+            binary_sha256,
+            stderr_task,
+        })
     }
 
     /// Returns true when the child process has not exited
@@ -58,6 +95,26 @@ impl ProcessContext {
         self.child.try_wait().is_ok_and(|status| status.is_none())
     }
 
+    /// Returns true when the child process exited on its own because it ran
+    /// out of memory
+    pub async fn is_out_of_memory(&mut self) -> bool {
+        if self.command.memory_limit.is_none() || self.sigkill_at.is_some() {
+            return false;
+        }
+        let Ok(Some(status)) = self.child.try_wait() else {
+            return false;
+        };
+        let Some(stderr_task) = self.stderr_task.as_mut() else {
+            return false;
+        };
+
+        // the rest of the node's stderr is read once the process exits
+        let alloc_failed = tokio::time::timeout(STDERR_DRAIN_TIMEOUT, stderr_task)
+            .await
+            .is_ok_and(|res| res.unwrap_or(false));
+        limits::is_out_of_memory(status, alloc_failed)
+    }
+
     /// Check if the running binary matches the provided sha256 hash
     pub fn is_sha256_eq(&self, sha256: &str) -> bool {
         self.binary_sha256 == sha256
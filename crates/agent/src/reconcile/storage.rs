@@ -13,7 +13,7 @@ use snops_common::{
         VERSION_FILE,
     },
     rpc::error::ReconcileError,
-    state::{HeightRequest, InternedId, ReconcileCondition, ReconcileStatus, TransferId},
+    state::{EnvId, HeightRequest, InternedId, ReconcileCondition, ReconcileStatus, TransferId},
 };
 use tokio::{process::Command, sync::Mutex, task::AbortHandle};
 use tracing::{error, info, trace};
@@ -77,7 +77,7 @@ impl Reconcile<(), ReconcileError> for BinaryReconciler<'_> {
             }
         };
 
-        let mut file_rec = FileReconciler::new(Arc::clone(state), src, dst)
+        let mut file_rec = FileReconciler::new(Arc::clone(state), src, dst.clone())
             .with_offline(target_binary.is_api_file() && !state.is_ws_online())
             .with_binary(target_binary)
             .with_tx_id(transfer.as_ref().map(|(tx, _)| *tx));
@@ -91,8 +91,9 @@ impl Reconcile<(), ReconcileError> for BinaryReconciler<'_> {
         }
 
         match file_res.inner {
-            // If the binary is OK, update the context
+            // If the binary is OK, make sure it runs before updating the context
             Some(true) => {
+                self_test_binary(&dst, target_binary.version.as_deref()).await?;
                 **ok_at = Some(Instant::now());
                 Ok(ReconcileStatus::default())
             }
@@ -113,6 +114,49 @@ impl Reconcile<(), ReconcileError> for BinaryReconciler<'_> {
     }
 }
 
+/// Time allowed for the node binary to report its version
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run the binary with `--version` to catch binaries that cannot execute on
+/// this agent (e.g. built for another architecture), and compare the reported
+/// version when the control plane expects one.
+async fn self_test_binary(path: &Path, expected: Option<&str>) -> Result<(), ReconcileError> {
+    let err = |e: String| ReconcileError::BinarySelfTest(path.to_owned(), e);
+
+    let output = tokio::time::timeout(
+        SELF_TEST_TIMEOUT,
+        Command::new(path)
+            .arg("--version")
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| err(format!("timed out after {}s", SELF_TEST_TIMEOUT.as_secs())))?
+    .map_err(|e| err(e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(err(format!(
+            "exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    // clap reports versions as `<name> <version>`
+    let reported = String::from_utf8_lossy(&output.stdout);
+    let version = reported.split_whitespace().next_back().unwrap_or_default();
+    if let Some(expected) = expected {
+        if version != expected {
+            return Err(err(format!(
+                "reported version `{version}`, expected `{expected}`"
+            )));
+        }
+    }
+
+    info!("binary self-test passed: {}", reported.trim());
+    Ok(())
+}
+
 /// Download the genesis block needed to run the node
 pub struct GenesisReconciler<'a> {
     pub state: Arc<GlobalState>,
@@ -194,10 +238,14 @@ impl Reconcile<(), ReconcileError> for GenesisReconciler<'_> {
     }
 }
 
+/// How long reading the ledger height from disk may take
+const LEDGER_HEIGHT_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub type LedgerModifyResult = Result<bool, ReconcileError>;
 
 pub struct LedgerReconciler<'a> {
     pub state: Arc<GlobalState>,
+    pub env_id: EnvId,
     pub env_info: Arc<AgentEnvInfo>,
     pub target_height: (usize, HeightRequest),
     pub last_height: &'a mut Option<(usize, HeightRequest)>,
@@ -224,9 +272,60 @@ impl LedgerReconciler<'_> {
         path.join(dir)
     }
 
+    /// Read the height of the ledger on disk, for when the node has not
+    /// reported a block since the agent started
+    async fn read_ledger_height(&self) -> Option<u32> {
+        let ledger_path = self.ledger_path();
+        if !ledger_path.exists() {
+            return None;
+        }
+
+        let mut command = Command::new(self.state.cli.path.join(SNARKOS_FILE));
+        command
+            .kill_on_drop(true)
+            .env("NETWORK", self.env_info.network.to_string())
+            .arg("ledger")
+            .arg("--ledger")
+            .arg(&ledger_path)
+            .arg("height");
+
+        let output = match tokio::time::timeout(LEDGER_HEIGHT_TIMEOUT, command.output()).await {
+            Ok(Ok(output)) if output.status.success() => output,
+            Ok(Ok(output)) => {
+                error!(
+                    "failed to read the ledger height: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                return None;
+            }
+            Ok(Err(e)) => {
+                error!("failed to spawn ledger height process: {e}");
+                return None;
+            }
+            Err(_) => {
+                error!("timed out reading the ledger height");
+                return None;
+            }
+        };
+
+        match String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<u32>()
+        {
+            Ok(height) => {
+                self.state.last_block_height.set(self.env_id, height);
+                Some(height)
+            }
+            Err(e) => {
+                error!("failed to parse ledger height output: {e}");
+                None
+            }
+        }
+    }
+
     /// Find the checkpoint to apply to the ledger
     /// Guaranteed error when target height is not the top, 0, or unlimited span
-    pub fn find_checkpoint(&self) -> Result<PathBuf, ReconcileError> {
+    pub async fn find_checkpoint(&self) -> Result<PathBuf, ReconcileError> {
         let (untar_base, ledger_dir) = self.untar_paths();
         let ledger_path = untar_base.join(ledger_dir);
 
@@ -248,10 +347,39 @@ impl LedgerReconciler<'_> {
             .transpose()?
             .ok_or(ReconcileError::MissingRetentionPolicy(self.target_height.1))?;
 
+        // checkpoints can only rewind the ledger, so heights above the current
+        // height are unreachable. Until the node reports a block in this env, the
+        // current height is read from the ledger on disk.
+        let current = match self.state.last_block_height.get(self.env_id) {
+            Some(height) => height,
+            None => self.read_ledger_height().await.unwrap_or_default(),
+        };
+        let check_reachable = |height: u32| {
+            if current != 0 && height > current {
+                Err(ReconcileError::HeightUnreachable(
+                    self.target_height.1,
+                    current,
+                ))
+            } else {
+                Ok(height)
+            }
+        };
+
         // Determine which checkpoint to use by the next available height/time
         match self.target_height.1 {
-            HeightRequest::Absolute(height) => manager.nearest_with_height(height),
+            HeightRequest::Absolute(height) => {
+                manager.nearest_with_height(check_reachable(height)?)
+            }
             HeightRequest::Checkpoint(span) => manager.nearest_with_span(span),
+            HeightRequest::Relative(offset) => {
+                // rewinding from an unreadable ledger would resolve against 0
+                if current == 0 {
+                    return Err(ReconcileError::HeightUnknown(self.target_height.1));
+                }
+                let height = (i64::from(current) + offset).clamp(0, i64::from(u32::MAX)) as u32;
+                trace!("resolved relative height {offset} from {current} to {height}");
+                manager.nearest_with_height(check_reachable(height)?)
+            }
             // top cannot be a target height
             _ => None,
         }
@@ -396,7 +524,7 @@ impl Reconcile<(), ReconcileError> for LedgerReconciler<'_> {
             // TODO: ledger downloading would enter a new code path that downloads a new one
 
             // Find the checkpoint for the reconciler's target height
-            let checkpoint = self.find_checkpoint()?;
+            let checkpoint = self.find_checkpoint().await?;
             trace!("Applying checkpoint: {}", checkpoint.display());
             // Start a task to modify the ledger with the checkpoint
             *self.modify_handle = Some(self.spawn_modify(checkpoint));
@@ -447,11 +575,13 @@ impl Reconcile<(), ReconcileError> for LedgerReconciler<'_> {
                     error!("failed to save last height to db: {e}");
                 }
             }
-            // A failure in the ledger modification process is handled at the
-            // moment...
+            // The modification is retried on the next reconcile as the last height
+            // is unchanged
             Ok(false) => {
                 error!("ledger modification to height {} failed", target_height.1);
-                // TODO: handle this failure.. maybe even by deleting the ledger
+                *self.pending_height = None;
+                *self.modify_handle = None;
+                return Err(ReconcileError::LedgerModifyFailed(target_height.1));
             }
             // Bubble an actual error up to the caller
             Err(err) => {
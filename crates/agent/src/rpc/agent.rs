@@ -6,7 +6,10 @@ use snops_common::{
         AgentNodeService, AgentNodeServiceRequest, AgentNodeServiceResponse,
         node::{NodeServiceRequest, NodeServiceResponse},
     },
-    state::snarkos_status::{SnarkOSBlockInfo, SnarkOSStatus},
+    state::{
+        AgentState,
+        snarkos_status::{SnarkOSBlockInfo, SnarkOSStatus},
+    },
 };
 use tarpc::context;
 
@@ -34,6 +37,10 @@ impl AgentNodeService for AgentNodeRpcServer {
             block_timestamp,
         }: SnarkOSBlockInfo,
     ) -> Result<(), ()> {
+        if let AgentState::Node(env_id, _) = self.state.get_agent_state().await.as_ref() {
+            self.state.last_block_height.set(*env_id, height);
+        }
+
         let Some(client) = self.state.client.read().await.clone() else {
             return Ok(()); // ignore if client is not available
         };
@@ -1,17 +1,19 @@
 //! Control plane-to-agent RPC.
 
-use std::net::IpAddr;
+use std::{net::IpAddr, time::Duration};
 
 use snops_common::{
     aot_cmds::AotCmd,
+    constant::SNARKOS_FILE,
     define_rpc_mux,
+    format::Compact,
     prelude::snarkos_status::SnarkOSLiteBlock,
     rpc::{
         control::{
             ControlServiceClient, ControlServiceRequest, ControlServiceResponse,
             agent::{
                 AgentMetric, AgentService, AgentServiceRequest, AgentServiceResponse, AgentStatus,
-                Handshake,
+                AotExecOutput, Handshake,
             },
         },
         error::{AgentError, SnarkosRequestError},
@@ -19,12 +21,40 @@ use snops_common::{
     state::{AgentId, AgentState, EnvId, InternedId, NetworkId, PortConfig, ReconcileOptions},
 };
 use tarpc::context::Context;
+use tokio::process::Command;
 use tracing::{error, info, trace};
 
 use crate::{
-    api, log::make_env_filter, metrics::MetricComputer, reconcile::default_binary, state::AppState,
+    api,
+    log::parse_env_filter,
+    metrics::MetricComputer,
+    reconcile::{command::node_ledger_paths, default_binary},
+    state::AppState,
 };
 
+/// How long an `exec_aot` command may run before it is killed
+const EXEC_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Returns true if the given `ledger` subcommand opens the ledger as the
+/// primary RocksDB instance, which fails or contends with a running node
+fn needs_stopped_node(args: &[String]) -> bool {
+    args.first().is_some_and(|cmd| cmd == "view")
+}
+
+/// Returns true if the given `ledger` subcommand only reads from the ledger
+fn is_read_only_ledger_cmd(args: &[String]) -> bool {
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["view", ..] | ["hash"] | ["height"] | ["checksum", ..] => true,
+        ["checkpoint", "view"] => true,
+        _ => false,
+    }
+}
+
 define_rpc_mux!(child;
     ControlServiceRequest => ControlServiceResponse;
     AgentServiceRequest => AgentServiceResponse;
@@ -94,13 +124,18 @@ impl AgentService for AgentRpcServer {
         // Queue a reconcile immediately as we have received new state.
         // The reconciler will decide if anything has actually changed
         self.state
-            .update_agent_state(handshake.state, handshake.reconcile_opts)
+            .update_agent_state(handshake.state.0, handshake.reconcile_opts)
             .await;
     }
 
-    async fn set_agent_state(self, _: Context, target: AgentState, opts: ReconcileOptions) {
+    async fn set_agent_state(
+        self,
+        _: Context,
+        target: Compact<AgentState>,
+        opts: ReconcileOptions,
+    ) {
         info!("Received new agent state, queuing reconcile...");
-        self.state.update_agent_state(target, opts).await;
+        self.state.update_agent_state(target.0, opts).await;
     }
 
     async fn clear_peer_addr(self, _: Context, agent_id: AgentId) {
@@ -144,7 +179,7 @@ impl AgentService for AgentRpcServer {
 
         let url = format!(
             "http://{}:{}/{network}{route}",
-            self.state.cli.get_local_ip(),
+            self.state.get_node_local_ip().await,
             self.state.cli.ports.rest
         );
         let response = reqwest::get(&url)
@@ -182,7 +217,7 @@ impl AgentService for AgentRpcServer {
 
         let url = format!(
             "http://{}:{}/{network}/transaction/broadcast",
-            self.state.cli.get_local_ip(),
+            self.state.get_node_local_ip().await,
             self.state.cli.ports.rest
         );
         let response = reqwest::Client::new()
@@ -219,6 +254,11 @@ impl AgentService for AgentRpcServer {
 
         match metric {
             AgentMetric::Tps => metrics.tps.get(),
+            AgentMetric::PingRtt => metrics
+                .ping_rtt
+                .map(|rtt| rtt.as_secs_f64() * 1000.0)
+                .unwrap_or_default(),
+            AgentMetric::LokiBuffered => metrics.loki_buffered as f64,
         }
     }
 
@@ -229,6 +269,7 @@ impl AgentService for AgentRpcServer {
         network: NetworkId,
         query: String,
         auth: String,
+        timeout: Option<Duration>,
     ) -> Result<String, AgentError> {
         info!("Executing authorization for {env_id}...");
 
@@ -252,6 +293,7 @@ impl AgentService for AgentRpcServer {
 
         // download the snarkOS binary
         api::check_binary(
+            &self.state.http_client,
             // attempt to use the specified "compute" binary
             info.storage
                 .binaries
@@ -271,13 +313,24 @@ impl AgentService for AgentRpcServer {
         })?;
 
         let start = std::time::Instant::now();
-        match AotCmd::new(aot_bin, network)
-            .execute(
-                serde_json::from_str(&auth).map_err(|_| AgentError::FailedToParseJson)?,
-                format!("{}{query}", self.state.endpoint),
-            )
-            .await
-        {
+        let aot = AotCmd::new(aot_bin, network);
+        let execute = aot.execute(
+            serde_json::from_str(&auth).map_err(|_| AgentError::FailedToParseJson)?,
+            format!("{}{query}", self.state.endpoint),
+        );
+        // the aot process is killed when the execution is dropped
+        let res = match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, execute).await {
+                Ok(res) => res,
+                Err(_) => {
+                    error!("authorization execution timed out after {timeout:?}");
+                    return Err(AgentError::ExecTimedOut);
+                }
+            },
+            None => execute.await,
+        };
+
+        match res {
             Ok(mut exec) => {
                 let elapsed = start.elapsed().as_millis();
 
@@ -301,12 +354,11 @@ impl AgentService for AgentRpcServer {
 
     async fn set_log_level(self, _: Context, level: String) -> Result<(), AgentError> {
         tracing::debug!("setting log level to {level}");
-        let level: tracing_subscriber::filter::LevelFilter = level
-            .parse()
-            .map_err(|_| AgentError::InvalidLogLevel(level.clone()))?;
+        let env_filter =
+            parse_env_filter(&level).map_err(|_| AgentError::InvalidLogLevel(level.clone()))?;
         self.state
             .log_level_handler
-            .modify(|filter| *filter = make_env_filter(level))
+            .reload(env_filter)
             .map_err(|_| AgentError::FailedToChangeLogLevel)?;
 
         Ok(())
@@ -362,4 +414,58 @@ impl AgentService for AgentRpcServer {
             version: self.version.to_string(),
         })
     }
+
+    async fn exec_aot(self, _: Context, args: Vec<String>) -> Result<AotExecOutput, AgentError> {
+        // only read-only commands are allowed as the node may be using the ledger
+        if !is_read_only_ledger_cmd(&args) {
+            return Err(AgentError::ExecNotAllowed(args.join(" ")));
+        }
+
+        let agent_state = self.state.get_agent_state().await;
+        let AgentState::Node(env_id, _) = agent_state.as_ref() else {
+            return Err(AgentError::InvalidState);
+        };
+        if needs_stopped_node(&args) && self.state.node_command.read().await.is_some() {
+            return Err(AgentError::ExecNodeRunning(args.join(" ")));
+        }
+
+        let info = self
+            .state
+            .get_env_info(*env_id)
+            .await
+            .map_err(|e| AgentError::FailedToGetEnvInfo(e.to_string()))?;
+        let (ledger_path, genesis_path) = node_ledger_paths(&self.state, &info);
+
+        let mut command = Command::new(self.state.cli.path.join(SNARKOS_FILE));
+        command
+            .kill_on_drop(true)
+            .env("NETWORK", info.network.to_string())
+            .arg("ledger")
+            .arg("--ledger")
+            .arg(&ledger_path);
+        if let Some(genesis) = &genesis_path {
+            command.arg("--genesis").arg(genesis);
+        }
+        command.args(&args);
+
+        info!("Executing `ledger {}`...", args.join(" "));
+        let output = match tokio::time::timeout(EXEC_TIMEOUT, command.output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                error!("failed to execute `ledger {}`: {e}", args.join(" "));
+                return Err(AgentError::FailedToSpawnProcess);
+            }
+            Err(_) => return Err(AgentError::ExecTimedOut),
+        };
+
+        Ok(AotExecOutput {
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    async fn get_node_command(self, _: Context) -> Option<Vec<String>> {
+        self.state.node_command.read().await.clone()
+    }
 }
@@ -1,19 +1,27 @@
-use std::sync::Arc;
+use std::{
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
 
 use anyhow::Result;
 use axum::{
-    Router,
+    Json, Router,
     extract::{
         State, WebSocketUpgrade,
         ws::{Message, WebSocket},
     },
+    http::StatusCode,
     response::{IntoResponse, Response},
     routing::get,
 };
 use futures::StreamExt;
-use snops_common::rpc::{
-    RpcTransport,
-    agent::{AgentNodeService, node::NodeServiceClient},
+use serde_json::json;
+use snops_common::{
+    rpc::{
+        RpcTransport,
+        agent::{AgentNodeService, node::NodeServiceClient},
+    },
+    state::{AgentState, EnvId},
 };
 use tarpc::server::Channel;
 use tokio::select;
@@ -21,9 +29,16 @@ use tracing::{error, info, warn};
 
 use crate::{
     rpc::agent::{AgentNodeRpcServer, MuxedMessageIncoming, MuxedMessageOutgoing},
-    state::AppState,
+    state::{AppState, GlobalState},
 };
 
+/// How long the node's REST server has to answer a readiness probe
+const READY_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Client for readiness probes of the local node, which is never reached
+/// through the peer proxy
+static PROBE_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
 pub async fn start(listener: tokio::net::TcpListener, state: AppState) -> Result<()> {
     let app = Router::new()
         .route("/node", get(node_ws_handler))
@@ -38,6 +53,72 @@ pub async fn start(listener: tokio::net::TcpListener, state: AppState) -> Result
     Ok(())
 }
 
+/// Serve the `/healthz` and `/readyz` probes, and nothing else, so the
+/// listener can be exposed beyond localhost
+pub async fn start_health(listener: tokio::net::TcpListener, state: AppState) -> Result<()> {
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(state);
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+/// The agent process is alive
+async fn healthz() -> StatusCode {
+    StatusCode::OK
+}
+
+/// The agent is connected to the control plane and its node, if it has
+/// one, is serving
+async fn readyz(State(state): State<AppState>) -> Response {
+    let connected = state.is_ws_online();
+    let node_serving = match state.get_agent_state().await.as_ref() {
+        AgentState::Inventory => None,
+        AgentState::Node(env_id, _) => Some(is_node_serving(&state, *env_id).await),
+    };
+
+    let ready = connected && node_serving.unwrap_or(true);
+    // node_serving is null when the agent has no node assignment
+    let body = json!({
+        "ready": ready,
+        "connected": connected,
+        "node_serving": node_serving,
+        "last_reconcile": *state.last_reconcile.read().await,
+    });
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(body)).into_response()
+}
+
+/// Check that the node process is running and its REST server answers
+async fn is_node_serving(state: &GlobalState, env_id: EnvId) -> bool {
+    if state.node_command.read().await.is_none() {
+        return false;
+    }
+    let Ok(info) = state.get_env_info(env_id).await else {
+        return false;
+    };
+
+    let url = format!(
+        "http://{}:{}/{}/block/height/latest",
+        state.get_node_local_ip().await,
+        state.cli.ports.rest,
+        info.network
+    );
+    PROBE_CLIENT
+        .get(&url)
+        .timeout(READY_PROBE_TIMEOUT)
+        .send()
+        .await
+        .is_ok_and(|res| res.status().is_success())
+}
+
 async fn node_ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
     ws.on_upgrade(|socket| handle_socket(socket, state))
         .into_response()
@@ -1,19 +1,23 @@
 use std::{
     collections::HashSet,
     net::IpAddr,
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
     time::{Duration, Instant},
 };
 
 use dashmap::DashMap;
 use indexmap::IndexMap;
+use rand::Rng;
 use reqwest::Url;
 use snops_common::{
     api::AgentEnvInfo,
     rpc::{agent::node::NodeServiceClient, control::ControlServiceClient, error::ReconcileError},
     state::{
-        AgentId, AgentPeer, AgentState, EnvId, ReconcileOptions, TransferId, TransferStatus,
-        snarkos_status::SnarkOSStatus,
+        AgentId, AgentPeer, AgentState, EnvId, NodeStatus, ReconcileOptions, ReconcileStatus,
+        TransferId, TransferStatus, snarkos_status::SnarkOSStatus,
     },
     util::OpaqueDebug,
 };
@@ -21,9 +25,19 @@ use tarpc::context;
 use tokio::sync::{RwLock, mpsc::Sender, oneshot};
 use tracing::{error, info};
 
-use crate::{cli::Cli, db::Database, log::ReloadHandler, metrics::Metrics, transfers::TransferTx};
+use crate::{
+    cli::Cli,
+    db::Database,
+    log::{LokiSender, ReloadHandler},
+    metrics::Metrics,
+    transfers::TransferTx,
+};
 
 pub const NODE_GRACEFUL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+/// The initial delay between control plane reconnect attempts.
+pub const RECONNECT_BACKOFF_BASE: Duration = Duration::from_secs(5);
+/// The maximum delay between control plane reconnect attempts.
+pub const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(60);
 
 pub type AppState = Arc<GlobalState>;
 pub type ClientLock = Arc<RwLock<Option<ControlServiceClient>>>;
@@ -39,7 +53,11 @@ pub struct GlobalState {
     pub agent_rpc_port: u16,
     pub cli: Cli,
     pub endpoint: String,
+    /// Client for outbound HTTP, dialing through the peer proxy when set
+    pub http_client: reqwest::Client,
     pub loki: Mutex<Option<Url>>,
+    /// Sends the node's log lines to the loki shipping task
+    pub loki_tx: LokiSender,
     /// Desired state the agent should be in. After each reconciliation, the
     /// agent will attempt to transition to this state.
     pub agent_state: RwLock<Arc<AgentState>>,
@@ -53,12 +71,45 @@ pub struct GlobalState {
 
     pub transfer_tx: TransferTx,
     pub transfers: Arc<DashMap<TransferId, TransferStatus>>,
+    /// Transfers the server refused while busy, and when they may be retried
+    pub throttled_transfers: DashMap<TransferId, Instant>,
 
     pub node_client: RwLock<Option<NodeServiceClient>>,
     pub last_node_status: RwLock<Option<(Instant, SnarkOSStatus)>>,
+    /// The redacted command line of the running node process
+    pub node_command: RwLock<Option<Vec<String>>>,
+    /// The outcome of the last reconcile, and whether the node was running
+    /// after it
+    pub last_reconcile: RwLock<Option<Result<ReconcileStatus<bool>, ReconcileError>>>,
+    /// The latest block height reported by the node
+    pub last_block_height: NodeHeight,
+    /// Number of agent states received from the control plane, for detecting
+    /// reconciles that were superseded by a newer state while they ran
+    pub agent_state_updates: AtomicU64,
     pub log_level_handler: ReloadHandler,
     /// A oneshot sender to shutdown the agent.
     pub shutdown: RwLock<Option<oneshot::Sender<()>>>,
+    /// The delay before the next control plane reconnect attempt.
+    pub reconnect_backoff: Mutex<Duration>,
+}
+
+/// The latest block height reported by the node, along with the env the node
+/// ran in. A height from another env's ledger says nothing about the current
+/// ledger, so it is only returned for the env it was reported in.
+#[derive(Debug, Default)]
+pub struct NodeHeight(Mutex<Option<(EnvId, u32)>>);
+
+impl NodeHeight {
+    pub fn set(&self, env_id: EnvId, height: u32) {
+        *self.0.lock().unwrap() = Some((env_id, height));
+    }
+
+    pub fn get(&self, env_id: EnvId) -> Option<u32> {
+        match *self.0.lock().unwrap() {
+            Some((id, height)) if id == env_id => Some(height),
+            _ => None,
+        }
+    }
 }
 
 impl GlobalState {
@@ -70,10 +121,39 @@ impl GlobalState {
         self.client.read().await.clone()
     }
 
+    /// Get the delay before the next reconnect attempt, with jitter to avoid
+    /// agents reconnecting in lockstep, and double the backoff for the attempt
+    /// after it.
+    pub fn next_reconnect_backoff(&self) -> Duration {
+        let mut backoff = self.reconnect_backoff.lock().unwrap();
+        let delay = *backoff;
+        *backoff = (delay * 2).min(RECONNECT_BACKOFF_MAX);
+
+        let jitter = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 2);
+        delay + Duration::from_millis(jitter)
+    }
+
+    /// Reset the reconnect backoff after a stable connection.
+    pub fn reset_reconnect_backoff(&self) {
+        *self.reconnect_backoff.lock().unwrap() = RECONNECT_BACKOFF_BASE;
+    }
+
     pub async fn get_agent_state(&self) -> Arc<AgentState> {
         self.agent_state.read().await.clone()
     }
 
+    /// Get the local address the node's servers are reachable on, which is
+    /// the node's bind address when it has one
+    pub async fn get_node_local_ip(&self) -> IpAddr {
+        match self.get_agent_state().await.as_ref() {
+            AgentState::Node(_, node) => match node.bind_addr {
+                Some(addr) if !addr.is_unspecified() => addr,
+                _ => self.cli.get_local_ip(),
+            },
+            _ => self.cli.get_local_ip(),
+        }
+    }
+
     // Resolve the addresses of the given agents.
     // Locks resolve_addrs
     pub async fn agentpeers_to_cli(&self, peers: &[AgentPeer]) -> Vec<String> {
@@ -162,6 +242,7 @@ impl GlobalState {
         }
         let state = Arc::new(state);
         *self.agent_state.write().await = state;
+        self.agent_state_updates.fetch_add(1, Ordering::AcqRel);
 
         // Queue a reconcile to apply the new state
         self.queue_reconcile(Duration::ZERO, opts).await;
@@ -234,4 +315,45 @@ impl GlobalState {
     pub async fn get_node_status(&self) -> Option<SnarkOSStatus> {
         self.last_node_status.read().await.clone().map(|(_, s)| s)
     }
+
+    /// Report a node status that did not come from the node itself to the
+    /// control plane
+    pub async fn report_node_status(&self, status: NodeStatus) {
+        let Some(client) = self.get_ws_client().await else {
+            return;
+        };
+
+        if let Err(e) = client.post_node_status(context::current(), status).await {
+            error!("failed to post node status: {e}");
+        }
+    }
+
+    pub async fn set_node_command(&self, command: Option<Vec<String>>) {
+        *self.node_command.write().await = command;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_node_height_env_switch() {
+        let env_a = EnvId::from_str("env-a").unwrap();
+        let env_b = EnvId::from_str("env-b").unwrap();
+
+        let height = NodeHeight::default();
+        assert_eq!(height.get(env_a), None);
+
+        height.set(env_a, 120);
+        assert_eq!(height.get(env_a), Some(120));
+        // the height of env a's ledger is unknown to env b
+        assert_eq!(height.get(env_b), None);
+
+        height.set(env_b, 7);
+        assert_eq!(height.get(env_b), Some(7));
+        assert_eq!(height.get(env_a), None);
+    }
 }
@@ -7,7 +7,10 @@ use colored::Colorize;
 use indexmap::IndexMap;
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::{
+    ThreadPoolBuilder,
+    iter::{IntoParallelIterator, ParallelIterator},
+};
 use snarkvm::{console::program::Network, prelude::Itertools, utilities::ToBytes};
 
 use crate::{Address, PrivateKey};
@@ -31,6 +34,13 @@ pub struct GenAccounts {
     /// If unpassed or used with --vanity, uses a random seed
     #[clap(name = "seed", short, long)]
     pub seed: Option<u64>,
+
+    /// Generate keys across this many threads, 0 for one per core.
+    /// Each account is derived from its own partition of the seed, so seeded
+    /// accounts are the same for any number of jobs, but differ from the
+    /// accounts generated without this flag.
+    #[clap(short, long, alias = "parallel")]
+    pub jobs: Option<usize>,
 }
 
 pub const BECH32M_CHARSET: &str = "0123456789acdefghjklmnpqrstuvwxyz";
@@ -59,6 +69,26 @@ impl bech32::WriteBase32 for VanityCheck<'_> {
     }
 }
 
+/// Generate accounts on the current rayon pool, deriving each account from
+/// its own ChaCha stream of the seed so the accounts do not depend on the
+/// number of threads.
+fn gen_partitioned<N: Network>(
+    seed: u64,
+    count: u16,
+) -> Result<IndexMap<Address<N>, PrivateKey<N>>> {
+    (0..count)
+        .into_par_iter()
+        .map(|i| {
+            let mut rng = ChaChaRng::seed_from_u64(seed);
+            rng.set_stream(i.into());
+            let key = PrivateKey::new(&mut rng)?;
+            let addr = Address::try_from(&key)?;
+            Ok((addr, key))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|accounts| accounts.into_iter().collect())
+}
+
 impl GenAccounts {
     pub fn parse<N: Network>(self) -> Result<()> {
         let mut rng = self
@@ -82,32 +112,47 @@ impl GenAccounts {
         }).transpose()?;
 
         // Add additional accounts to the public balances
-        let accounts: IndexMap<Address<N>, PrivateKey<N>> = (0..self.count)
-            .map(|_| {
-                if let Some(vanity) = &vanity {
-                    loop {
-                        let found_vanity = (0..65536).into_par_iter().find_map_any(|_| {
-                            let key = PrivateKey::new(&mut ChaChaRng::from_entropy()).unwrap();
-                            let addr = Address::try_from(&key).unwrap();
-                            let has_vanity = Err(true)
-                                == ToBytes::to_bytes_le(&addr)
-                                    .unwrap()
-                                    .write_base32(&mut VanityCheck(vanity));
-                            has_vanity.then_some((addr, key))
-                        });
-                        if let Some((addr, key)) = found_vanity {
-                            break Ok((addr, key));
-                        } else {
-                            continue;
+        let generate = || -> Result<IndexMap<Address<N>, PrivateKey<N>>> {
+            if let (None, Some(_)) = (&vanity, self.jobs) {
+                let seed = self.seed.unwrap_or_else(rand::random);
+                return gen_partitioned(seed, self.count);
+            }
+
+            (0..self.count)
+                .map(|_| {
+                    if let Some(vanity) = &vanity {
+                        loop {
+                            let found_vanity = (0..65536).into_par_iter().find_map_any(|_| {
+                                let key = PrivateKey::new(&mut ChaChaRng::from_entropy()).unwrap();
+                                let addr = Address::try_from(&key).unwrap();
+                                let has_vanity = Err(true)
+                                    == ToBytes::to_bytes_le(&addr)
+                                        .unwrap()
+                                        .write_base32(&mut VanityCheck(vanity));
+                                has_vanity.then_some((addr, key))
+                            });
+                            if let Some((addr, key)) = found_vanity {
+                                break Ok((addr, key));
+                            } else {
+                                continue;
+                            }
                         }
+                    } else {
+                        let key = PrivateKey::new(&mut rng)?;
+                        let addr = Address::try_from(&key)?;
+                        Ok((addr, key))
                     }
-                } else {
-                    let key = PrivateKey::new(&mut rng)?;
-                    let addr = Address::try_from(&key)?;
-                    Ok((addr, key))
-                }
-            })
-            .collect::<Result<IndexMap<_, _>>>()?;
+                })
+                .collect()
+        };
+
+        let accounts = match self.jobs {
+            Some(jobs) => ThreadPoolBuilder::new()
+                .num_threads(jobs)
+                .build()?
+                .install(generate)?,
+            None => generate()?,
+        };
 
         match self.output {
             // Write the accounts JSON file.
@@ -142,3 +187,45 @@ impl GenAccounts {
         Ok(())
     }
 }
+
+/// Print the address of a private key.
+#[derive(Debug, Clone, Parser)]
+pub struct AccountAddress<N: Network> {
+    /// The private key to derive the address of
+    pub private_key: PrivateKey<N>,
+}
+
+impl<N: Network> AccountAddress<N> {
+    pub fn parse(self) -> Result<()> {
+        println!("{}", Address::try_from(&self.private_key)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use snarkvm::console::network::MainnetV0;
+
+    use super::*;
+
+    #[test]
+    fn test_seeded_accounts_do_not_depend_on_jobs() {
+        let generate = |jobs| {
+            ThreadPoolBuilder::new()
+                .num_threads(jobs)
+                .build()
+                .unwrap()
+                .install(|| gen_partitioned::<MainnetV0>(42, 16))
+                .unwrap()
+        };
+
+        let single = generate(1);
+        assert_eq!(single.len(), 16);
+        assert!(single.iter().eq(generate(4).iter()));
+        assert!(
+            !single
+                .iter()
+                .eq(gen_partitioned::<MainnetV0>(43, 16).unwrap().iter())
+        );
+    }
+}
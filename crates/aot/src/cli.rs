@@ -13,12 +13,16 @@ use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt, reload, util::SubscriberInitExt};
 
 use crate::{
-    Network, accounts::GenAccounts, auth::AuthCommand, genesis::Genesis, ledger::Ledger,
+    Network,
+    accounts::{AccountAddress, GenAccounts},
+    auth::AuthCommand,
+    genesis::Genesis,
+    ledger::Ledger,
     program::ProgramCommand,
 };
 
 #[derive(Debug, Parser)]
-#[clap(author = "MONADIC.US")]
+#[clap(author = "MONADIC.US", version)]
 pub struct Cli<N: Network> {
     #[arg(long)]
     pub enable_profiling: bool,
@@ -43,6 +47,7 @@ pub struct Cli<N: Network> {
 pub enum Command<N: Network> {
     Genesis(Box<Genesis<N>>),
     Accounts(GenAccounts),
+    Address(AccountAddress<N>),
     Ledger(Ledger<N>),
     #[clap(subcommand)]
     Auth(Box<AuthCommand<N>>),
@@ -262,6 +267,7 @@ impl<N: Network> Cli<N> {
 
         match self.command {
             Command::Accounts(command) => command.parse::<N>(),
+            Command::Address(command) => command.parse(),
             Command::Genesis(command) => command.parse(),
             Command::Ledger(command) => command.parse(log_level_handler),
             Command::Auth(command) => command.parse(),
@@ -2,7 +2,7 @@ use std::{fs, path::PathBuf, str::FromStr};
 
 use aleo_std::StorageMode;
 use anyhow::{Result, anyhow, ensure};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use colored::Colorize;
 use indexmap::IndexMap;
 use rand::{CryptoRng, Rng, SeedableRng};
@@ -33,6 +33,14 @@ impl<N: Network, T: DeserializeOwned> FromStr for AddressMap<N, T> {
     }
 }
 
+/// The serialization format of the committee output file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CommitteeFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
 /// This command helps generate a custom genesis block given an initial private
 /// key, seed, and committee size.
 #[derive(Debug, Clone, Parser)]
@@ -55,6 +63,10 @@ pub struct Genesis<N: Network> {
     #[clap(long)]
     pub committee_output: Option<PathBuf>,
 
+    /// The format to write the committee output file in.
+    #[clap(long, value_enum, default_value_t = CommitteeFormat::Json)]
+    pub committee_format: CommitteeFormat,
+
     /// Additional number of accounts that aren't validators to add balances to.
     #[clap(long, default_value_t = 0)]
     pub additional_accounts: u16,
@@ -479,7 +491,12 @@ impl<N: Network> Genesis<N> {
                     .truncate(true)
                     .write(true)
                     .open(&committee_file)?;
-                serde_json::to_writer_pretty(file, &committee_members)?;
+                match self.committee_format {
+                    CommitteeFormat::Json => {
+                        serde_json::to_writer_pretty(file, &committee_members)?
+                    }
+                    CommitteeFormat::Yaml => serde_yaml::to_writer(file, &committee_members)?,
+                }
 
                 println!(
                     "Generated committee written to {}.",
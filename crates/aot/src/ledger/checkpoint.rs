@@ -1,19 +1,36 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-use anyhow::Result;
+use aleo_std::StorageMode;
+use anyhow::{Result, anyhow, bail};
 use clap::Parser;
-use snarkvm::{console::program::Network, ledger::Block, utilities::ToBytes};
-use snops_checkpoint::{Checkpoint, CheckpointManager, RetentionPolicy, path_from_height};
+use snarkvm::{
+    ledger::{
+        Block,
+        store::helpers::rocksdb::{RocksDB, internal::Database},
+    },
+    utilities::ToBytes,
+};
+use snops_checkpoint::{
+    Checkpoint, CheckpointHeader, CheckpointManager, RetentionPolicy, path_from_height,
+};
 use tracing::{info, trace};
 
 use super::truncate::Truncate;
-use crate::{DbLedger, ledger::util};
+use crate::Network;
 
 /// A command to interact with checkpoints.
 #[derive(Debug, Parser)]
 pub enum CheckpointCommand {
     /// Create a checkpoint for the given ledger.
-    Create,
+    Create {
+        /// Directory to write the checkpoint to. Defaults to the parent
+        /// directory of the ledger.
+        #[clap(long)]
+        checkpoint_dir: Option<PathBuf>,
+    },
     /// Apply a checkpoint to the given ledger.
     Apply {
         /// Checkpoint file to apply.
@@ -27,12 +44,22 @@ pub enum CheckpointCommand {
     View,
     /// Cleanup old checkpoints.
     Clean,
+    /// Show which checkpoints a retention policy would keep and delete,
+    /// without deleting any.
+    Plan {
+        /// The retention policy to plan with. i.e. `4h:1h,1W:1D`. Defaults to
+        /// the default retention policy.
+        #[clap(long)]
+        policy: Option<RetentionPolicy>,
+    },
 }
 
 impl CheckpointCommand {
     pub fn parse<N: Network>(self, genesis: Block<N>, ledger: PathBuf) -> Result<()> {
         match self {
-            CheckpointCommand::Create => open_and_checkpoint::<N>(genesis, ledger),
+            CheckpointCommand::Create { checkpoint_dir } => {
+                open_and_checkpoint::<N>(ledger, checkpoint_dir)
+            }
             CheckpointCommand::Apply { checkpoint, clean } => {
                 Truncate::rewind::<N>(genesis, ledger.clone(), checkpoint)?;
                 if clean {
@@ -57,24 +84,164 @@ impl CheckpointCommand {
                 );
                 Ok(())
             }
+            CheckpointCommand::Plan { policy } => {
+                let manager = CheckpointManager::load(ledger, policy.unwrap_or_default())?;
+                print!("{}", manager.plan());
+                Ok(())
+            }
+        }
+    }
+}
+
+pub fn open_and_checkpoint<N: Network>(
+    ledger_path: PathBuf,
+    checkpoint_dir: Option<PathBuf>,
+) -> Result<()> {
+    info!("creating checkpoint...");
+    let checkpoint = Checkpoint::<N>::new(ledger_path.clone())?;
+    let height = checkpoint.height();
+    let bytes = checkpoint.to_bytes_le()?;
+
+    info!("created checkpoint @ {height}; {} bytes", bytes.len());
+
+    let path = match checkpoint_dir {
+        Some(dir) => dir.join(format!("{height}.checkpoint")),
+        None => path_from_height(&ledger_path, height).ok_or_else(|| {
+            anyhow!("no checkpoint directory for ledger {ledger_path:?}, use --checkpoint-dir")
+        })?,
+    };
+
+    // write the checkpoint file
+    std::fs::write(&path, bytes)?;
+    trace!("checkpoint written to {path:?}");
+
+    Ok(())
+}
+
+/// Create a checkpoint of a ledger a node is writing to. The checkpoint is
+/// read from a point-in-time copy of the ledger so its header and content
+/// describe the same block. When `force` is false, the checkpoint is only
+/// created when the manager's retention policy is ready for one. Returns the
+/// height of the new checkpoint.
+pub fn checkpoint_live_ledger<N: Network>(
+    manager: &mut CheckpointManager,
+    ledger_path: &Path,
+    force: bool,
+) -> Result<Option<u32>> {
+    // skip copying the ledger when the checkpoint would not be kept
+    let header = CheckpointHeader::read_ledger::<N>(ledger_path.to_path_buf())?;
+    let wanted = if force {
+        manager.should_create(&header)
+    } else {
+        manager.should_poll(&header)
+    };
+    if !wanted {
+        return Ok(None);
+    }
+
+    let snapshot = ledger_path.with_extension("snapshot");
+    let out = ledger_path.with_extension("snapshot-checkpoint");
+    let res = snapshot_and_checkpoint::<N>(manager, ledger_path, &snapshot, &out);
+
+    for dir in [&snapshot, &out] {
+        if dir.exists() {
+            if let Err(e) = std::fs::remove_dir_all(dir) {
+                tracing::error!("error removing {dir:?}: {e}");
+            }
         }
     }
+
+    res.map(Some)
 }
 
-pub fn open_and_checkpoint<N: Network>(genesis: Block<N>, ledger_path: PathBuf) -> Result<()> {
-    let ledger: DbLedger<N> = util::open_ledger(genesis, ledger_path.clone())?;
-    let height = ledger.latest_height();
+fn snapshot_and_checkpoint<N: Network>(
+    manager: &mut CheckpointManager,
+    ledger_path: &Path,
+    snapshot: &Path,
+    out: &Path,
+) -> Result<u32> {
+    snapshot_ledger::<N>(ledger_path, snapshot)?;
 
-    info!("creating checkpoint @ {height}...");
-    let bytes = Checkpoint::<N>::new(ledger_path.clone())?.to_bytes_le()?;
+    if out.exists() {
+        std::fs::remove_dir_all(out)?;
+    }
+    std::fs::create_dir_all(out)?;
 
-    info!("created checkpoint; {} bytes", bytes.len());
+    // snarkvm opens one ledger per process, so the copy is read by a child
+    // process instead
+    let status = Command::new(std::env::current_exe()?)
+        .env("NETWORK", N::str_id().to_string())
+        .arg("ledger")
+        .arg("--ledger")
+        .arg(snapshot)
+        .arg("checkpoint")
+        .arg("create")
+        .arg("--checkpoint-dir")
+        .arg(out)
+        .status()?;
+    if !status.success() {
+        bail!("checkpoint process exited with {status}");
+    }
 
-    if let Some(path) = path_from_height(&ledger_path, height) {
-        // write the checkpoint file
-        std::fs::write(&path, bytes)?;
-        trace!("checkpoint written to {path:?}");
+    let Some(file) = std::fs::read_dir(out)?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "checkpoint"))
+    else {
+        bail!("checkpoint process did not write a checkpoint to {out:?}");
     };
 
+    Ok(manager.insert_file(&file)?.block_height)
+}
+
+/// Copy the ledger a node is writing to into `dest` as a rocksdb checkpoint.
+/// The copy is a consistent point-in-time view of the ledger and is mostly
+/// hard links to the ledger's files.
+pub fn snapshot_ledger<N: Network>(ledger_path: &Path, dest: &Path) -> Result<()> {
+    // snarkvm shares one database handle per process, so this is the handle
+    // the node is writing through
+    let db = RocksDB::open(N::ID, StorageMode::Custom(ledger_path.to_path_buf()))?;
+    snapshot_db(&db, dest)
+}
+
+fn snapshot_db(db: &rocksdb::DB, dest: &Path) -> Result<()> {
+    if dest.exists() {
+        std::fs::remove_dir_all(dest)?;
+    }
+    rocksdb::checkpoint::Checkpoint::new(db)?.create_checkpoint(dest)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_ignores_later_writes() {
+        let dir = std::env::temp_dir().join(format!("aot-snapshot-{}", std::process::id()));
+        let ledger = dir.join("ledger");
+        let snapshot = dir.join("ledger.snapshot");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        let db = rocksdb::DB::open(&opts, &ledger).unwrap();
+        db.put(b"header", b"1").unwrap();
+        db.put(b"content", b"1").unwrap();
+
+        snapshot_db(&db, &snapshot).unwrap();
+
+        // the node keeps writing after the snapshot is taken
+        db.put(b"header", b"2").unwrap();
+        db.put(b"content", b"2").unwrap();
+
+        let copy = rocksdb::DB::open_for_read_only(&rocksdb::Options::default(), &snapshot, false)
+            .unwrap();
+        assert_eq!(copy.get(b"header").unwrap().as_deref(), Some(&b"1"[..]));
+        assert_eq!(copy.get(b"content").unwrap().as_deref(), Some(&b"1"[..]));
+
+        drop(copy);
+        drop(db);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use sha2::{Digest, Sha256};
+use snarkvm::{
+    console::program::Network,
+    ledger::store::helpers::rocksdb::{BlockMap, MapID},
+    utilities::ToBytes,
+};
+use snops_common::api::LedgerChecksum;
+
+use super::hash::rocks_open;
+
+/// Print a checksum of the ledger's blocks, for comparing ledgers on
+/// different machines. The checksum is a sha256 of the block hashes in height
+/// order, so it does not depend on how the database files were compacted.
+#[derive(Debug, Args)]
+pub struct Checksum {
+    /// The height of the last block to include. Defaults to the latest block.
+    #[clap(long)]
+    pub to_height: Option<u32>,
+}
+
+impl Checksum {
+    /// Checksum the ledger's blocks. The ledger is opened read-only, so it
+    /// can be checksummed while a node runs on it.
+    pub fn parse<N: Network>(self, ledger: PathBuf) -> Result<()> {
+        let db = rocks_open(ledger)?;
+        let latest_height = latest_height::<N>(&db)?;
+
+        let prefix = block_ids_prefix::<N>();
+        let height = self.to_height.unwrap_or(latest_height);
+        if height > latest_height {
+            bail!("to-height {height} exceeds the ledger height {latest_height}");
+        }
+
+        let mut digest = Sha256::new();
+        for h in 0..=height {
+            let mut key = prefix.clone();
+            bincode::serialize_into(&mut key, &h)?;
+            let hash = db
+                .get(&key)?
+                .with_context(|| format!("missing block hash at height {h}"))?;
+            let hash = bincode::deserialize::<N::BlockHash>(&hash)?;
+            digest.update(hash.to_bytes_le()?);
+        }
+
+        let checksum = LedgerChecksum {
+            height,
+            checksum: format!("{:x}", digest.finalize()),
+        };
+        println!("{}", serde_json::to_string(&checksum)?);
+        Ok(())
+    }
+}
+
+/// Print the height of the ledger's latest block. Unlike a checksum, this
+/// only reads the keys of the block hash map, so it is cheap enough to run
+/// while reconciling a node.
+pub fn print_height<N: Network>(ledger: PathBuf) -> Result<()> {
+    let db = rocks_open(ledger)?;
+    println!("{}", latest_height::<N>(&db)?);
+    Ok(())
+}
+
+/// Read the height of the ledger's latest block, the highest key of the map
+/// from block heights to block hashes
+fn latest_height<N: Network>(db: &rocksdb::DB) -> Result<u32> {
+    let prefix = block_ids_prefix::<N>();
+    db.prefix_iterator(&prefix)
+        .map_while(|row| row.ok().filter(|(key, _)| key.starts_with(&prefix)))
+        .filter_map(|(key, _)| bincode::deserialize::<u32>(&key[prefix.len()..]).ok())
+        .max()
+        .context("the ledger has no blocks")
+}
+
+/// The key prefix of the map from block heights to block hashes
+fn block_ids_prefix<N: Network>() -> Vec<u8> {
+    let mut prefix = N::ID.to_le_bytes().to_vec();
+    prefix.extend_from_slice(&u16::from(MapID::Block(BlockMap::ID)).to_le_bytes());
+    prefix
+}
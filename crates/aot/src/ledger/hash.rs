@@ -154,7 +154,7 @@ pub fn hash_ledger(ledger: PathBuf) -> Result<()> {
     Ok(())
 }
 
-fn rocks_open(dir: PathBuf) -> Result<rocksdb::DB> {
+pub(super) fn rocks_open(dir: PathBuf) -> Result<rocksdb::DB> {
     let mut options = rocksdb::Options::default();
     options.set_compression_type(rocksdb::DBCompressionType::Lz4);
 
@@ -165,9 +165,9 @@ fn rocks_open(dir: PathBuf) -> Result<rocksdb::DB> {
     options.set_prefix_extractor(prefix_extractor);
     options.increase_parallelism(2);
     options.set_max_background_jobs(4);
-    options.create_if_missing(true);
 
-    let db = rocksdb::DB::open(&options, dir)?;
+    // opened read-only so a running node keeps its lock on the ledger
+    let db = rocksdb::DB::open_for_read_only(&options, dir, false)?;
 
     Ok(db)
 }
@@ -13,6 +13,7 @@ use crate::{
 };
 
 pub mod checkpoint;
+pub mod checksum;
 pub mod hash;
 pub mod init;
 pub mod query;
@@ -49,6 +50,11 @@ pub enum Commands<N: Network> {
     Query(query::LedgerQuery<N>),
     /// Hash the ledger.
     Hash,
+    /// Print a checksum of the ledger's blocks, for comparing the ledgers of
+    /// different nodes.
+    Checksum(checksum::Checksum),
+    /// Print the height of the ledger's latest block.
+    Height,
     #[clap(subcommand)]
     Checkpoint(CheckpointCommand),
 }
@@ -60,6 +66,15 @@ impl<N: Network> Ledger<N> {
             genesis, ledger, ..
         } = self;
 
+        // creating a checkpoint and reading the height only read the ledger's
+        // storage, so they work without a genesis block
+        if let Commands::Checkpoint(CheckpointCommand::Create { checkpoint_dir }) = self.command {
+            return checkpoint::open_and_checkpoint::<N>(ledger, checkpoint_dir);
+        }
+        if let Commands::Height = self.command {
+            return checksum::print_height::<N>(ledger);
+        }
+
         let genesis_block = if let Some(path) = genesis {
             Block::read_le(std::fs::File::open(path)?)?
         } else {
@@ -97,6 +112,8 @@ impl<N: Network> Ledger<N> {
             }
 
             Commands::Hash => hash::hash_ledger(ledger),
+            Commands::Checksum(checksum) => checksum.parse::<N>(ledger),
+            Commands::Height => checksum::print_height::<N>(ledger),
             Commands::Checkpoint(command) => command.parse::<N>(genesis_block, ledger),
         }
     }
@@ -1,6 +1,9 @@
-use std::str::FromStr;
+use std::{
+    io::{BufWriter, Write},
+    str::FromStr,
+};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use clap::Subcommand;
 use snarkvm::{
     console::program::{Entry, Identifier, Literal, Network, Plaintext},
@@ -20,6 +23,16 @@ pub enum View<N: Network> {
         /// The height of the block to view.
         block_height: u32,
     },
+    /// Export a range of blocks as JSON, one block per line.
+    Blocks {
+        /// The height of the first block to export.
+        #[clap(long, default_value_t = 0)]
+        from_height: u32,
+        /// The height of the last block to export. Defaults to the latest
+        /// block.
+        #[clap(long)]
+        to_height: Option<u32>,
+    },
     /// View the balance of an address.
     Balance {
         /// The address to view the balance of.
@@ -39,6 +52,27 @@ impl<N: Network> View<N> {
                 // Print information about the ledger
                 println!("{:#?}", ledger.get_block(block_height)?);
             }
+            View::Blocks {
+                from_height,
+                to_height,
+            } => {
+                let latest_height = ledger.latest_height();
+                let to_height = to_height.unwrap_or(latest_height);
+                if to_height > latest_height {
+                    bail!("to-height {to_height} exceeds the ledger height {latest_height}");
+                }
+                if from_height > to_height {
+                    bail!("from-height {from_height} is greater than to-height {to_height}");
+                }
+
+                // write each block as it is read rather than collecting the range
+                let mut out = BufWriter::new(std::io::stdout().lock());
+                for height in from_height..=to_height {
+                    serde_json::to_writer(&mut out, &ledger.get_block(height)?)?;
+                    writeln!(out)?;
+                }
+                out.flush()?;
+            }
             View::Top => {
                 println!("{:#?}", ledger.latest_block());
             }
@@ -9,6 +9,12 @@ use snarkvm::console::network::{CanaryV0, MainnetV0, TestnetV0};
 #[global_allocator]
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
+/// Heap profiling is available but inactive until `--heap-profile` enables it
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[allow(non_upper_case_globals)]
+#[unsafe(export_name = "_rjem_malloc_conf")]
+pub static malloc_conf: &[u8] = b"prof:true,prof_active:false\0";
+
 fn main() -> Result<()> {
     let network: NetworkId = env::var("NETWORK")
         .unwrap_or(NetworkId::Mainnet.to_string())
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+/// Dump a jemalloc heap profile into `dir` every time the process receives
+/// SIGUSR1.
+///
+/// Profiling is compiled into the allocator but inactive until this is
+/// called, so nodes that do not use `--heap-profile` pay no sampling cost.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub fn init(dir: PathBuf) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use tokio::signal::unix::{SignalKind, signal};
+
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("create heap profile dir {}", dir.display()))?;
+
+    // SAFETY: `prof.active` is a bool
+    unsafe { tikv_jemalloc_ctl::raw::write(b"prof.active\0", true) }
+        .map_err(|e| anyhow::anyhow!("activate heap profiling: {e}"))?;
+
+    let mut signals = signal(SignalKind::user_defined1()).context("listen for SIGUSR1")?;
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            let path = dir.join(format!("heap-{}.prof", unix_timestamp()));
+            match dump(&path) {
+                Ok(()) => tracing::info!("heap profile written to {}", path.display()),
+                Err(e) => tracing::error!("failed to write heap profile: {e}"),
+            }
+        }
+    });
+
+    tracing::info!("heap profiling enabled, send SIGUSR1 to dump a profile");
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn dump(path: &std::path::Path) -> anyhow::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `prof.dump` takes a nul terminated path that outlives the call
+    unsafe { tikv_jemalloc_ctl::raw::write(b"prof.dump\0", path.as_ptr()) }
+        .map_err(|e| anyhow::anyhow!("{e}"))
+}
+
+/// Seconds since the unix epoch, used to give each profile a unique name
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// jemalloc is not the allocator on this platform, so there is nothing to dump
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+pub fn init(_dir: PathBuf) -> anyhow::Result<()> {
+    tracing::warn!("--heap-profile is only supported on x86_64 linux, ignoring");
+    Ok(())
+}
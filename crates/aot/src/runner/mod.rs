@@ -23,8 +23,12 @@ use snarkvm::{
 use snops_checkpoint::{CheckpointManager, RetentionPolicy};
 use snops_common::state::{NodeType, snarkos_status::SnarkOSStatus};
 
-use crate::{Account, Address, DbLedger, Key, Network, cli::ReloadHandler};
+use crate::{
+    Account, Address, DbLedger, Key, Network, cli::ReloadHandler,
+    ledger::checkpoint::checkpoint_live_ledger,
+};
 
+mod heap_profile;
 mod metrics;
 mod rpc;
 
@@ -79,9 +83,20 @@ pub struct Runner<N: Network> {
     #[clap(long)]
     pub retention_policy: Option<RetentionPolicy>,
 
+    /// When present, creates a checkpoint every time the ledger advances by
+    /// this many blocks instead of following the retention policy's cadence.
+    /// The retention policy still decides which checkpoints are kept.
+    #[clap(long, requires = "retention_policy", value_parser = clap::value_parser!(u32).range(1..))]
+    pub checkpoint_every: Option<u32>,
+
     /// When present, connects to an agent RPC server on the given port.
     #[clap(long)]
     pub agent_rpc_port: Option<u16>,
+
+    /// When present, enables jemalloc heap profiling. A profile is written to
+    /// this directory each time the process receives SIGUSR1.
+    #[clap(long)]
+    pub heap_profile: Option<PathBuf>,
 }
 
 impl<N: Network> Runner<N> {
@@ -113,6 +128,10 @@ impl<N: Network> Runner<N> {
     async fn start_inner(self, agent: RpcClient<N>) -> Result<()> {
         agent.status(SnarkOSStatus::Starting);
 
+        if let Some(dir) = self.heap_profile.clone() {
+            heap_profile::init(dir)?;
+        }
+
         let bind_addr = self.bind_addr;
         let node_ip = SocketAddr::new(bind_addr, self.node);
         let rest_ip = SocketAddr::new(bind_addr, self.rest);
@@ -245,8 +264,27 @@ impl<N: Network> Runner<N> {
             // emit the initial block status
             agent.post_block(last_height, &blocks);
 
+            let checkpoint_every = self.checkpoint_every;
+            let ledger_path = self.ledger.clone();
+            let mut last_checkpoint_height = last_height;
+            // the manager is moved into this task while a checkpoint is being created
+            let mut pending_checkpoint: Option<tokio::task::JoinHandle<CheckpointManager>> = None;
+
             tokio::spawn(async move {
                 loop {
+                    // reclaim the manager once the in-flight checkpoint is written
+                    if pending_checkpoint
+                        .as_ref()
+                        .is_some_and(|handle| handle.is_finished())
+                    {
+                        if let Some(handle) = pending_checkpoint.take() {
+                            match handle.await {
+                                Ok(m) => manager = Some(m),
+                                Err(e) => tracing::error!("checkpoint task failed: {e}"),
+                            }
+                        }
+                    }
+
                     let Ok(height) = committee.current_height() else {
                         continue;
                     };
@@ -260,9 +298,32 @@ impl<N: Network> Runner<N> {
 
                         agent.post_block(height, &blocks);
 
-                        if let Some(manager) = &mut manager {
-                            if let Err(e) = manager.poll::<N>() {
-                                tracing::error!("backup loop error: {e:?}");
+                        // checkpoints are created from a copy of the ledger on a blocking
+                        // thread so they don't stall the node's runtime
+                        if let Some(mut m) = manager.take() {
+                            let due = match checkpoint_every {
+                                Some(every) => height / every > last_checkpoint_height / every,
+                                None => true,
+                            };
+                            if due {
+                                if checkpoint_every.is_some() {
+                                    last_checkpoint_height = height;
+                                }
+                                // block cadence checkpoints ignore the policy's cadence
+                                let force = checkpoint_every.is_some();
+                                let ledger_path = ledger_path.clone();
+                                pending_checkpoint = Some(tokio::task::spawn_blocking(move || {
+                                    match checkpoint_live_ledger::<N>(&mut m, &ledger_path, force) {
+                                        Ok(Some(height)) => {
+                                            tracing::info!("created checkpoint @ {height}")
+                                        }
+                                        Ok(None) => {}
+                                        Err(e) => tracing::error!("checkpoint error: {e:?}"),
+                                    }
+                                    m
+                                }));
+                            } else {
+                                manager = Some(m);
                             }
                         }
                     }
@@ -103,9 +103,31 @@ impl<N: Network> NodeService for NodeRpcServer<N> {
         // convert snarkVM transactions into transaction ids
         let tx_ids = transactions.iter().map(|tx| tx.id().to_string()).collect();
 
+        // rejected transactions are listed by the id they were broadcast with
+        let rejected = transactions
+            .iter()
+            .filter(|tx| tx.is_rejected())
+            .filter_map(|tx| tx.to_unconfirmed_transaction_id().ok())
+            .map(|id| id.to_string())
+            .collect();
+
+        let aborted = block_db
+            .get_block_aborted_transaction_ids(&hash)
+            .map_err(|e| {
+                AgentError::FailedToMakeRequest(format!(
+                    "aborted transactions for height {height}: {e:?}"
+                ))
+            })?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect();
+
         Ok(Some(SnarkOSLiteBlock {
             info,
             transactions: tx_ids,
+            rejected,
+            aborted,
         }))
     }
 }
@@ -41,6 +41,8 @@ pub enum ManagerInsertError {
     ModifyError(#[source] io::Error),
     #[error("error writing file: {0}")]
     WriteError(#[source] io::Error),
+    #[error("error reading checkpoint header: {0}")]
+    Header(#[from] CheckpointHeaderError),
 }
 
 #[derive(Debug, Error)]
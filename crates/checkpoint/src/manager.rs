@@ -1,4 +1,10 @@
-use std::{collections::BTreeMap, fs, path::PathBuf};
+#[cfg(feature = "write")]
+use std::path::Path;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::PathBuf,
+};
 
 use chrono::{DateTime, TimeDelta, Utc};
 use lazysort::SortedBy;
@@ -20,6 +26,14 @@ pub struct CheckpointManager {
     checkpoints: BTreeMap<DateTime<Utc>, (CheckpointHeader, PathBuf)>,
 }
 
+/// The checkpoints a retention policy would keep and delete, ordered from
+/// oldest to newest
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPlan {
+    pub keep: Vec<(CheckpointHeader, PathBuf)>,
+    pub delete: Vec<(CheckpointHeader, PathBuf)>,
+}
+
 /// Block timestamps are seconds since Unix epoch UTC
 fn datetime_from_int(timestamp: i64) -> DateTime<Utc> {
     DateTime::UNIX_EPOCH + TimeDelta::new(timestamp, 0).unwrap()
@@ -73,6 +87,20 @@ impl CheckpointManager {
         })
     }
 
+    /// Create a manager from checkpoint headers without reading them from disk
+    #[cfg(test)]
+    pub(crate) fn from_headers(
+        policy: RetentionPolicy,
+        headers: Vec<(CheckpointHeader, PathBuf)>,
+    ) -> Self {
+        Self {
+            #[cfg(feature = "write")]
+            storage_path: PathBuf::new(),
+            policy,
+            checkpoints: headers.into_iter().map(|c| (c.0.time(), c)).collect(),
+        }
+    }
+
     /// Cull checkpoints that are incompatible with the current block database
     #[cfg(feature = "write")]
     pub fn cull_incompatible<N: crate::aleo::Network>(
@@ -129,18 +157,65 @@ impl CheckpointManager {
     #[cfg(feature = "write")]
     pub fn poll<N: crate::aleo::Network>(&mut self) -> Result<bool, ManagerPollError> {
         let header = CheckpointHeader::read_ledger::<N>(self.storage_path.clone())?;
-        let time = header.time();
 
-        if !self.is_ready(&time) || header.block_height == 0 {
+        if !self.should_poll(&header) {
             return Ok(false);
         }
 
+        self.create_from_header::<N>(header)?;
+        Ok(true)
+    }
+
+    /// Check if the retention policy's cadence wants a checkpoint for the
+    /// given ledger header
+    pub fn should_poll(&self, header: &CheckpointHeader) -> bool {
+        header.block_height != 0 && self.is_ready(&header.time())
+    }
+
+    /// Check if a checkpoint for the given ledger header would be new
+    pub fn should_create(&self, header: &CheckpointHeader) -> bool {
+        header.block_height != 0 && !self.checkpoints.contains_key(&header.time())
+    }
+
+    /// Move a checkpoint file written outside of the manager into its storage
+    /// directory, then cull checkpoints the policy no longer needs. Returns
+    /// the header of the inserted checkpoint.
+    #[cfg(feature = "write")]
+    pub fn insert_file(&mut self, file: &Path) -> Result<CheckpointHeader, ManagerInsertError> {
+        use ManagerInsertError::*;
+
+        let header = CheckpointHeader::read_file(&file.to_path_buf())?;
+        let Some(path) = path_from_height(&self.storage_path, header.block_height) else {
+            return Err(InvalidStoragePath(self.storage_path.clone()));
+        };
+        fs::rename(file, &path).map_err(FileError)?;
+
+        trace!(
+            "checkpoint on {} @ {} moved to {path:?}",
+            header.time(),
+            header.block_height,
+        );
+
+        let time = header.time();
+        self.checkpoints.insert(time, (header.clone(), path));
+        self.cull_timestamp(time);
+        Ok(header)
+    }
+
+    /// Read the ledger content for a header, write the checkpoint, and cull
+    /// old checkpoints relative to the header's timestamp
+    #[cfg(feature = "write")]
+    fn create_from_header<N: crate::aleo::Network>(
+        &mut self,
+        header: CheckpointHeader,
+    ) -> Result<(), ManagerPollError> {
+        let time = header.time();
         trace!("creating checkpoint @ {}...", header.block_height);
         let checkpoint =
             crate::Checkpoint::<N>::new_from_header(self.storage_path.clone(), header)?;
         self.write_and_insert(checkpoint)?;
         self.cull_timestamp(time);
-        Ok(true)
+        Ok(())
     }
 
     /// Check if the manager is ready to create a new checkpoint given the
@@ -209,6 +284,33 @@ impl CheckpointManager {
         }
     }
 
+    /// Determine which checkpoints a cull would keep and delete, without
+    /// deleting anything
+    pub fn plan(&self) -> RetentionPlan {
+        self.plan_timestamp(Utc::now())
+    }
+
+    /// Determine which checkpoints a cull at the given timestamp would keep
+    /// and delete
+    pub fn plan_timestamp(&self, timestamp: DateTime<Utc>) -> RetentionPlan {
+        let times = self.checkpoints.keys().collect();
+        let rejected = self
+            .policy
+            .reject_with_time(timestamp, times)
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let mut plan = RetentionPlan::default();
+        for (time, checkpoint) in &self.checkpoints {
+            if rejected.contains(time) {
+                plan.delete.push(checkpoint.clone());
+            } else {
+                plan.keep.push(checkpoint.clone());
+            }
+        }
+        plan
+    }
+
     /// Get the retention policy used by this manager
     pub fn policy(&self) -> &RetentionPolicy {
         &self.policy
@@ -265,3 +367,22 @@ impl std::fmt::Display for CheckpointManager {
         Ok(())
     }
 }
+
+impl std::fmt::Display for RetentionPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (label, checkpoints) in [("keep", &self.keep), ("delete", &self.delete)] {
+            write!(f, "{label} {} checkpoints:", checkpoints.len())?;
+            for (header, path) in checkpoints {
+                write!(
+                    f,
+                    "\n  {}: block {}, {}",
+                    header.time(),
+                    header.block_height,
+                    path.display()
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
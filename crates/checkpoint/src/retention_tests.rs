@@ -1,9 +1,10 @@
 use std::collections::{BinaryHeap, HashSet};
-use std::{num::NonZeroU8, str::FromStr};
+use std::{num::NonZeroU8, path::PathBuf, str::FromStr};
 
-use chrono::{DateTime, TimeDelta};
+use chrono::{DateTime, TimeDelta, Utc};
 
 use super::retention::*;
+use crate::{CheckpointHeader, CheckpointManager};
 
 macro_rules! day {
     ($e:expr) => {
@@ -135,3 +136,106 @@ policy_test!(one_day_4h1h_8h4h_delay, "4h:1h,8h:4h", day!(1), min!(1), day!(1),
 
 policy_test!(one_day_spaced, "4h:1h,8h:4h,2D:12h", day!(7), hr!(1), hr!(1), + (24 * 7), = 6);
 policy_test!(one_day_spaced_delay, "4h:1h,8h:4h,2D:12h", day!(7), hr!(1), day!(1), + (24 * 7), = 6);
+
+/// A checkpoint header for a block created at the given time
+fn header_at(block_height: u32, time: DateTime<Utc>) -> (CheckpointHeader, PathBuf) {
+    let header = CheckpointHeader {
+        block_height,
+        timestamp: time.timestamp(),
+        block_hash: [0; 32],
+        genesis_hash: [0; 32],
+        content_len: 0,
+    };
+    // the paths do not exist, so culling only drops them from the manager
+    (
+        header,
+        PathBuf::from(format!("/nonexistent/{block_height}.checkpoint")),
+    )
+}
+
+#[test]
+fn plan_matches_cull() {
+    let policy = RetentionPolicy::from_str("4h:1h,1D:8h").unwrap();
+    let now = DateTime::UNIX_EPOCH + day!(2);
+    let headers = (0..48)
+        .map(|i| header_at(i, DateTime::UNIX_EPOCH + hr!(i as i64)))
+        .collect::<Vec<_>>();
+
+    let mut manager = CheckpointManager::from_headers(policy, headers);
+    let plan = manager.plan_timestamp(now);
+    assert_eq!(plan.keep.len() + plan.delete.len(), 48);
+    assert!(!plan.keep.is_empty());
+    assert!(!plan.delete.is_empty());
+
+    // planning does not remove anything
+    assert_eq!(manager.checkpoints().count(), 48);
+
+    manager.cull_timestamp(now);
+    let kept = manager
+        .checkpoints()
+        .map(|(h, _)| h.block_height)
+        .collect::<Vec<_>>();
+    let planned = plan
+        .keep
+        .iter()
+        .map(|(h, _)| h.block_height)
+        .collect::<Vec<_>>();
+    assert_eq!(kept, planned);
+}
+
+#[test]
+fn plan_empty_policy_keeps_all() {
+    let headers = (0..4)
+        .map(|i| header_at(i, DateTime::UNIX_EPOCH + hr!(i as i64)))
+        .collect();
+    let manager = CheckpointManager::from_headers(RetentionPolicy::new(vec![]), headers);
+
+    let plan = manager.plan_timestamp(DateTime::UNIX_EPOCH + day!(1));
+    assert_eq!(plan.keep.len(), 4);
+    assert!(plan.delete.is_empty());
+}
+
+#[test]
+fn should_create_skips_genesis_and_existing() {
+    let headers = (1..4)
+        .map(|i| header_at(i, DateTime::UNIX_EPOCH + hr!(i as i64)))
+        .collect();
+    let manager = CheckpointManager::from_headers(RetentionPolicy::default(), headers);
+
+    assert!(!manager.should_create(&header_at(0, DateTime::UNIX_EPOCH).0));
+    assert!(!manager.should_poll(&header_at(0, DateTime::UNIX_EPOCH + day!(1)).0));
+    assert!(!manager.should_create(&header_at(3, DateTime::UNIX_EPOCH + hr!(3)).0));
+    assert!(manager.should_create(&header_at(4, DateTime::UNIX_EPOCH + hr!(4)).0));
+}
+
+#[cfg(feature = "write")]
+#[test]
+fn insert_file_moves_checkpoint_into_storage() {
+    let dir = std::env::temp_dir().join(format!("snops-checkpoint-insert-{}", std::process::id()));
+    let out = dir.join("out");
+    std::fs::create_dir_all(&out).unwrap();
+
+    let mut manager =
+        CheckpointManager::load(dir.join("ledger"), RetentionPolicy::default()).unwrap();
+
+    // a checkpoint written by another process into a scratch directory
+    let (header, _) = header_at(7, Utc::now());
+    let file = out.join("7.checkpoint");
+    header
+        .write_bytes(std::fs::File::create(&file).unwrap())
+        .unwrap();
+
+    let inserted = manager.insert_file(&file).unwrap();
+    assert_eq!(inserted.block_height, 7);
+    assert!(!file.exists());
+    assert!(dir.join("7.checkpoint").exists());
+    assert_eq!(
+        manager
+            .checkpoints()
+            .map(|(h, _)| h.block_height)
+            .collect::<Vec<_>>(),
+        vec![7]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
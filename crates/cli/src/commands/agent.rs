@@ -1,12 +1,18 @@
 use std::str::FromStr;
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use clap::{ArgGroup, CommandFactory, Parser, ValueHint, error::ErrorKind};
-use reqwest::{Client, Response};
+use futures_util::StreamExt;
+use http::Uri;
+use reqwest::{Client, Response, StatusCode};
 use serde_json::json;
-use snops_common::state::AgentId;
+use snops_common::{api::AgentMetricsUpdate, state::AgentId};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{Message, client::IntoClientRequest},
+};
 
-use super::DUMMY_ID;
+use super::{DUMMY_ID, IdKind, resolve_id};
 use crate::Cli;
 
 /// For interacting with snop agents.
@@ -20,7 +26,7 @@ pub struct Agent {
 }
 
 /// Agent commands.
-#[derive(Debug, Parser)]
+#[derive(Clone, Debug, Parser)]
 enum AgentCommands {
     /// Find agents by set criteria.
     /// If all of client/compute/prover/validator are not specified it can be
@@ -59,24 +65,78 @@ enum AgentCommands {
     /// Get the specific agent.
     #[clap(alias = "i")]
     Info,
-    /// Kill the specific agent
+    /// Shut down the specific agent. Its node is stopped gracefully first so
+    /// its ledger is flushed.
+    #[clap(alias = "shutdown")]
     Kill,
 
+    /// Send the specific agent its current target state, without
+    /// re-applying its env.
+    Reconcile {
+        /// Fetch the latest env info before reconciling.
+        #[clap(long)]
+        refetch: bool,
+        /// Clear the node's last known height before reconciling.
+        #[clap(long)]
+        clear_height: bool,
+        /// When present, don't wait for the reconcile to finish before
+        /// returning.
+        #[clap(long = "async")]
+        async_mode: bool,
+    },
+
     /// List all agents.
     /// Ignores the agent id.
     #[clap(alias = "ls")]
-    List,
+    List {
+        /// Only list connected agents.
+        #[clap(long, conflicts_with = "offline")]
+        online: bool,
+        /// Only list disconnected agents.
+        #[clap(long)]
+        offline: bool,
+        /// The labels a listed agent should have.
+        #[clap(long, value_delimiter = ',', num_args = 1..)]
+        labels: Vec<String>,
+        /// Only list agents in this env.
+        #[clap(long)]
+        env: Option<String>,
+        /// The number of agents to skip.
+        #[clap(long)]
+        offset: Option<usize>,
+        /// The maximum number of agents to list.
+        #[clap(long)]
+        limit: Option<usize>,
+    },
 
     /// Get the specific agent's TPS.
     Tps,
 
+    /// Get the specific agent's metrics. Currently only TPS is reported.
+    Metrics {
+        /// Stream metric updates as a live table until interrupted.
+        #[clap(long, short)]
+        watch: bool,
+    },
+
     /// Get the specific agent's status.
     Status,
 
+    /// Get the command line of the node running on the agent, with its
+    /// private key and url credentials redacted.
+    Cmdline,
+
     /// Set the log level of the agent.
+    #[clap(alias = "logs")]
     SetLogLevel {
-        /// The log level to set.
-        level: String,
+        /// The log level or comma separated filter directives to set, e.g.
+        /// `trace` or `info,snops_agent::reconcile=trace`.
+        #[clap(required_unless_present = "level_flag")]
+        level: Option<String>,
+        /// The log level or filter directives to set, as an alternative to the
+        /// positional argument.
+        #[clap(long = "level", id = "level_flag", conflicts_with = "level")]
+        level_flag: Option<String>,
     },
 
     /// Set the log level of the node running on an agent.
@@ -89,7 +149,33 @@ enum AgentCommands {
 impl Agent {
     pub async fn run(self, url: &str, client: Client) -> Result<Response> {
         use AgentCommands::*;
-        Ok(match self.command {
+        // find and list ignore the agent id
+        if matches!(self.command, Find { .. } | List { .. })
+            || self.id == AgentId::from_str(DUMMY_ID).unwrap()
+        {
+            return self.command.run(url, self.id, client).await;
+        }
+
+        // the id is only resolved when the control plane does not know it
+        let res = self
+            .command
+            .clone()
+            .run(url, self.id, client.clone())
+            .await?;
+        if res.status() != StatusCode::NOT_FOUND {
+            return Ok(res);
+        }
+        match resolve_id(url, &client, IdKind::Agent, self.id).await? {
+            id if id == self.id => Ok(res),
+            id => self.command.run(url, id, client).await,
+        }
+    }
+}
+
+impl AgentCommands {
+    async fn run(self, url: &str, id: AgentId, client: Client) -> Result<Response> {
+        use AgentCommands::*;
+        Ok(match self {
             Find {
                 env,
                 labels,
@@ -121,12 +207,36 @@ impl Agent {
                     .send()
                     .await?
             }
-            List => {
+            List {
+                online,
+                offline,
+                labels,
+                env,
+                offset,
+                limit,
+            } => {
                 let ep = format!("{url}/api/v1/agents");
 
-                client.get(ep).send().await?
+                let mut query = vec![];
+                if online || offline {
+                    query.push(("online", online.to_string()));
+                }
+                if !labels.is_empty() {
+                    query.push(("labels", labels.join(",")));
+                }
+                if let Some(env) = env {
+                    query.push(("env", env));
+                }
+                if let Some(offset) = offset {
+                    query.push(("offset", offset.to_string()));
+                }
+                if let Some(limit) = limit {
+                    query.push(("limit", limit.to_string()));
+                }
+
+                client.get(ep).query(&query).send().await?
             }
-            _ if self.id == AgentId::from_str(DUMMY_ID).unwrap() => {
+            _ if id == AgentId::from_str(DUMMY_ID).unwrap() => {
                 let mut cmd = Cli::command();
                 cmd.error(
                     ErrorKind::MissingRequiredArgument,
@@ -135,35 +245,140 @@ impl Agent {
                 .exit();
             }
             Info => {
-                let ep = format!("{url}/api/v1/agents/{}", self.id);
+                let ep = format!("{url}/api/v1/agents/{id}");
 
                 client.get(ep).send().await?
             }
             Kill => {
-                let ep = format!("{url}/api/v1/agents/{}/kill", self.id);
+                let ep = format!("{url}/api/v1/agents/{id}/kill");
 
                 client.post(ep).send().await?
             }
+            Reconcile {
+                refetch,
+                clear_height,
+                async_mode,
+            } => {
+                let ep = format!("{url}/api/v1/agents/{id}/reconcile");
+
+                client
+                    .post(ep)
+                    .query(&[("async", async_mode)])
+                    .json(&json!({
+                        "refetch_info": refetch,
+                        "clear_last_height": clear_height,
+                    }))
+                    .send()
+                    .await?
+            }
             Status => {
-                let ep = format!("{url}/api/v1/agents/{}/status", self.id);
+                let ep = format!("{url}/api/v1/agents/{id}/status");
+
+                client.get(ep).send().await?
+            }
+            Cmdline => {
+                let ep = format!("{url}/api/v1/agents/{id}/cmdline");
 
                 client.get(ep).send().await?
             }
             Tps => {
-                let ep = format!("{url}/api/v1/agents/{}/tps", self.id);
+                let ep = format!("{url}/api/v1/agents/{id}/tps");
 
                 client.get(ep).send().await?
             }
-            SetLogLevel { level } => {
-                let ep = format!("{url}/api/v1/agents/{}/log/{level}", self.id);
+            Metrics { watch: true } => {
+                watch_metrics(url, id).await?;
+                std::process::exit(0);
+            }
+            Metrics { watch: false } => {
+                let ep = format!("{url}/api/v1/agents/{id}/tps");
+
+                client.get(ep).send().await?
+            }
+            SetLogLevel { level, level_flag } => {
+                // clap requires exactly one of the two
+                let level = level.or(level_flag).unwrap_or_default();
+                let ep = format!("{url}/api/v1/agents/{id}/log/{level}");
 
                 client.post(ep).send().await?
             }
 
             SetSnarkosLogLevel { verbosity } => {
-                let ep = format!("{url}/api/v1/agents/{}/aot/log/{verbosity}", self.id);
+                let ep = format!("{url}/api/v1/agents/{id}/aot/log/{verbosity}");
                 client.post(ep).send().await?
             }
         })
     }
 }
+
+/// Print a row for each metrics update streamed from the control plane
+async fn watch_metrics(url: &str, id: AgentId) -> Result<()> {
+    let (proto, hostname) = url.split_once("://").unwrap_or(("http", url));
+    let proto = match proto {
+        "wss" | "https" => "wss",
+        _ => "ws",
+    };
+
+    let req = Uri::from_str(&format!(
+        "{proto}://{hostname}/api/v1/agents/{id}/metrics/stream"
+    ))
+    .context("Invalid URI")?
+    .into_client_request()
+    .context("Invalid websocket request")?;
+
+    let (mut stream, _) = connect_async(req)
+        .await
+        .context("Failed to connect to websocket")?;
+
+    println!("{:<12} {:>10}", "TIMESTAMP", "TPS");
+    loop {
+        let msg = tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            msg = stream.next() => msg,
+        };
+
+        let update: AgentMetricsUpdate = match msg {
+            Some(Ok(Message::Text(text))) => serde_json::from_str(&text)
+                .with_context(|| format!("Failed to parse metrics: {text}"))?,
+            Some(Ok(Message::Close(_))) | None => bail!("Websocket closed"),
+            Some(Err(e)) => bail!("Websocket error: {e}"),
+            Some(Ok(_)) => continue,
+        };
+
+        println!("{:<12} {:>10.2}", update.timestamp, update.tps);
+    }
+
+    stream.close(None).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_level(args: &[&str]) -> Result<Option<String>, clap::Error> {
+        let agent = Agent::try_parse_from([&["agent", "agent-a"][..], args].concat())?;
+        match agent.command {
+            AgentCommands::SetLogLevel { level, level_flag } => Ok(level.or(level_flag)),
+            command => panic!("unexpected command {command:?}"),
+        }
+    }
+
+    #[test]
+    fn test_set_log_level_accepts_flag_and_positional() {
+        let filter = "info,snops_agent::reconcile=trace";
+        assert_eq!(
+            parse_level(&["logs", "--level", filter])
+                .unwrap()
+                .as_deref(),
+            Some(filter)
+        );
+        assert_eq!(
+            parse_level(&["set-log-level", "trace"]).unwrap().as_deref(),
+            Some("trace")
+        );
+
+        assert!(parse_level(&["logs"]).is_err());
+        assert!(parse_level(&["logs", "trace", "--level", "debug"]).is_err());
+    }
+}
@@ -7,7 +7,7 @@ use reqwest::{Client, RequestBuilder, Response};
 use serde_json::{Value, json};
 use snops_cli::events::EventsClient;
 use snops_common::{
-    action_models::{AleoValue, WithTargets},
+    action_models::{AleoValue, PriorityFee, WithTargets},
     events::{Event, EventKind, TransactionEvent},
     key_source::KeySource,
     node_targets::{NodeTarget, NodeTargetError, NodeTargets},
@@ -58,7 +58,7 @@ impl From<NodesOption> for NodeTargets {
 }
 
 /// Actions you can apply on a specific environment.
-#[derive(Debug, Parser)]
+#[derive(Clone, Debug, Parser)]
 pub enum Action {
     /// Turn the specified agents(and nodes) offline.
     #[clap(alias = "off")]
@@ -89,6 +89,15 @@ pub enum Action {
         #[clap(long = "async")]
         async_mode: bool,
     },
+    /// Take every node offline and record the heights they stopped at.
+    Freeze,
+    /// Bring the nodes of a frozen environment back online at the heights
+    /// they were frozen at.
+    Thaw {
+        /// When present, don't wait for reconciles to finish before returning
+        #[clap(long = "async")]
+        async_mode: bool,
+    },
     /// Execute an aleo program function on the environment. i.e.
     /// credits.aleo/transfer_public
     Execute {
@@ -103,9 +112,11 @@ pub enum Action {
         /// Desired cannon to fire the transaction
         #[clap(long, short)]
         cannon: Option<CannonId>,
-        /// The optional priority fee to use.
+        /// The optional priority fee to use in microcredits, or `auto` /
+        /// `auto:<fraction>` to use a fraction of the fee payer's public
+        /// balance.
         #[clap(long)]
-        priority_fee: Option<u32>,
+        priority_fee: Option<PriorityFee>,
         /// The fee record to use if you want to pay the fee privately.
         #[clap(long)]
         fee_record: Option<String>,
@@ -131,9 +142,11 @@ pub enum Action {
         /// Desired cannon to fire the transaction
         #[clap(long, short)]
         cannon: Option<CannonId>,
-        /// The optional priority fee to use.
+        /// The optional priority fee to use in microcredits, or `auto` /
+        /// `auto:<fraction>` to use a fraction of the fee payer's public
+        /// balance.
         #[clap(long)]
-        priority_fee: Option<u32>,
+        priority_fee: Option<PriorityFee>,
         /// The fee record to use if you want to pay the fee privately.
         #[clap(long)]
         fee_record: Option<String>,
@@ -148,8 +161,9 @@ pub enum Action {
         /// Configure the online state of the target nodes.
         #[clap(long, short)]
         online: Option<bool>,
-        /// Configure the height of the target nodes.
-        #[clap(long)]
+        /// Configure the height of the target nodes. Negative values such as
+        /// `-100` rewind from the node's current height.
+        #[clap(long, allow_hyphen_values = true)]
         height: Option<HeightRequest>,
         /// Configure the peers of the target nodes, or `none`.
         #[clap(long, short)]
@@ -157,7 +171,10 @@ pub enum Action {
         /// Configure the validators of the target nodes, or `none`.
         #[clap(long, short)]
         validators: Option<NodesOption>,
-        /// Set environment variables for a node: `--env FOO=bar`
+        /// Set environment variables for a node: `--env FOO=bar`. Values may
+        /// reference `{{node_key}}`, `{{node_type}}`, `{{node_id}}`,
+        /// `{{agent_id}}`, `{{env_id}}`, or `{{network}}`, which are expanded
+        /// per-node by the agent.
         #[clap(long, short, number_of_values = 1, value_parser = clap::value_parser!(KeyEqValue))]
         env: Option<Vec<KeyEqValue>>,
         // Remove environment variables from a node: `--del-env FOO,BAR`
@@ -229,6 +246,20 @@ impl Action {
                     std::process::exit(0);
                 }
             }
+            Freeze => {
+                let ep = format!("{url}/api/v1/env/{env_id}/action/freeze");
+                client.post(ep).send().await?
+            }
+            Thaw { async_mode } => {
+                let ep = format!("{url}/api/v1/env/{env_id}/action/thaw");
+                let req = client.post(ep);
+                if async_mode {
+                    req.send().await?
+                } else {
+                    post_and_wait(url, req, env_id).await?;
+                    std::process::exit(0);
+                }
+            }
 
             Execute {
                 private_key,
@@ -262,7 +293,7 @@ impl Action {
                     json["cannon"] = cannon.to_string().into();
                 }
                 if let Some(priority_fee) = priority_fee {
-                    json["priority_fee"] = priority_fee.into();
+                    json["priority_fee"] = json!(priority_fee);
                 }
                 if let Some(fee_record) = fee_record {
                     json["fee_record"] = fee_record.into();
@@ -305,7 +336,7 @@ impl Action {
                     json["cannon"] = cannon.to_string().into();
                 }
                 if let Some(priority_fee) = priority_fee {
-                    json["priority_fee"] = priority_fee.into();
+                    json["priority_fee"] = json!(priority_fee);
                 }
                 if let Some(fee_record) = fee_record {
                     json["fee_record"] = fee_record.into();
@@ -465,11 +496,15 @@ pub async fn post_and_wait_tx(url: &str, req: RequestBuilder) -> Result<()> {
                 broadcast_height = height;
                 broadcast_time = Some(timestamp);
             }
+            TransactionEvent::MempoolAccepted { node } => {
+                eprintln!("accepted into the mempool of {node}");
+            }
             TransactionEvent::Confirmed { hash } => {
                 eprintln!("confirmed with hash {hash}");
                 block_hash = Some(hash);
                 break;
             }
+            TransactionEvent::BlockSummary { .. } | TransactionEvent::CannonRestarted { .. } => {}
         }
     }
     println!(
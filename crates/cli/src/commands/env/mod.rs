@@ -1,20 +1,35 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
 
 use action::post_and_wait_tx;
 use anyhow::Result;
 use clap::{Parser, ValueHint};
 use clap_stdin::FileOrStdin;
-use reqwest::{Client, RequestBuilder, Response};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use serde_json::Value;
 use snops_cli::events::EventsClient;
 use snops_common::{
     action_models::AleoValue,
-    events::{AgentEvent, Event, EventKind},
+    api::{EnvInfo, NodeAdjacency},
+    constant::HEADER_ENV_UNCHANGED,
+    events::{
+        AgentEvent, Event, EventKind, LATENCY_BUCKETS_SECS, TransactionRecord, analyze_records,
+    },
     key_source::KeySource,
-    state::{AgentId, Authorization, CannonId, EnvId, InternedId, NodeKey, ReconcileStatus},
+    state::{
+        AgentId, Authorization, CannonId, EnvId, InternedId, LatestBlockInfo, NodeKey, NodeType,
+        ReconcileStatus,
+    },
 };
+use spec::SpecSource;
+
+use super::{IdKind, resolve_id};
 
 mod action;
+mod spec;
 
 /// For interacting with snop environments.
 #[derive(Debug, Parser)]
@@ -26,8 +41,57 @@ pub struct Env {
     command: EnvCommands,
 }
 
+/// Commands for a specific agent in an env.
+#[derive(Clone, Debug, Parser)]
+enum AgentCommands {
+    /// Run a read-only `ledger` subcommand of the node's aot binary against
+    /// its ledger. i.e `view top`, `view block 10`, `hash`, `checkpoint view`.
+    /// `view` commands are refused while the node is running.
+    Exec {
+        /// The ledger subcommand and its arguments.
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
+
+/// Commands for the cannons of an env.
+#[derive(Clone, Debug, Parser)]
+enum CannonCommands {
+    /// Create a cannon in the running env without re-applying its spec.
+    Create {
+        /// The new cannon's id.
+        #[clap(value_hint = ValueHint::Other)]
+        cannon: CannonId,
+        /// A JSON object with the cannon's `source` and `sink`, as in a cannon
+        /// document, or `-` for stdin.
+        payload: FileOrStdin<Value>,
+        /// Seed the cannon's generated transactions, overriding the payload's
+        /// `source.seed`.
+        #[clap(long)]
+        seed: Option<u64>,
+    },
+    /// Stop and remove a cannon from the running env.
+    Delete {
+        /// The cannon's id.
+        #[clap(value_hint = ValueHint::Other)]
+        cannon: CannonId,
+    },
+}
+
+/// Commands for an env's transaction record.
+#[derive(Clone, Debug, Parser)]
+enum TxRecordCommands {
+    /// Start recording every transaction status transition of the env to a
+    /// JSON lines file on the control plane.
+    Start,
+    /// Stop recording the env's transactions.
+    Stop,
+    /// Print the env's transaction record.
+    Download,
+}
+
 /// Env commands.
-#[derive(Debug, Parser)]
+#[derive(Clone, Debug, Parser)]
 enum EnvCommands {
     /// Run an action on an environment.
     #[clap(subcommand)]
@@ -39,6 +103,8 @@ enum EnvCommands {
         /// or combination.
         #[clap(value_hint = ValueHint::Other)]
         key: NodeKey,
+        #[clap(subcommand)]
+        command: Option<AgentCommands>,
     },
 
     /// List an env's agents
@@ -55,6 +121,18 @@ enum EnvCommands {
         auth: FileOrStdin<Authorization>,
     },
 
+    /// Create or delete cannons at runtime.
+    #[clap(subcommand)]
+    Cannon(CannonCommands),
+
+    /// Stop a wedged cannon and start it again with the same source and sink.
+    /// Its fired and received counters and pending transactions are kept.
+    RestartCannon {
+        /// The cannon's id.
+        #[clap(value_hint = ValueHint::Other)]
+        cannon: CannonId,
+    },
+
     /// Lookup an account's balance
     #[clap(alias = "bal")]
     Balance {
@@ -72,6 +150,46 @@ enum EnvCommands {
     /// Get the latest height from all agents in the env.
     Height,
 
+    /// Compare the ledgers of the env's nodes by checksumming their block
+    /// hashes.
+    LedgerChecksum {
+        /// The height of the last block to checksum. Defaults to the lowest
+        /// latest height reported by the nodes.
+        #[clap(long)]
+        height: Option<u32>,
+    },
+
+    /// Wait until the env's chain reaches a block height.
+    Wait {
+        /// The height to wait for.
+        #[clap(long)]
+        height: u32,
+        /// Seconds to wait before exiting with an error. Waits forever when
+        /// absent.
+        #[clap(long)]
+        timeout: Option<u64>,
+    },
+
+    /// Replay a recorded transaction sink file into a cannon's broadcast
+    /// endpoint. Files rotated from the sink file (`<file>.1`, `<file>.2`,
+    /// ...) are replayed after it, in order.
+    Replay {
+        /// Desired cannon to broadcast the transactions
+        #[clap(long, short, default_value = "default")]
+        cannon: CannonId,
+        /// Transactions to send per second
+        #[clap(long, short, default_value_t = 1.0)]
+        rate: f64,
+        /// The sink file containing one transaction per line
+        #[clap(value_hint = ValueHint::FilePath)]
+        file: PathBuf,
+    },
+
+    /// Record the env's transaction status transitions for offline latency
+    /// analysis.
+    #[clap(subcommand)]
+    TxRecord(TxRecordCommands),
+
     /// Lookup a transaction's block by a transaction id.
     #[clap(alias = "tx")]
     Transaction { id: String },
@@ -90,7 +208,12 @@ enum EnvCommands {
     /// List all environments.
     /// Ignores the env id.
     #[clap(alias = "ls")]
-    List,
+    List {
+        /// List a summary of each environment with its network, storage,
+        /// node and agent counts, and block height.
+        #[clap(long)]
+        json: bool,
+    },
 
     /// Show the current topology of a specific environment.
     #[clap(alias = "top")]
@@ -101,15 +224,68 @@ enum EnvCommands {
     #[clap(alias = "top-res")]
     TopologyResolved,
 
+    /// Render the configured peer and validator connections of a specific
+    /// environment as a Graphviz DOT graph.
+    #[clap(alias = "graph")]
+    TopologyGraph {
+        /// Print the node adjacency as JSON instead of DOT.
+        #[clap(long)]
+        json: bool,
+    },
+
     /// Apply an environment spec.
     #[clap(alias = "p")]
     Apply {
-        /// The environment spec file.
+        /// The environment spec file, `-` for stdin, or an http(s) url.
         #[clap(value_hint = ValueHint::AnyPath)]
-        spec: FileOrStdin<String>,
+        spec: SpecSource,
         /// When present, don't wait for reconciles to finish before returning
         #[clap(long = "async")]
         async_mode: bool,
+        /// Reconcile agents even when the spec is unchanged since the last
+        /// apply.
+        #[clap(long)]
+        force: bool,
+        /// Don't restart running nodes to apply the spec. Each node picks up
+        /// its new state the next time it goes offline.
+        #[clap(long)]
+        only_if_offline: bool,
+    },
+
+    /// Print a spec that re-creates the environment, for applying it on
+    /// another control plane with `import`.
+    Export,
+
+    /// Apply a spec exported by `export`, creating the environment on this
+    /// control plane.
+    Import {
+        /// The exported spec file, `-` for stdin, or an http(s) url.
+        #[clap(value_hint = ValueHint::AnyPath)]
+        spec: SpecSource,
+        /// When present, don't wait for reconciles to finish before returning
+        #[clap(long = "async")]
+        async_mode: bool,
+    },
+
+    /// Add the nodes of a spec's nodes document to the running environment
+    /// without re-applying the rest of its spec. Existing nodes are not
+    /// redelegated.
+    Scale {
+        /// The spec file with the nodes to add, `-` for stdin, or an http(s)
+        /// url.
+        #[clap(value_hint = ValueHint::AnyPath)]
+        spec: SpecSource,
+        /// When present, don't wait for reconciles to finish before returning
+        #[clap(long = "async")]
+        async_mode: bool,
+    },
+
+    /// Compare an environment spec against the running environment without
+    /// applying it.
+    Diff {
+        /// The environment spec file, `-` for stdin, or an http(s) url.
+        #[clap(value_hint = ValueHint::AnyPath)]
+        spec: SpecSource,
     },
 
     /// Lookup a mapping by program id and mapping name.
@@ -136,15 +312,49 @@ enum EnvCommands {
 
 impl Env {
     pub async fn run(self, url: &str, client: Client) -> Result<Response> {
-        let id = self.id;
         use EnvCommands::*;
-        Ok(match self.command {
+        // applied, imported, and diffed envs may not exist yet, and listing
+        // ignores the env id
+        if matches!(
+            self.command,
+            Apply { .. } | Import { .. } | Diff { .. } | List { .. }
+        ) {
+            return self.command.run(url, self.id, client).await;
+        }
+
+        // the id is only resolved when the control plane does not know it
+        let res = self
+            .command
+            .clone()
+            .run(url, self.id, client.clone())
+            .await?;
+        if res.status() != StatusCode::NOT_FOUND {
+            return Ok(res);
+        }
+        match resolve_id(url, &client, IdKind::Env, self.id).await? {
+            id if id == self.id => Ok(res),
+            id => self.command.run(url, id, client).await,
+        }
+    }
+}
+
+impl EnvCommands {
+    async fn run(self, url: &str, id: EnvId, client: Client) -> Result<Response> {
+        use EnvCommands::*;
+        Ok(match self {
             Action(action) => action.execute(url, id, client).await?,
-            Agent { key } => {
-                let ep = format!("{url}/api/v1/env/{id}/agents/{key}");
+            Agent { key, command } => match command {
+                None => {
+                    let ep = format!("{url}/api/v1/env/{id}/agents/{key}");
 
-                client.get(ep).send().await?
-            }
+                    client.get(ep).send().await?
+                }
+                Some(AgentCommands::Exec { args }) => {
+                    let ep = format!("{url}/api/v1/env/{id}/agents/{key}/exec");
+
+                    client.post(ep).json(&args).send().await?
+                }
+            },
             Agents => {
                 let ep = format!("{url}/api/v1/env/{id}/agents");
 
@@ -170,6 +380,54 @@ impl Env {
                     std::process::exit(0);
                 }
             }
+            Cannon(CannonCommands::Create {
+                cannon,
+                payload,
+                seed,
+            }) => {
+                let ep = format!("{url}/api/v1/env/{id}/cannons/{cannon}");
+                let mut payload = payload.contents()?;
+                if let Some(seed) = seed {
+                    let Some(source) = payload.get_mut("source").and_then(Value::as_object_mut)
+                    else {
+                        anyhow::bail!("--seed requires the cannon payload to have a `source`");
+                    };
+                    source.insert("seed".to_owned(), seed.into());
+                }
+
+                client.post(ep).json(&payload).send().await?
+            }
+            Cannon(CannonCommands::Delete { cannon }) => {
+                let ep = format!("{url}/api/v1/env/{id}/cannons/{cannon}");
+
+                client.delete(ep).send().await?
+            }
+            RestartCannon { cannon } => {
+                let ep = format!("{url}/api/v1/env/{id}/cannons/{cannon}/restart");
+
+                client.post(ep).send().await?
+            }
+            TxRecord(TxRecordCommands::Start) => {
+                let ep = format!("{url}/api/v1/env/{id}/transactions/record");
+
+                client.post(ep).send().await?
+            }
+            TxRecord(TxRecordCommands::Stop) => {
+                let ep = format!("{url}/api/v1/env/{id}/transactions/record");
+
+                client.delete(ep).send().await?
+            }
+            TxRecord(TxRecordCommands::Download) => {
+                let ep = format!("{url}/api/v1/env/{id}/transactions/record");
+                let res = client.get(ep).send().await?;
+
+                if !res.status().is_success() {
+                    res
+                } else {
+                    print!("{}", res.text().await?);
+                    std::process::exit(0);
+                }
+            }
             Balance { address: key } => {
                 let ep = format!("{url}/api/v1/env/{id}/balance/{key}");
 
@@ -190,10 +448,10 @@ impl Env {
 
                 client.get(ep).send().await?
             }
-            List => {
+            List { json } => {
                 let ep = format!("{url}/api/v1/env/list");
 
-                client.get(ep).send().await?
+                client.get(ep).query(&[("summary", json)]).send().await?
             }
             Topology => {
                 let ep = format!("{url}/api/v1/env/{id}/topology");
@@ -205,9 +463,48 @@ impl Env {
 
                 client.get(ep).send().await?
             }
-            Apply { spec, async_mode } => {
+            TopologyGraph { json } => {
+                let ep = format!("{url}/api/v1/env/{id}/topology/graph");
+                let res = client.get(ep).send().await?;
+
+                if json || !res.status().is_success() {
+                    res
+                } else {
+                    let graph = res.json::<BTreeMap<String, NodeAdjacency>>().await?;
+                    print!("{}", topology_dot(id, &graph));
+                    std::process::exit(0);
+                }
+            }
+            Apply {
+                spec,
+                async_mode,
+                force,
+                only_if_offline,
+            } => {
+                let ep = format!("{url}/api/v1/env/{id}/apply");
+                let query = [("force", force), ("only_if_offline", only_if_offline)];
+                let req = spec.attach(&client, client.post(ep).query(&query)).await?;
+                if async_mode {
+                    req.send().await?
+                } else {
+                    post_and_wait(url, req, id).await?;
+                    std::process::exit(0);
+                }
+            }
+            Export => {
+                let ep = format!("{url}/api/v1/env/{id}/export");
+                let res = client.get(ep).send().await?;
+
+                if !res.status().is_success() {
+                    res
+                } else {
+                    print!("{}", res.text().await?);
+                    std::process::exit(0);
+                }
+            }
+            Import { spec, async_mode } => {
                 let ep = format!("{url}/api/v1/env/{id}/apply");
-                let req = client.post(ep).body(spec.contents()?);
+                let req = spec.attach(&client, client.post(ep)).await?;
                 if async_mode {
                     req.send().await?
                 } else {
@@ -215,6 +512,21 @@ impl Env {
                     std::process::exit(0);
                 }
             }
+            Scale { spec, async_mode } => {
+                let ep = format!("{url}/api/v1/env/{id}/scale");
+                let req = spec.attach(&client, client.post(ep)).await?;
+                if async_mode {
+                    req.send().await?
+                } else {
+                    post_and_wait(url, req, id).await?;
+                    std::process::exit(0);
+                }
+            }
+            Diff { spec } => {
+                let ep = format!("{url}/api/v1/env/{id}/diff");
+
+                spec.attach(&client, client.post(ep)).await?.send().await?
+            }
             Mapping {
                 program,
                 mapping,
@@ -251,6 +563,10 @@ impl Env {
 
                 client.get(ep).send().await?
             }
+            Replay { cannon, rate, file } => {
+                replay_sink(url, &client, id, cannon, rate, file).await?;
+                std::process::exit(0);
+            }
             Transaction { id: hash } => {
                 let ep = format!("{url}/api/v1/env/{id}/transaction_block/{hash}");
 
@@ -266,10 +582,221 @@ impl Env {
 
                 client.get(ep).send().await?
             }
+            LedgerChecksum { height } => {
+                let ep = format!("{url}/api/v1/env/{id}/ledger/checksum");
+                let mut req = client.get(ep);
+                if let Some(height) = height {
+                    req = req.query(&[("height", height)]);
+                }
+
+                req.send().await?
+            }
+            Wait { height, timeout } => {
+                let wait = wait_for_height(url, &client, id, height);
+                match timeout {
+                    Some(secs) => tokio::time::timeout(Duration::from_secs(secs), wait)
+                        .await
+                        .map_err(|_| {
+                            anyhow::anyhow!("env {id} did not reach height {height} in {secs}s")
+                        })??,
+                    None => wait.await?,
+                }
+                std::process::exit(0);
+            }
         })
     }
 }
 
+/// Render a node adjacency map as a Graphviz DOT graph. Nodes are colored by
+/// type, and validator connections are drawn in bold red.
+fn topology_dot(env_id: EnvId, graph: &BTreeMap<String, NodeAdjacency>) -> String {
+    let mut dot = format!("digraph \"{env_id}\" {{\n");
+
+    for (key, node) in graph {
+        let color = match key.parse::<NodeKey>().map(|key| key.ty) {
+            Ok(NodeType::Validator) => "salmon",
+            Ok(NodeType::Client) => "lightblue",
+            Ok(NodeType::Prover) => "palegreen",
+            Err(_) => "white",
+        };
+        let style = if node.external {
+            "filled,dashed"
+        } else {
+            "filled"
+        };
+        dot.push_str(&format!(
+            "  \"{key}\" [style=\"{style}\", fillcolor={color}];\n"
+        ));
+    }
+
+    for (key, node) in graph {
+        for peer in &node.peers {
+            dot.push_str(&format!("  \"{key}\" -> \"{peer}\";\n"));
+        }
+        for validator in &node.validators {
+            dot.push_str(&format!(
+                "  \"{key}\" -> \"{validator}\" [color=red, style=bold];\n"
+            ));
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Poll an env's cached block info until it reaches the given height.
+async fn wait_for_height(url: &str, client: &Client, env_id: EnvId, height: u32) -> Result<()> {
+    // fail fast when the env does not exist, as the block info is missing
+    // until the env produces its first block
+    client
+        .get(format!("{url}/api/v1/env/{env_id}/info"))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let ep = format!("{url}/api/v1/env/{env_id}/block_info");
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+
+        let res = client.get(&ep).send().await?;
+        if !res.status().is_success() {
+            continue;
+        }
+        let info: LatestBlockInfo = res.json().await?;
+        if info.height >= height {
+            println!("env {env_id} reached height {}", info.height);
+            return Ok(());
+        }
+    }
+}
+
+/// Print the confirmation latencies of each cannon in a transaction record
+pub(super) fn analyze_tx_record(cannon: Option<CannonId>, file: PathBuf) -> Result<()> {
+    use std::io::BufRead;
+
+    let mut records = Vec::new();
+    for file in rotated_sink_files(file) {
+        let reader = std::io::BufReader::new(std::fs::File::open(&file)?);
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str::<TransactionRecord>(&line) {
+                Ok(record) if cannon.is_none_or(|c| c == record.cannon) => records.push(record),
+                Ok(_) => {}
+                Err(e) => eprintln!("warning: {}:{}: {e}", file.display(), i + 1),
+            }
+        }
+    }
+
+    let summary = analyze_records(records)
+        .into_iter()
+        .map(|(cannon, latencies)| (cannon.to_string(), latencies))
+        .collect::<BTreeMap<_, _>>();
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&serde_json::json!({
+            "buckets_secs": LATENCY_BUCKETS_SECS,
+            "cannons": summary,
+        }))?
+    );
+    Ok(())
+}
+
+/// Get a sink file followed by the files rotated from it, in the order they
+/// were written.
+fn rotated_sink_files(file: PathBuf) -> Vec<PathBuf> {
+    let mut files = vec![file];
+    loop {
+        let mut name = files[0].as_os_str().to_owned();
+        name.push(format!(".{}", files.len()));
+        let rotated = PathBuf::from(name);
+        if !rotated.exists() {
+            return files;
+        }
+        files.push(rotated);
+    }
+}
+
+/// Stream each line of a transaction sink file and the files rotated from it
+/// into a cannon's broadcast endpoint at a fixed rate, skipping lines that are
+/// not valid transactions.
+async fn replay_sink(
+    url: &str,
+    client: &Client,
+    env_id: EnvId,
+    cannon: CannonId,
+    rate: f64,
+    file: PathBuf,
+) -> Result<()> {
+    use tokio::io::AsyncBufReadExt;
+
+    anyhow::ensure!(
+        rate.is_finite() && rate > 0.0,
+        "rate must be a positive number"
+    );
+
+    let info: EnvInfo = client
+        .get(format!("{url}/api/v1/env/{env_id}/info"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let ep = format!(
+        "{url}/api/v1/env/{env_id}/cannons/{cannon}/{}/transaction/broadcast",
+        info.network
+    );
+
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+    let (mut sent, mut skipped, mut failed) = (0usize, 0usize, 0usize);
+
+    for file in rotated_sink_files(file) {
+        let name = file.display();
+        let mut lines = tokio::io::BufReader::new(tokio::fs::File::open(&file).await?).lines();
+
+        let mut line_num = 0usize;
+        while let Some(line) = lines.next_line().await? {
+            line_num += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let tx = match serde_json::from_str::<Value>(&line) {
+                Ok(tx) if tx.get("id").is_some_and(Value::is_string) => tx,
+                Ok(_) => {
+                    skipped += 1;
+                    eprintln!(
+                        "warning: {name}:{line_num}: missing transaction id ({skipped} skipped)"
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    skipped += 1;
+                    eprintln!("warning: {name}:{line_num}: {e} ({skipped} skipped)");
+                    continue;
+                }
+            };
+
+            interval.tick().await;
+            let res = client.post(&ep).json(&tx).send().await?;
+            if res.status().is_success() {
+                sent += 1;
+            } else {
+                failed += 1;
+                eprintln!("{name}:{line_num}: broadcast failed: {}", res.status());
+            }
+        }
+    }
+
+    println!("replayed {sent} transactions ({skipped} skipped, {failed} failed)");
+    Ok(())
+}
+
 pub async fn post_and_wait(url: &str, req: RequestBuilder, env_id: EnvId) -> Result<()> {
     use snops_common::events::EventFilter::*;
     use snops_common::events::EventKindFilter::*;
@@ -302,9 +829,17 @@ pub async fn post_and_wait(url: &str, req: RequestBuilder, env_id: EnvId) -> Res
         return Ok(());
     }
 
+    // no reconciles will happen when the applied spec is unchanged
+    let unchanged = res.headers().contains_key(HEADER_ENV_UNCHANGED);
+
     let mut node_map: HashMap<NodeKey, AgentId> = res.json().await?;
     println!("{}", serde_json::to_string_pretty(&node_map)?);
 
+    if unchanged {
+        eprintln!("environment unchanged");
+        return events.close().await;
+    }
+
     let filter = node_map
         .values()
         .copied()
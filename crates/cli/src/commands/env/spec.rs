@@ -0,0 +1,278 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result, bail};
+use clap_stdin::FileOrStdin;
+use reqwest::{Client, RequestBuilder, Url, header::CONTENT_TYPE};
+use serde::Deserialize;
+use serde_yaml::Value;
+
+/// Largest environment spec that will be fetched from a url.
+const MAX_REMOTE_SPEC_SIZE: u64 = 16 * 1024 * 1024;
+/// Version of the documents that are replaced by another spec.
+const INCLUDE_VERSION: &str = "include.snarkos.testing.monadic.us/v1";
+/// Maximum number of nested includes, matching the control plane.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// An environment spec read from a file, stdin (`-`), or an http(s) url.
+#[derive(Clone, Debug)]
+pub enum SpecSource {
+    Url(Url),
+    /// The spec, and the absolute path of the spec when it is a file
+    Local(FileOrStdin<String>, Option<PathBuf>),
+}
+
+impl FromStr for SpecSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Url::parse(s)
+                .map(Self::Url)
+                .map_err(|e| format!("invalid spec url: {e}"));
+        }
+
+        let path = (s != "-").then(|| std::path::absolute(s).ok()).flatten();
+        FileOrStdin::from_str(s)
+            .map(|spec| Self::Local(spec, path))
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl SpecSource {
+    /// Send the spec as the body of a request. The relative includes of a
+    /// spec from a url are resolved against the url by the control plane.
+    pub async fn attach(self, client: &Client, mut req: RequestBuilder) -> Result<RequestBuilder> {
+        if let SpecSource::Url(url) = &self {
+            req = req.query(&[("base", url.as_str())]);
+        }
+        Ok(req.body(self.contents(client).await?))
+    }
+
+    /// Read the contents of the spec. The includes of a local spec that refer
+    /// to files are replaced by the documents of those files.
+    pub async fn contents(self, client: &Client) -> Result<String> {
+        match self {
+            SpecSource::Local(spec, path) => {
+                // includes in a spec from stdin are relative to the working directory
+                let dir = match path.as_deref().and_then(Path::parent) {
+                    Some(dir) => dir.to_path_buf(),
+                    None => std::env::current_dir()?,
+                };
+                let mut chain = path.into_iter().collect();
+                inline_local_includes(&spec.contents()?, &dir, &mut chain)
+            }
+            SpecSource::Url(url) => fetch_spec(client, url).await,
+        }
+    }
+}
+
+/// The path of an include document that refers to a file.
+fn local_include(document: &Value) -> Option<&str> {
+    if document.get("version")?.as_str()? != INCLUDE_VERSION {
+        return None;
+    }
+    let include = document.get("include")?.as_str()?;
+    (!(include.starts_with("http://") || include.starts_with("https://"))).then_some(include)
+}
+
+/// Replace the include documents of a spec that refer to files with the
+/// documents of those files, as the control plane may run on another machine.
+/// Url includes are left for the control plane to resolve. Relative paths are
+/// resolved against `dir`, the directory of the including spec, and `chain`
+/// holds the specs being inlined.
+fn inline_local_includes(spec: &str, dir: &Path, chain: &mut Vec<PathBuf>) -> Result<String> {
+    let documents = parse_documents(spec)?;
+    // specs without local includes are sent as they are
+    if !documents.iter().any(|doc| local_include(doc).is_some()) {
+        return Ok(spec.to_owned());
+    }
+
+    let mut inlined = String::new();
+    for document in inline_documents(documents, dir, chain)? {
+        inlined.push_str("---\n");
+        inlined.push_str(&serde_yaml::to_string(&document)?);
+    }
+    Ok(inlined)
+}
+
+fn parse_documents(spec: &str) -> Result<Vec<Value>> {
+    let documents = serde_yaml::Deserializer::from_str(spec)
+        .map(Value::deserialize)
+        .collect::<Result<Vec<_>, _>>()
+        .context("failed to parse spec")?;
+    Ok(documents.into_iter().filter(|doc| !doc.is_null()).collect())
+}
+
+fn inline_documents(
+    documents: Vec<Value>,
+    dir: &Path,
+    chain: &mut Vec<PathBuf>,
+) -> Result<Vec<Value>> {
+    let mut inlined = Vec::with_capacity(documents.len());
+
+    for document in documents {
+        let Some(include) = local_include(&document) else {
+            inlined.push(document);
+            continue;
+        };
+
+        let path = dir.join(include);
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("failed to read included spec {}", path.display()))?;
+        if chain.contains(&path) {
+            bail!("included spec {} includes itself", path.display());
+        }
+        if chain.len() >= MAX_INCLUDE_DEPTH {
+            bail!(
+                "included spec {} exceeds the maximum include depth of {MAX_INCLUDE_DEPTH}",
+                path.display()
+            );
+        }
+
+        let spec = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read included spec {}", path.display()))?;
+        let documents = parse_documents(&spec)
+            .with_context(|| format!("in included spec {}", path.display()))?;
+
+        let include_dir = path.parent().unwrap_or(dir).to_path_buf();
+        chain.push(path);
+        inlined.extend(inline_documents(documents, &include_dir, chain)?);
+        chain.pop();
+    }
+
+    Ok(inlined)
+}
+
+async fn fetch_spec(client: &Client, url: Url) -> Result<String> {
+    let mut res = client
+        .get(url.clone())
+        .send()
+        .await
+        .with_context(|| format!("failed to fetch spec from {url}"))?;
+
+    let status = res.status();
+    if !status.is_success() {
+        bail!("failed to fetch spec from {url}: {status}");
+    }
+
+    // specs are yaml, but artifact stores often serve them as plain text or
+    // as an opaque file
+    if let Some(content_type) = res.headers().get(CONTENT_TYPE) {
+        let content_type = content_type.to_str().unwrap_or_default();
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if !(mime.contains("yaml")
+            || mime.starts_with("text/")
+            || mime == "application/octet-stream")
+        {
+            bail!("spec from {url} has unexpected content type `{content_type}`");
+        }
+    }
+
+    if res
+        .content_length()
+        .is_some_and(|len| len > MAX_REMOTE_SPEC_SIZE)
+    {
+        bail!("spec from {url} exceeds {MAX_REMOTE_SPEC_SIZE} bytes");
+    }
+
+    // the content length is not always present, so the limit is also enforced
+    // while reading the body
+    let mut body = Vec::new();
+    while let Some(chunk) = res
+        .chunk()
+        .await
+        .with_context(|| format!("failed to read spec from {url}"))?
+    {
+        if (body.len() + chunk.len()) as u64 > MAX_REMOTE_SPEC_SIZE {
+            bail!("spec from {url} exceeds {MAX_REMOTE_SPEC_SIZE} bytes");
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    String::from_utf8(body).with_context(|| format!("spec from {url} is not utf-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_spec(dir: &Path, name: &str, spec: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, spec).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_inline_local_includes() {
+        let dir = std::env::temp_dir().join(format!("snops-cli-include-{}", std::process::id()));
+        write_spec(
+            &dir,
+            "shared/storage.yaml",
+            "version: storage.snarkos.testing.monadic.us/v1\nid: base\n",
+        );
+        write_spec(
+            &dir,
+            "shared/include.yaml",
+            "version: include.snarkos.testing.monadic.us/v1\ninclude: storage.yaml\n",
+        );
+        let spec = "version: include.snarkos.testing.monadic.us/v1
+include: shared/include.yaml
+---
+version: include.snarkos.testing.monadic.us/v1
+include: https://example.com/cannon.yaml
+";
+
+        let inlined = inline_local_includes(spec, &dir, &mut vec![]).unwrap();
+        let documents = parse_documents(&inlined).unwrap();
+        assert_eq!(documents.len(), 2);
+        // nested includes are resolved against the including spec's directory
+        assert_eq!(
+            documents[0].get("version").and_then(Value::as_str),
+            Some("storage.snarkos.testing.monadic.us/v1")
+        );
+        // url includes are left for the control plane
+        assert_eq!(
+            documents[1].get("include").and_then(Value::as_str),
+            Some("https://example.com/cannon.yaml")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_inline_include_cycle() {
+        let dir =
+            std::env::temp_dir().join(format!("snops-cli-include-cycle-{}", std::process::id()));
+        let spec = write_spec(
+            &dir,
+            "a.yaml",
+            "version: include.snarkos.testing.monadic.us/v1\ninclude: b.yaml\n",
+        );
+        write_spec(
+            &dir,
+            "b.yaml",
+            "version: include.snarkos.testing.monadic.us/v1\ninclude: a.yaml\n",
+        );
+
+        let mut chain = vec![spec.canonicalize().unwrap()];
+        let contents = std::fs::read_to_string(&spec).unwrap();
+        let err = inline_local_includes(&contents, &dir, &mut chain).unwrap_err();
+        assert!(err.to_string().contains("includes itself"), "{err}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_spec_without_local_includes_is_unchanged() {
+        let spec = "# a comment\nversion: storage.snarkos.testing.monadic.us/v1\nid: base\n";
+        assert_eq!(
+            inline_local_includes(spec, Path::new("."), &mut vec![]).unwrap(),
+            spec
+        );
+    }
+}
@@ -1,7 +1,16 @@
-use anyhow::Result;
-use clap::{CommandFactory, Parser};
+use std::{
+    io::{BufRead, IsTerminal, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Result, bail};
+use clap::{CommandFactory, Parser, ValueHint};
+use reqwest::Client;
 use serde_json::Value;
-use snops_common::events::EventFilter;
+use snops_common::{
+    events::EventFilter,
+    state::{CannonId, InternedId},
+};
 
 use crate::{Cli, events::EventsClient};
 
@@ -10,6 +19,77 @@ pub(crate) static DUMMY_ID: &str = "dummy_value___";
 
 mod agent;
 mod env;
+mod storage;
+
+/// The kinds of ids the control plane can resolve from a partial id.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum IdKind {
+    Env,
+    Agent,
+}
+
+impl IdKind {
+    fn route(self) -> &'static str {
+        match self {
+            IdKind::Env => "env",
+            IdKind::Agent => "agents",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            IdKind::Env => "env",
+            IdKind::Agent => "agent",
+        }
+    }
+}
+
+/// Resolve a partial or mistyped id against the ids known to the control
+/// plane, for ids that were not found. Exact and unknown ids are returned as
+/// is. A single close match is used after confirming it at a terminal, and
+/// several matches fail with suggestions.
+pub(crate) async fn resolve_id(
+    url: &str,
+    client: &Client,
+    kind: IdKind,
+    id: InternedId,
+) -> Result<InternedId> {
+    let res = client
+        .get(format!("{url}/api/v1/{}/resolve", kind.route()))
+        .query(&[("id", id.to_string())])
+        .send()
+        .await?;
+    // control planes without id resolution only accept exact ids
+    if !res.status().is_success() {
+        return Ok(id);
+    }
+
+    let name = kind.name();
+    let candidates: Vec<InternedId> = res.json().await?;
+    match candidates.as_slice() {
+        [] => Ok(id),
+        [candidate] if *candidate == id => Ok(id),
+        [candidate] if std::io::stdin().is_terminal() => {
+            eprint!("{name} `{id}` not found, did you mean `{candidate}`? [y/N] ");
+            std::io::stderr().flush()?;
+
+            let mut answer = String::new();
+            std::io::stdin().lock().read_line(&mut answer)?;
+            if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                bail!("{name} `{id}` not found");
+            }
+            Ok(*candidate)
+        }
+        candidates => bail!(
+            "{name} `{id}` not found, did you mean: {}",
+            candidates
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
 
 #[derive(Debug, Parser)]
 pub enum Commands {
@@ -23,6 +103,8 @@ pub enum Commands {
     Agent(agent::Agent),
     #[clap(alias = "e")]
     Env(env::Env),
+    #[clap(alias = "s")]
+    Storage(storage::Storage),
     SetLogLevel {
         level: String,
     },
@@ -33,6 +115,18 @@ pub enum Commands {
         #[clap(default_value = "unfiltered")]
         filter: EventFilter,
     },
+    /// Summarize the confirmation latencies of a transaction record,
+    /// downloaded with `env tx-record download`, per cannon. Files rotated
+    /// from the record (`<file>.1`, `<file>.2`, ...) are read after it, in
+    /// order.
+    AnalyzeTxRecord {
+        /// Only include the transactions of this cannon.
+        #[clap(long, short)]
+        cannon: Option<CannonId>,
+        /// The downloaded transaction record.
+        #[clap(value_hint = ValueHint::FilePath)]
+        file: PathBuf,
+    },
     #[cfg(feature = "mangen")]
     Man(snops_common::mangen::Mangen),
     #[cfg(feature = "clipages")]
@@ -53,6 +147,7 @@ impl Commands {
             }
             Commands::Agent(agent) => agent.run(url, client).await,
             Commands::Env(env) => env.run(url, client).await,
+            Commands::Storage(storage) => storage.run(url, client).await,
             Commands::SetLogLevel { level } => {
                 client
                     .post(format!("{url}/api/v1/log/{level}"))
@@ -68,6 +163,10 @@ impl Commands {
                 client.close().await?;
                 return Ok(());
             }
+            Commands::AnalyzeTxRecord { cannon, file } => {
+                env::analyze_tx_record(cannon, file)?;
+                return Ok(());
+            }
             #[cfg(feature = "mangen")]
             Commands::Man(mangen) => {
                 mangen.run(
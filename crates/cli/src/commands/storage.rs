@@ -0,0 +1,39 @@
+use anyhow::Result;
+use clap::{Parser, ValueHint};
+use reqwest::{Client, Response};
+use snops_common::state::{NetworkId, StorageId};
+
+/// For interacting with snop storages.
+#[derive(Debug, Parser)]
+pub struct Storage {
+    #[clap(subcommand)]
+    command: StorageCommands,
+}
+
+/// Storage commands.
+#[derive(Debug, Parser)]
+enum StorageCommands {
+    /// Inspect a loaded storage's accounts, checkpoints, and binaries.
+    #[clap(alias = "i")]
+    Inspect {
+        /// The network the storage belongs to.
+        #[clap(value_hint = ValueHint::Other)]
+        network: NetworkId,
+        /// The storage id.
+        #[clap(value_hint = ValueHint::Other)]
+        id: StorageId,
+    },
+}
+
+impl Storage {
+    pub async fn run(self, url: &str, client: Client) -> Result<Response> {
+        use StorageCommands::*;
+        Ok(match self.command {
+            Inspect { network, id } => {
+                let ep = format!("{url}/api/v1/storage/{network}/{id}");
+
+                client.get(ep).send().await?
+            }
+        })
+    }
+}
@@ -67,7 +67,7 @@ pub struct ExecuteAction {
     pub inputs: Vec<AleoValue>,
     /// The optional priority fee
     #[serde(default)]
-    pub priority_fee: Option<u64>,
+    pub priority_fee: Option<PriorityFee>,
     /// The optional fee record for a private fee
     #[serde(default)]
     pub fee_record: Option<String>,
@@ -90,7 +90,7 @@ pub struct DeployAction {
     pub cannon: String,
     /// The optional priority fee
     #[serde(default)]
-    pub priority_fee: Option<u64>,
+    pub priority_fee: Option<PriorityFee>,
     /// The optional fee record for a private fee
     #[serde(default)]
     pub fee_record: Option<String>,
@@ -115,6 +115,96 @@ impl FromStr for AleoValue {
     }
 }
 
+/// The default fraction of the fee payer's public balance used for an `auto`
+/// priority fee.
+pub const DEFAULT_AUTO_PRIORITY_FEE: f64 = 0.01;
+
+/// A priority fee in microcredits, or a fraction of the fee payer's public
+/// balance resolved by the control plane (`auto` or `auto:0.05`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriorityFee {
+    Amount(u64),
+    Auto(f64),
+}
+
+impl PriorityFee {
+    /// Resolve the priority fee given the fee payer's public balance.
+    pub fn resolve(self, balance: u64) -> u64 {
+        match self {
+            PriorityFee::Amount(amount) => amount,
+            PriorityFee::Auto(fraction) => (balance as f64 * fraction) as u64,
+        }
+    }
+}
+
+impl FromStr for PriorityFee {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(auto) = s.strip_prefix("auto") else {
+            return s
+                .parse()
+                .map(PriorityFee::Amount)
+                .map_err(|e| format!("invalid priority fee `{s}`: {e}"));
+        };
+
+        let fraction = match auto.strip_prefix(':') {
+            Some(fraction) => fraction
+                .parse::<f64>()
+                .map_err(|e| format!("invalid priority fee fraction `{fraction}`: {e}"))?,
+            None if auto.is_empty() => DEFAULT_AUTO_PRIORITY_FEE,
+            None => return Err(format!("invalid priority fee `{s}`")),
+        };
+
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(format!(
+                "priority fee fraction must be in (0, 1], got {fraction}"
+            ));
+        }
+        Ok(PriorityFee::Auto(fraction))
+    }
+}
+
+impl std::fmt::Display for PriorityFee {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PriorityFee::Amount(amount) => write!(f, "{amount}"),
+            PriorityFee::Auto(fraction) => write!(f, "auto:{fraction}"),
+        }
+    }
+}
+
+impl Serialize for PriorityFee {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            PriorityFee::Amount(amount) => serializer.serialize_u64(*amount),
+            PriorityFee::Auto(_) => serializer.serialize_str(&self.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PriorityFee {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Amount(u64),
+            Str(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Amount(amount) => Ok(PriorityFee::Amount(amount)),
+            Raw::Str(s) => s.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Reconfig {
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -134,3 +224,41 @@ pub struct Reconfig {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub del_env: Option<IndexSet<String>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_fee_parse() {
+        assert_eq!("100".parse(), Ok(PriorityFee::Amount(100)));
+        assert_eq!(
+            "auto".parse(),
+            Ok(PriorityFee::Auto(DEFAULT_AUTO_PRIORITY_FEE))
+        );
+        assert_eq!("auto:0.5".parse(), Ok(PriorityFee::Auto(0.5)));
+        assert!("auto:0".parse::<PriorityFee>().is_err());
+        assert!("auto:2".parse::<PriorityFee>().is_err());
+        assert!("automatic".parse::<PriorityFee>().is_err());
+        assert!("-1".parse::<PriorityFee>().is_err());
+
+        assert_eq!(PriorityFee::Auto(0.5).resolve(1000), 500);
+        assert_eq!(PriorityFee::Amount(7).resolve(1000), 7);
+    }
+
+    #[test]
+    fn test_priority_fee_serde() {
+        assert_eq!(
+            serde_json::from_str::<PriorityFee>("100").unwrap(),
+            PriorityFee::Amount(100)
+        );
+        assert_eq!(
+            serde_json::from_str::<PriorityFee>("\"auto:0.25\"").unwrap(),
+            PriorityFee::Auto(0.25)
+        );
+        assert_eq!(
+            serde_json::to_string(&PriorityFee::Auto(0.25)).unwrap(),
+            "\"auto:0.25\""
+        );
+    }
+}
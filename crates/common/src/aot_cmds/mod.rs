@@ -1,5 +1,7 @@
 use std::{io, path::PathBuf, process::Stdio};
 
+use indexmap::IndexMap;
+use serde::Deserialize;
 use tokio::{
     io::AsyncWriteExt,
     process::{Child, Command},
@@ -19,6 +21,13 @@ pub struct AotCmd {
     network: NetworkId,
 }
 
+/// The input and output types of a program's function, i.e. `u64.public`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProgramFunction {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
 type Output = io::Result<std::process::Output>;
 impl AotCmd {
     pub fn new(bin: PathBuf, network: NetworkId) -> Self {
@@ -247,6 +256,8 @@ impl AotCmd {
     pub async fn execute(&self, auth: Authorization, query: String) -> Result<String, AotCmdError> {
         let mut command = Command::new(&self.bin);
         command
+            // the execution is killed when it is cancelled
+            .kill_on_drop(true)
             .env("NETWORK", self.network.to_string())
             .arg("auth")
             .arg("execute")
@@ -282,6 +293,57 @@ impl AotCmd {
         )
     }
 
+    /// List the functions of a program and their input and output types
+    pub async fn program_functions(
+        &self,
+        program: &str,
+    ) -> Result<IndexMap<String, ProgramFunction>, AotCmdError> {
+        let mut command = Command::new(&self.bin);
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(std::io::stderr())
+            .env("NETWORK", self.network.to_string())
+            .arg("program")
+            .arg("functions")
+            .arg("--json")
+            .arg("-");
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| CommandError::action("spawning", "aot program functions", e))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(program.as_bytes()).await.map_err(|e| {
+                CommandError::action("writing to", "aot program functions stdin", e)
+            })?;
+        }
+
+        Self::handle_output(
+            child.wait_with_output().await,
+            "output",
+            "aot program functions",
+            |bytes| Ok(serde_json::from_slice(&bytes)?),
+        )
+    }
+
+    /// Derive the address of a private key
+    pub async fn address(&self, private_key: &str) -> Result<String, AotCmdError> {
+        let mut command = Command::new(&self.bin);
+        command
+            .env("NETWORK", self.network.to_string())
+            .arg("address")
+            .arg(private_key);
+
+        Self::handle_output(
+            command.output().await,
+            "output",
+            "aot address",
+            Self::parse_string,
+        )
+        .map(|s| s.trim().to_string())
+    }
+
     pub async fn get_tx_id(&self, auth: &Authorization) -> Result<String, AotCmdError> {
         let mut command = Command::new(&self.bin);
         command
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use snops_checkpoint::RetentionPolicy;
@@ -6,7 +7,7 @@ use crate::{
     binaries::BinaryEntry,
     format::{DataFormat, DataHeaderOf},
     prelude::StorageId,
-    state::{InternedId, LatestBlockInfo, NetworkId},
+    state::{InternedId, LatestBlockInfo, NetworkId, NodeKey},
 };
 
 /// Metadata about a checkpoint file
@@ -22,6 +23,54 @@ pub struct EnvInfo {
     pub network: NetworkId,
     pub storage: StorageInfo,
     pub block: Option<LatestBlockInfo>,
+    /// Health of the environment's external nodes. This is observed at runtime
+    /// and is not persisted.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub external: IndexMap<NodeKey, ExternalNodeStatus>,
+}
+
+/// Reachability of an external node as observed by the control plane
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ExternalNodeStatus {
+    /// Whether the node responded to its most recent request
+    pub reachable: bool,
+    /// The latest height known for the node
+    pub height: Option<u32>,
+    /// The last time the node responded to a request
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+/// A checksum of a ledger's blocks, printed by the aot `ledger checksum`
+/// command. Ledgers with the same blocks up to a height have the same
+/// checksum.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LedgerChecksum {
+    /// The height of the last block in the checksum
+    pub height: u32,
+    /// Hex encoded sha256 of the block hashes in height order
+    pub checksum: String,
+}
+
+/// A snapshot of an agent's metrics, streamed from
+/// `/api/v1/agents/:id/metrics/stream`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AgentMetricsUpdate {
+    /// Unix timestamp (seconds) of when the metrics were read from the agent
+    pub timestamp: i64,
+    pub tps: f64,
+}
+
+/// The configured connections of a node, as returned by
+/// `/api/v1/env/:env_id/topology/graph`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct NodeAdjacency {
+    /// Whether the node is external to the environment. External nodes have
+    /// no configured connections.
+    pub external: bool,
+    /// Nodes this node connects to as peers
+    pub peers: Vec<NodeKey>,
+    /// Nodes this node connects to as validators
+    pub validators: Vec<NodeKey>,
 }
 
 /// Lighter-weight version of EnvInfo for the agent
@@ -125,6 +174,7 @@ impl DataFormat for EnvInfo {
             network: NetworkId::read_data(reader, &header.network)?,
             storage: StorageInfo::read_data(reader, &header.storage)?,
             block: Option::<LatestBlockInfo>::read_data(reader, &header.block)?,
+            external: IndexMap::new(),
         })
     }
 }
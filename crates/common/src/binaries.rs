@@ -22,6 +22,9 @@ pub struct BinaryEntry {
     pub sha256: Option<String>,
     #[serde(default)]
     pub size: Option<u64>,
+    /// The version the binary is expected to report with `--version`
+    #[serde(default)]
+    pub version: Option<String>,
 }
 
 impl BinaryEntry {
@@ -39,6 +42,7 @@ impl BinaryEntry {
                 ))),
                 sha256: self.sha256.clone(),
                 size: self.size,
+                version: self.version.clone(),
             },
         }
     }
@@ -52,7 +56,7 @@ impl BinaryEntry {
     pub fn check_sha256(&self) -> bool {
         self.sha256
             .as_ref()
-            .map(|s| s.len() == 32 && s.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(|s| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()))
             .unwrap_or(false)
     }
 
@@ -88,6 +92,9 @@ impl Display for BinaryEntry {
                 .as_deref()
                 .unwrap_or("not set")
         )?;
+        if let Some(version) = &self.version {
+            writeln!(f, "version: {version}")?;
+        }
         if let BinarySource::Path(path) = &self.source {
             if let Ok(time) = path.metadata().and_then(|m| m.modified()) {
                 writeln!(
@@ -153,7 +160,7 @@ impl<'de> Deserialize<'de> for BinarySource {
 
 impl DataFormat for BinaryEntry {
     type Header = u8;
-    const LATEST_HEADER: Self::Header = 1;
+    const LATEST_HEADER: Self::Header = 2;
 
     fn write_data<W: std::io::Write>(
         &self,
@@ -161,14 +168,15 @@ impl DataFormat for BinaryEntry {
     ) -> Result<usize, crate::format::DataWriteError> {
         Ok(self.source.to_string().write_data(writer)?
             + self.sha256.write_data(writer)?
-            + self.size.write_data(writer)?)
+            + self.size.write_data(writer)?
+            + self.version.write_data(writer)?)
     }
 
     fn read_data<R: std::io::Read>(
         reader: &mut R,
         header: &Self::Header,
     ) -> Result<Self, crate::format::DataReadError> {
-        if *header != Self::LATEST_HEADER {
+        if *header == 0 || *header > Self::LATEST_HEADER {
             return Err(DataReadError::unsupported(
                 "BinaryEntry",
                 Self::LATEST_HEADER,
@@ -182,6 +190,12 @@ impl DataFormat for BinaryEntry {
                 .map_err(|e| DataReadError::Custom(e.to_string()))?,
             sha256: reader.read_data(&())?,
             size: reader.read_data(&())?,
+            // expected versions were added in version 2
+            version: if *header > 1 {
+                reader.read_data(&())?
+            } else {
+                None
+            },
         })
     }
 }
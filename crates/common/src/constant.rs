@@ -3,6 +3,9 @@
 pub const ENV_AGENT_KEY: &str = "SNOPS_AGENT_KEY";
 /// The agent key header that is set to [`ENV_AGENT_KEY`].
 pub const HEADER_AGENT_KEY: &str = "x-snops-agent-key";
+/// Header set on env apply responses when the spec was unchanged and no
+/// agents were reconciled.
+pub const HEADER_ENV_UNCHANGED: &str = "x-snops-env-unchanged";
 /// The snarkOS binary file name.
 pub const SNARKOS_FILE: &str = "snarkos-aot";
 /// The snarkOS log file name.
@@ -5,6 +5,62 @@ use self::error::DatabaseError;
 pub mod error;
 pub mod tree;
 
+/// Default size of the database's page cache, in bytes
+pub const DEFAULT_CACHE_CAPACITY: u64 = 1024 * 1024 * 1024;
+/// Default interval between flushes of buffered writes, in milliseconds
+pub const DEFAULT_FLUSH_EVERY_MS: u64 = 500;
+
+/// Tuning options for the embedded database. The defaults are sled's own
+/// defaults.
+#[derive(Debug, Clone, clap::Parser, PartialEq, Eq)]
+pub struct DbOptions {
+    /// Size of the database's page cache, in bytes
+    #[clap(long = "db-cache-capacity", default_value_t = DEFAULT_CACHE_CAPACITY)]
+    pub cache_capacity: u64,
+
+    /// Milliseconds between flushes of buffered database writes to disk.
+    /// Longer intervals batch more writes per flush, at the cost of losing
+    /// more of them on a crash
+    #[clap(long = "db-flush-every-ms", default_value_t = DEFAULT_FLUSH_EVERY_MS)]
+    pub flush_every_ms: u64,
+
+    /// Favor write throughput over disk usage, for write-heavy workloads
+    #[clap(long = "db-high-throughput")]
+    pub high_throughput: bool,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            flush_every_ms: DEFAULT_FLUSH_EVERY_MS,
+            high_throughput: false,
+        }
+    }
+}
+
+impl DbOptions {
+    /// Open the database at a path with these options
+    pub fn open(&self, path: &Path) -> Result<sled::Db, DatabaseError> {
+        let mode = if self.high_throughput {
+            sled::Mode::HighThroughput
+        } else {
+            sled::Mode::LowSpace
+        };
+
+        Ok(sled::Config::new()
+            .path(path)
+            .cache_capacity(self.cache_capacity)
+            .flush_every_ms(Some(self.flush_every_ms))
+            .mode(mode)
+            .open()?)
+    }
+}
+
 pub trait Database: Sized {
-    fn open(path: &Path) -> Result<Self, DatabaseError>;
+    fn open_with(path: &Path, options: &DbOptions) -> Result<Self, DatabaseError>;
+
+    fn open(path: &Path) -> Result<Self, DatabaseError> {
+        Self::open_with(path, &DbOptions::default())
+    }
 }
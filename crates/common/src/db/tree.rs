@@ -3,6 +3,9 @@ use bytes::Buf;
 use super::error::DatabaseError;
 use crate::format::{DataFormat, read_dataformat, write_dataformat};
 
+/// Most keys removed in a single batch by [`DbTree::delete_with_prefix`]
+const DELETE_BATCH_SIZE: usize = 10_000;
+
 pub struct DbTree<K, V> {
     tree: sled::Tree,
     _phantom: std::marker::PhantomData<(K, V)>,
@@ -109,38 +112,34 @@ impl<K: DataFormat, V: DataFormat> DbTree<K, V> {
         Ok(self.tree.remove(key.to_byte_vec()?)?.is_some())
     }
 
+    /// Delete every row with a key starting with `prefix`, returning the
+    /// number of rows deleted. Keys are removed in batches without being
+    /// parsed, so large ranges are dropped quickly.
     pub fn delete_with_prefix<Prefix: DataFormat>(
         &self,
         prefix: &Prefix,
     ) -> Result<usize, DatabaseError> {
-        Ok(self
-            .tree
-            .scan_prefix(prefix.to_byte_vec()?)
-            .map(|row| {
-                let key_bytes = match row {
-                    Ok((key, _)) => key,
-                    Err(e) => {
-                        tracing::error!("Error reading row from store: {e}");
-                        return 0;
-                    }
-                };
-
-                let key = match K::read_data(&mut key_bytes.reader(), &K::LATEST_HEADER) {
-                    Ok(key) => key,
-                    Err(e) => {
-                        tracing::error!("Error parsing key from store: {e}");
-                        return 0;
-                    }
-                };
+        let mut deleted = 0;
+        let mut batch = sled::Batch::default();
+        let mut batched = 0;
+
+        for key in self.tree.scan_prefix(prefix.to_byte_vec()?).keys() {
+            batch.remove(key?);
+            batched += 1;
+
+            if batched == DELETE_BATCH_SIZE {
+                self.tree.apply_batch(std::mem::take(&mut batch))?;
+                deleted += batched;
+                batched = 0;
+            }
+        }
 
-                if let Err(e) = self.delete(&key) {
-                    tracing::error!("Error deleting key from store: {e}");
-                    return 0;
-                }
+        if batched > 0 {
+            self.tree.apply_batch(batch)?;
+            deleted += batched;
+        }
 
-                1
-            })
-            .sum())
+        Ok(deleted)
     }
 }
 
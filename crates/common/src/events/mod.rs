@@ -6,6 +6,8 @@ pub use traits::*;
 mod filter;
 pub use filter::*;
 mod filter_ops;
+mod record;
+pub use record::*;
 
 pub mod prelude {
     pub use super::filter::EventFilter::*;
@@ -19,3 +21,5 @@ mod test_filter;
 mod test_filter_ops;
 #[cfg(test)]
 mod test_filter_parse;
+#[cfg(test)]
+mod test_record;
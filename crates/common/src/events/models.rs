@@ -7,8 +7,8 @@ use super::EventFilter;
 use crate::{
     rpc::error::ReconcileError,
     state::{
-        AgentId, Authorization, EnvId, InternedId, LatestBlockInfo, NodeKey, NodeStatus,
-        ReconcileStatus, TransactionSendState,
+        AgentId, Authorization, EnvId, InternedId, InventoryReason, LatestBlockInfo, NodeKey,
+        NodeStatus, ReconcileStatus, TransactionSendState,
     },
 };
 
@@ -62,6 +62,8 @@ pub enum AgentEvent {
     NodeStatus(NodeStatus),
     /// An agent emits a block update
     BlockInfo(LatestBlockInfo),
+    /// An agent is moved to the inventory
+    Inventoried { reason: InventoryReason },
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -86,10 +88,23 @@ pub enum TransactionEvent {
         height: Option<u32>,
         timestamp: DateTime<Utc>,
     },
+    /// The broadcasted transaction was found in the mempool of a node
+    MempoolAccepted { node: NodeKey },
     /// The transaction broadcast has exceeded the maximum number of attempts
     BroadcastExceeded { attempts: u32 },
     /// The transaction has been confirmed by the network
     Confirmed { hash: String },
+    /// A new block was added to the network. Counts the cannon's transactions
+    /// that were included in the block.
+    BlockSummary {
+        height: u32,
+        confirmed: u32,
+        aborted: u32,
+        rejected: u32,
+    },
+    /// The cannon was restarted in place. Counts the transactions it fired
+    /// and received before the restart.
+    CannonRestarted { fired: usize, received: u64 },
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -112,6 +127,7 @@ pub enum EventKindFilter {
     AgentReconcileError,
     AgentNodeStatus,
     AgentBlockInfo,
+    AgentInventoried,
     TransactionAuthorizationReceived,
     TransactionExecuteAborted,
     TransactionExecuteAwaitingCompute,
@@ -120,8 +136,11 @@ pub enum EventKindFilter {
     TransactionExecuting,
     TransactionExecuteComplete,
     TransactionBroadcasted,
+    TransactionMempoolAccepted,
     TransactionBroadcastExceeded,
     TransactionConfirmed,
+    TransactionBlockSummary,
+    TransactionCannonRestarted,
 }
 
 impl EventKind {
@@ -140,6 +159,7 @@ impl EventKind {
             Agent(ReconcileError(_)) => AgentReconcileError,
             Agent(NodeStatus(_)) => AgentNodeStatus,
             Agent(BlockInfo(_)) => AgentBlockInfo,
+            Agent(Inventoried { .. }) => AgentInventoried,
             Transaction(AuthorizationReceived { .. }) => TransactionAuthorizationReceived,
             Transaction(ExecuteAborted(_)) => TransactionExecuteAborted,
             Transaction(ExecuteAwaitingCompute) => TransactionExecuteAwaitingCompute,
@@ -148,8 +168,11 @@ impl EventKind {
             Transaction(Executing) => TransactionExecuting,
             Transaction(ExecuteComplete { .. }) => TransactionExecuteComplete,
             Transaction(Broadcasted { .. }) => TransactionBroadcasted,
+            Transaction(MempoolAccepted { .. }) => TransactionMempoolAccepted,
             Transaction(BroadcastExceeded { .. }) => TransactionBroadcastExceeded,
             Transaction(Confirmed { .. }) => TransactionConfirmed,
+            Transaction(BlockSummary { .. }) => TransactionBlockSummary,
+            Transaction(CannonRestarted { .. }) => TransactionCannonRestarted,
         }
     }
 }
@@ -168,6 +191,7 @@ impl FromStr for EventKindFilter {
             "agent-reconcile-error" => Ok(Self::AgentReconcileError),
             "agent-node-status" => Ok(Self::AgentNodeStatus),
             "agent-block-info" => Ok(Self::AgentBlockInfo),
+            "agent-inventoried" => Ok(Self::AgentInventoried),
             "transaction-authorization-received" => Ok(Self::TransactionAuthorizationReceived),
             "transaction-execute-aborted" => Ok(Self::TransactionExecuteAborted),
             "transaction-execute-awaiting-compute" => Ok(Self::TransactionExecuteAwaitingCompute),
@@ -176,8 +200,11 @@ impl FromStr for EventKindFilter {
             "transaction-executing" => Ok(Self::TransactionExecuting),
             "transaction-execute-complete" => Ok(Self::TransactionExecuteComplete),
             "transaction-broadcasted" => Ok(Self::TransactionBroadcasted),
+            "transaction-mempool-accepted" => Ok(Self::TransactionMempoolAccepted),
             "transaction-broadcast-exceeded" => Ok(Self::TransactionBroadcastExceeded),
             "transaction-confirmed" => Ok(Self::TransactionConfirmed),
+            "transaction-block-summary" => Ok(Self::TransactionBlockSummary),
+            "transaction-cannon-restarted" => Ok(Self::TransactionCannonRestarted),
             _ => Err(format!("invalid event kind: {s}")),
         }
     }
@@ -196,6 +223,7 @@ impl Display for EventKindFilter {
             AgentReconcileError => "agent-reconcile-error",
             AgentNodeStatus => "agent-node-status",
             AgentBlockInfo => "agent-block-info",
+            AgentInventoried => "agent-inventoried",
             TransactionAuthorizationReceived => "transaction-authorization-received",
             TransactionExecuteAborted => "transaction-execute-aborted",
             TransactionExecuteAwaitingCompute => "transaction-execute-awaiting-compute",
@@ -204,8 +232,11 @@ impl Display for EventKindFilter {
             TransactionExecuting => "transaction-executing",
             TransactionExecuteComplete => "transaction-execute-complete",
             TransactionBroadcasted => "transaction-broadcasted",
+            TransactionMempoolAccepted => "transaction-mempool-accepted",
             TransactionBroadcastExceeded => "transaction-broadcast-exceeded",
             TransactionConfirmed => "transaction-confirmed",
+            TransactionBlockSummary => "transaction-block-summary",
+            TransactionCannonRestarted => "transaction-cannon-restarted",
         };
 
         write!(f, "{}", s)
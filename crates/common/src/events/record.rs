@@ -0,0 +1,179 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Event, EventKind, EventKindFilter};
+use crate::state::{CannonId, EnvId};
+
+/// Upper bounds of the latency histogram buckets, in seconds. Latencies above
+/// the last bound are counted in a final, unbounded bucket.
+pub const LATENCY_BUCKETS_SECS: [u64; 10] = [1, 2, 5, 10, 15, 30, 60, 120, 300, 600];
+
+/// A transaction status transition, written as one line of an environment's
+/// transaction record
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub time: DateTime<Utc>,
+    pub env: EnvId,
+    pub cannon: CannonId,
+    pub transaction: Arc<String>,
+    /// Latest block height of the environment when the transition was observed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Name of the transaction event, i.e. `transaction-confirmed`
+    pub status: String,
+}
+
+impl TransactionRecord {
+    /// Create a record from an event about a single transaction of a cannon
+    pub fn from_event(event: &Event, height: Option<u32>) -> Option<Self> {
+        let EventKind::Transaction(_) = &event.content else {
+            return None;
+        };
+
+        Some(Self {
+            time: event.created_at,
+            env: event.env?,
+            cannon: event.cannon?,
+            transaction: Arc::clone(event.transaction.as_ref()?),
+            height,
+            status: event.content.filter().to_string(),
+        })
+    }
+
+    fn is(&self, filter: EventKindFilter) -> bool {
+        self.status == filter.to_string()
+    }
+}
+
+/// Distribution of a set of latencies, in seconds
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+    /// Number of latencies in each bucket of [`LATENCY_BUCKETS_SECS`], followed
+    /// by the number of latencies above the last bucket
+    pub histogram: Vec<usize>,
+}
+
+impl LatencySummary {
+    pub fn from_secs(mut latencies: Vec<f64>) -> Self {
+        let mut histogram = vec![0; LATENCY_BUCKETS_SECS.len() + 1];
+        for latency in &latencies {
+            let bucket = LATENCY_BUCKETS_SECS
+                .iter()
+                .position(|bound| *latency <= *bound as f64)
+                .unwrap_or(LATENCY_BUCKETS_SECS.len());
+            histogram[bucket] += 1;
+        }
+
+        latencies.sort_by(f64::total_cmp);
+        // nearest-rank percentile
+        let percentile = |p: f64| {
+            let rank = (p * latencies.len() as f64).ceil() as usize;
+            latencies
+                .get(rank.saturating_sub(1))
+                .copied()
+                .unwrap_or_default()
+        };
+
+        Self {
+            count: latencies.len(),
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max: latencies.last().copied().unwrap_or_default(),
+            histogram,
+        }
+    }
+}
+
+/// Confirmation latencies of a cannon's transactions
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CannonLatencies {
+    /// Number of distinct transactions in the record
+    pub transactions: usize,
+    /// Number of transactions that were never confirmed
+    pub unconfirmed: usize,
+    /// Time from the first recorded transition of a transaction to its
+    /// confirmation
+    pub confirmation: LatencySummary,
+    /// Time from the last broadcast of a transaction to its confirmation
+    pub broadcast: LatencySummary,
+}
+
+#[derive(Default)]
+struct TransactionTimes {
+    first: Option<DateTime<Utc>>,
+    broadcasted: Option<DateTime<Utc>>,
+    confirmed: Option<DateTime<Utc>>,
+}
+
+/// Reconstruct per-transaction confirmation latencies from a transaction
+/// record, grouped by cannon and sorted by cannon id.
+pub fn analyze_records(
+    records: impl IntoIterator<Item = TransactionRecord>,
+) -> Vec<(CannonId, CannonLatencies)> {
+    let mut times: HashMap<CannonId, HashMap<Arc<String>, TransactionTimes>> = HashMap::new();
+
+    for record in records {
+        let tx = times
+            .entry(record.cannon)
+            .or_default()
+            .entry(Arc::clone(&record.transaction))
+            .or_default();
+
+        tx.first = Some(tx.first.map_or(record.time, |t| t.min(record.time)));
+
+        // only the transitions before the first confirmation are relevant
+        if tx.confirmed.is_some() {
+            continue;
+        }
+        if record.is(EventKindFilter::TransactionBroadcasted) {
+            tx.broadcasted = Some(record.time);
+        } else if record.is(EventKindFilter::TransactionConfirmed) {
+            tx.confirmed = Some(record.time);
+        }
+    }
+
+    let secs = |from: DateTime<Utc>, to: DateTime<Utc>| {
+        (to - from).num_milliseconds().max(0) as f64 / 1000.0
+    };
+
+    let mut cannons = times
+        .into_iter()
+        .map(|(cannon, txs)| {
+            let mut confirmation = Vec::new();
+            let mut broadcast = Vec::new();
+            let mut unconfirmed = 0;
+
+            for tx in txs.values() {
+                let Some(confirmed) = tx.confirmed else {
+                    unconfirmed += 1;
+                    continue;
+                };
+                if let Some(first) = tx.first {
+                    confirmation.push(secs(first, confirmed));
+                }
+                if let Some(broadcasted) = tx.broadcasted {
+                    broadcast.push(secs(broadcasted, confirmed));
+                }
+            }
+
+            let latencies = CannonLatencies {
+                transactions: txs.len(),
+                unconfirmed,
+                confirmation: LatencySummary::from_secs(confirmation),
+                broadcast: LatencySummary::from_secs(broadcast),
+            };
+            (cannon, latencies)
+        })
+        .collect::<Vec<_>>();
+
+    cannons.sort_by_key(|(cannon, _)| cannon.to_string());
+    cannons
+}
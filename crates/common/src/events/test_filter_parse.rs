@@ -41,6 +41,7 @@ fn test_each_filter() -> Result<(), EventFilterParseError> {
     );
     eq!("cannon-is(default)", CannonIs(InternedId::default()));
     eq!("event-is(agent-connected)", EventIs(AgentConnected));
+    eq!("event-is(agent-inventoried)", EventIs(AgentInventoried));
     eq!(
         "node-key-is(client/foo)",
         NodeKeyIs("client/foo".parse().unwrap())
@@ -193,6 +194,9 @@ fn test_str() {
     test!("transaction-is(foo)");
     test!("cannon-is(default)");
     test!("event-is(agent-connected)");
+    test!("event-is(transaction-block-summary)");
+    test!("event-is(transaction-cannon-restarted)");
+    test!("event-is(transaction-mempool-accepted)");
     test!("node-key-is(client/foo)");
     test!("node-target-is(client/any)");
     test!("node-target-is(client/any, validator/any)");
@@ -0,0 +1,85 @@
+use std::{str::FromStr, sync::Arc};
+
+use chrono::{DateTime, Duration, Utc};
+
+use super::{EventKindFilter, EventKindFilter::*, LATENCY_BUCKETS_SECS, TransactionRecord};
+use crate::{
+    events::{LatencySummary, analyze_records},
+    state::InternedId,
+};
+
+fn record(
+    start: DateTime<Utc>,
+    secs: i64,
+    cannon: &str,
+    tx: &str,
+    status: EventKindFilter,
+) -> TransactionRecord {
+    TransactionRecord {
+        time: start + Duration::seconds(secs),
+        env: InternedId::from_str("env").unwrap(),
+        cannon: InternedId::from_str(cannon).unwrap(),
+        transaction: Arc::new(tx.to_owned()),
+        height: None,
+        status: status.to_string(),
+    }
+}
+
+#[test]
+fn test_record_roundtrip() {
+    let record = record(Utc::now(), 0, "a", "at1", TransactionConfirmed);
+    let line = serde_json::to_string(&record).unwrap();
+    assert!(line.contains(r#""status":"transaction-confirmed""#));
+    assert_eq!(
+        serde_json::from_str::<TransactionRecord>(&line).unwrap(),
+        record
+    );
+}
+
+#[test]
+fn test_latency_summary() {
+    let summary = LatencySummary::from_secs((1..=100).map(f64::from).collect());
+    assert_eq!(summary.count, 100);
+    assert_eq!(summary.p50, 50.0);
+    assert_eq!(summary.p90, 90.0);
+    assert_eq!(summary.p99, 99.0);
+    assert_eq!(summary.max, 100.0);
+    assert_eq!(summary.histogram.len(), LATENCY_BUCKETS_SECS.len() + 1);
+    assert_eq!(summary.histogram.iter().sum::<usize>(), 100);
+
+    assert_eq!(LatencySummary::from_secs(vec![]).p99, 0.0);
+}
+
+#[test]
+fn test_analyze_records() {
+    let start = Utc::now();
+    let records = vec![
+        record(start, 0, "b", "at1", TransactionAuthorizationReceived),
+        record(start, 2, "b", "at1", TransactionBroadcasted),
+        record(start, 4, "b", "at1", TransactionBroadcasted),
+        record(start, 5, "b", "at1", TransactionConfirmed),
+        // a second confirmation is ignored
+        record(start, 9, "b", "at1", TransactionConfirmed),
+        record(start, 1, "b", "at2", TransactionAuthorizationReceived),
+        record(start, 1, "a", "at3", TransactionBroadcasted),
+        record(start, 3, "a", "at3", TransactionConfirmed),
+    ];
+
+    let cannons = analyze_records(records);
+    assert_eq!(cannons.len(), 2);
+
+    let (a, a_latencies) = &cannons[0];
+    assert_eq!(a.to_string(), "a");
+    assert_eq!(a_latencies.transactions, 1);
+    assert_eq!(a_latencies.unconfirmed, 0);
+    assert_eq!(a_latencies.confirmation.max, 2.0);
+    assert_eq!(a_latencies.broadcast.max, 2.0);
+
+    let (b, b_latencies) = &cannons[1];
+    assert_eq!(b.to_string(), "b");
+    assert_eq!(b_latencies.transactions, 2);
+    assert_eq!(b_latencies.unconfirmed, 1);
+    assert_eq!(b_latencies.confirmation.count, 1);
+    assert_eq!(b_latencies.confirmation.p99, 5.0);
+    assert_eq!(b_latencies.broadcast.p99, 1.0);
+}
@@ -0,0 +1,36 @@
+use base64::{Engine, prelude::BASE64_STANDARD_NO_PAD};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as _, ser::Error as _};
+
+use super::{DataFormat, read_dataformat, write_dataformat};
+
+/// A value serialized with serde as its base64 encoded [`DataFormat`],
+/// including its header. Used in RPC messages for values whose serde
+/// representation is much larger than their binary format.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Compact<T>(pub T);
+
+impl<T> From<T> for Compact<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: DataFormat> Serialize for Compact<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut buf = Vec::new();
+        write_dataformat(&mut buf, &self.0).map_err(S::Error::custom)?;
+        serializer.serialize_str(&BASE64_STANDARD_NO_PAD.encode(buf))
+    }
+}
+
+impl<'de, T: DataFormat> Deserialize<'de> for Compact<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let buf = BASE64_STANDARD_NO_PAD
+            .decode(encoded)
+            .map_err(D::Error::custom)?;
+        read_dataformat(&mut buf.as_slice())
+            .map(Self)
+            .map_err(D::Error::custom)
+    }
+}
@@ -3,6 +3,7 @@ use std::{
     io::{Read, Write},
 };
 
+mod compact;
 mod impl_checkpoint;
 mod impl_chrono;
 mod impl_collections;
@@ -13,9 +14,12 @@ mod impl_net;
 mod impl_strings;
 mod impl_tuples;
 mod packed_int;
+mod string_table;
 
+pub use compact::Compact;
 pub use impl_collections::{BytesFormat, EncodedFormat};
 pub use packed_int::*;
+pub use string_table::StringTable;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -0,0 +1,91 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use super::{DataFormat, DataReadError, DataWriteError, PackedUint};
+
+/// A table of distinct strings, for formats that repeat the same strings many
+/// times. Each string is written once, and referenced by its index.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StringTable {
+    strings: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl StringTable {
+    /// Add a string to the table, returning its index
+    pub fn intern(&mut self, s: &str) -> PackedUint {
+        if let Some(index) = self.indices.get(s) {
+            return PackedUint::from(*index);
+        }
+
+        let index = self.strings.len();
+        self.strings.push(s.to_owned());
+        self.indices.insert(s.to_owned(), index);
+        PackedUint::from(index)
+    }
+
+    /// Resolve a string by its index
+    pub fn get(&self, index: PackedUint) -> Result<&str, DataReadError> {
+        let index = usize::from(index);
+        self.strings
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| DataReadError::custom(format!("string table has no index {index}")))
+    }
+
+    /// Read an index from the reader and resolve its string
+    pub fn read_str<R: Read>(&self, reader: &mut R) -> Result<String, DataReadError> {
+        self.get(PackedUint::read_data(reader, &())?)
+            .map(str::to_owned)
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+impl DataFormat for StringTable {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        self.strings.write_data(writer)
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        let strings = Vec::<String>::read_data(reader, &())?;
+        let indices = strings
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.clone(), i))
+            .collect();
+        Ok(Self { strings, indices })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_string_table_roundtrip() {
+        let mut table = StringTable::default();
+        assert_eq!(table.intern("foo").0, 0);
+        assert_eq!(table.intern("bar").0, 1);
+        assert_eq!(table.intern("foo").0, 0);
+        assert_eq!(table.len(), 2);
+
+        let mut data = Vec::new();
+        table.write_data(&mut data).unwrap();
+        let read = StringTable::read_data(&mut &data[..], &()).unwrap();
+        assert_eq!(read, table);
+        assert_eq!(read.get(PackedUint(1)).unwrap(), "bar");
+        assert!(read.get(PackedUint(2)).is_err());
+    }
+}
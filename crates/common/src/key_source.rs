@@ -36,6 +36,8 @@ pub enum KeySource {
     Committee(Option<usize>),
     /// accounts.0 or accounts.$ (for replicas)
     Named(InternedId, Option<usize>),
+    /// accounts.* (a random key from the set each time it is resolved)
+    Random { set: InternedId },
 }
 
 impl<'de> Deserialize<'de> for KeySource {
@@ -84,7 +86,7 @@ impl FromStr for KeySource {
         // starts with "APrivateKey1zkp" use KeySource::Commitee(Option<usize>)
         // when the string is "committee.0" or "committee.$"
         // use KeySource::Named(String, Option<usize>) when the string is "\w+.0" or
-        // "\w+.$"
+        // "\w+.$", and KeySource::Random when the string is "\w+.*"
 
         if s == "local" {
             return Ok(KeySource::Local);
@@ -108,9 +110,10 @@ impl FromStr for KeySource {
 
         // named key (using regex with capture groups)
         lazy_static! {
-            static ref NAMED_KEYSOURCE_REGEX: regex::Regex =
-                regex::Regex::new(r"^(?P<name>[A-Za-z0-9][A-Za-z0-9\-_.]{0,63})\.(?P<idx>\d+|\$)$")
-                    .unwrap();
+            static ref NAMED_KEYSOURCE_REGEX: regex::Regex = regex::Regex::new(
+                r"^(?P<name>[A-Za-z0-9][A-Za-z0-9\-_.]{0,63})\.(?P<idx>\d+|\$|\*)$"
+            )
+            .unwrap();
             static ref NAMED_PROGRAM_REGEX: regex::Regex =
                 regex::Regex::new(r"^[A-Za-z0-9_]{1,256}\.aleo$").unwrap();
         }
@@ -125,6 +128,7 @@ impl FromStr for KeySource {
         let name = InternedId::from_str(groups.name("name").unwrap().as_str())
             .map_err(|_| KeySourceError::InvalidKeySource)?;
         let idx = match groups.name("idx").unwrap().as_str() {
+            "*" => return Ok(KeySource::Random { set: name }),
             "$" => None,
             idx => Some(idx.parse().map_err(KeySourceError::InvalidCommitteeIndex)?),
         };
@@ -150,6 +154,7 @@ impl fmt::Display for KeySource {
                 KeySource::Named(name, Some(idx)) => {
                     format!("{}.{}", name, idx)
                 }
+                KeySource::Random { set } => format!("{}.*", set),
             }
         )
     }
@@ -182,6 +187,7 @@ impl DataFormat for KeySource {
                 writer.write_data(&6u8)? + writer.write_data(key)?
             }
             KeySource::ProgramLiteral(key) => writer.write_data(&7u8)? + writer.write_data(key)?,
+            KeySource::Random { set } => writer.write_data(&8u8)? + writer.write_data(set)?,
         })
     }
 
@@ -209,6 +215,9 @@ impl DataFormat for KeySource {
             )),
             6u8 => Ok(KeySource::PublicKeyLiteral(reader.read_data(&())?)),
             7u8 => Ok(KeySource::ProgramLiteral(reader.read_data(&())?)),
+            8u8 => Ok(KeySource::Random {
+                set: reader.read_data(&())?,
+            }),
             n => Err(DataReadError::Custom(format!("invalid KeySource tag {n}"))),
         }
     }
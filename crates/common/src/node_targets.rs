@@ -1,5 +1,5 @@
 use core::fmt;
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 use http::StatusCode;
 use lazy_static::lazy_static;
@@ -15,7 +15,7 @@ use wildmatch::WildMatch;
 use crate::{
     format::*,
     impl_into_status_code,
-    state::{NodeKey, NodeType},
+    state::{NetworkId, NodeKey, NodeType},
 };
 
 #[derive(Debug, Error)]
@@ -42,19 +42,39 @@ impl DataFormat for NodeTargets {
         &self,
         writer: &mut W,
     ) -> Result<usize, DataWriteError> {
-        match self {
-            NodeTargets::None => vec![],
-            NodeTargets::One(target) => vec![target.clone()],
-            NodeTargets::Many(targets) => targets.clone(),
+        let targets = self.as_slice();
+
+        // ids and namespaces repeat across large target lists, so each is
+        // written once in a table ahead of the targets
+        let mut table = StringTable::default();
+        let mut body = Vec::new();
+        PackedUint::from(targets.len()).write_data(&mut body)?;
+        for target in targets {
+            target.write_with(&mut body, |body, s| table.intern(s).write_data(body))?;
         }
-        .write_data(writer)
+
+        let written = table.write_data(writer)?;
+        writer.write_all(&body)?;
+        Ok(written + body.len())
     }
 
     fn read_data<R: std::io::prelude::Read>(
         reader: &mut R,
         header: &Self::Header,
     ) -> Result<Self, DataReadError> {
-        let targets = Vec::<NodeTarget>::read_data(reader, header)?;
+        if header.0 < 2 {
+            let targets = Vec::<NodeTarget>::read_data(reader, header)?;
+            return Ok(NodeTargets::from(targets));
+        }
+
+        let table = StringTable::read_data(reader, &())?;
+        let len = usize::from(PackedUint::read_data(reader, &())?);
+        let mut targets = Vec::with_capacity(len);
+        for _ in 0..len {
+            targets.push(NodeTarget::read_with(reader, header, |reader| {
+                table.read_str(reader)
+            })?);
+        }
         Ok(NodeTargets::from(targets))
     }
 }
@@ -108,7 +128,7 @@ impl<'de> Deserialize<'de> for NodeTargets {
 
 lazy_static! {
     static ref NODE_TARGET_REGEX: Regex =
-        Regex::new(r"^(?P<ty>\*|any|client|validator|prover)\/(?P<id>[A-Za-z0-9\-*]+)(?:@(?P<ns>[A-Za-z0-9\-*]+))?$")
+        Regex::new(r"^(?:(?P<net>mainnet|testnet|canary)\/)?(?P<ty>\*|any|client|validator|prover)\/(?P<id>[A-Za-z0-9\-*]+)(?:@(?P<ns>[A-Za-z0-9\-*]+))?$")
             .unwrap();
 }
 
@@ -173,6 +193,9 @@ pub struct NodeTarget {
     pub ty: NodeTargetType,
     pub id: NodeTargetId,
     pub ns: NodeTargetNamespace,
+    /// The network of the matched nodes. If `None`, nodes of any network are
+    /// matched.
+    pub network: Option<NetworkId>,
 }
 
 impl FromStr for NodeTarget {
@@ -181,6 +204,11 @@ impl FromStr for NodeTarget {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let captures = NODE_TARGET_REGEX.captures(s).ok_or(NodeTargetError)?;
 
+        // match the network
+        let network = captures
+            .name("net")
+            .map(|net| NetworkId::from_str(net.as_str()).unwrap());
+
         // match the type
         let ty = match &captures["ty"] {
             "*" => NodeTargetType::All,
@@ -218,12 +246,20 @@ impl FromStr for NodeTarget {
             Some(id) => NodeTargetNamespace::Literal(id.as_str().into()),
         };
 
-        Ok(Self { ty, id, ns })
+        Ok(Self {
+            ty,
+            id,
+            ns,
+            network,
+        })
     }
 }
 
 impl fmt::Display for NodeTarget {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(network) = self.network {
+            write!(f, "{network}/")?;
+        }
         write!(
             f,
             "{}/{}{}",
@@ -266,11 +302,39 @@ impl<'de> Deserialize<'de> for NodeTarget {
 
 impl DataFormat for NodeTarget {
     type Header = (u8, DataHeaderOf<NodeType>);
-    const LATEST_HEADER: Self::Header = (1, NodeType::LATEST_HEADER);
+    /// Version 2 interns the strings of [`NodeTargets`]. A single target is
+    /// written the same way in both versions. Version 3 adds the network.
+    const LATEST_HEADER: Self::Header = (3, NodeType::LATEST_HEADER);
 
     fn write_data<W: std::io::prelude::Write>(
         &self,
         writer: &mut W,
+    ) -> Result<usize, DataWriteError> {
+        self.write_with(writer, |writer, s| s.to_owned().write_data(writer))
+    }
+
+    fn read_data<R: std::io::prelude::Read>(
+        reader: &mut R,
+        header: &Self::Header,
+    ) -> Result<Self, DataReadError> {
+        if header.0 == 0 || header.0 > Self::LATEST_HEADER.0 {
+            return Err(DataReadError::unsupported(
+                "NodeTarget",
+                format!("1 to {}", Self::LATEST_HEADER.0),
+                header.0,
+            ));
+        }
+
+        Self::read_with(reader, header, |reader| String::read_data(reader, &()))
+    }
+}
+
+impl NodeTarget {
+    /// Write the target, using `write_str` to write its id and namespace
+    fn write_with<W: std::io::prelude::Write>(
+        &self,
+        writer: &mut W,
+        mut write_str: impl FnMut(&mut W, &str) -> Result<usize, DataWriteError>,
     ) -> Result<usize, DataWriteError> {
         let mut written = 0;
         written += match self.ty {
@@ -280,34 +344,31 @@ impl DataFormat for NodeTarget {
         written += match &self.id {
             NodeTargetId::All => 0u8.write_data(writer)?,
             NodeTargetId::WildcardPattern(pattern) => {
-                1u8.write_data(writer)? + pattern.to_string().write_data(writer)?
+                1u8.write_data(writer)? + write_str(writer, &pattern.to_string())?
             }
-            NodeTargetId::Literal(id) => 2u8.write_data(writer)? + id.write_data(writer)?,
+            NodeTargetId::Literal(id) => 2u8.write_data(writer)? + write_str(writer, id.as_str())?,
         };
         written += match &self.ns {
             NodeTargetNamespace::All => 0u8.write_data(writer)?,
             NodeTargetNamespace::Local => 1u8.write_data(writer)?,
-            NodeTargetNamespace::Literal(ns) => 2u8.write_data(writer)? + ns.write_data(writer)?,
+            NodeTargetNamespace::Literal(ns) => {
+                2u8.write_data(writer)? + write_str(writer, ns.as_str())?
+            }
         };
+        written += self.network.write_data(writer)?;
 
         Ok(written)
     }
 
-    fn read_data<R: std::io::prelude::Read>(
+    /// Read a target, using `read_str` to read its id and namespace
+    fn read_with<R: std::io::prelude::Read>(
         reader: &mut R,
-        header: &Self::Header,
+        (version, node_type): &<Self as DataFormat>::Header,
+        mut read_str: impl FnMut(&mut R) -> Result<String, DataReadError>,
     ) -> Result<Self, DataReadError> {
-        if header.0 != Self::LATEST_HEADER.0 {
-            return Err(DataReadError::unsupported(
-                "NodeTarget",
-                Self::LATEST_HEADER.0,
-                header.0,
-            ));
-        }
-
         let ty = match reader.read_data(&())? {
             0u8 => NodeTargetType::All,
-            1u8 => NodeTargetType::One(NodeType::read_data(reader, &header.1)?),
+            1u8 => NodeTargetType::One(NodeType::read_data(reader, node_type)?),
             n => {
                 return Err(DataReadError::Custom(format!(
                     "invalid NodeTarget type discriminant: {n}"
@@ -317,11 +378,8 @@ impl DataFormat for NodeTarget {
 
         let id = match reader.read_data(&())? {
             0u8 => NodeTargetId::All,
-            1u8 => {
-                let pattern = String::read_data(reader, &())?;
-                NodeTargetId::WildcardPattern(WildMatch::new(&pattern))
-            }
-            2u8 => NodeTargetId::Literal(reader.read_data(&())?),
+            1u8 => NodeTargetId::WildcardPattern(WildMatch::new(&read_str(reader)?)),
+            2u8 => NodeTargetId::Literal(read_str(reader)?),
             n => {
                 return Err(DataReadError::Custom(format!(
                     "invalid NodeTarget ID discriminant: {n}"
@@ -332,7 +390,7 @@ impl DataFormat for NodeTarget {
         let ns = match reader.read_data(&())? {
             0u8 => NodeTargetNamespace::All,
             1u8 => NodeTargetNamespace::Local,
-            2u8 => NodeTargetNamespace::Literal(reader.read_data(&())?),
+            2u8 => NodeTargetNamespace::Literal(read_str(reader)?),
             n => {
                 return Err(DataReadError::Custom(format!(
                     "invalid NodeTarget namespace discriminant: {n}"
@@ -340,7 +398,19 @@ impl DataFormat for NodeTarget {
             }
         };
 
-        Ok(Self { ty, id, ns })
+        // networks were added in version 3
+        let network = if *version > 2 {
+            reader.read_data(&NetworkId::LATEST_HEADER)?
+        } else {
+            None
+        };
+
+        Ok(Self {
+            ty,
+            id,
+            ns,
+            network,
+        })
     }
 }
 
@@ -393,6 +463,7 @@ impl From<NodeKey> for NodeTarget {
                 .ns
                 .map(NodeTargetNamespace::Literal)
                 .unwrap_or(NodeTargetNamespace::Local),
+            network: value.network,
         }
     }
 }
@@ -412,8 +483,11 @@ impl NodeTarget {
         ty: NodeTargetType::All,
         id: NodeTargetId::All,
         ns: NodeTargetNamespace::All,
+        network: None,
     };
 
+    /// Whether the target matches a node key. A target or key without a
+    /// network matches keys or targets of every network.
     pub fn matches(&self, key: &NodeKey) -> bool {
         (match self.ty {
             NodeTargetType::All => true,
@@ -428,7 +502,22 @@ impl NodeTarget {
             NodeTargetNamespace::Literal(ns) => {
                 ns == "local" && key.ns.is_none() || (key.ns.as_ref() == Some(ns))
             }
-        })
+        }) && self
+            .network
+            .zip(key.network)
+            .is_none_or(|(target, key)| target == key)
+    }
+
+    /// Whether the target can match nodes of the given network
+    pub fn is_on_network(&self, network: NetworkId) -> bool {
+        self.network.is_none_or(|n| n == network)
+    }
+
+    /// Whether the target matches a node key of the given network. Keys are
+    /// named without their network inside an environment, so targets naming
+    /// another network match none of them.
+    pub fn matches_on(&self, network: NetworkId, key: &NodeKey) -> bool {
+        self.is_on_network(network) && self.matches(key)
     }
 }
 
@@ -452,4 +541,229 @@ impl NodeTargets {
             NodeTargets::Many(targets) => targets.iter().any(|target| target.matches(key)),
         }
     }
+
+    /// Equivalent to [`NodeTarget::matches_on`]
+    pub fn matches_on(&self, network: NetworkId, key: &NodeKey) -> bool {
+        self.as_slice()
+            .iter()
+            .any(|target| target.matches_on(network, key))
+    }
+
+    pub fn as_slice(&self) -> &[NodeTarget] {
+        match self {
+            NodeTargets::None => &[],
+            NodeTargets::One(target) => std::slice::from_ref(target),
+            NodeTargets::Many(targets) => targets.as_slice(),
+        }
+    }
+
+    /// Prepare these targets for matching against many node keys.
+    pub fn compile(&self) -> CompiledTargets<'_> {
+        compile_targets(self.as_slice().iter())
+    }
+
+    /// Prepare these targets for matching against many node keys of the
+    /// given network. Equivalent to [`NodeTargets::matches_on`].
+    pub fn compile_on(&self, network: NetworkId) -> CompiledTargets<'_> {
+        compile_targets(
+            self.as_slice()
+                .iter()
+                .filter(move |target| target.is_on_network(network)),
+        )
+    }
+}
+
+fn compile_targets<'a>(targets: impl Iterator<Item = &'a NodeTarget>) -> CompiledTargets<'a> {
+    let mut compiled = CompiledTargets::default();
+    for target in targets {
+        if *target == NodeTarget::ALL {
+            compiled.all = true;
+        }
+        match &target.id {
+            NodeTargetId::Literal(id) => compiled
+                .literals
+                .entry(id.as_str())
+                .or_default()
+                .push(target),
+            _ => compiled.patterns.push(target),
+        }
+    }
+    compiled
+}
+
+/// [`NodeTargets`] prepared by [`NodeTargets::compile`] for matching many
+/// keys. Targets with a literal ID are bucketed by that ID so a key is only
+/// checked against the targets that could name it.
+#[derive(Debug, Default)]
+pub struct CompiledTargets<'a> {
+    /// A target matches every key
+    all: bool,
+    literals: HashMap<&'a str, Vec<&'a NodeTarget>>,
+    patterns: Vec<&'a NodeTarget>,
+}
+
+impl CompiledTargets<'_> {
+    /// Equivalent to [`NodeTargets::matches`]
+    pub fn matches(&self, key: &NodeKey) -> bool {
+        self.all
+            || self
+                .literals
+                .get(key.id.as_str())
+                .is_some_and(|targets| targets.iter().any(|target| target.matches(key)))
+            || self.patterns.iter().any(|target| target.matches(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compiled_matches() {
+        let targets = [
+            "*/*",
+            "*/*@*",
+            "client/*",
+            "validator/1",
+            "validator/1@local",
+            "prover/foo-*",
+            "*/1@net",
+            "*/foo-bar@*",
+            "client/2@local",
+            "*/*@net",
+            "canary/validator/1",
+            "testnet/*/*",
+        ]
+        .map(|t| t.parse::<NodeTarget>().unwrap());
+        let mut keys = [
+            "client/1",
+            "client/2",
+            "validator/1",
+            "validator/1@net",
+            "prover/foo-bar",
+            "prover/foo-bar@net",
+            "client/foo-bar",
+            "prover/2",
+            "canary/validator/1",
+            "testnet/client/1",
+        ]
+        .map(|k| k.parse::<NodeKey>().unwrap())
+        .to_vec();
+        // parsing drops an explicit local namespace
+        keys.push(NodeKey {
+            ty: NodeType::Validator,
+            id: "1".to_owned(),
+            ns: Some("local".to_owned()),
+            network: None,
+        });
+
+        // every combination of up to three targets
+        let mut sets = vec![NodeTargets::None];
+        for (a, ta) in targets.iter().enumerate() {
+            sets.push(NodeTargets::One(ta.clone()));
+            for (b, tb) in targets.iter().enumerate().skip(a + 1) {
+                sets.push(vec![ta.clone(), tb.clone()].into());
+                for tc in targets.iter().skip(b + 1) {
+                    sets.push(vec![ta.clone(), tb.clone(), tc.clone()].into());
+                }
+            }
+        }
+
+        for set in &sets {
+            let compiled = set.compile();
+            for key in &keys {
+                assert_eq!(
+                    set.matches(key),
+                    compiled.matches(key),
+                    "{set} matching {key}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_interned_dataformat() {
+        let targets: NodeTargets = ["client", "validator", "prover"]
+            .iter()
+            .flat_map(|ty| (0..100).map(move |i| format!("{ty}/node-{i}@testnet")))
+            .chain(["*/*".to_owned(), "prover/node-*".to_owned()])
+            .map(|s| s.parse::<NodeTarget>().unwrap())
+            .collect::<Vec<_>>()
+            .into();
+
+        let mut interned = Vec::new();
+        write_dataformat(&mut interned, &targets).unwrap();
+        let read: NodeTargets = read_dataformat(&mut interned.as_slice()).unwrap();
+        assert_eq!(read, targets);
+
+        // targets written before interning are still readable
+        let legacy_header = (1, NodeType::LATEST_HEADER);
+        let mut legacy = Vec::new();
+        legacy_header.write_header(&mut legacy).unwrap();
+        legacy_header.write_data(&mut legacy).unwrap();
+        PackedUint::from(targets.as_slice().len())
+            .write_data(&mut legacy)
+            .unwrap();
+        for target in targets.as_slice() {
+            target.write_data(&mut legacy).unwrap();
+            // targets written before version 3 end without a network
+            assert_eq!(legacy.pop(), Some(0));
+        }
+        let read: NodeTargets = read_dataformat(&mut legacy.as_slice()).unwrap();
+        assert_eq!(read, targets);
+
+        assert!(
+            interned.len() * 3 < legacy.len() * 2,
+            "interned {} bytes, legacy {} bytes",
+            interned.len(),
+            legacy.len()
+        );
+    }
+
+    #[test]
+    fn test_target_network() {
+        let target = NodeTarget::from_str("canary/validator/*").unwrap();
+        assert_eq!(target.network, Some(NetworkId::Canary));
+        assert_eq!(target.to_string(), "canary/validator/*");
+
+        // keys without a network are matched by targets of any network
+        assert!(target.matches(&"validator/1".parse().unwrap()));
+        assert!(target.matches(&"canary/validator/1".parse().unwrap()));
+        assert!(!target.matches(&"testnet/validator/1".parse().unwrap()));
+        assert!(NodeTarget::ALL.matches(&"testnet/validator/1".parse().unwrap()));
+
+        let mut bytes = Vec::new();
+        write_dataformat(&mut bytes, &target).unwrap();
+        let read: NodeTarget = read_dataformat(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read, target);
+    }
+
+    #[test]
+    fn test_target_other_network() {
+        // env keys are named without their network
+        let key = NodeKey::from_str("validator/1").unwrap();
+        let targets = |t: &str| {
+            NodeTargets::from(
+                t.split(',')
+                    .map(|t| t.parse::<NodeTarget>().unwrap())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        for (set, canary) in [
+            ("testnet/validator/*", false),
+            ("testnet/*/*", false),
+            ("canary/validator/*", true),
+            ("validator/*", true),
+            ("testnet/validator/*,canary/validator/1", true),
+        ] {
+            let set = targets(set);
+            assert_eq!(set.matches_on(NetworkId::Canary, &key), canary, "{set}");
+            assert_eq!(
+                set.compile_on(NetworkId::Canary).matches(&key),
+                canary,
+                "{set}"
+            );
+        }
+    }
 }
@@ -1,12 +1,37 @@
 // rmp_serde and bincode have various limitations and are troublesome to debug.
 // the overhead of JSON for messages is not a concern for the RPC layer.
 
+use thiserror::Error;
+
+/// Version of the RPC message encoding. Every message is prefixed with this
+/// byte so peers built from different versions can detect the skew.
+pub const PROTOCOL_VERSION: u8 = 2;
+
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error("empty message")]
+    Empty,
+    /// Messages from before versioning start with a JSON value, and are
+    /// reported as version 0.
+    #[error("received protocol v{received}, expected v{PROTOCOL_VERSION}")]
+    VersionMismatch { received: u8 },
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
 pub fn encode<T: serde::Serialize>(msg: &T) -> serde_json::Result<Vec<u8>> {
-    serde_json::to_vec(msg)
+    let mut buf = vec![PROTOCOL_VERSION];
+    serde_json::to_writer(&mut buf, msg)?;
+    Ok(buf)
 }
 
-pub fn decode<'de, T: serde::Deserialize<'de>>(msg: &'de [u8]) -> serde_json::Result<T> {
-    serde_json::from_slice(msg)
+pub fn decode<'de, T: serde::Deserialize<'de>>(msg: &'de [u8]) -> Result<T, CodecError> {
+    match msg.split_first() {
+        None => Err(CodecError::Empty),
+        Some((&PROTOCOL_VERSION, msg)) => Ok(serde_json::from_slice(msg)?),
+        Some((b'{' | b'"', _)) => Err(CodecError::VersionMismatch { received: 0 }),
+        Some((&received, _)) => Err(CodecError::VersionMismatch { received }),
+    }
 }
 
 // pub fn encode<T: serde::Serialize>(msg: &T) -> Result<Vec<u8>,
@@ -18,3 +43,74 @@ pub fn decode<'de, T: serde::Deserialize<'de>>(msg: &'de [u8]) -> serde_json::Re
 // ) -> Result<T, rmp_serde::decode::Error> {
 //     rmp_serde::from_slice(msg)
 // }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_codec_roundtrip() {
+        let bin = encode(&vec![1u32, 2, 3]).unwrap();
+        assert_eq!(bin[0], PROTOCOL_VERSION);
+        assert_eq!(decode::<Vec<u32>>(&bin).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_codec_version_mismatch() {
+        // unversioned messages are plain json
+        let legacy = serde_json::to_vec(&serde_json::json!({ "Child": null })).unwrap();
+        assert!(matches!(
+            decode::<serde_json::Value>(&legacy),
+            Err(CodecError::VersionMismatch { received: 0 })
+        ));
+
+        let mut future = encode(&1u32).unwrap();
+        future[0] = PROTOCOL_VERSION + 1;
+        assert!(matches!(
+            decode::<u32>(&future),
+            Err(CodecError::VersionMismatch { received }) if received == PROTOCOL_VERSION + 1
+        ));
+
+        assert!(matches!(decode::<u32>(&[]), Err(CodecError::Empty)));
+    }
+
+    #[test]
+    fn test_compact_agent_state_size() {
+        use crate::{
+            format::Compact,
+            state::{AgentPeer, AgentState, HeightRequest, KeyState, NodeState},
+        };
+
+        let peers = |n: usize| {
+            (0..n)
+                .map(|i| AgentPeer::Internal(format!("agent-{i}").parse().unwrap(), 4130))
+                .collect::<Vec<_>>()
+        };
+        let state = AgentState::Node(
+            "env".parse().unwrap(),
+            Box::new(NodeState {
+                node_key: "validator/0".parse().unwrap(),
+                private_key: KeyState::None,
+                height: (0, HeightRequest::Top),
+                online: true,
+                peers: peers(500),
+                validators: peers(100),
+                env: Default::default(),
+                binary: None,
+                bind_addr: None,
+                cpu_limit: None,
+                memory_limit: None,
+            }),
+        );
+
+        let json = encode(&state).unwrap();
+        let compact = encode(&Compact(state.clone())).unwrap();
+        assert_eq!(decode::<Compact<AgentState>>(&compact).unwrap().0, state);
+        assert!(
+            compact.len() * 3 < json.len() * 2,
+            "compact {} bytes, json {} bytes",
+            compact.len(),
+            json.len()
+        );
+    }
+}
@@ -1,7 +1,8 @@
-use std::net::IpAddr;
+use std::{net::IpAddr, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
+use crate::format::Compact;
 use crate::rpc::error::*;
 use crate::state::snarkos_status::SnarkOSLiteBlock;
 use crate::state::{AgentId, ReconcileOptions};
@@ -14,7 +15,9 @@ use crate::{
 pub struct Handshake {
     pub jwt: Option<String>,
     pub loki: Option<String>,
-    pub state: AgentState,
+    /// Sent in its binary format, as resolved node states can hold hundreds
+    /// of peers
+    pub state: Compact<AgentState>,
     pub reconcile_opts: ReconcileOptions,
 }
 
@@ -33,7 +36,7 @@ pub trait AgentService {
 
     /// Control plane instructs the agent to reconcile towards a particular
     /// state.
-    async fn set_agent_state(to: AgentState, opts: ReconcileOptions);
+    async fn set_agent_state(to: Compact<AgentState>, opts: ReconcileOptions);
 
     /// Broadcast a transaction locally
     async fn broadcast_tx(tx: String) -> Result<(), AgentError>;
@@ -41,21 +44,23 @@ pub trait AgentService {
     /// Make a GET request to the snarkos server
     async fn snarkos_get(route: String) -> Result<String, SnarkosRequestError>;
 
-    /// Close the agent process
+    /// Close the agent process, gracefully shutting down its node first
     async fn kill();
 
     /// Locally execute an authorization, using the given query
     /// environment id is passed so the agent can determine which aot binary to
-    /// use
+    /// use. The execution is killed when it exceeds the timeout.
     async fn execute_authorization(
         env_id: EnvId,
         network: NetworkId,
         query: String,
         auth: String,
+        timeout: Option<Duration>,
     ) -> Result<String, AgentError>;
 
     async fn get_metric(metric: AgentMetric) -> f64;
 
+    /// Reload the agent's log filter from a level or filter directives
     async fn set_log_level(level: String) -> Result<(), AgentError>;
 
     /// Find a transaction's block hash by its transaction id
@@ -69,6 +74,23 @@ pub trait AgentService {
     async fn set_aot_log_level(verbosity: u8) -> Result<(), AgentError>;
 
     async fn get_status() -> Result<AgentStatus, AgentError>;
+
+    /// Run a read-only `ledger` subcommand of the node's aot binary against
+    /// the node's ledger, returning the command's output
+    async fn exec_aot(args: Vec<String>) -> Result<AotExecOutput, AgentError>;
+
+    /// Get the command line the running node was started with, with its
+    /// private key and url credentials redacted. Environment variables are
+    /// listed before the program as `NAME=value`.
+    async fn get_node_command() -> Option<Vec<String>>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AotExecOutput {
+    /// Exit code of the command, absent if it was terminated by a signal
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -80,4 +102,9 @@ pub struct AgentStatus {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AgentMetric {
     Tps,
+    /// Round trip time of the agent's latest ping to the control plane, in
+    /// milliseconds
+    PingRtt,
+    /// Number of the agent's log lines buffered while loki is unreachable
+    LokiBuffered,
 }
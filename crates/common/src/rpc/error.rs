@@ -91,6 +91,12 @@ pub enum AgentError {
     InvalidBlockHash,
     #[error("invalid transaction id")]
     InvalidTransactionId,
+    #[error("command not allowed: `{0}`")]
+    ExecNotAllowed(String),
+    #[error("command timed out")]
+    ExecTimedOut,
+    #[error("command requires the node to be stopped: `{0}`")]
+    ExecNodeRunning(String),
 }
 
 #[derive(Debug, Error, Serialize, Deserialize, AsRefStr)]
@@ -166,4 +172,20 @@ pub enum ReconcileError {
     NoAvailableCheckpoints(HeightRequest),
     #[error("failed to apply checkpoint: {0}")]
     CheckpointApplyError(String),
+    #[error("binary self-test failed for {0}: {1}")]
+    BinarySelfTest(PathBuf, String),
+    #[error("node binary is missing at {0}")]
+    BinaryMissing(PathBuf),
+    #[error("failed to modify ledger to height {0}")]
+    LedgerModifyFailed(HeightRequest),
+    #[error("insufficient disk space for {path}: {required} bytes required, {available} available")]
+    InsufficientDisk {
+        path: PathBuf,
+        required: u64,
+        available: u64,
+    },
+    #[error("height {0} is unreachable from current height {1}")]
+    HeightUnreachable(HeightRequest, u32),
+    #[error("height {0} is relative to the current height, which is not known yet")]
+    HeightUnknown(HeightRequest),
 }
@@ -19,6 +19,28 @@ pub struct AgentModeOptions {
     /// Enable functioning as a compute target when inventoried
     #[arg(long)]
     pub compute: bool,
+
+    /// Only function as a compute target and never run a node. Implies
+    /// `--compute` and overrides the node modes
+    #[arg(long)]
+    #[serde(default)]
+    pub compute_only: bool,
+}
+
+impl AgentModeOptions {
+    /// Compute-only agents always compute and never run a node
+    pub fn normalized(self) -> Self {
+        if !self.compute_only {
+            return self;
+        }
+        Self {
+            validator: false,
+            prover: false,
+            client: false,
+            compute: true,
+            compute_only: true,
+        }
+    }
 }
 
 impl From<AgentModeOptions> for u8 {
@@ -27,6 +49,7 @@ impl From<AgentModeOptions> for u8 {
             | ((mode.prover as u8) << 1)
             | ((mode.client as u8) << 2)
             | ((mode.compute as u8) << 3)
+            | ((mode.compute_only as u8) << 4)
     }
 }
 
@@ -37,7 +60,9 @@ impl From<u8> for AgentModeOptions {
             prover: mode & (1 << 1) != 0,
             client: mode & (1 << 2) != 0,
             compute: mode & (1 << 3) != 0,
+            compute_only: mode & (1 << 4) != 0,
         }
+        .normalized()
     }
 }
 
@@ -59,7 +84,12 @@ impl Display for AgentModeOptions {
             }
             s.push_str("client");
         }
-        if self.compute {
+        if self.compute_only {
+            if !s.is_empty() {
+                s.push_str(", ");
+            }
+            s.push_str("compute-only");
+        } else if self.compute {
             if !s.is_empty() {
                 s.push_str(", ");
             }
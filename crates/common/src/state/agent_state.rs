@@ -10,6 +10,28 @@ pub enum AgentState {
     Node(EnvId, Box<NodeState>),
 }
 
+/// Why an agent was moved to the inventory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InventoryReason {
+    /// The agent's environment was deleted
+    EnvDeleted(EnvId),
+    /// The agent's node was removed when its environment was re-applied
+    RemovedFromEnv(EnvId),
+    /// The agent's environment no longer exists on the control plane
+    EnvMissing(EnvId),
+}
+
+impl std::fmt::Display for InventoryReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EnvDeleted(env) => write!(f, "env {env} was deleted"),
+            Self::RemovedFromEnv(env) => write!(f, "removed from env {env}"),
+            Self::EnvMissing(env) => write!(f, "env {env} is missing"),
+        }
+    }
+}
+
 impl AgentState {
     pub fn map_node<F>(self, f: F) -> AgentState
     where
@@ -22,6 +22,8 @@ pub enum NodeStatus {
     Running { running_status: SnarkOSStatus },
     /// The node has exited with a status code
     Exited(u8),
+    /// The node aborted after failing to allocate within its memory limit
+    OutOfMemory,
     /// The node was online and is in the process of shutting down
     Stopping,
     /// The node has been stopped and some extra time is needed before it can be
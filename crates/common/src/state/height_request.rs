@@ -13,6 +13,13 @@ impl FromStr for HeightRequest {
             s => {
                 if let Ok(height) = s.parse() {
                     Ok(HeightRequest::Absolute(height))
+                } else if let Some(offset) = s
+                    .starts_with('-')
+                    .then(|| s.parse::<i64>().ok())
+                    .flatten()
+                    .filter(|offset| *offset < 0)
+                {
+                    Ok(HeightRequest::Relative(offset))
                 } else if let Ok(span) = s.parse() {
                     Ok(HeightRequest::Checkpoint(span))
                 } else {
@@ -29,6 +36,7 @@ impl Display for HeightRequest {
             HeightRequest::Top => write!(f, "top"),
             HeightRequest::Absolute(h) => write!(f, "{h}"),
             HeightRequest::Checkpoint(c) => write!(f, "{c}"),
+            HeightRequest::Relative(offset) => write!(f, "{offset}"),
         }
     }
 }
@@ -45,6 +53,9 @@ pub enum HeightRequest {
     Absolute(u32),
     /// Use the next checkpoint that matches this checkpoint span
     Checkpoint(snops_checkpoint::RetentionSpan),
+    /// Rewind the given (negative) number of blocks from the node's current
+    /// height, using the nearest checkpoint at or below the resulting height
+    Relative(i64),
     // the control plane doesn't know the heights the nodes are at
     // TruncateHeight(u32),
     // TruncateTime(i64),
@@ -52,7 +63,7 @@ pub enum HeightRequest {
 
 impl DataFormat for HeightRequest {
     type Header = (u8, DataHeaderOf<RetentionSpan>);
-    const LATEST_HEADER: Self::Header = (1, RetentionSpan::LATEST_HEADER);
+    const LATEST_HEADER: Self::Header = (2, RetentionSpan::LATEST_HEADER);
 
     fn write_data<W: std::io::prelude::Write>(
         &self,
@@ -66,6 +77,9 @@ impl DataFormat for HeightRequest {
             HeightRequest::Checkpoint(retention) => {
                 Ok(2u8.write_data(writer)? + retention.write_data(writer)?)
             }
+            HeightRequest::Relative(offset) => {
+                Ok(3u8.write_data(writer)? + offset.write_data(writer)?)
+            }
         }
     }
 
@@ -73,7 +87,7 @@ impl DataFormat for HeightRequest {
         reader: &mut R,
         header: &Self::Header,
     ) -> Result<Self, DataReadError> {
-        if header.0 != Self::LATEST_HEADER.0 {
+        if header.0 == 0 || header.0 > Self::LATEST_HEADER.0 {
             return Err(DataReadError::unsupported(
                 "HeightRequest",
                 Self::LATEST_HEADER.0,
@@ -84,6 +98,8 @@ impl DataFormat for HeightRequest {
             0u8 => Ok(HeightRequest::Top),
             1u8 => Ok(HeightRequest::Absolute(reader.read_data(&())?)),
             2u8 => Ok(HeightRequest::Checkpoint(reader.read_data(&header.1)?)),
+            // relative heights were added in version 2
+            3u8 if header.0 > 1 => Ok(HeightRequest::Relative(reader.read_data(&())?)),
             n => Err(DataReadError::Custom(format!(
                 "invalid HeightRequest discriminant: {n}"
             ))),
@@ -102,3 +118,20 @@ impl HeightRequest {
         *self == Self::Absolute(0) || *self == Self::Checkpoint(RetentionSpan::Unlimited)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_relative_height() {
+        assert_eq!("-100".parse(), Ok(HeightRequest::Relative(-100)));
+        assert_eq!("100".parse(), Ok(HeightRequest::Absolute(100)));
+        assert!("-0".parse::<HeightRequest>().is_err());
+        assert_eq!(HeightRequest::Relative(-100).to_string(), "-100");
+        assert_eq!(
+            serde_json::from_str::<HeightRequest>("-5").unwrap(),
+            HeightRequest::Relative(-5)
+        );
+    }
+}
@@ -6,6 +6,9 @@ use serde::de::Error;
 use super::INTERNED_ID_REGEX;
 use crate::{INTERN, format::DataFormat};
 
+/// Most ids suggested by [`InternedId::fuzzy_candidates`]
+pub const MAX_ID_CANDIDATES: usize = 8;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct InternedId(lasso::Spur);
 
@@ -26,6 +29,55 @@ impl InternedId {
     pub fn compute_id() -> Self {
         Self(INTERN.get_or_intern("compute"))
     }
+
+    /// Find the ids a partial or mistyped id could refer to, best match
+    /// first. Ids starting with or containing the partial id rank ahead of
+    /// ids within a few typos of it. An exact match is the only candidate.
+    pub fn fuzzy_candidates(partial: &str, ids: impl IntoIterator<Item = Self>) -> Vec<Self> {
+        let max_distance = (partial.len() / 3).max(1);
+        let lower = partial.to_ascii_lowercase();
+
+        let mut ranked = vec![];
+        for id in ids {
+            let name: &str = id.as_ref();
+            if name == partial {
+                return vec![id];
+            }
+
+            let name = name.to_ascii_lowercase();
+            let partial = lower.as_str();
+            let rank = if name.starts_with(partial) {
+                (0, name.len() - partial.len())
+            } else if name.contains(partial) {
+                (1, name.len() - partial.len())
+            } else {
+                match edit_distance(partial, &name) {
+                    distance if distance <= max_distance => (2, distance),
+                    _ => continue,
+                }
+            };
+            ranked.push((rank, id));
+        }
+
+        ranked.sort_unstable();
+        ranked.truncate(MAX_ID_CANDIDATES);
+        ranked.into_iter().map(|(_, id)| id).collect()
+    }
+}
+
+/// The levenshtein distance between two ascii strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.bytes().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.bytes().enumerate() {
+            row[j + 1] = (prev[j] + usize::from(ca != cb))
+                .min(prev[j + 1] + 1)
+                .min(row[j] + 1);
+        }
+        prev = row;
+    }
+    prev[b.len()]
 }
 
 impl Default for InternedId {
@@ -146,4 +198,24 @@ mod test {
         let id2 = InternedId::read_data(&mut buf.as_slice(), &()).unwrap();
         assert_eq!(id, id2);
     }
+
+    #[test]
+    fn test_fuzzy_candidates() {
+        let ids = ["default", "devnet-a", "devnet-b", "canary-1", "mainnet"]
+            .map(|id| InternedId::from_str(id).unwrap());
+        let candidates = |partial: &str| {
+            InternedId::fuzzy_candidates(partial, ids)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(candidates("default"), ["default"]);
+        assert_eq!(candidates("devnet"), ["devnet-a", "devnet-b"]);
+        assert_eq!(candidates("Canary"), ["canary-1"]);
+        assert_eq!(candidates("net-b"), ["devnet-b"]);
+        assert_eq!(candidates("defualt"), ["default"]);
+        assert_eq!(candidates("mainet"), ["mainnet"]);
+        assert!(candidates("testnet").is_empty());
+    }
 }
@@ -1,8 +1,8 @@
-use std::{fmt::Write, str::FromStr};
+use std::{fmt::Write, ops::Range, str::FromStr};
 
 use serde::de::Error;
 
-use super::{NODE_KEY_REGEX, NodeType};
+use super::{NODE_KEY_REGEX, NetworkId, NodeType};
 use crate::format::{DataFormat, DataFormatReader, DataFormatWriter, DataHeaderOf};
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -11,6 +11,9 @@ pub struct NodeKey {
     pub id: String,
     /// The node key namespace. If `None`, is a local node.
     pub ns: Option<String>, // TODO: string interning or otherwise not duplicating namespace
+    /// The network of the node (`canary/validator/0`). If `None`, the network
+    /// is inferred from the environment.
+    pub network: Option<NetworkId>,
 }
 
 impl FromStr for NodeKey {
@@ -21,6 +24,11 @@ impl FromStr for NodeKey {
             return Err("invalid node key string");
         };
 
+        // match the network
+        let network = captures
+            .name("net")
+            .map(|net| NetworkId::from_str(net.as_str()).unwrap());
+
         // match the type
         let ty = NodeType::from_str(&captures["ty"]).unwrap();
 
@@ -37,7 +45,30 @@ impl FromStr for NodeKey {
             Some(id) => Some(id.as_str().into()),
         };
 
-        Ok(Self { ty, id, ns })
+        Ok(Self {
+            ty,
+            id,
+            ns,
+            network,
+        })
+    }
+}
+
+impl NodeKey {
+    /// The range of indices a ranged node key (`validator/10..20`) expands
+    /// into. The end of the range is exclusive.
+    pub fn id_range(&self) -> Option<Range<usize>> {
+        let (start, end) = self.id.split_once("..")?;
+        Some(start.parse().ok()?..end.parse().ok()?)
+    }
+
+    /// This key without its network, as the key is named inside of an
+    /// environment
+    pub fn without_network(&self) -> Self {
+        Self {
+            network: None,
+            ..self.clone()
+        }
     }
 }
 
@@ -53,6 +84,9 @@ impl<'de> serde::Deserialize<'de> for NodeKey {
 
 impl std::fmt::Display for NodeKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(network) = self.network {
+            write!(f, "{network}/")?;
+        }
         write!(f, "{}/{}", self.ty, self.id)?;
         if let Some(ns) = &self.ns {
             f.write_char('@')?;
@@ -74,7 +108,8 @@ impl serde::Serialize for NodeKey {
 
 impl DataFormat for NodeKey {
     type Header = (u8, DataHeaderOf<NodeType>);
-    const LATEST_HEADER: Self::Header = (1, NodeType::LATEST_HEADER);
+    /// Version 2 adds the network
+    const LATEST_HEADER: Self::Header = (2, NodeType::LATEST_HEADER);
 
     fn write_data<W: std::io::Write>(
         &self,
@@ -84,6 +119,7 @@ impl DataFormat for NodeKey {
         written += writer.write_data(&self.ty)?;
         written += writer.write_data(&self.id)?;
         written += writer.write_data(&self.ns)?;
+        written += writer.write_data(&self.network)?;
         Ok(written)
     }
 
@@ -91,10 +127,10 @@ impl DataFormat for NodeKey {
         reader: &mut R,
         header: &Self::Header,
     ) -> Result<Self, crate::format::DataReadError> {
-        if header.0 != Self::LATEST_HEADER.0 {
+        if header.0 == 0 || header.0 > Self::LATEST_HEADER.0 {
             return Err(crate::format::DataReadError::unsupported(
                 "NodeKey",
-                Self::LATEST_HEADER.0,
+                format!("1 or {}", Self::LATEST_HEADER.0),
                 header.0,
             ));
         }
@@ -102,7 +138,50 @@ impl DataFormat for NodeKey {
         let ty = reader.read_data(&header.1)?;
         let id = reader.read_data(&())?;
         let ns = reader.read_data(&())?;
+        let network = if header.0 > 1 {
+            reader.read_data(&NetworkId::LATEST_HEADER)?
+        } else {
+            None
+        };
+
+        Ok(Self {
+            ty,
+            id,
+            ns,
+            network,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_node_key_range() {
+        let key = NodeKey::from_str("validator/10..20").unwrap();
+        assert_eq!(key.id_range(), Some(10..20));
+
+        let key = NodeKey::from_str("client/10..20@foo").unwrap();
+        assert_eq!(key.id_range(), Some(10..20));
+        assert_eq!(key.ns.as_deref(), Some("foo"));
+
+        assert_eq!(NodeKey::from_str("validator/10").unwrap().id_range(), None);
+        assert!(NodeKey::from_str("validator/a..b").is_err());
+        assert!(NodeKey::from_str("validator/1..").is_err());
+    }
 
-        Ok(Self { ty, id, ns })
+    #[test]
+    fn test_node_key_network() {
+        let key = NodeKey::from_str("canary/validator/0@foo").unwrap();
+        assert_eq!(key.network, Some(NetworkId::Canary));
+        assert_eq!(key.ty, NodeType::Validator);
+        assert_eq!(key.id, "0");
+        assert_eq!(key.to_string(), "canary/validator/0@foo");
+        assert_eq!(key.without_network().to_string(), "validator/0@foo");
+
+        // unprefixed keys leave the network to be inferred
+        assert_eq!(NodeKey::from_str("validator/0").unwrap().network, None);
+        assert!(NodeKey::from_str("devnet/validator/0").is_err());
     }
 }
@@ -1,4 +1,4 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
 use indexmap::IndexMap;
 
@@ -17,6 +17,12 @@ pub struct NodeState {
     pub validators: Vec<AgentPeer>,
     pub env: IndexMap<String, String>,
     pub binary: Option<InternedId>,
+    /// Address to bind the node to instead of the agent's bind address
+    pub bind_addr: Option<IpAddr>,
+    /// CPU limit of the node process in millicores (1000 is one core)
+    pub cpu_limit: Option<u32>,
+    /// Memory limit of the node process in MiB
+    pub memory_limit: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -70,7 +76,7 @@ impl DataFormat for NodeStateFormatHeader {
 impl DataFormat for NodeState {
     type Header = NodeStateFormatHeader;
     const LATEST_HEADER: Self::Header = NodeStateFormatHeader {
-        version: 2,
+        version: 4,
         node_key: NodeKey::LATEST_HEADER,
         key_state: KeyState::LATEST_HEADER,
         height: HeightRequest::LATEST_HEADER,
@@ -91,6 +97,9 @@ impl DataFormat for NodeState {
         written += self.validators.write_data(writer)?;
         written += self.env.write_data(writer)?;
         written += self.binary.write_data(writer)?;
+        written += self.bind_addr.write_data(writer)?;
+        written += self.cpu_limit.write_data(writer)?;
+        written += self.memory_limit.write_data(writer)?;
         Ok(written)
     }
 
@@ -119,6 +128,16 @@ impl DataFormat for NodeState {
         } else {
             None
         };
+        let bind_addr = if header.version > 2 {
+            reader.read_data(&())?
+        } else {
+            None
+        };
+        let (cpu_limit, memory_limit) = if header.version > 3 {
+            (reader.read_data(&())?, reader.read_data(&())?)
+        } else {
+            (None, None)
+        };
 
         Ok(NodeState {
             node_key,
@@ -129,6 +148,9 @@ impl DataFormat for NodeState {
             validators,
             env,
             binary,
+            bind_addr,
+            cpu_limit,
+            memory_limit,
         })
     }
 }
@@ -295,6 +317,9 @@ mod tests {
             validators: vec![],
             env: Default::default(),
             binary: None,
+            bind_addr: None,
+            cpu_limit: None,
+            memory_limit: None,
         },
         [
             NodeStateFormatHeader::LATEST_HEADER.to_byte_vec()?,
@@ -308,6 +333,9 @@ mod tests {
                 validators: vec![],
                 env: Default::default(),
                 binary: None,
+                bind_addr: None,
+                cpu_limit: None,
+                memory_limit: None,
             }
             .to_byte_vec()?,
         ]
@@ -13,6 +13,9 @@ pub struct ReconcileOptions {
     pub force_shutdown: bool,
     /// When true, the reconciler will clear the last height
     pub clear_last_height: bool,
+    /// When true, a running and healthy node is not restarted to apply the
+    /// new state. The change is deferred until the node next goes offline.
+    pub only_if_offline: bool,
 }
 
 impl ReconcileOptions {
@@ -21,6 +24,8 @@ impl ReconcileOptions {
             refetch_info: self.refetch_info || other.refetch_info,
             force_shutdown: self.force_shutdown || other.force_shutdown,
             clear_last_height: self.clear_last_height || other.clear_last_height,
+            // a restart is only deferred if every merged request allows it
+            only_if_offline: self.only_if_offline && other.only_if_offline,
         }
     }
 }
@@ -44,10 +49,14 @@ pub enum ReconcileCondition {
     InterruptedModify { reason: String },
     /// A file is missing and cannot be downloaded at the moment.
     MissingFile { path: String },
+    /// The file server is busy and asked to retry the transfer later.
+    ThrottledTransfer { source: String, retry_after: u64 },
     /// Waiting to reconnect to the controlplane
     PendingConnection,
     /// Waiting for the node to be shut down
     PendingShutdown,
+    /// A state change is deferred until the node goes offline
+    DeferredRestart,
     /// Waiting for the node to start up
     PendingStartup,
 }
@@ -158,3 +167,24 @@ impl<T> ReconcileStatus<T> {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_union_only_if_offline() {
+        let deferred = ReconcileOptions {
+            only_if_offline: true,
+            ..Default::default()
+        };
+        let forced = ReconcileOptions {
+            force_shutdown: true,
+            ..Default::default()
+        };
+
+        assert!(deferred.union(deferred).only_if_offline);
+        assert!(!deferred.union(forced).only_if_offline);
+        assert!(deferred.union(forced).force_shutdown);
+    }
+}
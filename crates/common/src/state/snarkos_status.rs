@@ -62,6 +62,12 @@ pub struct SnarkOSBlockInfo {
 pub struct SnarkOSLiteBlock {
     pub info: SnarkOSBlockInfo,
     pub transactions: Vec<String>,
+    /// Ids of transactions that were rejected in this block
+    #[serde(default)]
+    pub rejected: Vec<String>,
+    /// Ids of transactions that were aborted in this block
+    #[serde(default)]
+    pub aborted: Vec<String>,
 }
 
 impl SnarkOSLiteBlock {
@@ -66,14 +66,26 @@ impl DataFormat for TransactionSendState {
         reader: &mut R,
         header: &Self::Header,
     ) -> Result<Self, crate::format::DataReadError> {
-        if *header != Self::LATEST_HEADER {
-            return Err(crate::format::DataReadError::unsupported(
+        match header {
+            1 => Self::read_v1(reader),
+            _ => Err(crate::format::DataReadError::unsupported(
                 "CannonTransactionStatus",
                 Self::LATEST_HEADER,
                 *header,
-            ));
+            )),
         }
+    }
+}
 
+impl TransactionSendState {
+    /// Read a status written with the version 1 layout.
+    ///
+    /// When the layout changes, `LATEST_HEADER` is bumped and the reader for
+    /// the previous version is kept here so older records are upgraded to the
+    /// current variants rather than dropped. Versions without a reader,
+    /// including versions newer than `LATEST_HEADER`, are rejected with an
+    /// unsupported version error.
+    fn read_v1<R: std::io::Read>(reader: &mut R) -> Result<Self, crate::format::DataReadError> {
         let tag = u8::read_data(reader, &())?;
         Ok(match tag {
             0 => TransactionSendState::Authorized,
@@ -97,7 +109,7 @@ mod test {
     use chrono::DateTime;
 
     use super::TransactionSendState;
-    use crate::format::DataFormat;
+    use crate::format::{DataFormat, DataReadError, read_dataformat};
 
     macro_rules! case {
         ($name:ident, $ty:ty, $a:expr_2021, $b:expr_2021) => {
@@ -151,4 +163,49 @@ mod test {
         ]
         .concat()
     );
+
+    /// Read a status persisted with its header, as it is stored in the db
+    fn read_fixture(fixture: &[u8]) -> Result<TransactionSendState, DataReadError> {
+        read_dataformat(&mut &fixture[..])
+    }
+
+    #[test]
+    fn test_cannon_transaction_status_v1_fixtures() {
+        // each status as persisted by version 1, including the header
+        let timestamp = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let timestamp_bytes: [u8; 12] = [
+            0x00, 0xf1, 0x53, 0x65, 0, 0, 0, 0, // 1700000000 seconds
+            0, 0, 0, 0, // 0 nanoseconds
+        ];
+
+        assert_eq!(
+            read_fixture(&[1, 0]).unwrap(),
+            TransactionSendState::Authorized
+        );
+        assert_eq!(
+            read_fixture(&[[1, 1].as_slice(), &timestamp_bytes].concat()).unwrap(),
+            TransactionSendState::Executing(timestamp)
+        );
+        assert_eq!(read_fixture(&[1, 2]).unwrap(), TransactionSendState::Unsent);
+        assert_eq!(
+            read_fixture(&[[1, 3, 1, 0x2a, 0, 0, 0].as_slice(), &timestamp_bytes].concat())
+                .unwrap(),
+            TransactionSendState::Broadcasted(Some(42), timestamp)
+        );
+        assert_eq!(
+            read_fixture(&[[1, 3, 0].as_slice(), &timestamp_bytes].concat()).unwrap(),
+            TransactionSendState::Broadcasted(None, timestamp)
+        );
+    }
+
+    #[test]
+    fn test_cannon_transaction_status_unknown_version() {
+        // statuses with a version that has no reader are never guessed
+        for header in [0u8, TransactionSendState::LATEST_HEADER + 1, u8::MAX] {
+            assert!(matches!(
+                read_fixture(&[header, 2]),
+                Err(DataReadError::UnsupportedVersion(_))
+            ));
+        }
+    }
 }
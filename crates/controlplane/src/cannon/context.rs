@@ -1,28 +1,53 @@
-use std::sync::{Arc, atomic::AtomicUsize};
+use std::{
+    sync::{Arc, Mutex, atomic::AtomicUsize},
+    time::Duration,
+};
 
 use chrono::Utc;
 use dashmap::DashMap;
 use futures_util::{StreamExt, stream::FuturesUnordered};
 use lazysort::SortedBy;
+use rand_chacha::ChaChaRng;
 use snops_common::{
+    action_models::{AleoValue, ExecuteAction},
+    aot_cmds::AotCmd,
     events::{Event, TransactionAbortReason, TransactionEvent},
+    node_targets::NodeTargets,
     state::{AgentId, Authorization, CannonId, EnvId, NetworkId, TransactionSendState},
 };
-use tracing::{error, trace, warn};
+use tokio::{
+    sync::OnceCell,
+    time::{Instant, MissedTickBehavior},
+};
+use tracing::{error, info, trace, warn};
 
 use super::{
     CannonReceivers,
-    error::{CannonError, ExecutionContextError, SourceError},
+    error::{AuthorizeError, CannonError, ExecutionContextError, SourceError},
     file::TransactionSink,
-    sink::TxSink,
+    generate::{InputGen, TxGenerator},
+    sink::{FiringPacer, TxSink},
     source::TxSource,
     tracker::TransactionTracker,
 };
 use crate::{
     cannon::source::ComputeTarget,
+    env::{
+        Environment,
+        error::{EnvRequestError, ExecutionError},
+    },
+    server::actions::execute::execute_inner,
     state::{EmitEvent, GetGlobalState, GlobalState, REST_CLIENT},
 };
 
+/// How often the target's mempool depth is checked when pacing is enabled
+const MEMPOOL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Maximum number of generated authorizations in flight at once
+const MAX_PENDING_GENERATIONS: usize = 16;
+/// How often the execution limit is recounted from the compute agents in the
+/// pool
+const EXECUTION_LIMIT_REFRESH: Duration = Duration::from_secs(5);
+
 /// Information a transaction cannon needs for execution via spawned task
 pub struct ExecutionContext {
     pub(crate) state: Arc<GlobalState>,
@@ -35,6 +60,8 @@ pub struct ExecutionContext {
     pub(crate) sink: TxSink,
     pub(crate) fired_txs: Arc<AtomicUsize>,
     pub(crate) transactions: Arc<DashMap<Arc<String>, TransactionTracker>>,
+    /// Picks the generated inputs and keys, seeded by the source's `seed`
+    pub(crate) rng: Mutex<ChaChaRng>,
 }
 
 impl ExecutionContext {
@@ -85,14 +112,43 @@ impl ExecutionContext {
         let mut auth_execs = FuturesUnordered::new();
         let mut tx_shots = FuturesUnordered::new();
 
+        // the execution limit follows the number of compute agents, which is
+        // recounted periodically instead of scanning the pool per authorization
+        let mut execution_limit = source.execution_limit(state);
+        let mut execution_limit_interval = tokio::time::interval(EXECUTION_LIMIT_REFRESH);
+        execution_limit_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // pause broadcasting while the target's mempool is deeper than the cap
+        let mempool_pacing = sink.target.as_ref().zip(sink.max_mempool_depth);
+        let mut mempool_interval = tokio::time::interval(MEMPOOL_POLL_INTERVAL);
+        let mut mempool_checks = FuturesUnordered::new();
+        let mut mempool_full = false;
+
+        // hold broadcasts until the firing profile allows the next shot
+        let mut pacer = sink
+            .profile
+            .map(|profile| FiringPacer::new(profile, Instant::now().into_std()));
+        let mut next_shot: Option<Instant> = None;
+
+        // generate authorizations at the generator's rate
+        let generator = source.generate.as_ref();
+        let mut generate_interval = tokio::time::interval(
+            Duration::from_secs(1) / generator.map_or(1, |g| g.per_sec.max(1)),
+        );
+        generate_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut auth_gens = FuturesUnordered::new();
+        // the function's input types are resolved on the first generation
+        let input_gens = OnceCell::new();
+
         loop {
             tokio::select! {
                 // ------------------------
                 // Work generation
                 // ------------------------
 
-                // receive authorizations and forward the executions to the compute target
-                Some(tx_id) = rx.authorizations.recv() => {
+                // receive authorizations and forward the executions to the compute target,
+                // leaving them queued while the cannon is at its execution limit
+                Some(tx_id) = rx.authorizations.recv(), if auth_execs.len() < execution_limit => {
                     // ensure the transaction tracker exists
                     let Some(tracker) = self.transactions.get(&tx_id) else {
                         error!("cannon {env_id}.{cannon_id} missing transaction tracker for {tx_id}");
@@ -116,10 +172,25 @@ impl ExecutionContext {
 
                     auth_execs.push(self.execute_auth(tx_id, Arc::clone(auth), &query_path));
                 }
+                _ = generate_interval.tick(), if generator.is_some() && auth_gens.len() < MAX_PENDING_GENERATIONS => {
+                    if let Some(generator) = generator {
+                        auth_gens.push(self.generate_auth(generator, &input_gens));
+                    }
+                }
                 // receive transaction ids and forward them to the sink target
-                Some(tx) = rx.transactions.recv() => {
+                Some(tx) = rx.transactions.recv(), if !mempool_full && next_shot.is_none() => {
+                    if let Some(pacer) = pacer.as_mut() {
+                        let now = Instant::now();
+                        let delay = pacer.record_shot(now.into_std());
+                        if !delay.is_zero() {
+                            next_shot = Some(now + delay);
+                        }
+                    }
                     tx_shots.push(self.fire_tx(sink_pipe.clone(), tx));
                 }
+                _ = tokio::time::sleep_until(next_shot.unwrap_or_else(Instant::now)), if next_shot.is_some() => {
+                    next_shot = None;
+                }
 
                 // ------------------------
                 // Work results
@@ -130,6 +201,43 @@ impl ExecutionContext {
                         warn!("cannon {env_id}.{cannon_id} auth execute task {tx_id} failed: {e}");
                     }
                 },
+                Some(res) = auth_gens.next() => {
+                    match res {
+                        Ok(tx_id) => trace!("cannon {env_id}.{cannon_id} generated auth {tx_id}"),
+                        Err(e) => warn!("cannon {env_id}.{cannon_id} failed to generate an auth: {e}"),
+                    }
+                },
+                _ = execution_limit_interval.tick() => {
+                    execution_limit = source.execution_limit(state);
+                }
+                _ = mempool_interval.tick(), if mempool_pacing.is_some() && mempool_checks.is_empty() => {
+                    if let Some((target, _)) = mempool_pacing {
+                        mempool_checks.push(self.mempool_depth(target));
+                    }
+                }
+                Some(res) = mempool_checks.next() => {
+                    let Some((_, max_depth)) = mempool_pacing else {
+                        continue;
+                    };
+                    match res {
+                        Ok(depth) => {
+                            let full = depth > max_depth;
+                            if full != mempool_full {
+                                if full {
+                                    info!("cannon {env_id}.{cannon_id} pausing broadcasts, mempool depth {depth} exceeds {max_depth}");
+                                } else {
+                                    info!("cannon {env_id}.{cannon_id} resuming broadcasts, mempool depth {depth}");
+                                }
+                            }
+                            mempool_full = full;
+                        }
+                        // don't stall the cannon if the depth is unavailable
+                        Err(e) => {
+                            warn!("cannon {env_id}.{cannon_id} failed to get mempool depth: {e}");
+                            mempool_full = false;
+                        }
+                    }
+                }
                 Some(res) = tx_shots.next() => {
                     match res {
                         Ok(tx_id) => {
@@ -200,7 +308,11 @@ impl ExecutionContext {
             Err(e) => {
                 // reset the transaction status to authorized so it can be re-executed
                 self.write_tx_status(&tx_id, TransactionSendState::Authorized);
-                TransactionEvent::ExecuteFailed(e.to_string())
+                let reason = match &e {
+                    CannonError::Source(SourceError::ExecutionTimedOut(_)) => "timeout".to_owned(),
+                    e => e.to_string(),
+                };
+                TransactionEvent::ExecuteFailed(reason)
                     .with_cannon_ctx(self, tx_id.clone())
                     .emit(self);
                 Err((tx_id, e))
@@ -209,6 +321,102 @@ impl ExecutionContext {
         }
     }
 
+    /// Authorize an execution of the generator's function with random inputs,
+    /// then queue it like an authorization received from a client
+    async fn generate_auth(
+        &self,
+        generator: &TxGenerator,
+        input_gens: &OnceCell<Vec<InputGen>>,
+    ) -> Result<Arc<String>, ExecutionError> {
+        let env = self
+            .state
+            .get_env(self.env_id)
+            .ok_or_else(|| ExecutionError::EnvNotFound(self.env_id.to_string()))?;
+        let input_gens = input_gens
+            .get_or_try_init(|| self.resolve_inputs(generator, &env))
+            .await?;
+
+        // keys are resolved here so a seeded cannon picks the same accounts
+        let execution = generator
+            .sample(input_gens, &env.storage, &mut *self.rng.lock().unwrap())
+            .map_err(|e| {
+                AuthorizeError::InvalidProgramInputs(
+                    format!("{}/{}", generator.program, generator.function),
+                    e,
+                )
+            })?;
+
+        let action = ExecuteAction {
+            private_key: execution.private_key,
+            fee_private_key: execution.fee_private_key,
+            program: generator.program.clone(),
+            function: generator.function.clone(),
+            cannon: self.id.to_string(),
+            inputs: execution.inputs.into_iter().map(AleoValue::Other).collect(),
+            priority_fee: None,
+            fee_record: None,
+        };
+        let query = env.cannons.get(&self.id).map(|c| c.get_local_query());
+        execute_inner(&self.state, action, &env, query).await
+    }
+
+    /// Resolve the input types of the generator's function from the program
+    /// deployed on the env's nodes
+    async fn resolve_inputs(
+        &self,
+        generator: &TxGenerator,
+        env: &Environment,
+    ) -> Result<Vec<InputGen>, ExecutionError> {
+        let TxGenerator {
+            program, function, ..
+        } = generator;
+        let label = format!("{program}/{function}");
+
+        let source = self
+            .state
+            .snarkos_get::<String>(
+                self.env_id,
+                format!("/program/{program}"),
+                &NodeTargets::ALL,
+            )
+            .await
+            .map_err(CannonError::from)?;
+        let compute_bin = env.storage.resolve_compute_binary(&self.state).await?;
+        let functions = AotCmd::new(compute_bin, self.network)
+            .program_functions(&source)
+            .await?;
+
+        let Some(signature) = functions.get(function) else {
+            return Err(
+                AuthorizeError::InvalidProgramInputs(label, "unknown function".to_owned()).into(),
+            );
+        };
+
+        signature
+            .inputs
+            .iter()
+            .map(|ty| {
+                InputGen::parse(ty).ok_or_else(|| {
+                    AuthorizeError::InvalidProgramInputs(
+                        label.clone(),
+                        format!("cannot generate inputs of type {ty}"),
+                    )
+                    .into()
+                })
+            })
+            .collect()
+    }
+
+    /// Get the number of transactions in the mempool of the best scoring
+    /// target node
+    async fn mempool_depth(&self, target: &NodeTargets) -> Result<usize, EnvRequestError> {
+        Ok(self
+            .state
+            .snarkos_get::<Vec<serde_json::Value>>(self.env_id, "/memoryPool/transactions", target)
+            .await?
+            .len())
+    }
+
     /// Fire a transaction to the sink
     async fn fire_tx(
         &self,
@@ -320,11 +528,18 @@ impl ExecutionContext {
 
                 if let Some(addr) = addr {
                     let url = format!("http://{addr}/{network}/transaction/broadcast");
-                    let req = REST_CLIENT
+                    let mut req = REST_CLIENT
                         .post(url)
                         .header("Content-Type", "application/json")
-                        .body(tx_str.clone())
-                        .send();
+                        .body(tx_str.clone());
+                    if let Some(headers) = self
+                        .state
+                        .get_env(env_id)
+                        .and_then(|env| env.external_rest_headers(addr))
+                    {
+                        req = headers.apply(req);
+                    }
+                    let req = req.send();
                     let Ok(res) =
                         tokio::time::timeout(std::time::Duration::from_secs(5), req).await
                     else {
@@ -7,7 +7,7 @@ use snops_common::{
     db::error::DatabaseError,
     impl_into_status_code, impl_into_type_str,
     node_targets::NodeTargets,
-    state::{CannonId, EnvId, TxPipeId},
+    state::{AgentId, CannonId, EnvId, TxPipeId},
 };
 use strum_macros::AsRefStr;
 use thiserror::Error;
@@ -26,10 +26,13 @@ pub enum AuthorizeError {
     InvalidProgramInputs(String, String),
     #[error("execution {0} requires a valid private key: {1}")]
     MissingPrivateKey(String, String),
+    #[error("failed to resolve priority fee: {0}")]
+    PriorityFee(String),
 }
 
 impl_into_status_code!(AuthorizeError, |value| match value {
     Command(e) => e.into(),
+    PriorityFee(_) => StatusCode::BAD_REQUEST,
     _ => StatusCode::INTERNAL_SERVER_ERROR,
 });
 
@@ -71,6 +74,8 @@ pub enum SourceError {
     CannotAuthorizePlaybackTx,
     #[error("error selecting a valid `{0}`")]
     CouldNotSelect(&'static str),
+    #[error("execution on agent `{0}` timed out")]
+    ExecutionTimedOut(AgentId),
     #[error("error fetching state root from `{0}`: {1}")]
     FailedToGetStateRoot(String, #[source] reqwest::Error),
     #[error("error fetching latest height from `{0}`: {1}")]
@@ -194,6 +199,12 @@ pub enum CannonError {
     InvalidTransactionState(CannonId, String, String),
     #[error("binary error for cannon `{0}`: {1}")]
     BinaryError(CannonId, String),
+    #[error("snapshotting ledger for cannon `{0}`: {1}")]
+    LedgerSnapshot(CannonId, #[source] std::io::Error),
+    #[error("resolving ledger for cannon `{0}`: {1}")]
+    LedgerLocation(CannonId, #[source] std::io::Error),
+    #[error("authorization rate limit exceeded for cannon `{0}`")]
+    RateLimited(CannonId),
 }
 
 impl_into_status_code!(CannonError, |value| match value {
@@ -206,6 +217,7 @@ impl_into_status_code!(CannonError, |value| match value {
     Source(e) => e.into(),
     State(e) => e.into(),
     TransactionAlreadyExists(_, _) => StatusCode::CONFLICT,
+    RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
     _ => StatusCode::INTERNAL_SERVER_ERROR,
 });
 
@@ -1,55 +1,264 @@
 use std::{
     fs::File,
-    io::{BufWriter, Write},
-    path::PathBuf,
+    io::Write,
+    path::{Path, PathBuf},
     sync::Mutex,
 };
 
 use snops_common::state::TxPipeId;
-use tracing::debug;
+use tracing::{debug, info};
 
-use super::error::CannonError;
+use super::{error::CannonError, sink::RotatePolicy};
 use crate::cannon::error::TransactionSinkError;
 
+/// A newline-delimited file of transactions, optionally rotated.
+///
+/// Multiple cannons may share a sink and write to it concurrently. Writes are
+/// serialized by the sink, and each record is written to the file with a
+/// single append so that sinks opened on the same file by different envs
+/// cannot interleave partial lines either.
 #[derive(Debug)]
-pub struct TransactionSink(Mutex<Option<BufWriter<File>>>);
+pub struct TransactionSink {
+    /// Path of the first file in the set, successors are numbered after it
+    path: PathBuf,
+    rotate_after: Option<RotatePolicy>,
+    writer: Mutex<Option<SinkWriter>>,
+}
 
-impl TransactionSink {
-    /// Create a new transaction sink
-    pub fn new(storage_dir: PathBuf, target: TxPipeId) -> Result<Self, CannonError> {
-        let target = storage_dir.join(target.to_string());
-        debug!("opening tx sink @ {target:?}");
+#[derive(Debug)]
+struct SinkWriter {
+    /// The file is unbuffered as every record is flushed as it is written
+    file: File,
+    /// Index of the file being written to, 0 being the un-numbered file
+    index: u32,
+    /// Size of the current file
+    bytes: u64,
+    /// Transactions written to the current file since it was opened
+    lines: u64,
+}
 
-        let f = File::options()
+/// Path of the `index`th file of a rotated sink. The first file keeps the
+/// configured name and its successors are suffixed with their number.
+pub fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    if index == 0 {
+        return path.to_owned();
+    }
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+impl SinkWriter {
+    /// Open the `index`th file of the set for appending. Only its size is
+    /// known, as counting its lines would mean reading the whole file.
+    fn open(path: &Path, index: u32) -> Result<Self, TransactionSinkError> {
+        let path = rotated_path(path, index);
+        debug!("opening tx sink @ {path:?}");
+
+        let file = File::options()
             .create(true)
             .append(true)
-            .open(&target)
-            .map_err(|_| TransactionSinkError::FailedToOpenSource(target))?;
+            .open(&path)
+            .map_err(|_| TransactionSinkError::FailedToOpenSource(path.clone()))?;
+
+        let bytes = file
+            .metadata()
+            .map_err(|_| TransactionSinkError::FailedToOpenSource(path.clone()))?
+            .len();
+        Ok(Self {
+            file,
+            index,
+            bytes,
+            lines: 0,
+        })
+    }
+}
+
+impl TransactionSink {
+    /// Create a new transaction sink
+    pub fn new(
+        storage_dir: PathBuf,
+        target: TxPipeId,
+        rotate_after: Option<RotatePolicy>,
+    ) -> Result<Self, CannonError> {
+        let path = storage_dir.join(target.to_string());
+
+        // resume writing to the newest file of a previously rotated set
+        let mut index = 0;
+        if rotate_after.is_some() {
+            while rotated_path(&path, index + 1).exists() {
+                index += 1;
+            }
+        }
+
+        // the line count of an existing file is unknown, so rotating by lines
+        // starts a new file rather than overfilling the newest one
+        if matches!(rotate_after, Some(RotatePolicy::Lines(_)))
+            && std::fs::metadata(rotated_path(&path, index)).is_ok_and(|meta| meta.len() > 0)
+        {
+            index += 1;
+        }
+
+        let writer = SinkWriter::open(&path, index)?;
 
-        Ok(Self(Mutex::new(Some(BufWriter::new(f)))))
+        Ok(Self {
+            path,
+            rotate_after,
+            writer: Mutex::new(Some(writer)),
+        })
     }
 
     /// Write a line to the transaction sink
     pub fn write(&self, line: &str) -> Result<(), CannonError> {
         let mut lock = self
-            .0
+            .writer
             .lock()
             .map_err(|_| TransactionSinkError::FailedToLock)?;
 
-        if lock.is_none() {
+        let Some(writer) = lock.as_mut() else {
             return Ok(());
+        };
+
+        // move on to the next file before writing if the current one is full.
+        // the handle is only replaced once the successor has been opened
+        if writer.bytes > 0
+            && self
+                .rotate_after
+                .is_some_and(|policy| policy.is_crossed(writer.bytes, writer.lines))
+        {
+            let next = SinkWriter::open(&self.path, writer.index + 1)?;
+            info!(
+                "rotated tx sink {} to file {}",
+                self.path.display(),
+                next.index
+            );
+            *writer = next;
         }
 
-        let writer = lock.as_mut().unwrap();
-        writer
-            .write_all(line.trim().as_bytes())
-            .map_err(TransactionSinkError::FailedToWrite)?;
+        // the record and its newline are written together, as two writes could
+        // be split by a writer appending to the same file from another handle
+        let line = line.trim();
+        let mut record = Vec::with_capacity(line.len() + 1);
+        record.extend_from_slice(line.as_bytes());
+        record.push(b'\n');
         writer
-            .write_all(b"\n")
-            .map_err(TransactionSinkError::FailedToWrite)?;
-        writer
-            .flush()
+            .file
+            .write_all(&record)
             .map_err(TransactionSinkError::FailedToWrite)?;
+
+        writer.bytes += record.len() as u64;
+        writer.lines += 1;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_by_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let id: TxPipeId = "txs".parse().unwrap();
+
+        let sink =
+            TransactionSink::new(dir.path().to_owned(), id, Some(RotatePolicy::Lines(2))).unwrap();
+        for tx in ["a", "b", "c", "d", "e"] {
+            sink.write(tx).unwrap();
+        }
+        drop(sink);
+
+        let path = dir.path().join("txs");
+        let read = |index| std::fs::read_to_string(rotated_path(&path, index)).unwrap();
+        assert_eq!(read(0), "a\nb\n");
+        assert_eq!(read(1), "c\nd\n");
+        assert_eq!(read(2), "e\n");
+
+        // re-opening the sink starts a file after the newest one
+        let sink =
+            TransactionSink::new(dir.path().to_owned(), id, Some(RotatePolicy::Lines(2))).unwrap();
+        for tx in ["f", "g", "h"] {
+            sink.write(tx).unwrap();
+        }
+        assert_eq!(read(2), "e\n");
+        assert_eq!(read(3), "f\ng\n");
+        assert_eq!(read(4), "h\n");
+    }
+
+    #[test]
+    fn test_rotate_by_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let id: TxPipeId = "txs".parse().unwrap();
+
+        let sink =
+            TransactionSink::new(dir.path().to_owned(), id, Some(RotatePolicy::Bytes(4))).unwrap();
+        sink.write("a").unwrap();
+        drop(sink);
+
+        // re-opening the sink resumes the newest file from its size
+        let sink =
+            TransactionSink::new(dir.path().to_owned(), id, Some(RotatePolicy::Bytes(4))).unwrap();
+        for tx in ["b", "c"] {
+            sink.write(tx).unwrap();
+        }
+
+        let path = dir.path().join("txs");
+        let read = |index| std::fs::read_to_string(rotated_path(&path, index)).unwrap();
+        assert_eq!(read(0), "a\nb\n");
+        assert_eq!(read(1), "c\n");
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        use std::{collections::HashSet, sync::Arc, thread};
+
+        const THREADS: usize = 8;
+        const LINES: usize = 200;
+
+        let dir = tempfile::tempdir().unwrap();
+        let id: TxPipeId = "txs".parse().unwrap();
+
+        // two sinks on the same file, as when an env is re-applied while the
+        // previous env's cannons are still writing
+        let sinks = [
+            Arc::new(TransactionSink::new(dir.path().to_owned(), id, None).unwrap()),
+            Arc::new(TransactionSink::new(dir.path().to_owned(), id, None).unwrap()),
+        ];
+
+        // records larger than a typical buffer to catch split writes
+        let record = |t: usize, i: usize| {
+            serde_json::json!({ "thread": t, "index": i, "data": "x".repeat(16 * 1024) })
+                .to_string()
+        };
+
+        let handles = (0..THREADS)
+            .map(|t| {
+                let sink = Arc::clone(&sinks[t % sinks.len()]);
+                thread::spawn(move || {
+                    for i in 0..LINES {
+                        sink.write(&record(t, i)).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        drop(sinks);
+
+        let contents = std::fs::read_to_string(dir.path().join("txs")).unwrap();
+        assert!(contents.ends_with('\n'));
+
+        let mut seen = HashSet::new();
+        for line in contents.lines() {
+            let value: serde_json::Value = serde_json::from_str(line).expect("interleaved line");
+            let key = (
+                value["thread"].as_u64().unwrap(),
+                value["index"].as_u64().unwrap(),
+            );
+            assert_eq!(line, record(key.0 as usize, key.1 as usize));
+            assert!(seen.insert(key), "duplicate line {key:?}");
+        }
+        assert_eq!(seen.len(), THREADS * LINES);
+    }
+}
@@ -0,0 +1,271 @@
+use indexmap::IndexMap;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use snops_common::{key_source::KeySource, state::KeyState};
+
+use crate::schema::storage::LoadedStorage;
+
+/// Generates executions of a program's function with random inputs that
+/// match the function's signature.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TxGenerator {
+    /// The program to execute, i.e. `credits.aleo`
+    pub program: String,
+    /// The function to execute
+    pub function: String,
+    /// The private key to sign the executions with
+    #[serde(default = "TxGenerator::default_private_key")]
+    pub private_key: KeySource,
+    /// A private key to pay the fee with. Defaults to the `private_key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_private_key: Option<KeySource>,
+    /// Number of executions generated per second
+    #[serde(default = "TxGenerator::default_per_sec")]
+    pub per_sec: u32,
+    /// How the input values are generated
+    #[serde(default)]
+    pub inputs: InputDistribution,
+}
+
+impl TxGenerator {
+    pub fn default_private_key() -> KeySource {
+        KeySource::Committee(None)
+    }
+
+    pub fn default_per_sec() -> u32 {
+        1
+    }
+
+    /// Pick the keys and generate the inputs of one execution. A seeded `rng`
+    /// generates the same executions every run.
+    pub fn sample<R: Rng>(
+        &self,
+        input_gens: &[InputGen],
+        storage: &LoadedStorage,
+        rng: &mut R,
+    ) -> Result<GeneratedExecution, String> {
+        let mut resolve_key = |key: &KeySource| match storage.sample_keysource_pk_with(key, rng) {
+            KeyState::Literal(pk) => KeySource::PrivateKeyLiteral(pk),
+            _ => key.clone(),
+        };
+        let private_key = resolve_key(&self.private_key);
+        let fee_private_key = self.fee_private_key.as_ref().map(resolve_key);
+
+        let inputs = input_gens
+            .iter()
+            .map(|input| input.sample(rng, &self.inputs, storage))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(GeneratedExecution {
+            private_key,
+            fee_private_key,
+            inputs,
+        })
+    }
+}
+
+/// The keys and inputs of a generated execution
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeneratedExecution {
+    pub private_key: KeySource,
+    pub fee_private_key: Option<KeySource>,
+    pub inputs: Vec<String>,
+}
+
+/// Knobs for the values generated for each input type
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InputDistribution {
+    /// Inclusive ranges for integer inputs by type, i.e.
+    /// `u64: { min: 1, max: 1000 }`. Integers use the full range of their
+    /// type when absent.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub ranges: IndexMap<String, IntRange>,
+    /// The accounts address inputs are drawn from, i.e. `accounts.$`.
+    /// Defaults to the committee.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub addresses: Option<KeySource>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntRange {
+    pub min: i128,
+    pub max: i128,
+}
+
+/// A generator for a single input, parsed from its type
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputGen {
+    Address,
+    Boolean,
+    Field,
+    Scalar,
+    /// An integer type and its bounds
+    Integer(&'static str, i128, i128),
+    Array(Box<InputGen>, usize),
+}
+
+impl InputGen {
+    /// Parse an input type listed by `aot program functions`, i.e.
+    /// `u64.public` or `[address; 4u32].private`. Records, futures, and
+    /// structs cannot be generated.
+    pub fn parse(ty: &str) -> Option<Self> {
+        match ty.rsplit_once('.')? {
+            (ty, "constant" | "public" | "private") => Self::parse_plaintext(ty),
+            _ => None,
+        }
+    }
+
+    fn parse_plaintext(ty: &str) -> Option<Self> {
+        if let Some(inner) = ty.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            let (elem, len) = inner.rsplit_once(';')?;
+            let len = len.trim().strip_suffix("u32")?.parse().ok()?;
+            return Some(Self::Array(
+                Box::new(Self::parse_plaintext(elem.trim())?),
+                len,
+            ));
+        }
+
+        Some(match ty {
+            "address" => Self::Address,
+            "boolean" => Self::Boolean,
+            "field" => Self::Field,
+            "scalar" => Self::Scalar,
+            "u8" => Self::Integer("u8", 0, u8::MAX as i128),
+            "u16" => Self::Integer("u16", 0, u16::MAX as i128),
+            "u32" => Self::Integer("u32", 0, u32::MAX as i128),
+            "u64" => Self::Integer("u64", 0, u64::MAX as i128),
+            // the top half of u128 is not reachable from the range knobs
+            "u128" => Self::Integer("u128", 0, i128::MAX),
+            "i8" => Self::Integer("i8", i8::MIN as i128, i8::MAX as i128),
+            "i16" => Self::Integer("i16", i16::MIN as i128, i16::MAX as i128),
+            "i32" => Self::Integer("i32", i32::MIN as i128, i32::MAX as i128),
+            "i64" => Self::Integer("i64", i64::MIN as i128, i64::MAX as i128),
+            "i128" => Self::Integer("i128", i128::MIN, i128::MAX),
+            _ => return None,
+        })
+    }
+
+    /// Generate a random value for this input
+    pub fn sample<R: Rng>(
+        &self,
+        rng: &mut R,
+        dist: &InputDistribution,
+        storage: &LoadedStorage,
+    ) -> Result<String, String> {
+        Ok(match self {
+            Self::Address => {
+                let pool = dist.addresses.clone().unwrap_or(KeySource::Committee(None));
+                match storage.sample_keysource_addr_with(&pool, rng) {
+                    KeyState::Literal(addr) => addr,
+                    _ => return Err(format!("key {pool} does not resolve a valid addr")),
+                }
+            }
+            Self::Boolean => rng.gen_bool(0.5).to_string(),
+            Self::Field => format!("{}field", rng.r#gen::<u64>()),
+            Self::Scalar => format!("{}scalar", rng.r#gen::<u64>()),
+            Self::Integer(ty, min, max) => {
+                let (min, max) = match dist.ranges.get(*ty) {
+                    Some(range) => (range.min.max(*min), range.max.min(*max)),
+                    None => (*min, *max),
+                };
+                if min > max {
+                    return Err(format!("range for {ty} is empty"));
+                }
+                format!("{}{ty}", rng.gen_range(min..=max))
+            }
+            Self::Array(elem, len) => {
+                let values = (0..*len)
+                    .map(|_| elem.sample(rng, dist, storage))
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("[{}]", values.join(", "))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use snops_common::state::{InternedId, NetworkId};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_input_types() {
+        assert_eq!(InputGen::parse("address.public"), Some(InputGen::Address));
+        assert_eq!(
+            InputGen::parse("u64.private"),
+            Some(InputGen::Integer("u64", 0, u64::MAX as i128))
+        );
+        assert_eq!(
+            InputGen::parse("[boolean; 4u32].constant"),
+            Some(InputGen::Array(Box::new(InputGen::Boolean), 4))
+        );
+        assert_eq!(
+            InputGen::parse("[[field; 2u32]; 3u32].public"),
+            Some(InputGen::Array(
+                Box::new(InputGen::Array(Box::new(InputGen::Field), 2)),
+                3
+            ))
+        );
+
+        assert_eq!(InputGen::parse("credits.record"), None);
+        assert_eq!(InputGen::parse("credits.aleo/credits.record"), None);
+        assert_eq!(InputGen::parse("point.public"), None);
+        assert_eq!(InputGen::parse("u64"), None);
+    }
+
+    #[test]
+    fn test_seeded_generation_is_reproducible() {
+        let storage = LoadedStorage {
+            id: InternedId::from_str("base").unwrap(),
+            network: NetworkId::default(),
+            version: 0,
+            committee: (0..4)
+                .map(|i| (format!("aleo1addr{i}"), format!("APrivateKey1pk{i}")))
+                .collect(),
+            accounts: Default::default(),
+            retention_policy: None,
+            persist: false,
+            native_genesis: false,
+            binaries: Default::default(),
+            ledger_url: None,
+            generation: None,
+            connect: None,
+        };
+        let generator: TxGenerator = serde_yaml::from_str(
+            "program: credits.aleo\nfunction: transfer_public\nfee-private-key: committee.$",
+        )
+        .unwrap();
+        let input_gens = [
+            InputGen::Address,
+            InputGen::Integer("u64", 0, u64::MAX as i128),
+            InputGen::Field,
+            InputGen::Boolean,
+        ];
+
+        let generate = |seed: u64| {
+            let mut rng = ChaChaRng::seed_from_u64(seed);
+            (0..8)
+                .map(|_| generator.sample(&input_gens, &storage, &mut rng).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let executions = generate(7);
+        assert_eq!(executions, generate(7));
+        assert_ne!(executions, generate(8));
+        // the keys are resolved to literals from the committee
+        assert!(executions.iter().all(|e| matches!(
+            (&e.private_key, &e.fee_private_key),
+            (
+                KeySource::PrivateKeyLiteral(_),
+                Some(KeySource::PrivateKeyLiteral(_))
+            )
+        )));
+    }
+}
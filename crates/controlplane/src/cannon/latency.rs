@@ -0,0 +1,205 @@
+use std::{fmt::Write, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use snops_common::{
+    events::{Event, EventKind, TransactionEvent},
+    state::{CannonId, EnvId},
+};
+
+/// Name of the exported authorization to confirmation latency histogram
+const LATENCY_METRIC: &str = "snops_cannon_tx_confirmation_seconds";
+/// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.5, 1.0, 2.5, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+/// A cumulative prometheus histogram
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Number of observations at or below each bound in [`LATENCY_BUCKETS`]
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(self.buckets.iter_mut()) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Measures the wall time from the first authorization a cannon receives for
+/// a transaction to the transaction's confirmation, per env and cannon.
+#[derive(Debug, Default)]
+pub struct ConfirmationLatency {
+    /// Time each pending transaction's authorization was first received
+    received: DashMap<(EnvId, CannonId, Arc<String>), DateTime<Utc>>,
+    histograms: DashMap<(EnvId, CannonId), Histogram>,
+}
+
+impl ConfirmationLatency {
+    /// Update the latencies from a transaction event
+    pub fn observe(&self, event: &Event) {
+        let EventKind::Transaction(content) = &event.content else {
+            return;
+        };
+        let (Some(env), Some(cannon), Some(tx)) = (event.env, event.cannon, &event.transaction)
+        else {
+            return;
+        };
+        let key = (env, cannon, Arc::clone(tx));
+
+        match content {
+            // authorizations are received again when their execution is retried
+            TransactionEvent::AuthorizationReceived { .. } => {
+                self.received.entry(key).or_insert(event.created_at);
+            }
+            TransactionEvent::Confirmed { .. } => {
+                let Some((_, received)) = self.received.remove(&key) else {
+                    return;
+                };
+                let secs = (event.created_at - received).num_milliseconds().max(0) as f64 / 1000.0;
+                self.histograms
+                    .entry((env, cannon))
+                    .or_default()
+                    .observe(secs);
+            }
+            // these transactions will never be confirmed
+            TransactionEvent::ExecuteAborted(_)
+            | TransactionEvent::ExecuteExceeded { .. }
+            | TransactionEvent::BroadcastExceeded { .. } => {
+                self.received.remove(&key);
+            }
+            _ => {}
+        }
+    }
+
+    /// Forget the pending transactions and latencies of a cannon
+    pub fn remove_cannon(&self, env_id: EnvId, cannon_id: CannonId) {
+        self.received
+            .retain(|(env, cannon, _), _| *env != env_id || *cannon != cannon_id);
+        self.histograms.remove(&(env_id, cannon_id));
+    }
+
+    /// Forget the pending transactions and latencies of an env
+    pub fn remove_env(&self, env_id: EnvId) {
+        self.received.retain(|(env, _, _), _| *env != env_id);
+        self.histograms.retain(|(env, _), _| *env != env_id);
+    }
+
+    /// Render the latency histograms in the prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP {LATENCY_METRIC} Seconds from a cannon receiving a transaction's authorization to its confirmation"
+        );
+        let _ = writeln!(out, "# TYPE {LATENCY_METRIC} histogram");
+
+        for entry in self.histograms.iter() {
+            let (env, cannon) = entry.key();
+            let histogram = entry.value();
+            let labels = format!("env=\"{env}\",cannon=\"{cannon}\"");
+
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(
+                    out,
+                    "{LATENCY_METRIC}_bucket{{{labels},le=\"{bound}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{LATENCY_METRIC}_bucket{{{labels},le=\"+Inf\"}} {}",
+                histogram.count
+            );
+            let _ = writeln!(out, "{LATENCY_METRIC}_sum{{{labels}}} {}", histogram.sum);
+            let _ = writeln!(
+                out,
+                "{LATENCY_METRIC}_count{{{labels}}} {}",
+                histogram.count
+            );
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::TimeDelta;
+    use snops_common::{
+        events::EventHelpers,
+        state::{Authorization, InternedId},
+    };
+
+    use super::*;
+
+    fn event(content: TransactionEvent, tx: &str, secs: i64) -> Event {
+        let mut event = content
+            .with_env_id(InternedId::from_str("env").unwrap())
+            .with_cannon(InternedId::from_str("cannon").unwrap())
+            .with_transaction(Arc::new(tx.to_owned()));
+        event.created_at = DateTime::UNIX_EPOCH + TimeDelta::seconds(secs);
+        event
+    }
+
+    fn received() -> TransactionEvent {
+        TransactionEvent::AuthorizationReceived {
+            authorization: Arc::new(Authorization::Program {
+                auth: serde_json::Value::Null,
+                fee_auth: None,
+            }),
+        }
+    }
+
+    fn confirmed() -> TransactionEvent {
+        TransactionEvent::Confirmed {
+            hash: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_confirmation_latency() {
+        let latency = ConfirmationLatency::default();
+
+        latency.observe(&event(received(), "a", 0));
+        latency.observe(&event(TransactionEvent::Executing, "a", 1));
+        // only the first authorization counts
+        latency.observe(&event(received(), "a", 2));
+        latency.observe(&event(confirmed(), "a", 4));
+        // confirmations of unknown transactions are ignored
+        latency.observe(&event(confirmed(), "b", 5));
+        // aborted transactions are forgotten
+        latency.observe(&event(received(), "c", 0));
+        latency.observe(&event(
+            TransactionEvent::BroadcastExceeded { attempts: 3 },
+            "c",
+            1,
+        ));
+
+        assert!(latency.received.is_empty());
+
+        let rendered = latency.render();
+        assert!(rendered.contains(&format!(
+            "{LATENCY_METRIC}_bucket{{env=\"env\",cannon=\"cannon\",le=\"2.5\"}} 0"
+        )));
+        assert!(rendered.contains(&format!(
+            "{LATENCY_METRIC}_bucket{{env=\"env\",cannon=\"cannon\",le=\"5\"}} 1"
+        )));
+        assert!(rendered.contains(&format!(
+            "{LATENCY_METRIC}_sum{{env=\"env\",cannon=\"cannon\"}} 4"
+        )));
+        assert!(rendered.contains(&format!(
+            "{LATENCY_METRIC}_count{{env=\"env\",cannon=\"cannon\"}} 1"
+        )));
+    }
+}
@@ -0,0 +1,62 @@
+use std::{io, path::PathBuf};
+
+use url::Url;
+
+/// Where the local query service of a cannon reads its ledger from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LedgerLocation {
+    /// A storage directory on the control plane's disk
+    Local(PathBuf),
+    /// A storage directory that is not on the control plane's disk. It is
+    /// copied to a local scratch directory before the query service starts.
+    Remote(Url),
+}
+
+impl LedgerLocation {
+    /// Resolve the directory to read the ledger and genesis block from.
+    ///
+    /// Remote ledgers are read through a mounted filesystem, i.e. NFS or an
+    /// S3 bucket mounted with `mountpoint-s3`, so only `file://` urls are
+    /// supported.
+    pub fn resolve(&self) -> io::Result<PathBuf> {
+        let url = match self {
+            Self::Local(path) => return Ok(path.clone()),
+            Self::Remote(url) => url,
+        };
+
+        if url.scheme() != "file" {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unsupported remote ledger scheme `{}`", url.scheme()),
+            ));
+        }
+        url.to_file_path().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid remote ledger path `{url}`"),
+            )
+        })
+    }
+
+    pub fn is_remote(&self) -> bool {
+        matches!(self, Self::Remote(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_remote_ledger() {
+        let url = Url::parse("file:///mnt/ledgers/base").unwrap();
+        assert_eq!(
+            LedgerLocation::Remote(url).resolve().unwrap(),
+            PathBuf::from("/mnt/ledgers/base")
+        );
+
+        let url = Url::parse("s3://ledgers/base").unwrap();
+        let err = LedgerLocation::Remote(url).resolve().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,36 @@
+use std::{sync::Mutex, time::Instant};
+
+/// A token bucket limiting how many authorizations a cannon accepts per
+/// second. The bucket holds up to one second's worth of tokens.
+#[derive(Debug)]
+pub struct AuthLimiter {
+    rate: f64,
+    /// (available tokens, last refill)
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl AuthLimiter {
+    pub fn new(per_sec: u32) -> Self {
+        let rate = f64::from(per_sec);
+        Self {
+            rate,
+            bucket: Mutex::new((rate, Instant::now())),
+        }
+    }
+
+    /// Take a token from the bucket, returning false when none are available
+    pub fn try_acquire(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, last) = &mut *bucket;
+
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.rate);
+        *last = now;
+
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
@@ -1,26 +1,34 @@
 pub mod context;
 pub mod error;
 pub mod file;
+pub mod generate;
+pub mod latency;
+pub mod ledger;
+mod limiter;
 mod net;
 pub mod router;
 pub mod sink;
+mod snapshot;
 pub mod source;
 pub mod tracker;
 
 use std::{
+    io,
     path::PathBuf,
     sync::{
-        Arc,
+        Arc, Mutex,
         atomic::{AtomicU64, AtomicUsize},
     },
 };
 
 use context::ExecutionContext;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
 use snops_common::{
     aot_cmds::AotCmd,
     format::PackedUint,
-    state::{Authorization, CannonId, EnvId, NetworkId, StorageId, TransactionSendState},
+    state::{Authorization, CannonId, EnvId, NetworkId, TransactionSendState},
 };
 use tokio::{
     sync::{
@@ -31,13 +39,21 @@ use tokio::{
 };
 use tracing::{error, trace, warn};
 use tracker::TransactionTracker;
+use uuid::Uuid;
 
 use self::{
     error::{CannonError, CannonInstanceError},
+    ledger::LedgerLocation,
+    limiter::AuthLimiter,
     sink::TxSink,
-    source::TxSource,
+    snapshot::LedgerSnapshot,
+    source::{LocalService, QueryTarget, TxSource},
 };
-use crate::{cannon::source::QueryTarget, state::GlobalState};
+use crate::{cli::Cli, state::GlobalState};
+
+/// Directory (within the control plane data path) that holds cannon ledger
+/// snapshots
+const SNAPSHOT_DIR: &str = "cannon_snapshots";
 
 /*
 
@@ -93,10 +109,16 @@ pub struct CannonInstance {
     // TODO: run the actual cannon in this task
     pub task: Option<AbortHandle>,
 
-    /// Child process must exist for the duration of the cannon instance.
-    /// This value is never used
-    #[allow(dead_code)]
-    child: Option<tokio::process::Child>,
+    /// Child process must exist for the duration of the cannon instance,
+    /// unless the cannon is stopped
+    child: Mutex<Option<tokio::process::Child>>,
+
+    /// Ledger copy the child process queries when the local query service is
+    /// snapshotted. Removed when the instance is dropped.
+    snapshot: Option<LedgerSnapshot>,
+
+    /// Limits the rate of authorizations received through `proxy_auth`
+    auth_limiter: Option<AuthLimiter>,
 
     /// channel to send transaction ids to the the task
     pub(crate) tx_sender: UnboundedSender<Arc<String>>,
@@ -104,6 +126,8 @@ pub struct CannonInstance {
     pub(crate) auth_sender: UnboundedSender<Arc<String>>,
     /// transaction ids that are currently being processed
     pub(crate) transactions: Arc<DashMap<Arc<String>, TransactionTracker>>,
+    /// broadcasted transaction ids that were found in a target node's mempool
+    pub(crate) mempool_accepted: DashSet<Arc<String>>,
 
     pub(crate) received_txs: Arc<AtomicU64>,
     pub(crate) fired_txs: Arc<AtomicUsize>,
@@ -114,7 +138,7 @@ pub struct CannonReceivers {
     authorizations: UnboundedReceiver<Arc<String>>,
 }
 
-pub type CannonInstanceMeta = (EnvId, NetworkId, StorageId, PathBuf);
+pub type CannonInstanceMeta = (EnvId, NetworkId, LedgerLocation, PathBuf);
 
 impl CannonInstance {
     /// Increment and save the received transaction count
@@ -157,35 +181,22 @@ impl CannonInstance {
                 }
             };
 
-        let statuses = match state.db.tx_status.read_with_prefix(&(env_id, cannon_id)) {
-            Ok(statuses) => statuses,
+        let indices = match state.db.tx_index.read_with_prefix(&(env_id, cannon_id)) {
+            Ok(indices) => indices,
             Err(e) => {
-                error!("cannon {env_id}.{cannon_id} failed to restore transaction statuses: {e}");
+                error!("cannon {env_id}.{cannon_id} failed to restore transaction indices: {e}");
                 return (transactions, received_txs);
             }
         };
 
-        // Walk through the statuses and restore the transactions (every transaction has
-        // a status)
-        for (key, status) in statuses {
-            // Ensure the transaction has an index
-            let index = match state.db.tx_index.restore(&key) {
-                Ok(Some(index)) => index.0,
-                Ok(None) => {
-                    warn!(
-                        "cannon {env_id}.{cannon_id} failed to restore index for transaction {} (missing index)",
-                        key.2
-                    );
-                    continue;
-                }
-                Err(e) => {
-                    error!(
-                        "cannon {env_id}.{cannon_id} failed to parse index for transaction {}: {e}",
-                        key.2
-                    );
-                    continue;
-                }
-            };
+        // Walk through the indices and restore the transactions (every transaction has
+        // an index)
+        for (key, index) in indices {
+            // Skip the received transaction count
+            if key.2.is_empty() {
+                continue;
+            }
+            let index = index.0;
 
             let authorization = match state.db.tx_auths.restore(&key) {
                 Ok(auth) => auth.map(Arc::new),
@@ -220,6 +231,27 @@ impl CannonInstance {
                 }
             };
 
+            // Ensure the transaction has a status. Statuses written by older format
+            // versions are upgraded by the reader. A status that cannot be read (i.e.
+            // written by a newer version) is left in the store untouched.
+            let status = match state.db.tx_status.restore(&key) {
+                Ok(Some(status)) => status,
+                Ok(None) => {
+                    warn!(
+                        "cannon {env_id}.{cannon_id} failed to restore status for transaction {} (missing status)",
+                        key.2
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    error!(
+                        "cannon {env_id}.{cannon_id} failed to parse status for transaction {}: {e}",
+                        key.2
+                    );
+                    continue;
+                }
+            };
+
             transactions.insert(
                 key.2,
                 TransactionTracker {
@@ -234,14 +266,51 @@ impl CannonInstance {
         (transactions, received_txs)
     }
 
+    /// Resolve the ledger the cannon's local query service reads, copying it
+    /// so the query service does not lock the original. Remote ledgers are
+    /// always copied to local disk. The copy runs on a blocking thread, as a
+    /// remote ledger is copied over the network.
+    async fn query_ledger(
+        state: &GlobalState,
+        env_id: EnvId,
+        id: CannonId,
+        ledger: &LedgerLocation,
+        source: &TxSource,
+    ) -> Result<(PathBuf, Option<LedgerSnapshot>), CannonError> {
+        let storage_path = ledger
+            .resolve()
+            .map_err(|e| CannonError::LedgerLocation(id, e))?;
+        let copy_ledger = ledger.is_remote()
+            || matches!(
+                &source.query,
+                QueryTarget::Local(LocalService { snapshot: true, .. })
+            );
+        if !copy_ledger {
+            return Ok((storage_path, None));
+        }
+
+        // each instance gets its own directory so a replacement cannon does
+        // not remove the snapshot of the one it replaces
+        let dst = state
+            .cli
+            .path
+            .join(SNAPSHOT_DIR)
+            .join(format!("{env_id}-{id}-{}", Uuid::new_v4()));
+        let copy = tokio::task::spawn_blocking(move || LedgerSnapshot::new(&storage_path, dst))
+            .await
+            .map_err(|e| CannonError::LedgerSnapshot(id, io::Error::other(e)))?
+            .map_err(|e| CannonError::LedgerSnapshot(id, e))?;
+        Ok((copy.path().to_path_buf(), Some(copy)))
+    }
+
     /// Create a new active transaction cannon
     /// with the given source and sink.
     ///
     /// Locks the global state's tests and storage for reading.
-    pub fn new(
+    pub async fn new(
         global_state: Arc<GlobalState>,
         id: CannonId,
-        (env_id, network, storage_id, aot_bin): CannonInstanceMeta,
+        (env_id, network, ledger, aot_bin): CannonInstanceMeta,
         source: TxSource,
         sink: TxSink,
     ) -> Result<(Self, CannonReceivers), CannonError> {
@@ -249,16 +318,25 @@ impl CannonInstance {
         let query_port = source.get_query_port()?;
         let fired_txs = Arc::new(AtomicUsize::new(0));
 
-        let storage_path = global_state.storage_path(network, storage_id);
-
         // spawn child process for ledger service if the source is local
-        let child = query_port
-            .map(|port| AotCmd::new(aot_bin, network).ledger_query(storage_path, port))
-            .transpose()
-            .map_err(|e| CannonError::Command(id, e))?;
+        let mut snapshot = None;
+        let child = match query_port {
+            Some(port) => {
+                let (storage_path, copy) =
+                    Self::query_ledger(&global_state, env_id, id, &ledger, &source).await?;
+                snapshot = copy;
+                Some(
+                    AotCmd::new(aot_bin, network)
+                        .ledger_query(storage_path, port)
+                        .map_err(|e| CannonError::Command(id, e))?,
+                )
+            }
+            None => None,
+        };
 
         let (auth_sender, auth_receiver) = tokio::sync::mpsc::unbounded_channel();
         let (transactions, received_txs) = Self::restore_transactions(&global_state, env_id, id);
+        let auth_limiter = source.max_auths_per_sec.map(AuthLimiter::new);
 
         Ok((
             Self {
@@ -271,11 +349,14 @@ impl CannonInstance {
                 tx_sender,
                 auth_sender,
                 query_port,
-                child,
+                child: Mutex::new(child),
+                snapshot,
+                auth_limiter,
                 task: None,
                 fired_txs,
                 received_txs: Arc::new(received_txs),
                 transactions: Arc::new(transactions),
+                mempool_accepted: DashSet::new(),
             },
             CannonReceivers {
                 transactions: tx_receiver,
@@ -295,6 +376,11 @@ impl CannonInstance {
             fired_txs: Arc::clone(&self.fired_txs),
             state: Arc::clone(&self.global_state),
             transactions: Arc::clone(&self.transactions),
+            rng: Mutex::new(
+                self.source
+                    .seed
+                    .map_or_else(ChaChaRng::from_entropy, ChaChaRng::seed_from_u64),
+            ),
         }
     }
 
@@ -403,20 +489,33 @@ impl CannonInstance {
     ) -> Result<(), CannonError> {
         let key = (self.env_id, self.id, Arc::clone(&tx_id));
 
-        // if the transaction is in the cache, it has already been broadcasted
-        if let Some(cache) = self.global_state.env_network_cache.get(&self.env_id) {
-            if cache.has_transaction(&tx_id) {
-                if let Err(e) = TransactionTracker::delete(&self.global_state, &key) {
-                    error!(
-                        "cannon {}.{} failed to delete {tx_id} (in proxy_broadcast): {e:?}",
-                        self.env_id, self.id
-                    );
-                }
-                return Err(CannonError::TransactionAlreadyExists(
-                    self.id,
-                    tx_id.to_string(),
-                ));
+        // if the transaction is in the cache or was confirmed before the control
+        // plane restarted, it has already been broadcasted
+        let in_cache = self
+            .global_state
+            .env_network_cache
+            .get(&self.env_id)
+            .is_some_and(|cache| cache.has_transaction(&tx_id));
+        let confirmed = || {
+            matches!(
+                self.global_state
+                    .db
+                    .tx_confirmed
+                    .restore(&(self.env_id, Arc::clone(&tx_id))),
+                Ok(Some(_))
+            )
+        };
+        if in_cache || confirmed() {
+            if let Err(e) = TransactionTracker::delete(&self.global_state, &key) {
+                error!(
+                    "cannon {}.{} failed to delete {tx_id} (in proxy_broadcast): {e:?}",
+                    self.env_id, self.id
+                );
             }
+            return Err(CannonError::TransactionAlreadyExists(
+                self.id,
+                tx_id.to_string(),
+            ));
         }
 
         // prevent already queued transactions from being re-broadcasted
@@ -476,6 +575,14 @@ impl CannonInstance {
 
     /// Called by axum to forward /cannon/<id>/auth to a listen source
     pub async fn proxy_auth(&self, body: Authorization) -> Result<Arc<String>, CannonError> {
+        if self
+            .auth_limiter
+            .as_ref()
+            .is_some_and(|limiter| !limiter.try_acquire())
+        {
+            return Err(CannonError::RateLimited(self.id));
+        }
+
         let Some(storage) = self
             .global_state
             .get_env(self.env_id)
@@ -536,11 +643,61 @@ impl CannonInstance {
     }
 }
 
+impl CannonInstance {
+    /// Abort the cannon's task and kill its query service, releasing the
+    /// ledger the query service reads. A stopped cannon no longer fires
+    /// transactions.
+    pub async fn stop(&self) {
+        if let Some(handle) = &self.task {
+            handle.abort();
+        }
+
+        let child = self.child.lock().ok().and_then(|mut child| child.take());
+        if let Some(mut child) = child {
+            if let Err(e) = child.kill().await {
+                warn!(
+                    "cannon {}.{} failed to kill query service: {e}",
+                    self.env_id, self.id
+                );
+            }
+        }
+    }
+}
+
 impl Drop for CannonInstance {
     fn drop(&mut self) {
         // cancel the task on drop
         if let Some(handle) = self.task.take() {
             handle.abort();
         }
+
+        // kill the query service before removing the snapshot it reads
+        if let Ok(Some(child)) = self.child.get_mut().map(Option::as_mut) {
+            let _ = child.start_kill();
+        }
+
+        // removing a snapshot walks the whole ledger copy, so it runs on a
+        // blocking thread when dropped inside the runtime
+        if let Some(snapshot) = self.snapshot.take() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(handle) => drop(handle.spawn_blocking(move || drop(snapshot))),
+                Err(_) => drop(snapshot),
+            }
+        }
+    }
+}
+
+/// Remove the ledger snapshots left behind by a control plane that did not
+/// shut down cleanly. No cannons run yet when this is called, so every
+/// snapshot is stale.
+pub async fn remove_stale_snapshots(cli: &Cli) {
+    let dir = cli.path.join(SNAPSHOT_DIR);
+    match tokio::fs::remove_dir_all(&dir).await {
+        Ok(()) => trace!("removed stale ledger snapshots in {}", dir.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => error!(
+            "failed to remove stale ledger snapshots in {}: {e}",
+            dir.display()
+        ),
     }
 }
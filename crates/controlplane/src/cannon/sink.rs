@@ -1,5 +1,9 @@
+use std::time::{Duration, Instant};
+
 use serde::{Deserialize, Serialize};
-use snops_common::state::TxPipeId;
+use snops_common::state::{CannonId, TxPipeId};
+
+use crate::env::error::PrepareError;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -31,10 +35,165 @@ pub struct TxSink {
     /// Time to wait before re-trying to authorize a transaction
     #[serde(default = "TxSink::default_retry_timeout")]
     pub authorize_timeout: u32,
+    /// When present, the `file_name` file is rotated into numbered
+    /// successors once it crosses this size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotate_after: Option<RotatePolicy>,
+    /// When present, broadcasting is paused while the target's mempool holds
+    /// more than this many transactions, resuming once it drains
+    ///
+    /// Requires `target` to be set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_mempool_depth: Option<usize>,
+    /// When present, broadcasts follow this profile instead of firing
+    /// transactions as soon as they are ready
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<FiringProfile>,
+}
+
+/// Threshold at which a recorded transaction file is rotated
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RotatePolicy {
+    /// Rotate once the file contains this many bytes
+    Bytes(u64),
+    /// Rotate once the file contains this many transactions
+    Lines(u64),
+}
+
+impl RotatePolicy {
+    /// Check if a file with the given size has reached this threshold
+    pub fn is_crossed(&self, bytes: u64, lines: u64) -> bool {
+        match self {
+            RotatePolicy::Bytes(max) => bytes >= *max,
+            RotatePolicy::Lines(max) => lines >= *max,
+        }
+    }
+}
+
+/// Shape of the traffic a cannon broadcasts over time
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FiringProfile {
+    /// Fire `count` transactions as fast as they are ready, then idle for
+    /// `interval` seconds before the next burst
+    Burst { count: u32, interval: u32 },
+    /// Fire at a rate (transactions per second) that moves linearly from
+    /// `start_rate` to `end_rate` over `duration` seconds, then holds
+    /// `end_rate`
+    #[serde(rename_all = "kebab-case")]
+    Ramp {
+        start_rate: u32,
+        end_rate: u32,
+        duration: u32,
+    },
+}
+
+/// Tracks the progress of a cannon through its firing profile
+#[derive(Debug)]
+pub struct FiringPacer {
+    profile: FiringProfile,
+    started: Instant,
+    /// Transactions fired in the current burst
+    fired: u32,
+}
+
+impl FiringPacer {
+    pub fn new(profile: FiringProfile, started: Instant) -> Self {
+        Self {
+            profile,
+            started,
+            fired: 0,
+        }
+    }
+
+    /// Record a fired transaction, returning how long to wait before the
+    /// next transaction may be fired
+    pub fn record_shot(&mut self, now: Instant) -> Duration {
+        match self.profile {
+            FiringProfile::Burst { count, interval } => {
+                self.fired += 1;
+                if self.fired < count.max(1) {
+                    return Duration::ZERO;
+                }
+                self.fired = 0;
+                Duration::from_secs(interval.into())
+            }
+            FiringProfile::Ramp {
+                start_rate,
+                end_rate,
+                duration,
+            } => {
+                let progress = match duration {
+                    0 => 1.0,
+                    d => (now.duration_since(self.started).as_secs_f64() / f64::from(d)).min(1.0),
+                };
+                let (start, end) = (f64::from(start_rate.max(1)), f64::from(end_rate.max(1)));
+                Duration::from_secs_f64(1.0 / (start + (end - start) * progress))
+            }
+        }
+    }
 }
 
 impl TxSink {
     pub fn default_retry_timeout() -> u32 {
         60
     }
+
+    /// Check that options depending on a `target` are only set with one
+    pub fn validate(&self, cannon_id: CannonId) -> Result<(), PrepareError> {
+        if self.max_mempool_depth.is_some() && self.target.is_none() {
+            return Err(PrepareError::MempoolDepthWithoutTarget(cannon_id));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{FiringPacer, FiringProfile};
+
+    #[test]
+    fn test_burst_idles_after_count() {
+        let now = Instant::now();
+        let mut pacer = FiringPacer::new(
+            FiringProfile::Burst {
+                count: 3,
+                interval: 10,
+            },
+            now,
+        );
+
+        for _ in 0..2 {
+            assert_eq!(pacer.record_shot(now), Duration::ZERO);
+            assert_eq!(pacer.record_shot(now), Duration::ZERO);
+            assert_eq!(pacer.record_shot(now), Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn test_ramp_interpolates_rate() {
+        let start = Instant::now();
+        let mut pacer = FiringPacer::new(
+            FiringProfile::Ramp {
+                start_rate: 1,
+                end_rate: 9,
+                duration: 60,
+            },
+            start,
+        );
+
+        assert_eq!(pacer.record_shot(start), Duration::from_secs(1));
+        assert_eq!(
+            pacer.record_shot(start + Duration::from_secs(30)),
+            Duration::from_secs_f64(1.0 / 5.0)
+        );
+        // the end rate is held once the ramp completes
+        assert_eq!(
+            pacer.record_shot(start + Duration::from_secs(600)),
+            Duration::from_secs_f64(1.0 / 9.0)
+        );
+    }
 }
@@ -0,0 +1,86 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use snops_common::constant::{LEDGER_BASE_DIR, SNARKOS_GENESIS_FILE};
+use tracing::{error, trace};
+
+/// A point-in-time copy of a storage's ledger and genesis block.
+///
+/// Used by the local query service so it does not contend on RocksDB locks
+/// with anything writing to the original ledger. The copy is removed when the
+/// snapshot is dropped.
+#[derive(Debug)]
+pub struct LedgerSnapshot(PathBuf);
+
+impl LedgerSnapshot {
+    /// Snapshot the ledger in `storage_path` into the `dst` directory
+    pub fn new(storage_path: &Path, dst: PathBuf) -> io::Result<Self> {
+        if dst.exists() {
+            fs::remove_dir_all(&dst)?;
+        }
+        fs::create_dir_all(&dst)?;
+
+        // the snapshot is cleaned up if any of the copies fail
+        let snapshot = Self(dst);
+
+        let ledger = storage_path.join(LEDGER_BASE_DIR);
+        if ledger.exists() {
+            copy_ledger_dir(&ledger, &snapshot.0.join(LEDGER_BASE_DIR))?;
+        }
+        fs::copy(
+            storage_path.join(SNARKOS_GENESIS_FILE),
+            snapshot.0.join(SNARKOS_GENESIS_FILE),
+        )?;
+
+        trace!(
+            "snapshotted ledger {} to {}",
+            storage_path.display(),
+            snapshot.0.display()
+        );
+        Ok(snapshot)
+    }
+
+    /// The directory containing the snapshotted ledger
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for LedgerSnapshot {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.0) {
+            error!("failed to remove ledger snapshot {}: {e}", self.0.display());
+        }
+    }
+}
+
+/// Recursively copy a RocksDB directory. Immutable SST files are hardlinked
+/// when possible and the lock file is skipped.
+fn copy_ledger_dir(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let (from, to) = (entry.path(), dst.join(entry.file_name()));
+
+        if entry.file_type()?.is_dir() {
+            copy_ledger_dir(&from, &to)?;
+            continue;
+        }
+
+        if entry.file_name() == "LOCK" {
+            continue;
+        }
+
+        // hard links fail across filesystems, in which case the file is copied
+        if from.extension().is_some_and(|ext| ext == "sst") && fs::hard_link(&from, &to).is_ok() {
+            continue;
+        }
+
+        fs::copy(&from, &to)?;
+    }
+
+    Ok(())
+}
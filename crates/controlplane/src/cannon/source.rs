@@ -1,22 +1,25 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use snops_common::events::{EventHelpers, TransactionEvent};
+use snops_common::rpc::error::AgentError;
 use snops_common::state::{Authorization, TransactionSendState};
 use snops_common::{INTERN, lasso::Spur, node_targets::NodeTargets, state::NetworkId};
 use tracing::error;
 
 use super::context::CtxEventHelper;
+use super::generate::TxGenerator;
 use super::{
     ExecutionContext,
     error::{CannonError, SourceError},
     net::get_available_port,
     tracker::TransactionTracker,
 };
-use crate::env::set::find_compute_agent;
-use crate::state::EmitEvent;
+use crate::env::set::{count_compute_agents, find_compute_agent};
+use crate::error::StateError;
+use crate::state::{EmitEvent, GlobalState};
 
 /// Represents an instance of a local query service.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -32,6 +35,10 @@ pub struct LocalService {
     /// requires cannon to have an associated env_id
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub sync_from: Option<NodeTargets>,
+    /// When true, the query service reads from a copy of the ledger rather
+    /// than the live ledger directory, avoiding lock contention with writers.
+    #[serde(default)]
+    pub snapshot: bool,
 }
 
 impl LocalService {
@@ -88,7 +95,10 @@ pub enum QueryTarget {
 
 impl Default for QueryTarget {
     fn default() -> Self {
-        QueryTarget::Local(LocalService { sync_from: None })
+        QueryTarget::Local(LocalService {
+            sync_from: None,
+            snapshot: false,
+        })
     }
 }
 
@@ -103,6 +113,19 @@ where
     }))
 }
 
+/// Deserialize an optional per second rate, rejecting a rate of 0
+fn deser_rate<'de, D>(deser: D) -> Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match Option::<u32>::deserialize(deser)? {
+        Some(0) => Err(serde::de::Error::custom(
+            "rate must be at least 1 per second",
+        )),
+        rate => Ok(rate),
+    }
+}
+
 fn ser_labels<S>(labels: &Option<Vec<Spur>>, ser: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
@@ -124,6 +147,7 @@ where
 #[serde(rename_all = "kebab-case", untagged)]
 pub enum ComputeTarget {
     /// Use the agent pool to generate executions
+    #[serde(rename_all = "kebab-case")]
     Agent {
         #[serde(
             default,
@@ -132,6 +156,10 @@ pub enum ComputeTarget {
             skip_serializing_if = "Option::is_none"
         )]
         labels: Option<Vec<Spur>>,
+        /// Seconds an execution may run before the agent kills its aot
+        /// process and the execution fails
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        execution_timeout: Option<u64>,
     },
     /// Use demox' API to generate executions
     #[serde(rename_all = "kebab-case")]
@@ -140,7 +168,10 @@ pub enum ComputeTarget {
 
 impl Default for ComputeTarget {
     fn default() -> Self {
-        ComputeTarget::Agent { labels: None }
+        ComputeTarget::Agent {
+            labels: None,
+            execution_timeout: None,
+        }
     }
 }
 
@@ -153,6 +184,26 @@ pub struct TxSource {
     pub query: QueryTarget,
     #[serde(default)]
     pub compute: ComputeTarget,
+    /// Maximum number of authorizations per second accepted from clients
+    #[serde(
+        default,
+        deserialize_with = "deser_rate",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_auths_per_sec: Option<u32>,
+    /// Maximum number of authorizations executed at once. Further
+    /// authorizations are queued until an execution completes. Defaults to the
+    /// number of compute agents available to the cannon.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_executions: Option<u32>,
+    /// When present, the cannon generates its own authorizations for a
+    /// program's function with random inputs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generate: Option<TxGenerator>,
+    /// Seeds the random inputs and keys of generated authorizations, so
+    /// the cannon generates the same sequence of transactions every run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
 }
 
 impl TxSource {
@@ -165,6 +216,20 @@ impl TxSource {
             get_available_port().ok_or(SourceError::TxSourceUnavailablePort)?,
         ))
     }
+
+    /// Maximum number of authorizations the cannon executes at once
+    pub fn execution_limit(&self, state: &GlobalState) -> usize {
+        if let Some(max) = self.max_concurrent_executions {
+            return max.max(1) as usize;
+        }
+        match &self.compute {
+            // each agent executes one authorization at a time
+            ComputeTarget::Agent { labels, .. } => {
+                count_compute_agents(state, labels.as_deref().unwrap_or_default()).max(1)
+            }
+            ComputeTarget::Demox { .. } => usize::MAX,
+        }
+    }
 }
 
 impl ComputeTarget {
@@ -176,9 +241,13 @@ impl ComputeTarget {
         auth: &Authorization,
     ) -> Result<(), CannonError> {
         match self {
-            ComputeTarget::Agent { labels } => {
+            ComputeTarget::Agent {
+                labels,
+                execution_timeout,
+            } => {
                 // find a client, mark it as busy
-                let (agent_id, client, _busy) =
+                // the in-flight authorization is tracked until this execution completes
+                let (agent_id, client, _busy, _load) =
                     find_compute_agent(&ctx.state, &labels.clone().unwrap_or_default())
                         .ok_or(SourceError::NoAvailableAgents("authorization"))?;
 
@@ -199,15 +268,23 @@ impl ComputeTarget {
                 }
 
                 // execute the authorization
-                let transaction_json = client
+                let timeout = execution_timeout.map(Duration::from_secs);
+                let transaction_json = match client
                     .execute_authorization(
                         ctx.env_id,
                         ctx.network,
                         query_path.to_owned(),
                         serde_json::to_string(&auth)
                             .map_err(|e| SourceError::Json("authorize tx", e))?,
+                        timeout,
                     )
-                    .await?;
+                    .await
+                {
+                    Err(StateError::Agent(AgentError::ExecTimedOut)) => {
+                        return Err(SourceError::ExecutionTimedOut(agent_id).into());
+                    }
+                    res => res?,
+                };
 
                 let transaction = match serde_json::from_str::<Arc<Value>>(&transaction_json) {
                     Ok(transaction) => transaction,
@@ -295,3 +372,20 @@ impl ComputeTarget {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_auths_per_sec() {
+        let source: TxSource = serde_yaml::from_str("max-auths-per-sec: 10").unwrap();
+        assert_eq!(source.max_auths_per_sec, Some(10));
+
+        let source: TxSource = serde_yaml::from_str("max-concurrent-executions: 2").unwrap();
+        assert_eq!(source.max_auths_per_sec, None);
+
+        // a limit of 0 would never accept an authorization
+        assert!(serde_yaml::from_str::<TxSource>("max-auths-per-sec: 0").is_err());
+    }
+}
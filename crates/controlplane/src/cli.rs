@@ -8,13 +8,23 @@ use std::{
 #[cfg(any(feature = "clipages", feature = "mangen"))]
 use clap::CommandFactory;
 use clap::Parser;
+use snops_common::db::{Database as _, DbOptions};
+use tracing::error;
 use url::Url;
 
+use crate::{db::Database, server::error::StartError};
+
 #[derive(Debug, Parser)]
 pub struct Cli {
     #[clap(long = "bind", default_value_t = IpAddr::V4(Ipv4Addr::UNSPECIFIED))]
     pub bind_addr: IpAddr,
 
+    /// Only bind to the loopback interface, keeping the API unreachable from
+    /// other machines. The control plane refuses to start when `--bind` is
+    /// set to an address that is not a loopback address.
+    #[arg(long)]
+    pub bind_loopback_only: bool,
+
     /// Control plane server port
     #[arg(long, default_value_t = 1234)]
     pub port: u16,
@@ -36,6 +46,23 @@ pub struct Cli {
     #[arg(long, default_value = "snops-control-data")]
     pub path: PathBuf,
 
+    /// Maximum number of content downloads (binaries, genesis blocks) served
+    /// at once. Agents are asked to retry later when exceeded. Unlimited when
+    /// unset.
+    #[arg(long, env = "MAX_CONCURRENT_DOWNLOADS")]
+    pub max_concurrent_downloads: Option<usize>,
+
+    /// Seconds each storage generation command (aot genesis and accounts)
+    /// may run before it is killed and the env apply fails.
+    #[arg(long, default_value_t = 600)]
+    pub storage_gen_timeout: u64,
+
+    /// Directory that env specs can include local files from. Specs are
+    /// resolved on the control plane, so local includes are rejected when
+    /// unset and url includes are always allowed.
+    #[arg(long, env = "SNOPS_INCLUDE_DIR")]
+    pub include_dir: Option<PathBuf>,
+
     #[arg(long)]
     /// Hostname to advertise to the control plane, used when resolving the
     /// control plane's address for external cannons can be an external IP
@@ -44,14 +71,27 @@ pub struct Cli {
     /// must contain http:// or https://
     pub hostname: Option<String>,
 
-    #[cfg(any(feature = "clipages", feature = "mangen"))]
+    #[clap(flatten)]
+    pub db: DbOptions,
+
     #[clap(subcommand)]
-    pub command: Commands,
+    pub command: Option<Commands>,
 }
 
-#[cfg(any(feature = "clipages", feature = "mangen"))]
 #[derive(Debug, Parser)]
 pub enum Commands {
+    /// Write the contents of the control plane's store to an archive. The
+    /// control plane must not be running.
+    Backup {
+        /// Path of the archive to write.
+        path: PathBuf,
+    },
+    /// Replace the contents of the control plane's store with an archive
+    /// written by `backup`. The control plane must not be running.
+    Restore {
+        /// Path of the archive to read.
+        path: PathBuf,
+    },
     #[cfg(feature = "mangen")]
     Man(snops_common::mangen::Mangen),
     #[cfg(feature = "clipages")]
@@ -59,9 +99,28 @@ pub enum Commands {
 }
 
 impl Cli {
-    #[cfg(any(feature = "clipages", feature = "mangen"))]
-    pub fn run(self) {
-        match self.command {
+    /// Run the subcommand and exit, if one was provided
+    pub fn run(&mut self) {
+        let Some(command) = self.command.take() else {
+            return;
+        };
+
+        match command {
+            Commands::Backup { path } => {
+                let res = Database::open_with(&self.path.join("store"), &self.db)
+                    .and_then(|db| db.backup(&path));
+                if let Err(e) = res {
+                    error!("failed to write backup {}: {e}", path.display());
+                    std::process::exit(1);
+                }
+            }
+            Commands::Restore { path } => {
+                let res = Database::restore(&self.path.join("store"), &self.db, &path);
+                if let Err(e) = res {
+                    error!("failed to restore backup {}: {e}", path.display());
+                    std::process::exit(1);
+                }
+            }
             #[cfg(feature = "mangen")]
             Commands::Man(mangen) => {
                 mangen
@@ -81,6 +140,20 @@ impl Cli {
         std::process::exit(0);
     }
 
+    /// Apply `--bind-loopback-only` to the bind address, replacing an
+    /// unspecified address with the IPv4 loopback address.
+    pub fn resolve_bind_addr(&mut self) -> Result<(), StartError> {
+        if !self.bind_loopback_only || self.bind_addr.is_loopback() {
+            return Ok(());
+        }
+        if !self.bind_addr.is_unspecified() {
+            return Err(StartError::NotLoopback(self.bind_addr));
+        }
+
+        self.bind_addr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        Ok(())
+    }
+
     pub fn get_local_addr(&self) -> SocketAddr {
         let ip = if self.bind_addr.is_unspecified() {
             IpAddr::V4(Ipv4Addr::LOCALHOST)
@@ -1,8 +1,16 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::Arc,
+};
 
 use snops_common::{
-    db::{Database as DatabaseTrait, error::DatabaseError, tree::DbTree},
-    format::PackedUint,
+    db::{Database as DatabaseTrait, DbOptions, error::DatabaseError, tree::DbTree},
+    format::{
+        BytesFormat, DataFormat, DataFormatReader, DataReadError, DataWriteError, PackedUint,
+        read_dataformat, write_dataformat,
+    },
     state::{AgentId, Authorization, CannonId, EnvId, NetworkId, StorageId, TransactionSendState},
 };
 
@@ -12,6 +20,7 @@ use crate::{
 };
 
 pub type TxEntry = (EnvId, CannonId, Arc<String>);
+pub type ConfirmedTxEntry = (EnvId, Arc<String>);
 
 pub struct Database {
     #[allow(unused)]
@@ -38,11 +47,15 @@ pub struct Database {
     pub(crate) tx_index: DbTree<TxEntry, PackedUint>,
     /// Number of attempts for the transaction's current state
     pub(crate) tx_attempts: DbTree<TxEntry, PackedUint>,
+    /// Transactions confirmed in each environment and the unix time they were
+    /// confirmed at, so they are not re-broadcasted after the control plane
+    /// restarts
+    pub(crate) tx_confirmed: DbTree<ConfirmedTxEntry, i64>,
 }
 
 impl DatabaseTrait for Database {
-    fn open(path: &Path) -> Result<Self, DatabaseError> {
-        let db = sled::open(path)?;
+    fn open_with(path: &Path, options: &DbOptions) -> Result<Self, DatabaseError> {
+        let db = options.open(path)?;
         let envs = DbTree::new(db.open_tree(b"v2/envs")?);
         let storage = DbTree::new(db.open_tree(b"v2/storage")?);
         let agents = DbTree::new(db.open_tree(b"v2/agents")?);
@@ -51,6 +64,7 @@ impl DatabaseTrait for Database {
         let tx_status = DbTree::new(db.open_tree(b"v2/tx_status")?);
         let tx_index = DbTree::new(db.open_tree(b"v2/tx_index")?);
         let tx_attempts = DbTree::new(db.open_tree(b"v2/tx_attempts")?);
+        let tx_confirmed = DbTree::new(db.open_tree(b"v2/tx_confirmed")?);
 
         Ok(Self {
             db,
@@ -62,6 +76,280 @@ impl DatabaseTrait for Database {
             tx_status,
             tx_index,
             tx_attempts,
+            tx_confirmed,
+        })
+    }
+}
+
+/// Schema version of the database, used as the prefix of every tree name
+const SCHEMA_VERSION: &str = "v2";
+
+/// Names of the trees in the database, without the schema prefix
+const TREES: [&str; 9] = [
+    "envs",
+    "storage",
+    "agents",
+    "tx_auths",
+    "tx_blobs",
+    "tx_status",
+    "tx_index",
+    "tx_attempts",
+    "tx_confirmed",
+];
+
+/// A raw copy of every tree in the database. Values are copied as-is, so
+/// they keep their own format headers.
+struct Backup {
+    schema: String,
+    trees: Vec<(String, Vec<(BytesFormat, BytesFormat)>)>,
+}
+
+impl DataFormat for Backup {
+    type Header = u8;
+    const LATEST_HEADER: Self::Header = 1;
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        Ok(self.schema.write_data(writer)? + self.trees.write_data(writer)?)
+    }
+
+    fn read_data<R: Read>(reader: &mut R, header: &Self::Header) -> Result<Self, DataReadError> {
+        if *header != Self::LATEST_HEADER {
+            return Err(DataReadError::unsupported(
+                "Backup",
+                Self::LATEST_HEADER,
+                header,
+            ));
+        }
+
+        Ok(Self {
+            schema: reader.read_data(&())?,
+            trees: reader.read_data(&((), ((), ())))?,
         })
     }
 }
+
+impl Database {
+    /// Delete the tracked transactions of the cannons under `prefix`, either
+    /// an env id or an env and cannon id, returning the number of rows
+    /// deleted. Every tree is cleared even if another fails.
+    pub fn delete_tx_trackers<Prefix: DataFormat>(
+        &self,
+        prefix: &Prefix,
+    ) -> Result<usize, DatabaseError> {
+        [
+            self.tx_attempts.delete_with_prefix(prefix),
+            self.tx_auths.delete_with_prefix(prefix),
+            self.tx_blobs.delete_with_prefix(prefix),
+            self.tx_index.delete_with_prefix(prefix),
+            self.tx_status.delete_with_prefix(prefix),
+        ]
+        .into_iter()
+        .sum()
+    }
+
+    /// Write every tree in the database to an archive at `path`
+    pub fn backup(&self, path: &Path) -> Result<(), DatabaseError> {
+        let mut trees = Vec::with_capacity(TREES.len());
+        for name in TREES {
+            let tree = self.db.open_tree(format!("{SCHEMA_VERSION}/{name}"))?;
+            let rows = tree
+                .iter()
+                .map(|row| row.map(|(k, v)| (k.to_vec().into(), v.to_vec().into())))
+                .collect::<Result<Vec<_>, _>>()?;
+            trees.push((name.to_owned(), rows));
+        }
+
+        let backup = Backup {
+            schema: SCHEMA_VERSION.to_owned(),
+            trees,
+        };
+
+        let mut writer = BufWriter::new(File::create(path).map_err(DataWriteError::from)?);
+        write_dataformat(&mut writer, &backup)?;
+        writer.flush().map_err(DataWriteError::from)?;
+        Ok(())
+    }
+
+    /// Replace the database at `path` with an archive written by
+    /// [`Database::backup`]. The archive is restored into a new database next
+    /// to `path`, which is only moved into place once it is complete, so a
+    /// failed restore leaves the existing database untouched.
+    pub fn restore(path: &Path, options: &DbOptions, archive: &Path) -> Result<(), DatabaseError> {
+        let mut reader = BufReader::new(File::open(archive).map_err(DataReadError::from)?);
+        let backup: Backup = read_dataformat(&mut reader)?;
+
+        if backup.schema != SCHEMA_VERSION {
+            return Err(
+                DataReadError::unsupported("backup schema", SCHEMA_VERSION, backup.schema).into(),
+            );
+        }
+        if let Some((name, _)) = backup
+            .trees
+            .iter()
+            .find(|(n, _)| !TREES.contains(&n.as_str()))
+        {
+            return Err(DataReadError::custom(format!("unknown tree in backup: {name}")).into());
+        }
+
+        // leftovers of an interrupted restore are discarded
+        let restoring = path.with_extension("restoring");
+        let replaced = path.with_extension("replaced");
+        for dir in [&restoring, &replaced] {
+            if dir.exists() {
+                std::fs::remove_dir_all(dir).map_err(DataWriteError::from)?;
+            }
+        }
+
+        {
+            let db = options.open(&restoring)?;
+            for (name, rows) in backup.trees {
+                let tree = db.open_tree(format!("{SCHEMA_VERSION}/{name}"))?;
+                for (key, value) in rows {
+                    tree.insert(key.0, value.0)?;
+                }
+            }
+            db.flush()?;
+        }
+
+        // swap the restored database into place, putting the existing one
+        // back if that fails
+        if path.exists() {
+            std::fs::rename(path, &replaced).map_err(DataWriteError::from)?;
+        }
+        if let Err(e) = std::fs::rename(&restoring, path) {
+            if replaced.exists() {
+                let _ = std::fs::rename(&replaced, path);
+            }
+            return Err(DataWriteError::from(e).into());
+        }
+        if replaced.exists() {
+            std::fs::remove_dir_all(&replaced).map_err(DataWriteError::from)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use snops_common::state::InternedId;
+
+    use super::*;
+
+    #[test]
+    fn test_backup_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("backup");
+        let options = DbOptions::default();
+
+        let db = Database::open(&dir.path().join("a")).unwrap();
+        db.db
+            .open_tree("v2/envs")
+            .unwrap()
+            .insert(b"key", b"value")
+            .unwrap();
+        db.backup(&archive).unwrap();
+
+        let store = dir.path().join("b");
+        let existing = Database::open(&store).unwrap();
+        existing
+            .db
+            .open_tree("v2/agents")
+            .unwrap()
+            .insert(b"stale", b"row")
+            .unwrap();
+        existing.db.flush().unwrap();
+        drop(existing);
+
+        // a bad archive leaves the existing database untouched
+        std::fs::write(dir.path().join("bad"), b"not a backup").unwrap();
+        assert!(Database::restore(&store, &options, &dir.path().join("bad")).is_err());
+        let existing = Database::open(&store).unwrap();
+        assert!(!existing.db.open_tree("v2/agents").unwrap().is_empty());
+        drop(existing);
+
+        Database::restore(&store, &options, &archive).unwrap();
+
+        let restored = Database::open(&store).unwrap();
+        let envs = restored.db.open_tree("v2/envs").unwrap();
+        assert_eq!(envs.get(b"key").unwrap().as_deref(), Some(&b"value"[..]));
+        assert!(restored.db.open_tree("v2/agents").unwrap().is_empty());
+        assert!(!store.with_extension("restoring").exists());
+        assert!(!store.with_extension("replaced").exists());
+    }
+
+    #[test]
+    fn test_delete_tx_trackers() {
+        let dir = std::env::temp_dir().join(format!("snops-tx-trackers-{}", std::process::id()));
+        let db = Database::open(&dir).unwrap();
+
+        let id = |s: &str| InternedId::from_str(s).unwrap();
+        let entries = [("env", "a"), ("env", "b"), ("other", "a")]
+            .map(|(env, cannon)| (id(env), id(cannon), Arc::new(format!("{env}-{cannon}"))));
+        for (i, entry) in entries.iter().enumerate() {
+            db.tx_index.save(entry, &PackedUint(i as u64)).unwrap();
+            db.tx_attempts.save(entry, &PackedUint(1)).unwrap();
+        }
+
+        assert_eq!(db.delete_tx_trackers(&(id("env"), id("a"))).unwrap(), 2);
+        assert_eq!(db.delete_tx_trackers(&id("env")).unwrap(), 2);
+        assert_eq!(
+            db.tx_index
+                .read_all()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>(),
+            [entries[2].clone()]
+        );
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Compares tearing down a million transaction trackers with
+    /// `delete_tx_trackers` against deleting each parsed key. Run with
+    /// `cargo test -p snops --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "writes a million transaction trackers"]
+    fn test_delete_tx_trackers_timing() {
+        const TRACKERS: usize = 1_000_000;
+
+        let dir = std::env::temp_dir().join(format!("snops-tx-teardown-{}", std::process::id()));
+        let id = |s: &str| InternedId::from_str(s).unwrap();
+        let prefix = (id("env"), id("cannon"));
+        let populate = |db: &Database| {
+            for i in 0..TRACKERS {
+                let entry = (prefix.0, prefix.1, Arc::new(format!("at1tx{i}")));
+                db.tx_index.save(&entry, &PackedUint(i as u64)).unwrap();
+                db.tx_attempts.save(&entry, &PackedUint(1)).unwrap();
+            }
+        };
+
+        let per_key = Database::open(&dir.join("per-key")).unwrap();
+        populate(&per_key);
+        let start = std::time::Instant::now();
+        let mut deleted = 0;
+        for tree in [&per_key.tx_index, &per_key.tx_attempts] {
+            for (key, _) in tree.read_with_prefix(&prefix).unwrap() {
+                deleted += tree.delete(&key).unwrap() as usize;
+            }
+        }
+        let per_key_elapsed = start.elapsed();
+        assert_eq!(deleted, TRACKERS * 2);
+
+        let batched = Database::open(&dir.join("batched")).unwrap();
+        populate(&batched);
+        let start = std::time::Instant::now();
+        assert_eq!(batched.delete_tx_trackers(&prefix).unwrap(), TRACKERS * 2);
+        let batched_elapsed = start.elapsed();
+
+        println!(
+            "deleting {TRACKERS} trackers: per key {per_key_elapsed:?}, batched {batched_elapsed:?}"
+        );
+        assert!(batched_elapsed < per_key_elapsed);
+
+        drop((per_key, batched));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
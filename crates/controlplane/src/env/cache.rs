@@ -7,7 +7,10 @@ use std::{
 use bimap::BiHashMap;
 use chrono::{DateTime, TimeDelta, Utc};
 use lazy_static::lazy_static;
-use snops_common::state::{LatestBlockInfo, NodeKey};
+use snops_common::{
+    api::ExternalNodeStatus,
+    state::{LatestBlockInfo, NodeKey},
+};
 
 use crate::state::GlobalState;
 
@@ -111,6 +114,18 @@ impl NetworkCache {
             .is_some_and(ResponsiveRecord::has_penalty)
     }
 
+    /// Get the observed status of an external peer
+    pub fn external_peer_status(&self, key: &NodeKey) -> ExternalNodeStatus {
+        let record = self.external_peer_record.get(key);
+        ExternalNodeStatus {
+            reachable: record.is_some_and(|r| r.failed_attempts == 0),
+            height: self.external_peer_infos.get(key).map(|i| i.height),
+            last_success: record
+                .filter(|r| r.total_successes > 0)
+                .map(|r| r.last_success),
+        }
+    }
+
     /// Update a peer's node info if the provided block hash exists in the cache
     pub fn update_peer_info_for_hash(&mut self, key: &NodeKey, hash: &str) {
         // ensure info exists
@@ -227,7 +242,7 @@ impl ResponsiveRecord {
         // The penalty is based on the time since the last successful attempt.
         // The longer the time since the last success, the longer the penalty
         Some(
-            (self.last_success - self.last_attempt)
+            (self.last_attempt - self.last_success)
                 .min(TimeDelta::seconds(Self::MAX_PENALTY as i64)),
         )
     }
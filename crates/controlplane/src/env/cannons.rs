@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, atomic::Ordering},
+};
+
+use snops_common::{
+    events::{EventHelpers, TransactionEvent},
+    state::{CannonId, EnvId, TxPipeId},
+};
+use tokio::sync::Semaphore;
+use tracing::{error, info};
+
+use super::{
+    Environment,
+    error::{EnvError, PrepareError},
+    prepare_cannon,
+};
+use crate::{
+    cannon::{CannonInstance, file::TransactionSink, sink::TxSink, source::TxSource},
+    persist::PersistEnv,
+    state::{EmitEvent, GlobalState},
+};
+
+impl Environment {
+    /// Create a cannon in a running environment without re-applying its spec.
+    /// The cannon starts immediately and is served at the same routes as the
+    /// cannons from the spec.
+    pub async fn add_cannon(
+        env_id: EnvId,
+        cannon_id: CannonId,
+        source: TxSource,
+        sink: TxSink,
+        state: Arc<GlobalState>,
+    ) -> Result<(), EnvError> {
+        let _lock = state.lock_env(env_id).await;
+        let env = state
+            .get_env(env_id)
+            .ok_or(PrepareError::EnvNotFound(env_id))?;
+
+        if env.cannons.contains_key(&cannon_id) {
+            Err(PrepareError::DuplicateCannon(cannon_id))?
+        }
+        sink.validate(cannon_id)?;
+
+        let compute_aot_bin = env.storage.resolve_compute_binary(&state).await?;
+
+        let mut sinks = env.sinks.clone();
+        let (mut instance, rx) = prepare_cannon(
+            &state,
+            &env.storage,
+            &mut sinks,
+            (
+                env_id,
+                env.network,
+                env.storage.ledger_location(&state),
+                compute_aot_bin,
+            ),
+            (cannon_id, source, sink),
+        )
+        .await?;
+        // the environment is already running, so the cannon does not need to
+        // wait for it to be ready
+        instance.spawn_local(rx, Arc::new(Semaphore::new(1)))?;
+
+        let mut cannons = env.cannons.clone();
+        cannons.insert(cannon_id, Arc::new(instance));
+
+        Self::replace_cannons(env_id, &env, cannons, sinks, &state);
+        info!("{env_id}: created cannon {cannon_id}");
+        Ok(())
+    }
+
+    /// Stop and remove a cannon from a running environment, along with its
+    /// transaction trackers.
+    pub async fn remove_cannon(
+        env_id: EnvId,
+        cannon_id: CannonId,
+        state: &GlobalState,
+    ) -> Result<(), EnvError> {
+        let _lock = state.lock_env(env_id).await;
+        let env = state
+            .get_env(env_id)
+            .ok_or(PrepareError::EnvNotFound(env_id))?;
+
+        let mut cannons = env.cannons.clone();
+        if cannons.remove(&cannon_id).is_none() {
+            Err(PrepareError::MissingCannon(cannon_id))?
+        }
+
+        // the cannon's task is aborted when the last reference to it is dropped
+        Self::replace_cannons(env_id, &env, cannons, env.sinks.clone(), state);
+
+        if let Err(e) = state.db.delete_tx_trackers(&(env_id, cannon_id)) {
+            error!("{env_id}.{cannon_id}: Failed to delete transaction persistence: {e}");
+        }
+
+        state.tx_latency.remove_cannon(env_id, cannon_id);
+
+        info!("{env_id}: removed cannon {cannon_id}");
+        Ok(())
+    }
+
+    /// Stop a cannon and start it again with the same source and sink, for
+    /// when its execution context is wedged. The cannon keeps its fired and
+    /// received counters, and its pending transactions are restored from
+    /// persistence. The stopped cannon stays in the environment if the new
+    /// one fails to start, so the restart can be retried.
+    pub async fn restart_cannon(
+        env_id: EnvId,
+        cannon_id: CannonId,
+        state: Arc<GlobalState>,
+    ) -> Result<(), EnvError> {
+        let _lock = state.lock_env(env_id).await;
+        let env = state
+            .get_env(env_id)
+            .ok_or(PrepareError::EnvNotFound(env_id))?;
+        let compute_aot_bin = env.storage.resolve_compute_binary(&state).await?;
+        let prev = env
+            .cannons
+            .get(&cannon_id)
+            .cloned()
+            .ok_or(PrepareError::MissingCannon(cannon_id))?;
+
+        let source = prev.source.clone();
+        let sink = prev.sink.clone();
+        let fired_txs = Arc::clone(&prev.fired_txs);
+        let received_txs = Arc::clone(&prev.received_txs);
+
+        // the previous cannon is stopped before the new one is created so its
+        // query service releases the ledger. It is only replaced once the new
+        // cannon is running.
+        prev.stop().await;
+
+        let mut sinks = env.sinks.clone();
+        let (mut instance, rx) = prepare_cannon(
+            &state,
+            &env.storage,
+            &mut sinks,
+            (
+                env_id,
+                env.network,
+                env.storage.ledger_location(&state),
+                compute_aot_bin,
+            ),
+            (cannon_id, source, sink),
+        )
+        .await?;
+        instance.fired_txs = fired_txs;
+        instance.received_txs = received_txs;
+        instance.spawn_local(rx, Arc::new(Semaphore::new(1)))?;
+
+        TransactionEvent::CannonRestarted {
+            fired: instance.fired_txs.load(Ordering::Relaxed),
+            received: instance.received_txs.load(Ordering::Relaxed),
+        }
+        .with_cannon(cannon_id)
+        .with_env_id(env_id)
+        .emit(&state);
+
+        let mut cannons = env.cannons.clone();
+        cannons.insert(cannon_id, Arc::new(instance));
+        Self::replace_cannons(env_id, &env, cannons, sinks, &state);
+        info!("{env_id}: restarted cannon {cannon_id}");
+        Ok(())
+    }
+
+    /// Replace an environment in place with a copy that has different cannons.
+    /// The caller must hold the env's lock.
+    fn replace_cannons(
+        env_id: EnvId,
+        env: &Environment,
+        cannons: HashMap<CannonId, Arc<CannonInstance>>,
+        sinks: HashMap<TxPipeId, Arc<TransactionSink>>,
+        state: &GlobalState,
+    ) {
+        let next_env = Arc::new(Environment {
+            id: env_id,
+            storage: Arc::clone(&env.storage),
+            network: env.network,
+            node_peers: env.node_peers.clone(),
+            node_states: env.node_states.clone(),
+            sinks,
+            cannons,
+            // the environment no longer matches the spec it was applied from
+            spec_hash: 0,
+            frozen_heights: env.frozen_heights.clone(),
+        });
+
+        if let Err(e) = state
+            .db
+            .envs
+            .save(&env_id, &PersistEnv::from(next_env.as_ref()))
+        {
+            error!("failed to save env {env_id} to persistence: {e}");
+        }
+
+        // the env is replaced in place to keep its cached network info
+        state.envs.insert(env_id, next_env);
+    }
+}
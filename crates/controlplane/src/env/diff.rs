@@ -0,0 +1,105 @@
+use indexmap::IndexMap;
+use serde::Serialize;
+use snops_common::state::{EnvId, NetworkId, NodeKey, StorageId};
+
+use super::{EnvNodeState, Environment, error::EnvError, flatten_document, select_storage};
+use crate::{schema::ItemDocument, state::GlobalState};
+
+/// A summary of what applying an env spec would change relative to the
+/// currently running environment.
+#[derive(Debug, Default, Serialize)]
+pub struct EnvDiff {
+    /// Nodes that are not part of the current environment
+    pub added: Vec<NodeKey>,
+    /// Nodes that would be removed from the current environment
+    pub removed: Vec<NodeKey>,
+    /// Nodes whose configuration would change
+    pub modified: Vec<NodeKey>,
+    /// The storage the spec would use
+    pub storage: StorageId,
+    /// The network the spec would use
+    pub network: NetworkId,
+    /// When true, agents would refetch storage info and potentially wipe their
+    /// ledgers
+    pub storage_changed: bool,
+    /// When true, agents would clear their last known height
+    pub clear_last_height: bool,
+}
+
+impl Environment {
+    /// Compare an env spec against the current environment without applying
+    /// it.
+    pub fn diff(
+        env_id: EnvId,
+        documents: Vec<ItemDocument>,
+        state: &GlobalState,
+    ) -> Result<EnvDiff, EnvError> {
+        let prev_env = state.get_env(env_id);
+
+        let mut diff = EnvDiff::default();
+        let mut storage_docs = vec![];
+        let mut incoming = IndexMap::<NodeKey, EnvNodeState>::default();
+
+        for document in documents {
+            match document {
+                ItemDocument::Storage(doc) => storage_docs.push(doc),
+                ItemDocument::Nodes(doc) => {
+                    if let Some(n) = doc.network {
+                        diff.network = n;
+                    }
+
+                    // nodes are keyed and flattened the same way applying the
+                    // spec would
+                    let nodes = flatten_document(doc, diff.network)?;
+                    for (node_key, node) in nodes.internal {
+                        incoming.insert(node_key, EnvNodeState::Internal(node));
+                    }
+                    for (node_key, node) in nodes.external {
+                        incoming.insert(node_key, EnvNodeState::External(node));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // resolve the storage the same way applying the spec would
+        let storage_doc = select_storage(storage_docs, diff.network)?;
+        diff.storage = storage_doc.id;
+
+        let Some(env) = prev_env else {
+            diff.added = incoming.into_keys().collect();
+            diff.clear_last_height = !storage_doc.persist;
+            return Ok(diff);
+        };
+
+        // storage is re-fetched when its identity or version changes
+        diff.storage_changed = storage_doc.id != env.storage.id
+            || storage_doc.regen != env.storage.version
+            || diff.network != env.storage.network;
+
+        for (key, node) in &incoming {
+            if !env.node_peers.contains_left(key) {
+                diff.added.push(key.clone());
+                continue;
+            }
+
+            let changed = match (env.node_states.get(key).as_deref(), node) {
+                (Some(EnvNodeState::Internal(a)), EnvNodeState::Internal(b)) => a != b,
+                (Some(EnvNodeState::External(a)), EnvNodeState::External(b)) => a != b,
+                _ => true,
+            };
+            if changed {
+                diff.modified.push(key.clone());
+            }
+        }
+
+        diff.removed = env
+            .node_peers
+            .left_values()
+            .filter(|key| !incoming.contains_key(*key))
+            .cloned()
+            .collect();
+
+        Ok(diff)
+    }
+}
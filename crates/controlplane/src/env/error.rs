@@ -1,10 +1,14 @@
+use std::net::IpAddr;
+
 use axum::http::StatusCode;
 use serde::{Serialize, Serializer, ser::SerializeStruct};
 use snops_common::{
     aot_cmds::AotCmdError,
     impl_into_status_code, impl_into_type_str,
+    key_source::KeySource,
+    node_targets::NodeTarget,
     rpc::error::SnarkosRequestError,
-    state::{AgentId, EnvId, NodeKey, TimelineId},
+    state::{AgentId, CannonId, EnvId, NetworkId, NodeKey, StorageId, TimelineId},
 };
 use strum_macros::AsRefStr;
 use thiserror::Error;
@@ -112,6 +116,8 @@ pub enum DelegationError {
     AgentAlreadyClaimed(AgentId, NodeKey),
     #[error("agent {0} does not support the mode needed for {1}")]
     AgentMissingMode(AgentId, NodeKey),
+    #[error("agent {0} has no address {2} for node {1} to bind to")]
+    AgentMissingBindAddr(AgentId, NodeKey, IpAddr),
     #[error("agent {0} not found for node {1}")]
     AgentNotFound(AgentId, NodeKey),
     #[error("insufficient number of agents to satisfy the request: have {0}: need {1}")]
@@ -123,7 +129,7 @@ pub enum DelegationError {
 impl_into_status_code!(DelegationError, |value| match value {
     AgentAlreadyClaimed(_, _) => StatusCode::IM_USED,
     AgentNotFound(_, _) => StatusCode::NOT_FOUND,
-    AgentMissingMode(_, _) => StatusCode::BAD_REQUEST,
+    AgentMissingMode(_, _) | AgentMissingBindAddr(_, _, _) => StatusCode::BAD_REQUEST,
     InsufficientAgentCount(_, _) | NoAvailableAgents(_) => {
         StatusCode::SERVICE_UNAVAILABLE
     }
@@ -133,12 +139,34 @@ impl_into_status_code!(DelegationError, |value| match value {
 pub enum PrepareError {
     #[error("duplicate node key: {0}")]
     DuplicateNodeKey(NodeKey),
-    #[error("multiple storage documents found in env")]
-    MultipleStorage,
+    #[error("multiple storage documents found for network {0}: {}", .1.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    MultipleStorage(NetworkId, Vec<StorageId>),
     #[error("missing storage document in env")]
     MissingStorage,
+    #[error("no storage document declares network {0}, found: {}", .1.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    MissingStorageForNetwork(NetworkId, Vec<StorageId>),
     #[error("cannot have a node with zero replicas")]
     NodeHas0Replicas,
+    #[error("invalid node range `{0}`: {1}")]
+    InvalidNodeRange(NodeKey, &'static str),
+    #[error("node targets match no nodes: {}", .0.iter().map(|(key, field, target)| format!("{key}.{field} `{target}`")).collect::<Vec<_>>().join(", "))]
+    UnresolvableTargets(Vec<(NodeKey, &'static str, NodeTarget)>),
+    #[error("default key `{1}` does not resolve to a private key for node {0}")]
+    UnresolvableDefaultKey(NodeKey, KeySource),
+    #[error("node {0} cannot use the random key `{1}`, as a node keeps a single key")]
+    RandomNodeKey(NodeKey, KeySource),
+    #[error("env `{0}` not found")]
+    EnvNotFound(EnvId),
+    #[error("nodes document network {1} does not match the env network {0}")]
+    NetworkMismatch(NetworkId, NetworkId),
+    #[error("node {0} is not in the env network {1}")]
+    NodeKeyNetworkMismatch(NodeKey, NetworkId),
+    #[error("cannon `{0}` already exists")]
+    DuplicateCannon(CannonId),
+    #[error("cannon `{0}` not found")]
+    MissingCannon(CannonId),
+    #[error("cannon `{0}` sets a max mempool depth without a sink target")]
+    MempoolDepthWithoutTarget(CannonId),
     #[error(transparent)]
     Reconcile(#[from] ReconcileError),
     #[error(transparent)]
@@ -146,8 +174,20 @@ pub enum PrepareError {
 }
 
 impl_into_status_code!(PrepareError, |value| match value {
-    DuplicateNodeKey(_) | MultipleStorage | NodeHas0Replicas => StatusCode::BAD_REQUEST,
-    MissingStorage => StatusCode::NOT_FOUND,
+    DuplicateNodeKey(_)
+    | MultipleStorage(_, _)
+    | NodeHas0Replicas
+    | InvalidNodeRange(_, _)
+    | UnresolvableTargets(_)
+    | UnresolvableDefaultKey(_, _)
+    | RandomNodeKey(_, _)
+    | NetworkMismatch(_, _)
+    | NodeKeyNetworkMismatch(_, _)
+    | DuplicateCannon(_)
+    | MempoolDepthWithoutTarget(_) => StatusCode::BAD_REQUEST,
+    MissingStorage | MissingStorageForNetwork(_, _) | EnvNotFound(_) | MissingCannon(_) => {
+        StatusCode::NOT_FOUND
+    }
     Cannon(e) => e.into(),
     Reconcile(e) => e.into(),
 });
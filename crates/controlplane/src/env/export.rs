@@ -0,0 +1,237 @@
+use indexmap::IndexMap;
+use serde_json::{Value, json};
+
+use super::Environment;
+use crate::{
+    persist::{PersistEnv, PersistNode},
+    schema::storage::LoadedStorage,
+};
+
+impl Environment {
+    /// Render the environment as a spec that re-creates it when applied, for
+    /// moving an environment to another control plane.
+    pub fn export(&self) -> Result<String, serde_yaml::Error> {
+        export_spec(&PersistEnv::from(self), &self.storage)
+    }
+}
+
+/// Render the storage, nodes, and cannons of a persisted environment as
+/// spec documents.
+///
+/// The storage is exported with the options it was generated or downloaded
+/// with, and the REST header values of external nodes are replaced with a
+/// placeholder that must be filled in before the spec is applied.
+pub fn export_spec(env: &PersistEnv, storage: &LoadedStorage) -> Result<String, serde_yaml::Error> {
+    let info = storage.info();
+    let mut storage_doc = json!({
+        "version": "storage.snarkos.testing.monadic.us/v1",
+        "id": info.id,
+        "name": info.id,
+        "regen": info.version,
+        "network": env.network,
+        "persist": info.persist,
+        "retention-policy": info.retention_policy,
+        // the binaries from `info` are rewritten to this control plane's api
+        "binaries": storage.binaries,
+        "ledger-url": storage.ledger_url,
+        "generate": storage.generation,
+        "connect": storage.connect,
+    });
+    // storages persisted before their generation options were kept only know
+    // whether they use the native genesis block
+    if storage.generation.is_none() && info.native_genesis {
+        storage_doc["generate"] = json!({});
+    }
+
+    let mut nodes = env.nodes.iter().collect::<Vec<_>>();
+    nodes.sort_by_cached_key(|(key, _)| key.to_string());
+
+    let mut internal = IndexMap::new();
+    let mut external = IndexMap::new();
+    for (key, node) in nodes {
+        match node {
+            PersistNode::Internal(_, node) => {
+                let mut node = node.as_ref().clone();
+                // agent ids are not portable between control planes
+                node.agent = None;
+                internal.insert(key, node);
+            }
+            PersistNode::External(node) => {
+                // the importer fills in the header values, which are rejected
+                // while they are still redacted
+                external.insert(key, node.redacted());
+            }
+        }
+    }
+
+    let nodes_doc = json!({
+        "version": "nodes.snarkos.testing.monadic.us/v1",
+        "name": env.id,
+        "network": env.network,
+        "nodes": internal,
+        "external": external,
+    });
+
+    let mut cannons = env.cannons.iter().collect::<Vec<_>>();
+    cannons.sort_by_key(|(id, _, _)| *id);
+    let cannon_docs = cannons.into_iter().map(|(id, source, sink)| {
+        json!({
+            "version": "cannon.snarkos.testing.monadic.us/v1",
+            "name": id,
+            "source": source,
+            "sink": sink,
+        })
+    });
+
+    let mut spec = String::new();
+    for doc in [storage_doc, nodes_doc].into_iter().chain(cannon_docs) {
+        spec.push_str("---\n");
+        spec.push_str(&serde_yaml::to_string(&strip_nulls(doc))?);
+    }
+    Ok(spec)
+}
+
+/// Remove null fields so the exported spec only contains the options that
+/// were set
+fn strip_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, strip_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(strip_nulls).collect()),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use snops_common::state::{InternedId, NetworkId, NodeKey};
+
+    use super::*;
+    use crate::schema::{
+        ItemDocument,
+        nodes::{ExternalNode, Node, RestHeaders},
+        storage::{GenesisGeneration, StorageGeneration},
+    };
+
+    #[test]
+    fn test_export_spec_round_trip() {
+        let storage = LoadedStorage {
+            id: InternedId::from_str("base").unwrap(),
+            network: NetworkId::default(),
+            version: 2,
+            committee: Default::default(),
+            accounts: Default::default(),
+            retention_policy: None,
+            persist: true,
+            native_genesis: false,
+            binaries: Default::default(),
+            ledger_url: None,
+            generation: Some(StorageGeneration {
+                genesis: Some(GenesisGeneration {
+                    seed: Some(7),
+                    ..Default::default()
+                }),
+                accounts: Default::default(),
+                transactions: vec![],
+            }),
+            connect: None,
+        };
+
+        let mut node: Node = serde_yaml::from_str("key: committee.0\nagent: agent-1").unwrap();
+        let env = PersistEnv {
+            id: InternedId::from_str("env").unwrap(),
+            storage_id: storage.id,
+            network: storage.network,
+            nodes: vec![(
+                NodeKey::from_str("validator/0").unwrap(),
+                PersistNode::Internal(
+                    InternedId::from_str("agent-1").unwrap(),
+                    Box::new(node.clone()),
+                ),
+            )],
+            cannons: vec![],
+            spec_hash: 0,
+            frozen_heights: vec![],
+        };
+
+        let spec = export_spec(&env, &storage).unwrap();
+        let documents = Environment::deserialize_bytes(spec.as_bytes()).unwrap();
+        assert_eq!(documents.len(), 2);
+
+        let ItemDocument::Storage(storage_doc) = &documents[0] else {
+            panic!("expected a storage document");
+        };
+        assert_eq!(storage_doc.id, storage.id);
+        assert_eq!(storage_doc.regen, 2);
+        assert!(storage_doc.persist);
+        // the genesis block is generated with the same options
+        let genesis = storage_doc.generate.as_ref().unwrap().genesis.as_ref();
+        assert_eq!(genesis.unwrap().seed, Some(7));
+
+        let ItemDocument::Nodes(nodes_doc) = &documents[1] else {
+            panic!("expected a nodes document");
+        };
+        // the agent pin is dropped
+        node.agent = None;
+        assert_eq!(
+            nodes_doc
+                .nodes
+                .get(&NodeKey::from_str("validator/0").unwrap()),
+            Some(&node)
+        );
+    }
+
+    #[test]
+    fn test_export_spec_redacts_rest_headers() {
+        let storage = LoadedStorage {
+            id: InternedId::from_str("base").unwrap(),
+            network: NetworkId::default(),
+            version: 0,
+            committee: Default::default(),
+            accounts: Default::default(),
+            retention_policy: None,
+            persist: false,
+            native_genesis: true,
+            binaries: Default::default(),
+            ledger_url: None,
+            generation: None,
+            connect: None,
+        };
+
+        let node = ExternalNode {
+            bft: None,
+            node: Some("127.0.0.1:4130".parse().unwrap()),
+            rest: Some("127.0.0.1:3030".parse().unwrap()),
+            rest_headers: Some(RestHeaders(
+                [("authorization".to_owned(), "Bearer secret".to_owned())]
+                    .into_iter()
+                    .collect(),
+            )),
+        };
+        let env = PersistEnv {
+            id: InternedId::from_str("env").unwrap(),
+            storage_id: storage.id,
+            network: storage.network,
+            nodes: vec![(
+                NodeKey::from_str("client/external").unwrap(),
+                PersistNode::External(node),
+            )],
+            cannons: vec![],
+            spec_hash: 0,
+            frozen_heights: vec![],
+        };
+
+        let spec = export_spec(&env, &storage).unwrap();
+        assert!(!spec.contains("secret"));
+        // the spec cannot be imported until the header values are filled in
+        assert!(Environment::deserialize_bytes(spec.as_bytes()).is_err());
+        let filled = spec.replace("<redacted>", "Bearer other");
+        assert!(Environment::deserialize_bytes(filled.as_bytes()).is_ok());
+    }
+}
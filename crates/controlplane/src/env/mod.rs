@@ -1,7 +1,11 @@
 use core::fmt;
 use std::{
     collections::{HashMap, HashSet},
-    sync::Arc,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 
 use bimap::BiMap;
@@ -9,21 +13,23 @@ use dashmap::DashMap;
 use futures_util::future::join_all;
 use indexmap::{IndexMap, IndexSet, map::Entry};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use snops_common::{
-    api::{AgentEnvInfo, EnvInfo},
-    node_targets::NodeTargets,
+    api::{AgentEnvInfo, EnvInfo, ExternalNodeStatus, NodeAdjacency},
+    key_source::KeySource,
+    node_targets::{NodeTarget, NodeTargetId, NodeTargetType, NodeTargets},
     state::{
-        AgentId, AgentPeer, AgentState, CannonId, EnvId, NetworkId, NodeKey, NodeState,
-        ReconcileOptions, TxPipeId,
+        AgentId, AgentPeer, AgentState, CannonId, EnvId, InventoryReason, KeyState, NetworkId,
+        NodeKey, NodeState, ReconcileOptions, TxPipeId,
     },
 };
-use tokio::sync::Semaphore;
+use tokio::sync::{Semaphore, watch};
 use tracing::{error, info, trace, warn};
 
 use self::error::*;
 use crate::{
     cannon::{
-        CannonInstance, CannonInstanceMeta,
+        CannonInstance, CannonInstanceMeta, CannonReceivers,
         file::TransactionSink,
         sink::TxSink,
         source::{ComputeTarget, QueryTarget, TxSource},
@@ -33,14 +39,19 @@ use crate::{
     persist::PersistEnv,
     schema::{
         ItemDocument,
-        nodes::{ExternalNode, Node},
-        storage::LoadedStorage,
+        error::StorageError,
+        nodes::{self, ExternalNode, Node, RestHeaders},
+        storage::{self, LoadedStorage},
     },
     state::{Agent, GlobalState},
 };
 
 pub mod cache;
+pub mod cannons;
+pub mod diff;
 pub mod error;
+pub mod export;
+pub mod scale;
 pub mod set;
 
 #[derive(Debug)]
@@ -57,6 +68,11 @@ pub struct Environment {
     pub sinks: HashMap<TxPipeId, Arc<TransactionSink>>,
     /// Map of cannon ids to their cannon instances
     pub cannons: HashMap<CannonId, Arc<CannonInstance>>,
+    /// Hash of the spec this environment was applied from
+    pub spec_hash: u64,
+    /// Heights of the nodes when the environment was frozen, which they are
+    /// brought back online at when it is thawed
+    pub frozen_heights: DashMap<NodeKey, u32>,
 }
 
 /// The effective test state of a node.
@@ -94,34 +110,98 @@ pub enum PortType {
 impl Environment {
     /// Deserialize (YAML) many documents into a `Vec` of documents.
     pub fn deserialize(str: &str) -> Result<Vec<ItemDocument>, DeserializeError> {
-        serde_yaml::Deserializer::from_str(str)
-            .enumerate()
-            .map(|(i, doc)| ItemDocument::deserialize(doc).map_err(|e| DeserializeError { i, e }))
-            .collect()
+        Self::deserialize_bytes(str.as_bytes())
     }
 
     /// Deserialize (YAML) many documents into a `Vec` of documents.
     pub fn deserialize_bytes(str: &[u8]) -> Result<Vec<ItemDocument>, DeserializeError> {
         serde_yaml::Deserializer::from_slice(str)
             .enumerate()
-            .map(|(i, doc)| ItemDocument::deserialize(doc).map_err(|e| DeserializeError { i, e }))
+            .map(|(i, doc)| {
+                ItemDocument::deserialize(doc).map_err(|e| DeserializeError::locate(str, i, e))
+            })
             .collect()
     }
 
+    /// Hash an environment spec so re-applying an identical spec can be
+    /// detected.
+    pub fn hash_spec(spec: &[u8]) -> u64 {
+        let digest = Sha256::digest(spec);
+        u64::from_le_bytes(digest[..8].try_into().unwrap())
+    }
+
+    /// Get the node map of this environment when it was applied from a spec
+    /// with the given hash and every node's agent is already in the state the
+    /// spec resolves to. Returns `None` when applying the spec would change
+    /// anything.
+    pub fn unchanged_node_map(
+        &self,
+        state: &GlobalState,
+        spec_hash: u64,
+    ) -> Option<HashMap<NodeKey, AgentId>> {
+        if self.spec_hash != spec_hash {
+            return None;
+        }
+
+        // the nodes are cloned so no node state references are held while
+        // peers are resolved against the pool
+        let nodes = self
+            .node_states
+            .iter()
+            .filter_map(|entry| match entry.value() {
+                EnvNodeState::Internal(node) => Some((entry.key().clone(), node.clone())),
+                EnvNodeState::External(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut node_map = HashMap::new();
+        for (key, node) in nodes {
+            let agent_id = self.get_agent_by_key(&key)?;
+            let agent_state = state.pool.get(&agent_id)?.state().clone();
+            let AgentState::Node(env_id, prev_state) = agent_state else {
+                return None;
+            };
+            if env_id != self.id {
+                return None;
+            }
+
+            // compare against the state an apply would send, keeping the
+            // current height like `update_all_agents` does
+            let mut next_state = self.resolve_node_state(state, agent_id, &key, &node);
+            if prev_state.height.1 == next_state.height.1 {
+                next_state.height.0 = prev_state.height.0;
+            }
+            if *prev_state != next_state {
+                return None;
+            }
+
+            node_map.insert(key, agent_id);
+        }
+
+        Some(node_map)
+    }
+
     /// Apply an environment spec. This will attempt to delegate the given node
     /// configurations to available agents, or update existing agents with new
     /// configurations.
     ///
     /// **This will error if the current env is not unset before calling to
     /// ensure tests are properly cleaned up.**
+    ///
+    /// When `only_if_offline` is set, running nodes are not restarted to apply
+    /// the spec and pick up their new state once they next go offline.
     pub async fn apply(
         env_id: EnvId,
         documents: Vec<ItemDocument>,
+        spec_hash: u64,
+        only_if_offline: bool,
         state: Arc<GlobalState>,
     ) -> Result<HashMap<NodeKey, AgentId>, EnvError> {
+        // the previous env must not change while the new one is built from it
+        let _lock = state.lock_env(env_id).await;
         let prev_env = state.get_env(env_id);
 
-        let mut storage_doc = None;
+        let mut storage_docs = vec![];
 
         let (mut node_peers, mut node_states) = match prev_env {
             Some(ref env) => {
@@ -136,6 +216,8 @@ impl Environment {
 
         let mut pending_cannons = HashMap::new();
         let mut agents_to_inventory = IndexSet::<AgentId>::default();
+        // nodes using a nodes document's default key, validated once storage is ready
+        let mut default_key_nodes = vec![];
 
         // default cannon will target any node for query and broadcast target
         // any available compute will be used as well.
@@ -144,7 +226,14 @@ impl Environment {
             (
                 TxSource {
                     query: QueryTarget::Node(NodeTargets::ALL),
-                    compute: ComputeTarget::Agent { labels: None },
+                    compute: ComputeTarget::Agent {
+                        labels: None,
+                        execution_timeout: None,
+                    },
+                    max_auths_per_sec: None,
+                    max_concurrent_executions: None,
+                    generate: None,
+                    seed: None,
                 },
                 TxSink {
                     target: Some(NodeTargets::ALL),
@@ -153,6 +242,9 @@ impl Environment {
                     broadcast_timeout: TxSink::default_retry_timeout(),
                     authorize_attempts: Some(3),
                     authorize_timeout: TxSink::default_retry_timeout(),
+                    rotate_after: None,
+                    max_mempool_depth: None,
+                    profile: None,
                 },
             ),
         );
@@ -160,23 +252,23 @@ impl Environment {
         for document in documents {
             match document {
                 ItemDocument::Storage(doc) => {
-                    if storage_doc.is_none() {
-                        storage_doc = Some(doc);
-                        // TODO: ensure storage does not change from prev_env
-                    } else {
-                        Err(PrepareError::MultipleStorage)?;
-                    }
+                    // TODO: ensure storage does not change from prev_env
+                    storage_docs.push(doc);
                 }
 
                 ItemDocument::Cannon(cannon) => {
+                    cannon.sink.validate(cannon.name)?;
                     pending_cannons.insert(cannon.name, (cannon.source, cannon.sink));
                 }
 
-                ItemDocument::Nodes(nodes) => {
-                    if let Some(n) = nodes.network {
+                ItemDocument::Nodes(doc) => {
+                    if let Some(n) = doc.network {
                         network = n;
                     }
 
+                    let nodes = flatten_document(doc, network)?;
+                    default_key_nodes.extend(nodes.default_key_nodes);
+
                     // maps of states and peers that are new to this environment
                     let mut incoming_states = IndexMap::default();
                     let mut updated_states = IndexMap::<NodeKey, EnvNodeState>::default();
@@ -185,50 +277,39 @@ impl Environment {
                     // set of resolved keys that will be present (new and old)
                     let mut agent_keys = HashSet::new();
 
-                    // flatten replicas
-                    for (doc_node_key, mut doc_node) in nodes.nodes {
-                        let num_replicas = doc_node.replicas.unwrap_or(1);
-                        // nobody needs more than 10k replicas anyway
-                        for i in 0..num_replicas.min(10000) {
-                            let node_key = match num_replicas {
-                                0 => Err(PrepareError::NodeHas0Replicas)?,
-                                1 => doc_node_key.to_owned(),
-                                _ => {
-                                    let mut node_key = doc_node_key.to_owned();
-                                    if !node_key.id.is_empty() {
-                                        node_key.id.push('-');
-                                    }
-                                    node_key.id.push_str(&i.to_string());
-                                    node_key
-                                }
-                            };
-                            agent_keys.insert(node_key.clone());
-
-                            // nodes in flattened_nodes have replicas unset
-                            doc_node.replicas.take();
+                    for (node_key, node) in nodes.internal {
+                        agent_keys.insert(node_key.clone());
 
-                            // replace the key with a new one
-                            let mut node = doc_node.to_owned();
-                            if let Some(key) = node.key.as_mut() {
-                                *key = key.with_index(i);
-                            }
+                        // Skip delegating nodes that are already present in the node map
+                        // Agents are able to determine what updates need to be applied
+                        // based on their resolved node states.
+                        if node_peers.contains_left(&node_key) {
+                            info!("{env_id}: updating node {node_key}");
+                            updated_states.insert(node_key, EnvNodeState::Internal(node));
+                            continue;
+                        }
 
-                            // Skip delegating nodes that are already present in the node map
-                            // Agents are able to determine what updates need to be applied
-                            // based on their resolved node states.
-                            if node_peers.contains_left(&node_key) {
-                                info!("{env_id}: updating node {node_key}");
-                                updated_states.insert(node_key, EnvNodeState::Internal(node));
-                                continue;
+                        match incoming_states.entry(node_key) {
+                            Entry::Occupied(ent) => {
+                                Err(PrepareError::DuplicateNodeKey(ent.key().clone()))?
                             }
+                            Entry::Vacant(ent) => ent.insert(EnvNodeState::Internal(node)),
+                        };
+                    }
 
-                            match incoming_states.entry(node_key) {
-                                Entry::Occupied(ent) => {
-                                    Err(PrepareError::DuplicateNodeKey(ent.key().clone()))?
-                                }
-                                Entry::Vacant(ent) => ent.insert(EnvNodeState::Internal(node)),
-                            };
-                        }
+                    // ensure every peer/validator target can reach a node before
+                    // any agents are delegated
+                    let known_keys = agent_keys
+                        .iter()
+                        .chain(nodes.external.keys())
+                        .collect::<Vec<_>>();
+                    let unresolvable = unresolvable_targets(
+                        incoming_states.iter().chain(updated_states.iter()),
+                        &known_keys,
+                        network,
+                    );
+                    if !unresolvable.is_empty() {
+                        Err(PrepareError::UnresolvableTargets(unresolvable))?
                     }
 
                     // list of nodes that will be removed after applying this document
@@ -342,13 +423,29 @@ impl Environment {
         }
 
         // prepare the storage after all the other documents
-        // as it depends on the network id
-        let storage = storage_doc
-            .ok_or(PrepareError::MissingStorage)?
-            .prepare(&state, network)
-            .await?;
+        // as it depends on the network id. cleaning up the env while the
+        // storage is prepared cancels it
+        let storage_doc = select_storage(storage_docs, network)?;
+        let storage_id = storage_doc.id;
+        let (guard, cancel_rx) = PrepareGuard::register(&state, env_id);
+        let storage = storage_doc.prepare(&state, network, cancel_rx).await;
+        if guard.cancelled() {
+            Err(StorageError::GenerationCancelled(storage_id))?
+        }
+        let storage = storage?;
 
-        let storage_id = storage.id;
+        // ensure the default key resolves to a private key for every node using it
+        for node_key in default_key_nodes {
+            let Some(EnvNodeState::Internal(node)) = node_states.get(&node_key) else {
+                continue;
+            };
+            let Some(key) = &node.key else {
+                continue;
+            };
+            if matches!(storage.lookup_keysource_pk(key), KeyState::None) {
+                Err(PrepareError::UnresolvableDefaultKey(node_key, key.clone()))?
+            }
+        }
 
         // this semaphor prevents cannons from starting until the environment is
         // created
@@ -367,12 +464,18 @@ impl Environment {
             &storage,
             prev_env.clone(),
             cannons_ready,
-            (env_id, network, storage_id, compute_aot_bin),
+            (
+                env_id,
+                network,
+                storage.ledger_location(&state),
+                compute_aot_bin,
+            ),
             pending_cannons
                 .into_iter()
                 .map(|(n, (source, sink))| (n, source, sink))
                 .collect(),
-        )?;
+        )
+        .await?;
 
         let storage_changed = prev_env
             .as_ref()
@@ -388,6 +491,9 @@ impl Environment {
             node_states,
             sinks,
             cannons,
+            spec_hash,
+            // applying a spec sets the nodes' states, which ends a freeze
+            frozen_heights: Default::default(),
         });
 
         if let Err(e) = state.db.envs.save(&env_id, &PersistEnv::from(env.as_ref())) {
@@ -403,11 +509,7 @@ impl Environment {
             );
             // reconcile agents that are freed up from the delta between environments
             state
-                .update_agent_states(
-                    agents_to_inventory
-                        .into_iter()
-                        .map(|id| (id, AgentState::Inventory)),
-                )
+                .inventory_agents(agents_to_inventory, InventoryReason::RemovedFromEnv(env_id))
                 .await;
         }
 
@@ -417,6 +519,7 @@ impl Environment {
             ReconcileOptions {
                 refetch_info: storage_changed,
                 clear_last_height,
+                only_if_offline,
                 ..Default::default()
             },
         )
@@ -472,32 +575,55 @@ impl Environment {
     }
 
     pub async fn cleanup(id: EnvId, state: &GlobalState) -> Result<(), EnvError> {
+        // abort the storage generation of every apply of the env that is
+        // still in progress
+        let pending = state
+            .pending_prepares
+            .iter()
+            .filter(|ent| ent.key().0 == id)
+            .map(|ent| *ent.key())
+            .collect::<Vec<_>>();
+        let mut cancelled = false;
+        for key in pending {
+            if let Some((_, cancel)) = state.pending_prepares.remove(&key) {
+                info!("{id}: Cancelling storage preparation...");
+                cancelled |= cancel.send(true).is_ok();
+            }
+        }
+
+        // wait for the changes that are still in progress, which the
+        // cancellation above lets applies finish early
+        let lock = state.lock_env(id).await;
+
         // clear the env state
         info!("{id}: Deleting persistence...");
 
-        let env = state.remove_env(id).ok_or(CleanupError::EnvNotFound(id))?;
+        let Some(env) = state.remove_env(id) else {
+            state.release_env_lock(id, lock);
+            // an env that was never applied has nothing else to clean up
+            if cancelled {
+                return Ok(());
+            }
+            return Err(CleanupError::EnvNotFound(id).into());
+        };
 
         if let Err(e) = state.db.envs.delete(&id) {
             error!("{id}: Failed to delete env persistence: {e}");
         }
 
+        // stop recording the env's transactions, keeping the record file
+        state.tx_recorders.remove(&id);
+        state.tx_latency.remove_env(id);
+
         // TODO: write all of these values to a file before deleting them
 
         // cleanup cannon transaction trackers
-        if let Err(e) = state.db.tx_attempts.delete_with_prefix(&id) {
-            error!("{id}: Failed to delete env tx_attempts persistence: {e}");
-        }
-        if let Err(e) = state.db.tx_auths.delete_with_prefix(&id) {
-            error!("{id}: Failed to delete env tx_auths persistence: {e}");
-        }
-        if let Err(e) = state.db.tx_blobs.delete_with_prefix(&id) {
-            error!("{id}: Failed to delete env tx_blobs persistence: {e}");
-        }
-        if let Err(e) = state.db.tx_index.delete_with_prefix(&id) {
-            error!("{id}: Failed to delete env tx_index persistence: {e}");
+        match state.db.delete_tx_trackers(&id) {
+            Ok(deleted) => trace!("{id}: Deleted {deleted} transaction tracker rows"),
+            Err(e) => error!("{id}: Failed to delete env transaction persistence: {e}"),
         }
-        if let Err(e) = state.db.tx_status.delete_with_prefix(&id) {
-            error!("{id}: Failed to delete env tx_status persistence: {e}");
+        if let Err(e) = state.db.tx_confirmed.delete_with_prefix(&id) {
+            error!("{id}: Failed to delete env tx_confirmed persistence: {e}");
         }
 
         if let Some(storage) = state.try_unload_storage(env.network, env.storage.id) {
@@ -507,7 +633,7 @@ impl Environment {
         trace!("{id}: Inventorying agents...");
 
         state
-            .update_agent_states(
+            .inventory_agents(
                 env.node_peers
                     .right_values()
                     // find all agents associated with the env
@@ -515,14 +641,15 @@ impl Environment {
                         EnvPeer::Internal(id) => Some(*id),
                         _ => None,
                     })
-                    .map(|id| (id, AgentState::Inventory))
                     // this collect is necessary because the iter sent to reconcile_agents
                     // must be owned by this thread. Without this, the iter would hold a reference
                     // to the env.node_peers.right_values(), which is NOT Send
                     .collect::<Vec<_>>(),
+                InventoryReason::EnvDeleted(id),
             )
             .await;
 
+        state.release_env_lock(id, lock);
         Ok(())
     }
 
@@ -555,9 +682,12 @@ impl Environment {
         pool: &'a DashMap<AgentId, Agent>,
         port_type: PortType,
     ) -> impl Iterator<Item = (&'a NodeKey, AgentPeer)> + 'a {
+        // env keys are named without their network, so targets naming another
+        // network are left out
+        let targets = targets.compile_on(self.network);
         self.node_peers
             .iter()
-            .filter(|(key, _)| targets.matches(key))
+            .filter(move |(key, _)| targets.matches(key))
             .filter_map(move |(key, value)| match value {
                 EnvPeer::Internal(id) => {
                     let agent = pool.get(id)?;
@@ -621,7 +751,8 @@ impl Environment {
             }
 
             // Only agents that reference the node are relevant
-            node.peers.matches(key) || node.validators.matches(key)
+            node.peers.matches_on(self.network, key)
+                || node.validators.matches_on(self.network, key)
         })
     }
 
@@ -635,41 +766,50 @@ impl Environment {
         let Some(key) = self.get_node_key_by_agent(agent_id) else {
             return;
         };
-        let pending_reconciles = self
+
+        // Snapshot the nodes that reference the updated node so no node state
+        // references are held while agents are looked up. Map references are
+        // never held across another map's lookup, an await, or an update.
+        let referencing = self
             .nodes_with_peer(key)
             .filter_map(|ent| {
                 let EnvNodeState::Internal(env_node) = ent.value() else {
                     return None;
                 };
+                Some((self.get_agent_by_key(ent.key())?, env_node.clone()))
+            })
+            .collect::<Vec<_>>();
 
-                // Lookup agent and get current state
-                let agent_id = self.get_agent_by_key(ent.key())?;
-
-                // If the port didn't change, we're not updating the agents' states
-                if !is_port_change {
-                    return Some((agent_id, None));
-                }
-
-                let agent = state.pool.get(&agent_id)?;
+        let mut pending_reconciles = Vec::with_capacity(referencing.len());
+        for (id, env_node) in referencing {
+            // If the port didn't change, we're not updating the agents' states
+            if !is_port_change {
+                pending_reconciles.push((id, None));
+                continue;
+            }
 
-                let AgentState::Node(env_id, node_state) = agent.state() else {
-                    return None;
-                };
+            // The state is cloned so the agent is released before peers are
+            // resolved against the pool
+            let Some(agent_state) = state.pool.get(&id).map(|agent| agent.state().clone()) else {
+                continue;
+            };
+            let AgentState::Node(env_id, node_state) = agent_state else {
+                continue;
+            };
 
-                // Determine if the node's peers and validators have changed
-                let (peers, validators) = self.resolve_node_peers(&state.pool, agent_id, env_node);
-                if peers == node_state.peers && validators == node_state.validators {
-                    return None;
-                }
+            // Determine if the node's peers and validators have changed
+            let (peers, validators) = self.resolve_node_peers(&state.pool, id, &env_node);
+            if peers == node_state.peers && validators == node_state.validators {
+                continue;
+            }
 
-                // Update the node's peers and validators
-                let mut new_state = node_state.clone();
-                new_state.peers = peers;
-                new_state.validators = validators;
+            // Update the node's peers and validators
+            let mut new_state = node_state;
+            new_state.peers = peers;
+            new_state.validators = validators;
 
-                Some((agent_id, Some(AgentState::Node(*env_id, new_state))))
-            })
-            .collect::<Vec<_>>();
+            pending_reconciles.push((id, Some(AgentState::Node(env_id, new_state))));
+        }
 
         // Call the clear peer addr RPC for all agents that reference the node
         if is_ip_change {
@@ -704,15 +844,47 @@ impl Environment {
         }
     }
 
+    /// Headers for REST requests to the external node with the given REST
+    /// address
+    pub fn external_rest_headers(&self, addr: SocketAddr) -> Option<RestHeaders> {
+        self.node_states.iter().find_map(|n| match n.value() {
+            EnvNodeState::External(ExternalNode {
+                rest: Some(rest),
+                rest_headers,
+                ..
+            }) if *rest == addr => rest_headers.clone(),
+            _ => None,
+        })
+    }
+
     pub fn get_cannon(&self, id: CannonId) -> Option<Arc<CannonInstance>> {
         self.cannons.get(&id).cloned()
     }
 
     pub fn info(&self, state: &GlobalState) -> EnvInfo {
+        let cache = state.env_network_cache.get(&self.id);
+        let external = self
+            .node_states
+            .iter()
+            .filter(|n| matches!(n.value(), EnvNodeState::External(_)))
+            .map(|n| {
+                let status = cache
+                    .as_ref()
+                    .map(|c| c.external_peer_status(n.key()))
+                    .unwrap_or(ExternalNodeStatus {
+                        reachable: false,
+                        height: None,
+                        last_success: None,
+                    });
+                (n.key().clone(), status)
+            })
+            .collect();
+
         EnvInfo {
             network: self.network,
             storage: self.storage.info(),
             block: state.get_env_block_info(self.id),
+            external,
         }
     }
 
@@ -756,25 +928,320 @@ impl Environment {
         let not_me = |agent: &AgentPeer| !matches!(agent, AgentPeer::Internal(candidate_id, _) if *candidate_id == id);
 
         // resolve the peers and validators from node targets
-        let mut peers: Vec<_> = self
-            .matching_nodes(&node.peers, pool, PortType::Node)
-            .filter(not_me)
-            .collect();
-        peers.sort();
-
-        let mut validators: Vec<_> = self
-            .matching_nodes(&node.validators, pool, PortType::Bft)
-            .filter(not_me)
-            .collect();
-        validators.sort();
+        let peers = sort_peers(
+            self.matching_peers(&node.peers, pool, PortType::Node)
+                .filter(|(_, peer)| not_me(peer)),
+        );
+        let validators = sort_peers(
+            self.matching_peers(&node.validators, pool, PortType::Bft)
+                .filter(|(_, peer)| not_me(peer)),
+        );
 
         (peers, validators)
     }
+
+    /// Resolve the configured peer and validator connections of every node,
+    /// ordered by node key. Unlike [`Self::resolve_node_peers`], this does not
+    /// depend on which agents are online.
+    pub fn topology_graph(&self) -> IndexMap<NodeKey, NodeAdjacency> {
+        let mut keys = self.node_peers.left_values().collect::<Vec<_>>();
+        keys.sort_by_cached_key(|key| key.to_string());
+
+        keys.iter()
+            .map(|&key| {
+                let adjacency = match self.node_states.get(key).as_deref() {
+                    Some(EnvNodeState::Internal(node)) => {
+                        let edges = |targets: &NodeTargets| {
+                            keys.iter()
+                                .filter(|&&k| k != key && targets.matches_on(self.network, k))
+                                .map(|&k| k.clone())
+                                .collect()
+                        };
+                        NodeAdjacency {
+                            external: false,
+                            peers: edges(&node.peers),
+                            validators: edges(&node.validators),
+                        }
+                    }
+                    _ => NodeAdjacency {
+                        external: true,
+                        ..Default::default()
+                    },
+                };
+                (key.clone(), adjacency)
+            })
+            .collect()
+    }
+}
+
+/// Sort resolved peers by their node key so the resulting order depends only
+/// on the topology and not on the ports the agents happen to be assigned.
+fn sort_peers<'a>(peers: impl Iterator<Item = (&'a NodeKey, AgentPeer)>) -> Vec<AgentPeer> {
+    let mut peers: Vec<_> = peers.map(|(key, peer)| (key.to_string(), peer)).collect();
+    peers.sort();
+    peers.into_iter().map(|(_, peer)| peer).collect()
+}
+
+/// Find the `peers` and `validators` targets of internal nodes that name
+/// another network or do not match any of the known keys. Empty and `any`
+/// targets of the env's network are always allowed.
+fn unresolvable_targets<'a>(
+    nodes: impl Iterator<Item = (&'a NodeKey, &'a EnvNodeState)>,
+    known_keys: &[&NodeKey],
+    network: NetworkId,
+) -> Vec<(NodeKey, &'static str, NodeTarget)> {
+    let mut unresolvable = vec![];
+
+    for (key, node) in nodes {
+        let EnvNodeState::Internal(node) = node else {
+            continue;
+        };
+
+        for (field, targets) in [("peers", &node.peers), ("validators", &node.validators)] {
+            let targets = match targets {
+                NodeTargets::None => continue,
+                NodeTargets::One(target) => std::slice::from_ref(target),
+                NodeTargets::Many(targets) => targets.as_slice(),
+            };
+
+            unresolvable.extend(
+                targets
+                    .iter()
+                    .filter(|target| {
+                        !target.is_on_network(network)
+                            || !is_any_target(target)
+                                && !known_keys.iter().any(|k| target.matches(k))
+                    })
+                    .map(|target| (key.clone(), field, target.clone())),
+            );
+        }
+    }
+
+    unresolvable
+}
+
+/// Whether a target matches every node type and id (e.g. `any/any`)
+fn is_any_target(target: &NodeTarget) -> bool {
+    matches!(
+        target,
+        NodeTarget {
+            ty: NodeTargetType::All,
+            id: NodeTargetId::All,
+            ..
+        }
+    )
+}
+
+/// The nodes of a nodes document, prepared for an env
+struct DocumentNodes {
+    /// Internal nodes with their replicas flattened, in document order
+    internal: Vec<(NodeKey, Node)>,
+    /// Internal nodes that fall back to the document's default key
+    default_key_nodes: Vec<NodeKey>,
+    external: IndexMap<NodeKey, ExternalNode>,
+}
+
+/// Prepare the nodes of a nodes document the same way for applying, scaling,
+/// and diffing an env of the given network. Node keys are stripped of their
+/// network, nodes without a key fall back to the document's default key, and
+/// replicas are flattened into a node each.
+fn flatten_document(
+    doc: nodes::Document,
+    network: NetworkId,
+) -> Result<DocumentNodes, PrepareError> {
+    let external = strip_key_networks(doc.external, network)?;
+
+    let mut internal = vec![];
+    let mut default_key_nodes = vec![];
+    for (doc_node_key, mut doc_node) in strip_key_networks(doc.nodes, network)? {
+        // nodes without a key fall back to the document's default key
+        let is_default_key = doc_node.key.is_none() && doc.default_key.is_some();
+        if is_default_key {
+            doc_node.key = doc.default_key.clone();
+        }
+
+        for (node_key, node) in flatten_replicas(doc_node_key, doc_node)? {
+            if is_default_key {
+                default_key_nodes.push(node_key.clone());
+            }
+            internal.push((node_key, node));
+        }
+    }
+
+    Ok(DocumentNodes {
+        internal,
+        default_key_nodes,
+        external,
+    })
+}
+
+/// Remove the networks named by node keys (`canary/validator/0`) so nodes are
+/// keyed the same with and without the prefix. Keys must name the env's
+/// network.
+fn strip_key_networks<T>(
+    nodes: IndexMap<NodeKey, T>,
+    network: NetworkId,
+) -> Result<IndexMap<NodeKey, T>, PrepareError> {
+    let mut stripped = IndexMap::with_capacity(nodes.len());
+    for (key, node) in nodes {
+        if key.network.is_some_and(|n| n != network) {
+            return Err(PrepareError::NodeKeyNetworkMismatch(key, network));
+        }
+        match stripped.entry(key.without_network()) {
+            Entry::Occupied(ent) => return Err(PrepareError::DuplicateNodeKey(ent.key().clone())),
+            Entry::Vacant(ent) => ent.insert(node),
+        };
+    }
+    Ok(stripped)
+}
+
+/// Flatten a node document entry into a node per replica. Replicas receive
+/// the replica index as a suffix to their node key and key source.
+pub fn flatten_replicas(
+    doc_node_key: NodeKey,
+    mut doc_node: Node,
+) -> Result<Vec<(NodeKey, Node)>, PrepareError> {
+    // random keys are only meant for cannons, which pick a key per use
+    if let Some(key @ KeySource::Random { .. }) = &doc_node.key {
+        return Err(PrepareError::RandomNodeKey(doc_node_key, key.clone()));
+    }
+
+    // ranged keys (`validator/10..20`) expand into one node per index
+    if let Some(range) = doc_node_key.id_range() {
+        if doc_node.replicas.is_some() {
+            return Err(PrepareError::InvalidNodeRange(
+                doc_node_key,
+                "ranged nodes cannot set replicas",
+            ));
+        }
+        if range.is_empty() {
+            return Err(PrepareError::InvalidNodeRange(
+                doc_node_key,
+                "range must be ascending",
+            ));
+        }
+        if range.len() > MAX_REPLICAS {
+            return Err(PrepareError::InvalidNodeRange(
+                doc_node_key,
+                "range exceeds the replica limit",
+            ));
+        }
+
+        return Ok(range
+            .map(|i| {
+                let mut node_key = doc_node_key.to_owned();
+                node_key.id = i.to_string();
+
+                let mut node = doc_node.to_owned();
+                if let Some(key) = node.key.as_mut() {
+                    *key = key.with_index(i);
+                }
+                (node_key, node)
+            })
+            .collect());
+    }
+
+    let num_replicas = doc_node.replicas.unwrap_or(1);
+    // nodes in flattened_nodes have replicas unset
+    doc_node.replicas.take();
+
+    let mut nodes = vec![];
+    for i in 0..num_replicas.min(MAX_REPLICAS) {
+        let node_key = match num_replicas {
+            0 => Err(PrepareError::NodeHas0Replicas)?,
+            1 => doc_node_key.to_owned(),
+            _ => {
+                let mut node_key = doc_node_key.to_owned();
+                if !node_key.id.is_empty() {
+                    node_key.id.push('-');
+                }
+                node_key.id.push_str(&i.to_string());
+                node_key
+            }
+        };
+
+        // replace the key with a new one
+        let mut node = doc_node.to_owned();
+        if let Some(key) = node.key.as_mut() {
+            *key = key.with_index(i);
+        }
+
+        nodes.push((node_key, node));
+    }
+
+    Ok(nodes)
+}
+
+/// Nobody needs more than 10k replicas anyway
+const MAX_REPLICAS: usize = 10000;
+
+/// Identifies each apply's storage preparation
+static NEXT_PREPARE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Registers a storage preparation so cleaning up the env cancels it. The
+/// registration is removed when the guard is dropped, including when the
+/// apply is dropped mid-preparation.
+struct PrepareGuard<'a> {
+    state: &'a GlobalState,
+    key: (EnvId, u64),
+}
+
+impl<'a> PrepareGuard<'a> {
+    fn register(state: &'a GlobalState, env_id: EnvId) -> (Self, watch::Receiver<bool>) {
+        let key = (env_id, NEXT_PREPARE_ID.fetch_add(1, Ordering::Relaxed));
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        state.pending_prepares.insert(key, cancel_tx);
+        (Self { state, key }, cancel_rx)
+    }
+
+    /// Whether the cleanup cancelled the preparation, which removes its
+    /// sender
+    fn cancelled(self) -> bool {
+        self.state.pending_prepares.remove(&self.key).is_none()
+    }
+}
+
+impl Drop for PrepareGuard<'_> {
+    fn drop(&mut self) {
+        self.state.pending_prepares.remove(&self.key);
+    }
+}
+
+/// Select the storage document to use for the env's network.
+///
+/// A lone storage document is used unless it declares a different network.
+/// When multiple are present, exactly one must declare the env's network.
+fn select_storage(
+    docs: Vec<Box<storage::Document>>,
+    network: NetworkId,
+) -> Result<Box<storage::Document>, PrepareError> {
+    if docs.is_empty() {
+        return Err(PrepareError::MissingStorage);
+    }
+
+    let ids = docs.iter().map(|doc| doc.id).collect::<Vec<_>>();
+    let is_single = docs.len() == 1;
+    let mut matching = docs
+        .into_iter()
+        .filter(|doc| match doc.network {
+            Some(doc_network) => doc_network == network,
+            None => is_single,
+        })
+        .collect::<Vec<_>>();
+
+    match matching.len() {
+        0 => Err(PrepareError::MissingStorageForNetwork(network, ids)),
+        1 => Ok(matching.remove(0)),
+        _ => Err(PrepareError::MultipleStorage(
+            network,
+            matching.iter().map(|doc| doc.id).collect(),
+        )),
+    }
 }
 
 // TODO remove this type complexity problem
 #[allow(clippy::type_complexity)]
-pub fn prepare_cannons(
+pub async fn prepare_cannons(
     state: Arc<GlobalState>,
     storage: &LoadedStorage,
     prev_env: Option<Arc<Environment>>,
@@ -791,25 +1258,10 @@ pub fn prepare_cannons(
     let mut cannons = HashMap::default();
     let mut sinks = HashMap::default();
 
-    for (name, source, sink) in pending_cannons.into_iter() {
-        // create file sinks for all the cannons that use files as output
-        if let Some(file_name) = sink.file_name {
-            // prevent re-creating sinks that were in the previous env
-            if let std::collections::hash_map::Entry::Vacant(e) = sinks.entry(file_name) {
-                e.insert(Arc::new(TransactionSink::new(
-                    storage.path(&state),
-                    file_name,
-                )?));
-            }
-        }
-
-        let (mut instance, rx) = CannonInstance::new(
-            Arc::clone(&state),
-            name, // instanced cannons use the same name as the config
-            cannon_meta.clone(),
-            source,
-            sink,
-        )?;
+    for pending in pending_cannons.into_iter() {
+        let name = pending.0;
+        let (mut instance, rx) =
+            prepare_cannon(&state, storage, &mut sinks, cannon_meta.clone(), pending).await?;
 
         // instanced cannons receive the fired count from the previous environment
         if let Some(prev_cannon) = prev_env.as_ref().and_then(|e| e.cannons.get(&name)) {
@@ -821,3 +1273,366 @@ pub fn prepare_cannons(
 
     Ok((cannons, sinks))
 }
+
+/// Create a single cannon instance without spawning it. The cannon's file sink
+/// is created unless it already exists in `sinks`.
+pub async fn prepare_cannon(
+    state: &Arc<GlobalState>,
+    storage: &LoadedStorage,
+    sinks: &mut HashMap<TxPipeId, Arc<TransactionSink>>,
+    cannon_meta: CannonInstanceMeta,
+    (name, source, sink): (CannonId, TxSource, TxSink),
+) -> Result<(CannonInstance, CannonReceivers), EnvError> {
+    // create file sinks for all the cannons that use files as output
+    if let Some(file_name) = sink.file_name {
+        // prevent re-creating sinks that were in the previous env
+        if let std::collections::hash_map::Entry::Vacant(e) = sinks.entry(file_name) {
+            e.insert(Arc::new(TransactionSink::new(
+                storage.path(state),
+                file_name,
+                sink.rotate_after,
+            )?));
+        }
+    }
+
+    Ok(CannonInstance::new(
+        Arc::clone(state),
+        name, // instanced cannons use the same name as the config
+        cannon_meta,
+        source,
+        sink,
+    )
+    .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, str::FromStr, time::Duration};
+
+    use snops_common::state::{InternedId, PortConfig};
+
+    use super::*;
+    use crate::{
+        server::jwt::Claims,
+        state::{AgentAddrs, AgentFlags},
+    };
+
+    /// An env without nodes or cannons
+    fn bare_env(env_id: EnvId) -> Environment {
+        Environment {
+            id: env_id,
+            storage: Arc::new(LoadedStorage {
+                id: InternedId::from_str("base").unwrap(),
+                network: NetworkId::default(),
+                version: 0,
+                committee: Default::default(),
+                accounts: Default::default(),
+                retention_policy: None,
+                persist: false,
+                native_genesis: true,
+                binaries: Default::default(),
+                ledger_url: None,
+                generation: None,
+                connect: None,
+            }),
+            network: NetworkId::default(),
+            node_peers: Default::default(),
+            node_states: Default::default(),
+            sinks: Default::default(),
+            cannons: Default::default(),
+            spec_hash: 0,
+            frozen_heights: Default::default(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_env_lock_serializes_env_replacements() {
+        let state = GlobalState::load_for_test().await;
+        let env_id = EnvId::from_str("env").unwrap();
+        state.insert_env(env_id, Arc::new(bare_env(env_id)));
+
+        // each task replaces the env with a copy based on the version it read,
+        // the way cannon and node changes do
+        let tasks = (0..16).map(|_| {
+            let state = Arc::clone(&state);
+            tokio::spawn(async move {
+                let _lock = state.lock_env(env_id).await;
+                let prev = state.get_env(env_id).unwrap();
+                tokio::task::yield_now().await;
+                let next = Environment {
+                    spec_hash: prev.spec_hash + 1,
+                    ..bare_env(env_id)
+                };
+                state.envs.insert(env_id, Arc::new(next));
+            })
+        });
+        for res in join_all(tasks).await {
+            res.unwrap();
+        }
+
+        // no replacement was lost
+        assert_eq!(state.get_env(env_id).unwrap().spec_hash, 16);
+    }
+
+    #[tokio::test]
+    async fn test_env_lock_is_per_env() {
+        let state = GlobalState::load_for_test().await;
+        let env_a = EnvId::from_str("env-a").unwrap();
+        let env_b = EnvId::from_str("env-b").unwrap();
+
+        let _lock = state.lock_env(env_a).await;
+        let wait = Duration::from_millis(100);
+        assert!(
+            tokio::time::timeout(wait, state.lock_env(env_a))
+                .await
+                .is_err()
+        );
+        assert!(
+            tokio::time::timeout(wait, state.lock_env(env_b))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_peer_addr_updates_do_not_deadlock() {
+        let state = GlobalState::load_for_test().await;
+        let env_id = EnvId::from_str("env").unwrap();
+        let node: Node =
+            serde_yaml::from_str("{ peers: validator/any, validators: validator/any }").unwrap();
+
+        let node_peers = BiMap::new();
+        let node_states = DashMap::new();
+        let mut agents = Vec::new();
+        for i in 0..8u16 {
+            let id = AgentId::from_str(&format!("agent-{i}")).unwrap();
+            let key = NodeKey::from_str(&format!("validator/{i}")).unwrap();
+            let agent = Agent::from_components(
+                Claims { id, nonce: 0 },
+                AgentState::Node(env_id, Box::new(node.into_state(key.clone()))),
+                AgentFlags {
+                    mode: Default::default(),
+                    labels: Default::default(),
+                    local_pk: false,
+                },
+                Some(PortConfig {
+                    node: 4130 + i,
+                    bft: 5000 + i,
+                    rest: 3030 + i,
+                    metrics: 9000 + i,
+                }),
+                Some(AgentAddrs {
+                    external: None,
+                    internal: vec!["127.0.0.1".parse().unwrap()],
+                }),
+            );
+            state.pool.insert(id, agent);
+            node_peers.insert(key.clone(), EnvPeer::Internal(id));
+            node_states.insert(key, EnvNodeState::Internal(node.clone()));
+            agents.push(id);
+        }
+
+        let env = Arc::new(Environment {
+            node_peers,
+            node_states,
+            ..bare_env(env_id)
+        });
+        state.insert_env(env_id, Arc::clone(&env));
+
+        // every agent's address changes while the env's node and agent states
+        // are rewritten the way an apply does
+        let tasks = agents.iter().flat_map(|&id| {
+            let update = tokio::spawn({
+                let (state, env) = (Arc::clone(&state), Arc::clone(&env));
+                async move { env.update_peer_addr(&state, id, true, true).await }
+            });
+            let apply = tokio::spawn({
+                let (state, env, node) = (Arc::clone(&state), Arc::clone(&env), node.clone());
+                async move {
+                    let key = env.get_node_key_by_agent(id).unwrap().clone();
+                    env.node_states
+                        .insert(key.clone(), EnvNodeState::Internal(node.clone()));
+                    let (peers, validators) = env.resolve_node_peers(&state.pool, id, &node);
+                    let mut node_state = node.into_state(key);
+                    node_state.peers = peers;
+                    node_state.validators = validators;
+                    state
+                        .update_agent_states([(id, AgentState::Node(env_id, Box::new(node_state)))])
+                        .await;
+                }
+            });
+            [update, apply]
+        });
+
+        let results = tokio::time::timeout(Duration::from_secs(10), join_all(tasks))
+            .await
+            .expect("peer addr updates deadlocked with concurrent state updates");
+        for res in results {
+            res.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sort_peers_ignores_ports() {
+        let keys: Vec<NodeKey> = ["client/0", "validator/0", "validator/1", "prover/0"]
+            .into_iter()
+            .map(|k| k.parse().unwrap())
+            .collect();
+        let ids: Vec<AgentId> = ["agent-a", "agent-b", "agent-c"]
+            .into_iter()
+            .map(|id| id.parse().unwrap())
+            .collect();
+        let external: SocketAddr = "127.0.0.1:4130".parse().unwrap();
+
+        let resolve = |ports: [u16; 3], reversed: bool| {
+            let mut peers = vec![
+                (&keys[0], AgentPeer::Internal(ids[2], ports[0])),
+                (&keys[1], AgentPeer::Internal(ids[0], ports[1])),
+                (&keys[2], AgentPeer::Internal(ids[1], ports[2])),
+                (&keys[3], AgentPeer::External(external)),
+            ];
+            if reversed {
+                peers.reverse();
+            }
+            sort_peers(peers.into_iter())
+                .into_iter()
+                .map(|peer| match peer {
+                    AgentPeer::Internal(id, _) => id.to_string(),
+                    AgentPeer::External(addr) => addr.to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let expected = vec!["agent-c", "127.0.0.1:4130", "agent-a", "agent-b"];
+        assert_eq!(resolve([5000, 5001, 5002], false), expected);
+        assert_eq!(resolve([5002, 5000, 5001], false), expected);
+        assert_eq!(resolve([5001, 5002, 5000], true), expected);
+    }
+
+    #[test]
+    fn test_unresolvable_targets() {
+        let node = |peers: &str, validators: &str| {
+            let yaml = format!("{{ peers: {peers}, validators: {validators} }}");
+            EnvNodeState::Internal(serde_yaml::from_str::<Node>(&yaml).unwrap())
+        };
+        let key = |k: &str| k.parse::<NodeKey>().unwrap();
+
+        // client/ext stands in for an external node
+        let keys = [key("validator/0"), key("client/0"), key("client/ext")];
+        let known_keys = keys.iter().collect::<Vec<_>>();
+
+        let nodes = [
+            (key("validator/0"), node("[]", "validator/*")),
+            (
+                key("client/0"),
+                node("any/any", "[validator/0, validator/1]"),
+            ),
+            (key("client/1"), node("[prover/any, client/ext]", "[]")),
+            // targets of another network match none of the env's nodes
+            (
+                key("client/2"),
+                node("[canary/client/0, testnet/any/any]", "[]"),
+            ),
+        ];
+
+        let unresolvable = unresolvable_targets(
+            nodes.iter().map(|(k, n)| (k, n)),
+            &known_keys,
+            NetworkId::Canary,
+        )
+        .into_iter()
+        .map(|(key, field, target)| format!("{key}.{field} {target}"))
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            unresolvable,
+            vec![
+                "client/0.validators validator/1",
+                "client/1.peers prover/any",
+                "client/2.peers testnet/any/any",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flatten_replicas_rejects_random_keys() {
+        let node = serde_yaml::from_str::<Node>("{ key: accounts.*, replicas: 2 }").unwrap();
+        assert!(matches!(
+            flatten_replicas(NodeKey::from_str("client/bench").unwrap(), node),
+            Err(PrepareError::RandomNodeKey(_, KeySource::Random { .. }))
+        ));
+
+        let node = serde_yaml::from_str::<Node>("{ key: accounts.$, replicas: 2 }").unwrap();
+        assert_eq!(
+            flatten_replicas(NodeKey::from_str("client/bench").unwrap(), node)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_flatten_document() {
+        let doc = serde_yaml::from_str::<nodes::Document>(
+            r#"
+name: test
+default_key: committee.0
+nodes:
+  canary/validator/0:
+    key: committee.1
+  client/0: {}
+external:
+  canary/client/ext: 11.12.13.14
+"#,
+        )
+        .unwrap();
+
+        let nodes = flatten_document(doc, NetworkId::Canary).unwrap();
+        let keys = nodes
+            .internal
+            .iter()
+            .map(|(key, node)| (key.to_string(), node.key.as_ref().map(|k| k.to_string())))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                ("validator/0".to_owned(), Some("committee.1".to_owned())),
+                ("client/0".to_owned(), Some("committee.0".to_owned())),
+            ]
+        );
+        let key = |k: &str| k.parse::<NodeKey>().unwrap();
+        assert_eq!(nodes.default_key_nodes, vec![key("client/0")]);
+        assert_eq!(
+            nodes.external.keys().collect::<Vec<_>>(),
+            vec![&key("client/ext")]
+        );
+    }
+
+    #[test]
+    fn test_strip_key_networks() {
+        let nodes = |keys: &[&str]| {
+            keys.iter()
+                .map(|k| (k.parse::<NodeKey>().unwrap(), ()))
+                .collect::<IndexMap<_, _>>()
+        };
+
+        let stripped = strip_key_networks(
+            nodes(&["canary/validator/0", "client/0"]),
+            NetworkId::Canary,
+        )
+        .unwrap();
+        assert_eq!(stripped, nodes(&["validator/0", "client/0"]));
+
+        assert!(matches!(
+            strip_key_networks(nodes(&["testnet/validator/0"]), NetworkId::Canary),
+            Err(PrepareError::NodeKeyNetworkMismatch(_, NetworkId::Canary))
+        ));
+        assert!(matches!(
+            strip_key_networks(
+                nodes(&["canary/validator/0", "validator/0"]),
+                NetworkId::Canary
+            ),
+            Err(PrepareError::DuplicateNodeKey(_))
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bimap::BiMap;
+use indexmap::{IndexMap, map::Entry};
+use snops_common::state::{AgentId, AgentState, EnvId, KeyState, NodeKey};
+use tracing::{error, info};
+
+use super::{
+    EnvNodeState, EnvPeer, Environment,
+    error::{EnvError, PrepareError},
+    flatten_document,
+    set::{BusyMode, get_agent_mappings, labels_from_nodes, pair_with_nodes},
+    unresolvable_targets,
+};
+use crate::{persist::PersistEnv, schema::nodes, state::GlobalState};
+
+impl Environment {
+    /// Add the nodes of a nodes document to a running environment without
+    /// re-applying its spec. Only the new nodes are delegated and reconciled.
+    /// Existing nodes are left untouched unless they reference a new node as a
+    /// peer or validator, in which case only their peers are re-resolved.
+    ///
+    /// Returns the node map of the newly delegated nodes.
+    pub async fn add_nodes(
+        env_id: EnvId,
+        doc: nodes::Document,
+        state: Arc<GlobalState>,
+    ) -> Result<HashMap<NodeKey, AgentId>, EnvError> {
+        let _lock = state.lock_env(env_id).await;
+        let env = state
+            .get_env(env_id)
+            .ok_or(PrepareError::EnvNotFound(env_id))?;
+
+        if let Some(network) = doc.network {
+            if network != env.network {
+                Err(PrepareError::NetworkMismatch(env.network, network))?
+            }
+        }
+        let nodes = flatten_document(doc, env.network)?;
+
+        let mut incoming_states = IndexMap::<NodeKey, EnvNodeState>::default();
+        for (node_key, node) in nodes.internal {
+            // the default key is validated here as the storage is already loaded
+            if let (true, Some(key)) = (nodes.default_key_nodes.contains(&node_key), &node.key) {
+                if matches!(env.storage.lookup_keysource_pk(key), KeyState::None) {
+                    Err(PrepareError::UnresolvableDefaultKey(
+                        node_key.clone(),
+                        key.clone(),
+                    ))?
+                }
+            }
+
+            insert_new_node(
+                &env,
+                &mut incoming_states,
+                node_key,
+                EnvNodeState::Internal(node),
+            )?;
+        }
+
+        // get a set of all labels the nodes can reference
+        let labels = labels_from_nodes(&incoming_states);
+
+        for (node_key, node) in nodes.external {
+            insert_new_node(
+                &env,
+                &mut incoming_states,
+                node_key,
+                EnvNodeState::External(node),
+            )?;
+        }
+
+        // new nodes may reference both existing and new nodes
+        let known_keys = env
+            .node_peers
+            .left_values()
+            .chain(incoming_states.keys())
+            .collect::<Vec<_>>();
+        let unresolvable = unresolvable_targets(incoming_states.iter(), &known_keys, env.network);
+        if !unresolvable.is_empty() {
+            Err(PrepareError::UnresolvableTargets(unresolvable))?
+        }
+
+        let mut incoming_peers = BiMap::default();
+
+        // ensure the "busy" is in scope until the initial reconcile completes and
+        // locks the agents into a non-inventory state
+        let free_agents = get_agent_mappings(BusyMode::Env, &state, &labels);
+        let _busy: Vec<_> = match pair_with_nodes(free_agents, &incoming_states, &labels) {
+            Ok(pairs) => pairs,
+            Err(errors) => {
+                for error in &errors {
+                    error!("delegation error: {error}");
+                }
+                return Err(EnvError::Delegation(errors));
+            }
+        }
+        .map(|(key, id, busy)| {
+            incoming_peers.insert(key, EnvPeer::Internal(id));
+            busy
+        })
+        .collect();
+
+        info!(
+            "{env_id}: delegated {} new nodes to agents",
+            incoming_peers.len()
+        );
+        for (key, node) in &incoming_peers {
+            info!("node {key}: {node}");
+        }
+
+        for (node_key, node) in &incoming_states {
+            if let EnvNodeState::External(_) = node {
+                incoming_peers.insert(node_key.clone(), EnvPeer::External(node_key.clone()));
+            }
+        }
+
+        let new_keys = incoming_states.keys().cloned().collect::<Vec<_>>();
+
+        let mut node_peers = env.node_peers.clone();
+        let mut node_states = env.node_states.clone();
+        node_peers.extend(incoming_peers);
+        node_states.extend(incoming_states);
+
+        let next_env = Arc::new(Environment {
+            id: env_id,
+            storage: Arc::clone(&env.storage),
+            network: env.network,
+            node_peers,
+            node_states,
+            sinks: env.sinks.clone(),
+            cannons: env.cannons.clone(),
+            // the environment no longer matches the spec it was applied from
+            spec_hash: 0,
+            frozen_heights: env.frozen_heights.clone(),
+        });
+
+        if let Err(e) = state
+            .db
+            .envs
+            .save(&env_id, &PersistEnv::from(next_env.as_ref()))
+        {
+            error!("failed to save env {env_id} to persistence: {e}");
+        }
+
+        // the env is replaced in place to keep its cached network info
+        state.envs.insert(env_id, Arc::clone(&next_env));
+
+        let mut pending_changes = vec![];
+        let mut node_map = HashMap::new();
+
+        for entry in next_env.node_states.iter() {
+            let key = entry.key();
+            let EnvNodeState::Internal(node) = entry.value() else {
+                continue;
+            };
+            let Some(agent_id) = next_env.get_agent_by_key(key) else {
+                continue;
+            };
+
+            // new nodes are fully resolved
+            if new_keys.contains(key) {
+                let next_state = next_env.resolve_node_state(&state, agent_id, key, node);
+                node_map.insert(key.clone(), agent_id);
+                pending_changes.push((agent_id, AgentState::Node(env_id, Box::new(next_state))));
+                continue;
+            }
+
+            // existing nodes only re-resolve their peers when they reference
+            // one of the new nodes
+            if !new_keys.iter().any(|new_key| {
+                node.peers.matches_on(env.network, new_key)
+                    || node.validators.matches_on(env.network, new_key)
+            }) {
+                continue;
+            }
+
+            let Some(agent) = state.pool.get(&agent_id) else {
+                continue;
+            };
+            let AgentState::Node(_, node_state) = agent.state() else {
+                continue;
+            };
+
+            let (peers, validators) = next_env.resolve_node_peers(&state.pool, agent_id, node);
+            if peers == node_state.peers && validators == node_state.validators {
+                continue;
+            }
+
+            let mut next_state = node_state.clone();
+            next_state.peers = peers;
+            next_state.validators = validators;
+            pending_changes.push((agent_id, AgentState::Node(env_id, next_state)));
+        }
+
+        state.update_agent_states(pending_changes).await;
+        Ok(node_map)
+    }
+}
+
+/// Insert a node that must not already be part of the environment or the
+/// incoming nodes
+fn insert_new_node(
+    env: &Environment,
+    incoming: &mut IndexMap<NodeKey, EnvNodeState>,
+    node_key: NodeKey,
+    node: EnvNodeState,
+) -> Result<(), PrepareError> {
+    if env.node_peers.contains_left(&node_key) {
+        return Err(PrepareError::DuplicateNodeKey(node_key));
+    }
+
+    match incoming.entry(node_key) {
+        Entry::Occupied(ent) => Err(PrepareError::DuplicateNodeKey(ent.key().clone())),
+        Entry::Vacant(ent) => {
+            ent.insert(node);
+            Ok(())
+        }
+    }
+}
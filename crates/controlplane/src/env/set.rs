@@ -1,5 +1,7 @@
 use std::{
+    cmp::Reverse,
     collections::{HashMap, HashSet},
+    net::IpAddr,
     sync::{Arc, Weak, mpsc},
 };
 
@@ -13,12 +15,13 @@ use snops_common::{
 };
 
 use super::{DelegationError, EnvNodeState};
-use crate::state::{Agent, AgentClient, Busy, GlobalState};
+use crate::state::{Agent, AgentAddrs, AgentClient, Busy, ComputeLoadGuard, GlobalState};
 
 pub struct AgentMapping {
     id: AgentId,
     claim: Weak<Busy>,
     mask: FixedBitSet,
+    addrs: Option<AgentAddrs>,
 }
 
 /// Ways of describing how an agent can be busy
@@ -45,7 +48,8 @@ impl AgentMapping {
                 agent.get_compute_claim()
             }
             BusyMode::Env => {
-                if !(agent.is_node_capable() && agent.is_inventory()) {
+                // compute-only agents are never paired with nodes
+                if !(agent.is_node_capable() && agent.is_inventory()) || agent.is_compute_only() {
                     return None;
                 }
                 agent.get_env_claim()
@@ -61,6 +65,7 @@ impl AgentMapping {
             id: agent.id(),
             claim,
             mask: agent.mask(labels),
+            addrs: agent.addrs().cloned(),
         })
     }
 
@@ -69,9 +74,15 @@ impl AgentMapping {
             id: agent_id,
             claim: agent.get_env_claim(),
             mask: agent.mask(labels),
+            addrs: agent.addrs().cloned(),
         })
     }
 
+    /// Check if a node can bind to the given address on this agent
+    pub fn can_bind(&self, addr: IpAddr) -> bool {
+        self.addrs.as_ref().is_some_and(|a| a.contains(addr))
+    }
+
     /// Attempt to atomically claim the agent
     pub fn claim(&self) -> Option<Arc<Busy>> {
         // avoid needlessly upgrading the weak pointer
@@ -87,6 +98,15 @@ impl AgentMapping {
         (Arc::strong_count(&arc) == 2).then_some(arc)
     }
 
+    /// Sum the weights of the preferred labels this agent has
+    pub fn label_weight(&self, weights: &[(usize, u32)]) -> u32 {
+        weights
+            .iter()
+            .filter(|(bit, _)| self.mask.contains(*bit))
+            .map(|(_, weight)| weight)
+            .sum()
+    }
+
     /// Attempt to atomically claim the agent if there is a mask subset
     pub fn claim_if_subset(&self, mask: &FixedBitSet) -> Option<Arc<Busy>> {
         if mask.is_subset(&self.mask) {
@@ -120,6 +140,7 @@ pub fn labels_from_nodes(nodes: &IndexMap<NodeKey, EnvNodeState>) -> Vec<Spur> {
         match node {
             EnvNodeState::Internal(n) => {
                 labels.extend(&n.labels);
+                labels.extend(n.preferred_labels.keys());
             }
             EnvNodeState::External(_) => {}
         }
@@ -149,22 +170,38 @@ fn _find_compute_agent_by_mask<'a, I: Iterator<Item = &'a Agent>>(
 
 /// Find an agent that can compute and has the given labels by checking each
 /// label individually
-pub fn find_compute_agent(
-    state: &GlobalState,
+///
+/// Agents that are already executing authorizations remain candidates, and the
+/// agent with the fewest in-flight authorizations is chosen. The returned guard
+/// counts the authorization against the agent until it is dropped.
+pub fn find_compute_agent<'a>(
+    state: &'a GlobalState,
     labels: &[Spur],
-) -> Option<(AgentId, AgentClient, Arc<Busy>)> {
-    state.pool.iter().find_map(|a| {
-        if !a.can_compute() || a.is_compute_claimed() || !labels.iter().all(|l| a.has_label(*l)) {
-            return None;
-        }
-        let arc = a.make_busy();
-        a.client_owned()
-            .and_then(|c| (Arc::strong_count(&arc) == 2).then_some((a.id(), c, arc)))
-    })
+) -> Option<(AgentId, AgentClient, Arc<Busy>, ComputeLoadGuard<'a>)> {
+    let (id, client) = state
+        .pool
+        .iter()
+        .filter(|a| a.is_compute_target() && labels.iter().all(|l| a.has_label(*l)))
+        .filter_map(|a| Some((a.id(), a.client_owned()?)))
+        .min_by_key(|(id, _)| state.get_compute_load(*id))?;
+
+    let busy = state.pool.get(&id)?.make_busy();
+    Some((id, client, busy, state.track_compute(id)))
+}
+
+/// Count the agents that can compute and have all of the given labels,
+/// whether or not they are busy
+pub fn count_compute_agents(state: &GlobalState, labels: &[Spur]) -> usize {
+    state
+        .pool
+        .iter()
+        .filter(|a| a.is_compute_target() && labels.iter().all(|l| a.has_label(*l)))
+        .count()
 }
 
 /// Given a map of nodes and list of agent mappings, attempt to pair each node
-/// with an agent in parallel
+/// with an agent. Nodes that prefer labels are paired first, and the other
+/// nodes are paired in parallel.
 pub fn pair_with_nodes(
     agents: Vec<AgentMapping>,
     nodes: &IndexMap<NodeKey, EnvNodeState>,
@@ -182,8 +219,16 @@ pub fn pair_with_nodes(
         // split into nodes that want specific agents and nodes that want specific labels
         .filter_map(|(key, env_node)| match env_node {
             EnvNodeState::Internal(n) => match n.agent {
-                Some(agent) => Some((Some((key, agent)), None)),
-                None => Some((None, Some((key, n.mask(key, labels))))),
+                Some(agent) => Some((Some((key, agent, n.bind_addr)), None)),
+                None => Some((
+                    None,
+                    Some((
+                        key,
+                        n.mask(key, labels),
+                        n.label_weights(labels),
+                        n.bind_addr,
+                    )),
+                )),
             },
             EnvNodeState::External(_) => None,
         })
@@ -212,7 +257,7 @@ pub fn pair_with_nodes(
 
     // walk through all the nodes that want specific agents and attempt to pair them
     // with an agent
-    want_ids.into_par_iter().for_each(|(key, id)| {
+    want_ids.into_par_iter().for_each(|(key, id, bind_addr)| {
         // ensure the agent exists
         let Some(agent) = agent_map.get(&id) else {
             let _ = errors_tx.send(DelegationError::AgentNotFound(id, key.clone()));
@@ -225,6 +270,12 @@ pub fn pair_with_nodes(
             return;
         }
 
+        // ensure the node's bind address is one of the agent's addresses
+        if let Some(addr) = bind_addr.filter(|addr| !agent.can_bind(*addr)) {
+            let _ = errors_tx.send(DelegationError::AgentMissingBindAddr(id, key.clone(), addr));
+            return;
+        }
+
         // attempt to claim the agent
         if let Some(claim) = agent.claim() {
             let _ = claimed_tx.send((key.clone(), id, claim));
@@ -233,12 +284,48 @@ pub fn pair_with_nodes(
         }
     });
 
-    // walk through all the nodes that want specific labels/modes and attempt to
-    // pair them with an agent that has the matching mask
-    want_labels.into_par_iter().for_each(|(key, mask)| {
-        // find the first agent that can be claimed that fits the mask
-        match agents
+    // nodes that prefer labels are paired first, highest weights first, so a
+    // node without preferences cannot claim an agent that another node prefers
+    let (mut preferring, indifferent): (Vec<_>, Vec<_>) = want_labels
+        .into_iter()
+        .partition(|(_, _, weights, _)| !weights.is_empty());
+    preferring.sort_by_cached_key(|(_, _, weights, _)| {
+        Reverse(weights.iter().map(|(_, weight)| weight).sum::<u32>())
+    });
+
+    // the highest weight each label is preferred with by any node
+    let mut preferred_weights = HashMap::<usize, u32>::new();
+    for (bit, weight) in preferring.iter().flat_map(|(_, _, weights, _)| weights) {
+        let max = preferred_weights.entry(*bit).or_default();
+        *max = (*max).max(*weight);
+    }
+    let preferred_weights = preferred_weights.into_iter().collect::<Vec<_>>();
+
+    // attempt to pair a node that wants specific labels/modes with an agent
+    // that has the matching mask
+    let pair = |(key, mask, weights, bind_addr): (
+        &NodeKey,
+        FixedBitSet,
+        Vec<(usize, u32)>,
+        Option<IpAddr>,
+    )| {
+        let mut candidates = agents
             .iter()
+            .filter(|a| bind_addr.is_none_or(|addr| a.can_bind(addr)))
+            .collect::<Vec<_>>();
+        // agents with more of the node's preferred labels are tried first, then
+        // the agents other nodes prefer the least
+        candidates.sort_by_cached_key(|a| {
+            (
+                Reverse(a.label_weight(&weights)),
+                a.label_weight(&preferred_weights),
+            )
+        });
+
+        // find the first agent that can be claimed that fits the mask and owns
+        // the node's bind address
+        match candidates
+            .into_iter()
             .find_map(|a| a.claim_if_subset(&mask).map(|c| (a.id, c)))
         {
             Some((id, claim)) => {
@@ -248,7 +335,12 @@ pub fn pair_with_nodes(
                 let _ = errors_tx.send(DelegationError::NoAvailableAgents(key.clone()));
             }
         }
-    });
+    };
+
+    // nodes with preferences are paired one at a time so a node with lower
+    // weights cannot claim the agent of a node with higher weights
+    preferring.into_iter().for_each(&pair);
+    indifferent.into_par_iter().for_each(&pair);
 
     let errors = errors_rx.try_iter().collect::<Vec<_>>();
     if errors.is_empty() {
@@ -257,3 +349,92 @@ pub fn pair_with_nodes(
         Err(errors)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use snops_common::{
+        INTERN,
+        rpc::control::agent::AgentServiceClient,
+        state::{AgentModeOptions, NodeType},
+    };
+
+    use super::*;
+    use crate::{schema::nodes::Node, state::AgentFlags};
+
+    #[test]
+    fn test_pair_preferring_nodes_first() {
+        let labels = [INTERN.get_or_intern("tier-high")];
+        let busy = [Arc::new(Busy), Arc::new(Busy)];
+
+        let agent = |id: &str, busy: &Arc<Busy>, tier_high: bool| {
+            let mut mask = FixedBitSet::with_capacity(labels.len() + MASK_PREFIX_LEN);
+            mask.insert(NodeType::Validator.bit());
+            mask.insert(NodeType::Client.bit());
+            mask.set(MASK_PREFIX_LEN, tier_high);
+            AgentMapping {
+                id: AgentId::from_str(id).unwrap(),
+                claim: Arc::downgrade(busy),
+                mask,
+                addrs: None,
+            }
+        };
+        // the preferred agent comes first in the pool
+        let agents = vec![agent("high", &busy[0], true), agent("low", &busy[1], false)];
+
+        // the node without preferences comes first in the spec
+        let node = |yaml: &str| EnvNodeState::Internal(serde_yaml::from_str::<Node>(yaml).unwrap());
+        let nodes = IndexMap::from_iter([
+            (NodeKey::from_str("client/0").unwrap(), node("{}")),
+            (
+                NodeKey::from_str("validator/0").unwrap(),
+                node("preferred_labels:\n  tier-high: 10"),
+            ),
+        ]);
+
+        let pairs = pair_with_nodes(agents, &nodes, &labels)
+            .unwrap_or_else(|e| panic!("failed to pair nodes: {e:?}"))
+            .map(|(key, id, _)| (key.to_string(), id.to_string()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(pairs["validator/0"], "high");
+        assert_eq!(pairs["client/0"], "low");
+    }
+
+    #[tokio::test]
+    async fn test_find_least_loaded_compute_agent() {
+        let state = GlobalState::load_for_test().await;
+        let flags = AgentFlags {
+            mode: AgentModeOptions {
+                compute: true,
+                ..Default::default()
+            },
+            labels: Default::default(),
+            local_pk: false,
+        };
+
+        let ids = ["agent-a", "agent-b", "agent-c"].map(|id| AgentId::from_str(id).unwrap());
+        for id in ids {
+            let (transport, _) = tarpc::transport::channel::unbounded();
+            let rpc = AgentServiceClient::new(tarpc::client::Config::default(), transport).spawn();
+            state.pool.insert(id, Agent::new(rpc, id, flags.clone()));
+        }
+
+        // every agent is executing, with the second agent the least loaded
+        let _loads = [
+            state.track_compute(ids[0]),
+            state.track_compute(ids[0]),
+            state.track_compute(ids[1]),
+            state.track_compute(ids[2]),
+            state.track_compute(ids[2]),
+        ];
+        let _busy = ids.map(|id| state.pool.get(&id).unwrap().make_busy());
+
+        let (id, _, _, load) = find_compute_agent(&state, &[]).unwrap();
+        assert_eq!(id, ids[1]);
+        assert_eq!(state.get_compute_load(ids[1]), 2);
+
+        drop(load);
+        assert_eq!(state.get_compute_load(ids[1]), 1);
+    }
+}
@@ -1,17 +1,76 @@
-use serde::{Serialize, Serializer, ser::SerializeStruct};
+use std::fmt;
+
+use serde::{Deserialize, Serialize, Serializer, ser::SerializeStruct};
 use snops_common::state::AgentId;
 use snops_common::{impl_into_status_code, impl_into_type_str};
 use strum_macros::AsRefStr;
 use thiserror::Error;
 
+use crate::schema::ItemDocument;
+
 #[derive(Debug, Error)]
-#[error("`{i}`: `{e}`")]
 pub struct DeserializeError {
+    /// Index of the document in the spec
     pub i: usize,
+    /// The document's `version`, if it could be parsed
+    pub version: Option<String>,
+    /// The document's `name`, if it could be parsed
+    pub name: Option<String>,
+    /// Line and column (1-based) of the error within the spec
+    pub location: Option<(usize, usize)>,
     #[source]
     pub e: serde_yaml::Error,
 }
 
+impl DeserializeError {
+    /// Describe the failure of the `i`th document of a spec, re-reading the
+    /// document to find its name, version, and the location of the error.
+    pub fn locate(spec: &[u8], i: usize, e: serde_yaml::Error) -> Self {
+        let doc = || serde_yaml::Deserializer::from_slice(spec).nth(i);
+
+        let value = doc().and_then(|d| serde_yaml::Value::deserialize(d).ok());
+        let field = |key: &str| {
+            value
+                .as_ref()
+                .and_then(|v| v.get(key))
+                .and_then(|v| v.as_str())
+                .map(str::to_owned)
+        };
+        let version = field("version");
+        let name = field("name");
+
+        // documents are buffered to read their version tag, which loses the
+        // error location, so the document is read again without buffering
+        let e = match (version.as_deref(), doc()) {
+            (Some(version), Some(doc)) => ItemDocument::untagged_error(version, doc).unwrap_or(e),
+            _ => e,
+        };
+        let location = e.location().map(|l| (l.line(), l.column()));
+
+        Self {
+            i,
+            version,
+            name,
+            location,
+            e,
+        }
+    }
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "document {}", self.i)?;
+        match (&self.version, &self.name) {
+            (Some(version), Some(name)) => write!(f, " (`{name}` {version})")?,
+            (Some(version), None) => write!(f, " ({version})")?,
+            (None, Some(name)) => write!(f, " (`{name}`)")?,
+            (None, None) => {}
+        }
+        // the error includes its location when it is known
+        write!(f, ": {}", self.e)
+    }
+}
+
 impl_into_status_code!(DeserializeError);
 
 #[derive(Debug, Error, AsRefStr)]
@@ -26,7 +85,34 @@ pub enum StateError {
     SourceAgentNotFound(AgentId),
 }
 
-impl_into_status_code!(StateError);
+#[derive(Debug, Error, AsRefStr)]
+pub enum PrometheusError {
+    #[error("prometheus query failed: {0}")]
+    Query(#[from] prometheus_http_query::Error),
+    #[error("invalid prometheus query: {0}")]
+    InvalidQuery(String),
+    #[error("prometheus query timed out")]
+    TimedOut,
+    #[error("unsupported prometheus query response")]
+    UnsupportedResponse,
+}
+
+impl_into_status_code!(PrometheusError, |value| match value {
+    Query(_) => axum::http::StatusCode::BAD_GATEWAY,
+    InvalidQuery(_) => axum::http::StatusCode::BAD_REQUEST,
+    TimedOut => axum::http::StatusCode::GATEWAY_TIMEOUT,
+    UnsupportedResponse => axum::http::StatusCode::BAD_REQUEST,
+});
+
+impl_into_status_code!(StateError, |value| match value {
+    Agent(snops_common::prelude::error::AgentError::ExecNotAllowed(_)) => {
+        axum::http::StatusCode::BAD_REQUEST
+    }
+    Agent(snops_common::prelude::error::AgentError::ExecNodeRunning(_)) => {
+        axum::http::StatusCode::CONFLICT
+    }
+    _ => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+});
 
 impl_into_type_str!(StateError, |value| match value {
     Agent(e) => format!("{}.{}", value.as_ref(), e.as_ref()),
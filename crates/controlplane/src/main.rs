@@ -4,10 +4,10 @@ use clap::Parser;
 use cli::Cli;
 use prometheus_http_query::Client as PrometheusClient;
 use schema::storage::{DEFAULT_AGENT_BINARY, DEFAULT_AOT_BINARY};
-use snops_common::db::Database;
-use state::GlobalState;
+use snops_common::{constant::ENV_AGENT_KEY, db::Database};
+use state::{GlobalState, PrometheusBreaker};
 use tokio::select;
-use tracing::{error, info, level_filters::LevelFilter, trace};
+use tracing::{error, info, level_filters::LevelFilter, trace, warn};
 use tracing_subscriber::{EnvFilter, prelude::*, reload};
 
 pub mod agent_version;
@@ -64,11 +64,15 @@ async fn main() {
         .try_init()
         .unwrap();
 
-    // For documentation purposes will exit after running the command.
-    #[cfg(any(feature = "clipages", feature = "mangen"))]
-    Cli::parse().run();
+    let mut cli = Cli::parse();
 
-    let cli = Cli::parse();
+    // Subcommands (backups, documentation) exit after running.
+    cli.run();
+
+    if let Err(e) = cli.resolve_bind_addr() {
+        error!("{e}");
+        std::process::exit(1);
+    }
 
     info!("Using AOT binary:\n{}", DEFAULT_AOT_BINARY.to_string());
     info!("Using Agent binary:\n{}", DEFAULT_AGENT_BINARY.to_string());
@@ -77,10 +81,11 @@ async fn main() {
     let prometheus = cli
         .prometheus
         .as_ref()
-        .and_then(|p| PrometheusClient::try_from(p.as_str()).ok());
+        .and_then(|p| PrometheusClient::try_from(p.as_str()).ok())
+        .map(PrometheusBreaker::new);
 
     trace!("Creating store");
-    let db = db::Database::open(&cli.path.join("store")).expect("open database");
+    let db = db::Database::open_with(&cli.path.join("store"), &cli.db).expect("open database");
     let socket_addr = SocketAddr::new(cli.bind_addr, cli.port);
 
     trace!("Loading state");
@@ -88,6 +93,20 @@ async fn main() {
         .await
         .expect("load state");
 
+    // the REST API is unauthenticated, while the agent key only guards the
+    // agent websocket
+    if !socket_addr.ip().is_loopback() {
+        warn!(
+            "binding to {}: the API is unauthenticated, so anyone who can reach this address can control envs. Use --bind-loopback-only or restrict access to this address",
+            socket_addr.ip()
+        );
+        if state.agent_key.is_none() {
+            warn!(
+                "no agent key: anyone who can reach this address can connect as an agent. Set {ENV_AGENT_KEY} to require a key for agent connections"
+            );
+        }
+    }
+
     // start the task that manages external peer block status
     let info_task = tokio::spawn(state::external_peers::block_info_task(Arc::clone(&state)));
     // start the task that manages transaction tracking status
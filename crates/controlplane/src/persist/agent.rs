@@ -295,6 +295,9 @@ mod test {
                 validators: vec![],
                 env: Default::default(),
                 binary: None,
+                bind_addr: None,
+                cpu_limit: None,
+                memory_limit: None,
             })),
             AgentFlags {
                 mode: AgentModeOptions::from(5u8),
@@ -323,6 +326,9 @@ mod test {
                 validators: vec![],
                 env: Default::default(),
                 binary: None,
+                bind_addr: None,
+                cpu_limit: None,
+                memory_limit: None,
             }.to_byte_vec()?,
             AgentFlags {
                 mode: AgentModeOptions::from(5u8),
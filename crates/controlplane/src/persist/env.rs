@@ -25,6 +25,7 @@ pub struct PersistEnvFormatHeader {
     tx_source: DataHeaderOf<TxSource>,
     tx_sink: DataHeaderOf<TxSink>,
     network: DataHeaderOf<NetworkId>,
+    node_key: DataHeaderOf<NodeKey>,
 }
 
 pub struct PersistEnv {
@@ -35,6 +36,10 @@ pub struct PersistEnv {
     pub nodes: Vec<(NodeKey, PersistNode)>,
     /// Loaded cannon configs in this env
     pub cannons: Vec<(CannonId, TxSource, TxSink)>,
+    /// Hash of the spec the env was applied from
+    pub spec_hash: u64,
+    /// Heights of the nodes when the env was frozen
+    pub frozen_heights: Vec<(NodeKey, u32)>,
 }
 
 impl From<&Environment> for PersistEnv {
@@ -75,6 +80,12 @@ impl From<&Environment> for PersistEnv {
                 .iter()
                 .map(|(id, cannon)| (*id, cannon.source.clone(), cannon.sink.clone()))
                 .collect(),
+            spec_hash: value.spec_hash,
+            frozen_heights: value
+                .frozen_heights
+                .iter()
+                .map(|entry| (entry.key().clone(), *entry.value()))
+                .collect(),
         }
     }
 }
@@ -88,6 +99,7 @@ impl PersistEnv {
         let storage = state
             .storage
             .get(&(self.network, self.storage_id))
+            .map(|storage| Arc::clone(storage.value()))
             .ok_or(PrepareError::MissingStorage)?;
 
         let mut node_map = BiMap::default();
@@ -109,12 +121,18 @@ impl PersistEnv {
 
         let (cannons, sinks) = prepare_cannons(
             Arc::clone(&state),
-            storage.value(),
+            &storage,
             None,
             cannons_ready,
-            (self.id, self.network, self.storage_id, compute_aot_bin),
+            (
+                self.id,
+                self.network,
+                storage.ledger_location(&state),
+                compute_aot_bin,
+            ),
             self.cannons,
-        )?;
+        )
+        .await?;
 
         // ensure on hydrate that all transactions that were interrupted are
         // marked as authorized
@@ -145,13 +163,16 @@ impl PersistEnv {
             node_states: initial_nodes,
             sinks,
             cannons,
+            spec_hash: self.spec_hash,
+            frozen_heights: self.frozen_heights.into_iter().collect(),
         })
     }
 }
 
 impl DataFormat for PersistEnvFormatHeader {
     type Header = u8;
-    const LATEST_HEADER: Self::Header = 2;
+    /// Version 3 adds the node key header
+    const LATEST_HEADER: Self::Header = 3;
 
     fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
         let mut written = 0;
@@ -160,6 +181,7 @@ impl DataFormat for PersistEnvFormatHeader {
         written += write_dataformat(writer, &self.tx_source)?;
         written += write_dataformat(writer, &self.tx_sink)?;
         written += writer.write_data(&self.network)?;
+        written += writer.write_data(&self.node_key)?;
         Ok(written)
     }
 
@@ -174,13 +196,21 @@ impl DataFormat for PersistEnvFormatHeader {
 
         let version = reader.read_data(&())?;
         let nodes = read_dataformat(reader)?;
-        let tx_source = read_dataformat(reader)?;
+        let tx_source: DataHeaderOf<TxSource> = read_dataformat(reader)?;
         let tx_sink = read_dataformat(reader)?;
         let network = if *header > 1 {
             reader.read_data(&())?
         } else {
             0
         };
+        // node keys were read with the node target header, and gained their
+        // network in the same release as node targets
+        let node_key = if *header > 2 {
+            reader.read_data(&((), ()))?
+        } else {
+            let (version, node_type) = tx_source.node_targets;
+            (if version > 2 { 2 } else { 1 }, node_type)
+        };
 
         Ok(PersistEnvFormatHeader {
             version,
@@ -188,6 +218,7 @@ impl DataFormat for PersistEnvFormatHeader {
             tx_source,
             tx_sink,
             network,
+            node_key,
         })
     }
 }
@@ -195,11 +226,12 @@ impl DataFormat for PersistEnvFormatHeader {
 impl DataFormat for PersistEnv {
     type Header = PersistEnvFormatHeader;
     const LATEST_HEADER: Self::Header = PersistEnvFormatHeader {
-        version: 1,
+        version: 3,
         nodes: PersistNode::LATEST_HEADER,
         tx_source: TxSource::LATEST_HEADER,
         tx_sink: TxSink::LATEST_HEADER,
         network: NetworkId::LATEST_HEADER,
+        node_key: NodeKey::LATEST_HEADER,
     };
 
     fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
@@ -210,12 +242,14 @@ impl DataFormat for PersistEnv {
         written += writer.write_data(&self.nodes)?;
         written += writer.write_data(&self.cannons)?;
         written += writer.write_data(&self.network)?;
+        written += writer.write_data(&self.spec_hash)?;
+        written += writer.write_data(&self.frozen_heights)?;
 
         Ok(written)
     }
 
     fn read_data<R: Read>(reader: &mut R, header: &Self::Header) -> Result<Self, DataReadError> {
-        if header.version != Self::LATEST_HEADER.version {
+        if header.version == 0 || header.version > Self::LATEST_HEADER.version {
             return Err(DataReadError::unsupported(
                 "PersistEnv",
                 Self::LATEST_HEADER.version,
@@ -225,13 +259,25 @@ impl DataFormat for PersistEnv {
 
         let id = reader.read_data(&())?;
         let storage_id = reader.read_data(&())?;
-        let nodes = reader.read_data(&(header.tx_source.node_targets, header.nodes.clone()))?;
+        let nodes = reader.read_data(&(header.node_key, header.nodes.clone()))?;
         let cannons = reader.read_data(&((), header.tx_source.clone(), header.tx_sink.clone()))?;
         let network = if header.network > 0 {
             reader.read_data(&header.network)?
         } else {
             NetworkId::default()
         };
+        // spec hashes were added in version 2
+        let spec_hash = if header.version > 1 {
+            reader.read_data(&())?
+        } else {
+            0
+        };
+        // frozen heights were added in version 3
+        let frozen_heights = if header.version > 2 {
+            reader.read_data(&(header.node_key, ()))?
+        } else {
+            Vec::new()
+        };
 
         Ok(PersistEnv {
             id,
@@ -239,6 +285,8 @@ impl DataFormat for PersistEnv {
             network,
             nodes,
             cannons,
+            spec_hash,
+            frozen_heights,
         })
     }
 }
@@ -250,7 +298,7 @@ mod tests {
 
     use snops_common::{
         format::{DataFormat, read_dataformat, write_dataformat},
-        state::{InternedId, NetworkId},
+        state::{InternedId, NetworkId, NodeKey},
     };
 
     use crate::{
@@ -295,6 +343,7 @@ mod tests {
             TxSinkFormatHeader::LATEST_HEADER.to_byte_vec()?,
             TxSink::LATEST_HEADER.to_byte_vec()?,
             NetworkId::LATEST_HEADER.to_byte_vec()?,
+            NodeKey::LATEST_HEADER.to_byte_vec()?,
         ]
         .concat()
     );
@@ -308,6 +357,8 @@ mod tests {
             network: Default::default(),
             nodes: Default::default(),
             cannons: Default::default(),
+            spec_hash: 42,
+            frozen_heights: vec![(NodeKey::from_str("validator/0")?, 7)],
         },
         [
             PersistEnvFormatHeader::LATEST_HEADER.to_byte_vec()?,
@@ -317,7 +368,30 @@ mod tests {
             Vec::<(String, PersistNode)>::new().to_byte_vec()?,
             Vec::<(InternedId, TxSource, TxSink)>::new().to_byte_vec()?,
             NetworkId::default().to_byte_vec()?,
+            42u64.to_byte_vec()?,
+            vec![(NodeKey::from_str("validator/0")?, 7u32)].to_byte_vec()?,
         ]
         .concat()
     );
+
+    #[test]
+    fn env_header_legacy_node_key() -> Result<(), Box<dyn std::error::Error>> {
+        // version 2 headers read node keys with the node target header
+        let data = [
+            2u8.to_byte_vec()?,
+            PersistEnv::LATEST_HEADER.version.to_byte_vec()?,
+            PersistNodeFormatHeader::LATEST_HEADER.to_byte_vec()?,
+            PersistNode::LATEST_HEADER.to_byte_vec()?,
+            TxSourceFormatHeader::LATEST_HEADER.to_byte_vec()?,
+            TxSource::LATEST_HEADER.to_byte_vec()?,
+            TxSinkFormatHeader::LATEST_HEADER.to_byte_vec()?,
+            TxSink::LATEST_HEADER.to_byte_vec()?,
+            NetworkId::LATEST_HEADER.to_byte_vec()?,
+        ]
+        .concat();
+
+        let header = read_dataformat::<_, PersistEnvFormatHeader>(&mut &data[..])?;
+        assert_eq!(header.node_key, NodeKey::LATEST_HEADER);
+        Ok(())
+    }
 }
@@ -142,11 +142,15 @@ mod tests {
                 key: None,
                 height: HeightRequest::Top,
                 labels: Default::default(),
+                preferred_labels: Default::default(),
                 agent: None,
                 validators: NodeTargets::None,
                 peers: NodeTargets::None,
                 env: Default::default(),
                 binary: None,
+                bind_addr: None,
+                cpu_limit: None,
+                memory_limit: None,
             })
         ),
         [
@@ -158,11 +162,15 @@ mod tests {
                 key: None,
                 height: HeightRequest::Top,
                 labels: Default::default(),
+                preferred_labels: Default::default(),
                 agent: None,
                 validators: NodeTargets::None,
                 peers: NodeTargets::None,
                 env: Default::default(),
                 binary: None,
+                bind_addr: None,
+                cpu_limit: None,
+                memory_limit: None,
             }
             .to_byte_vec()?,
         ]
@@ -175,14 +183,16 @@ mod tests {
         PersistNode::External(ExternalNode {
             bft: None,
             node: None,
-            rest: None
+            rest: None,
+            rest_headers: None,
         }),
         [
             1u8.to_byte_vec()?,
             ExternalNode {
                 bft: None,
                 node: None,
-                rest: None
+                rest: None,
+                rest_headers: None,
             }
             .to_byte_vec()?,
         ]
@@ -1,7 +1,7 @@
 use snops_common::{node_targets::NodeTargets, state::TxPipeId};
 
 use super::prelude::*;
-use crate::cannon::sink::TxSink;
+use crate::cannon::sink::{FiringProfile, RotatePolicy, TxSink};
 
 #[derive(Debug, Clone)]
 pub struct TxSinkFormatHeader {
@@ -39,7 +39,7 @@ impl DataFormat for TxSinkFormatHeader {
 impl DataFormat for TxSink {
     type Header = TxSinkFormatHeader;
     const LATEST_HEADER: Self::Header = TxSinkFormatHeader {
-        version: 2,
+        version: 5,
         node_targets: NodeTargets::LATEST_HEADER,
     };
 
@@ -51,6 +51,9 @@ impl DataFormat for TxSink {
         written += self.authorize_attempts.write_data(writer)?;
         written += self.broadcast_timeout.write_data(writer)?;
         written += self.authorize_timeout.write_data(writer)?;
+        written += self.rotate_after.write_data(writer)?;
+        written += self.max_mempool_depth.write_data(writer)?;
+        written += self.profile.write_data(writer)?;
         Ok(written)
     }
 
@@ -66,6 +69,9 @@ impl DataFormat for TxSink {
                         authorize_attempts: None,
                         broadcast_timeout: TxSink::default_retry_timeout(),
                         authorize_timeout: TxSink::default_retry_timeout(),
+                        rotate_after: None,
+                        max_mempool_depth: None,
+                        profile: None,
                     })
                 }
                 1u8 => {
@@ -77,19 +83,31 @@ impl DataFormat for TxSink {
                         authorize_attempts: None,
                         broadcast_timeout: TxSink::default_retry_timeout(),
                         authorize_timeout: TxSink::default_retry_timeout(),
+                        rotate_after: None,
+                        max_mempool_depth: None,
+                        profile: None,
                     })
                 }
                 n => Err(DataReadError::Custom(format!(
                     "invalid TxSink discriminant: {n}"
                 ))),
             },
-            n if n == Self::LATEST_HEADER.version => {
+            n @ 2u8..=5u8 => {
                 let file_name: Option<TxPipeId> = reader.read_data(&())?;
                 let target: Option<NodeTargets> = reader.read_data(&header.node_targets)?;
                 let broadcast_attempts: Option<u32> = reader.read_data(&())?;
                 let authorize_attempts: Option<u32> = reader.read_data(&())?;
                 let broadcast_timeout: u32 = reader.read_data(&())?;
                 let authorize_timeout: u32 = reader.read_data(&())?;
+                // file rotation was added in version 3
+                let rotate_after: Option<RotatePolicy> =
+                    if n > 2 { reader.read_data(&())? } else { None };
+                // mempool pacing was added in version 4
+                let max_mempool_depth: Option<usize> =
+                    if n > 3 { reader.read_data(&())? } else { None };
+                // firing profiles were added in version 5
+                let profile: Option<FiringProfile> =
+                    if n > 4 { reader.read_data(&())? } else { None };
                 Ok(TxSink {
                     file_name,
                     target,
@@ -97,13 +115,76 @@ impl DataFormat for TxSink {
                     authorize_attempts,
                     broadcast_timeout,
                     authorize_timeout,
+                    rotate_after,
+                    max_mempool_depth,
+                    profile,
                 })
             }
             n => Err(DataReadError::unsupported(
                 "TxSink",
-                format!("1 or {}", Self::LATEST_HEADER.version),
+                format!("1 to {}", Self::LATEST_HEADER.version),
                 n,
             )),
         }
     }
 }
+
+impl DataFormat for RotatePolicy {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        match self {
+            RotatePolicy::Bytes(n) => Ok(0u8.write_data(writer)? + n.write_data(writer)?),
+            RotatePolicy::Lines(n) => Ok(1u8.write_data(writer)? + n.write_data(writer)?),
+        }
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        match reader.read_data(&())? {
+            0u8 => Ok(RotatePolicy::Bytes(reader.read_data(&())?)),
+            1u8 => Ok(RotatePolicy::Lines(reader.read_data(&())?)),
+            n => Err(DataReadError::Custom(format!(
+                "invalid RotatePolicy discriminant: {n}"
+            ))),
+        }
+    }
+}
+
+impl DataFormat for FiringProfile {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        match self {
+            FiringProfile::Burst { count, interval } => Ok(0u8.write_data(writer)?
+                + count.write_data(writer)?
+                + interval.write_data(writer)?),
+            FiringProfile::Ramp {
+                start_rate,
+                end_rate,
+                duration,
+            } => Ok(1u8.write_data(writer)?
+                + start_rate.write_data(writer)?
+                + end_rate.write_data(writer)?
+                + duration.write_data(writer)?),
+        }
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        match reader.read_data(&())? {
+            0u8 => Ok(FiringProfile::Burst {
+                count: reader.read_data(&())?,
+                interval: reader.read_data(&())?,
+            }),
+            1u8 => Ok(FiringProfile::Ramp {
+                start_rate: reader.read_data(&())?,
+                end_rate: reader.read_data(&())?,
+                duration: reader.read_data(&())?,
+            }),
+            n => Err(DataReadError::Custom(format!(
+                "invalid FiringProfile discriminant: {n}"
+            ))),
+        }
+    }
+}
@@ -1,7 +1,10 @@
-use snops_common::node_targets::NodeTargets;
+use snops_common::{key_source::KeySource, node_targets::NodeTargets};
 
 use super::prelude::*;
-use crate::cannon::source::{ComputeTarget, LocalService, QueryTarget, TxSource};
+use crate::cannon::{
+    generate::{InputDistribution, IntRange, TxGenerator},
+    source::{ComputeTarget, LocalService, QueryTarget, TxSource},
+};
 
 #[derive(Debug, Clone)]
 pub struct TxSourceFormatHeader {
@@ -38,7 +41,7 @@ impl DataFormat for TxSourceFormatHeader {
 impl DataFormat for TxSource {
     type Header = TxSourceFormatHeader;
     const LATEST_HEADER: Self::Header = TxSourceFormatHeader {
-        version: 1,
+        version: 7,
         node_targets: NodeTargets::LATEST_HEADER,
     };
 
@@ -49,6 +52,7 @@ impl DataFormat for TxSource {
             QueryTarget::Local(local) => {
                 written += 0u8.write_data(writer)?;
                 written += local.sync_from.write_data(writer)?;
+                written += local.snapshot.write_data(writer)?;
             }
             QueryTarget::Node(node) => {
                 written += 1u8.write_data(writer)?;
@@ -57,9 +61,13 @@ impl DataFormat for TxSource {
         }
 
         match &self.compute {
-            ComputeTarget::Agent { labels } => {
+            ComputeTarget::Agent {
+                labels,
+                execution_timeout,
+            } => {
                 written += 0u8.write_data(writer)?;
                 written += labels.write_data(writer)?;
+                written += execution_timeout.write_data(writer)?;
             }
             ComputeTarget::Demox { demox_api } => {
                 written += 1u8.write_data(writer)?;
@@ -67,11 +75,16 @@ impl DataFormat for TxSource {
             }
         }
 
+        written += self.max_auths_per_sec.write_data(writer)?;
+        written += self.generate.write_data(writer)?;
+        written += self.max_concurrent_executions.write_data(writer)?;
+        written += self.seed.write_data(writer)?;
+
         Ok(written)
     }
 
     fn read_data<R: Read>(reader: &mut R, header: &Self::Header) -> Result<Self, DataReadError> {
-        if header.version != Self::LATEST_HEADER.version {
+        if header.version == 0 || header.version > Self::LATEST_HEADER.version {
             return Err(DataReadError::unsupported(
                 "TxSource",
                 Self::LATEST_HEADER.version,
@@ -82,6 +95,12 @@ impl DataFormat for TxSource {
         let query = match reader.read_data(&())? {
             0u8 => QueryTarget::Local(LocalService {
                 sync_from: reader.read_data(&header.node_targets)?,
+                // snapshots were added in version 2
+                snapshot: if header.version > 1 {
+                    reader.read_data(&())?
+                } else {
+                    false
+                },
             }),
             1u8 => QueryTarget::Node(reader.read_data(&header.node_targets)?),
             n => {
@@ -94,6 +113,12 @@ impl DataFormat for TxSource {
         let compute = match reader.read_data(&())? {
             0u8 => ComputeTarget::Agent {
                 labels: reader.read_data(&())?,
+                // execution timeouts were added in version 7
+                execution_timeout: if header.version > 6 {
+                    reader.read_data(&())?
+                } else {
+                    None
+                },
             },
             1u8 => ComputeTarget::Demox {
                 demox_api: reader.read_data(&())?,
@@ -105,17 +130,107 @@ impl DataFormat for TxSource {
             }
         };
 
-        Ok(TxSource { query, compute })
+        // auth rate limits were added in version 3
+        let max_auths_per_sec = if header.version > 2 {
+            reader.read_data(&())?
+        } else {
+            None
+        };
+
+        // generators were added in version 4
+        let generate = if header.version > 3 {
+            reader.read_data(&())?
+        } else {
+            None
+        };
+
+        // execution limits were added in version 5
+        let max_concurrent_executions = if header.version > 4 {
+            reader.read_data(&())?
+        } else {
+            None
+        };
+
+        // seeds were added in version 6
+        let seed = if header.version > 5 {
+            reader.read_data(&())?
+        } else {
+            None
+        };
+
+        Ok(TxSource {
+            query,
+            compute,
+            max_auths_per_sec,
+            max_concurrent_executions,
+            generate,
+            seed,
+        })
+    }
+}
+
+impl DataFormat for TxGenerator {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        let mut written = 0;
+        // key sources are versioned independently of the generator
+        written += KeySource::LATEST_HEADER.write_data(writer)?;
+        written += self.program.write_data(writer)?;
+        written += self.function.write_data(writer)?;
+        written += self.private_key.write_data(writer)?;
+        written += self.fee_private_key.write_data(writer)?;
+        written += self.per_sec.write_data(writer)?;
+        written += self.inputs.ranges.write_data(writer)?;
+        written += self.inputs.addresses.write_data(writer)?;
+        Ok(written)
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        let key_source: DataHeaderOf<KeySource> = reader.read_data(&())?;
+        Ok(TxGenerator {
+            program: reader.read_data(&())?,
+            function: reader.read_data(&())?,
+            private_key: reader.read_data(&key_source)?,
+            fee_private_key: reader.read_data(&key_source)?,
+            per_sec: reader.read_data(&())?,
+            inputs: InputDistribution {
+                ranges: reader.read_data(&((), ()))?,
+                addresses: reader.read_data(&key_source)?,
+            },
+        })
+    }
+}
+
+impl DataFormat for IntRange {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        Ok(self.min.write_data(writer)? + self.max.write_data(writer)?)
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        Ok(IntRange {
+            min: reader.read_data(&())?,
+            max: reader.read_data(&())?,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use snops_common::{INTERN, node_targets::NodeTargets};
+    use snops_common::{
+        INTERN, format::PackedUint, key_source::KeySource, node_targets::NodeTargets,
+    };
 
     use crate::{
-        cannon::source::{ComputeTarget, LocalService, QueryTarget, TxSource},
+        cannon::{
+            generate::{InputDistribution, IntRange, TxGenerator},
+            source::{ComputeTarget, LocalService, QueryTarget, TxSource},
+        },
         persist::{TxSourceFormatHeader, prelude::*},
     };
 
@@ -155,16 +270,32 @@ mod tests {
         source_local_local_none,
         TxSource,
         TxSource {
-            query: QueryTarget::Local(LocalService { sync_from: None }),
-            compute: ComputeTarget::Agent { labels: None }
+            query: QueryTarget::Local(LocalService {
+                sync_from: None,
+                snapshot: false,
+            }),
+            compute: ComputeTarget::Agent {
+                labels: None,
+                execution_timeout: None,
+            },
+            max_auths_per_sec: None,
+            max_concurrent_executions: None,
+            generate: None,
+            seed: None,
         },
         [
             TxSourceFormatHeader::LATEST_HEADER.to_byte_vec()?,
             TxSource::LATEST_HEADER.to_byte_vec()?,
-            0u8.to_byte_vec()?, // querytarget local discriminant
-            0u8.to_byte_vec()?, // sync from empty option
-            0u8.to_byte_vec()?, // computetarget agent discriminant
-            0u8.to_byte_vec()?, // labels empty option
+            0u8.to_byte_vec()?,   // querytarget local discriminant
+            0u8.to_byte_vec()?,   // sync from empty option
+            false.to_byte_vec()?, // snapshot
+            0u8.to_byte_vec()?,   // computetarget agent discriminant
+            0u8.to_byte_vec()?,   // labels empty option
+            0u8.to_byte_vec()?,   // execution timeout empty option
+            0u8.to_byte_vec()?,   // max auths per sec empty option
+            0u8.to_byte_vec()?,   // generate empty option
+            0u8.to_byte_vec()?,   // max concurrent executions empty option
+            0u8.to_byte_vec()?,   // seed empty option
         ]
         .concat()
     );
@@ -174,19 +305,31 @@ mod tests {
         TxSource,
         TxSource {
             query: QueryTarget::Local(LocalService {
-                sync_from: Some(NodeTargets::One("client/*".parse()?))
+                sync_from: Some(NodeTargets::One("client/*".parse()?)),
+                snapshot: true,
             }),
             compute: ComputeTarget::Agent {
-                labels: Some(vec![INTERN.get_or_intern("foo")])
-            }
+                labels: Some(vec![INTERN.get_or_intern("foo")]),
+                execution_timeout: Some(60),
+            },
+            max_auths_per_sec: Some(10),
+            max_concurrent_executions: Some(4),
+            generate: None,
+            seed: None,
         },
         [
             TxSourceFormatHeader::LATEST_HEADER.to_byte_vec()?,
             TxSource::LATEST_HEADER.to_byte_vec()?,
             0u8.to_byte_vec()?, // querytarget local discriminant
             Some(NodeTargets::One("client/*".parse()?)).to_byte_vec()?,
-            0u8.to_byte_vec()?, // computetarget agent discriminant
+            true.to_byte_vec()?, // snapshot
+            0u8.to_byte_vec()?,  // computetarget agent discriminant
             Some(vec!["foo".to_owned()]).to_byte_vec()?,
+            Some(60u64).to_byte_vec()?,
+            Some(10u32).to_byte_vec()?,
+            None::<TxGenerator>.to_byte_vec()?,
+            Some(4u32).to_byte_vec()?,
+            None::<u64>.to_byte_vec()?,
         ]
         .concat()
     );
@@ -198,7 +341,11 @@ mod tests {
             query: QueryTarget::Node(NodeTargets::One("client/*".parse()?)),
             compute: ComputeTarget::Demox {
                 demox_api: "foo".to_owned()
-            }
+            },
+            max_auths_per_sec: None,
+            max_concurrent_executions: None,
+            generate: None,
+            seed: None,
         },
         [
             TxSourceFormatHeader::LATEST_HEADER.to_byte_vec()?,
@@ -207,6 +354,63 @@ mod tests {
             NodeTargets::One("client/*".parse()?).to_byte_vec()?,
             1u8.to_byte_vec()?, // computetarget demox discriminant
             "foo".to_owned().to_byte_vec()?,
+            None::<u32>.to_byte_vec()?,
+            None::<TxGenerator>.to_byte_vec()?,
+            None::<u32>.to_byte_vec()?,
+            None::<u64>.to_byte_vec()?,
+        ]
+        .concat()
+    );
+
+    case!(
+        source_generate,
+        TxSource,
+        TxSource {
+            query: QueryTarget::Node(NodeTargets::ALL),
+            compute: ComputeTarget::Agent {
+                labels: None,
+                execution_timeout: None,
+            },
+            max_auths_per_sec: None,
+            max_concurrent_executions: None,
+            generate: Some(TxGenerator {
+                program: "credits.aleo".to_owned(),
+                function: "transfer_public".to_owned(),
+                private_key: KeySource::Committee(None),
+                fee_private_key: None,
+                per_sec: 5,
+                inputs: InputDistribution {
+                    ranges: [("u64".to_owned(), IntRange { min: 1, max: 100 })]
+                        .into_iter()
+                        .collect(),
+                    addresses: Some("accounts.$".parse()?),
+                },
+            }),
+            seed: Some(7),
+        },
+        [
+            TxSourceFormatHeader::LATEST_HEADER.to_byte_vec()?,
+            TxSource::LATEST_HEADER.to_byte_vec()?,
+            1u8.to_byte_vec()?, // querytarget node discriminant
+            NodeTargets::ALL.to_byte_vec()?,
+            0u8.to_byte_vec()?, // computetarget agent discriminant
+            0u8.to_byte_vec()?, // labels empty option
+            0u8.to_byte_vec()?, // execution timeout empty option
+            None::<u32>.to_byte_vec()?,
+            1u8.to_byte_vec()?, // generate option
+            KeySource::LATEST_HEADER.to_byte_vec()?,
+            "credits.aleo".to_owned().to_byte_vec()?,
+            "transfer_public".to_owned().to_byte_vec()?,
+            KeySource::Committee(None).to_byte_vec()?,
+            None::<KeySource>.to_byte_vec()?,
+            5u32.to_byte_vec()?,
+            PackedUint::from(1usize).to_byte_vec()?, // ranges length
+            "u64".to_owned().to_byte_vec()?,
+            1i128.to_byte_vec()?,
+            100i128.to_byte_vec()?,
+            Some("accounts.$".parse::<KeySource>()?).to_byte_vec()?,
+            None::<u32>.to_byte_vec()?,
+            Some(7u64).to_byte_vec()?,
         ]
         .concat()
     );
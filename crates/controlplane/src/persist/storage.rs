@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use indexmap::IndexMap;
 use snops_checkpoint::RetentionPolicy;
 use snops_common::{
@@ -6,6 +8,7 @@ use snops_common::{
     state::{InternedId, NetworkId, StorageId},
 };
 use tracing::warn;
+use url::Url;
 
 use super::prelude::*;
 use crate::{
@@ -13,7 +16,8 @@ use crate::{
     schema::{
         error::StorageError,
         storage::{
-            LoadedStorage, STORAGE_DIR, pick_account_addr, pick_additional_addr,
+            Accounts, GenesisBalances, GenesisCommissions, GenesisGeneration, LoadedStorage,
+            STORAGE_DIR, StorageGeneration, Transaction, pick_account_addr, pick_additional_addr,
             pick_commitee_addr, read_to_addrs,
         },
     },
@@ -29,6 +33,9 @@ pub struct PersistStorage {
     pub retention_policy: Option<RetentionPolicy>,
     pub native_genesis: bool,
     pub binaries: IndexMap<InternedId, BinaryEntry>,
+    pub ledger_url: Option<String>,
+    pub generation: Option<StorageGeneration>,
+    pub connect: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -87,6 +94,9 @@ impl From<&LoadedStorage> for PersistStorage {
             retention_policy: storage.retention_policy.clone(),
             native_genesis: storage.native_genesis,
             binaries: storage.binaries.clone(),
+            ledger_url: storage.ledger_url.as_ref().map(Url::to_string),
+            generation: storage.generation.clone(),
+            connect: storage.connect.as_ref().map(Url::to_string),
         }
     }
 }
@@ -131,6 +141,21 @@ impl PersistStorage {
             native_genesis: self.native_genesis,
             accounts,
             binaries: self.binaries,
+            ledger_url: self.ledger_url.and_then(|url| match Url::parse(&url) {
+                Ok(url) => Some(url),
+                Err(e) => {
+                    warn!("storage {id} has an invalid ledger url {url}: {e}");
+                    None
+                }
+            }),
+            generation: self.generation,
+            connect: self.connect.and_then(|url| match Url::parse(&url) {
+                Ok(url) => Some(url),
+                Err(e) => {
+                    warn!("storage {id} has an invalid connect url {url}: {e}");
+                    None
+                }
+            }),
         })
     }
 }
@@ -138,7 +163,7 @@ impl PersistStorage {
 impl DataFormat for PersistStorage {
     type Header = PersistStorageFormatHeader;
     const LATEST_HEADER: Self::Header = PersistStorageFormatHeader {
-        version: 1,
+        version: 3,
         retention_policy: RetentionPolicy::LATEST_HEADER,
         network: NetworkId::LATEST_HEADER,
         binaries: BinaryEntry::LATEST_HEADER,
@@ -155,6 +180,9 @@ impl DataFormat for PersistStorage {
         written += self.retention_policy.write_data(writer)?;
         written += self.native_genesis.write_data(writer)?;
         written += self.binaries.write_data(writer)?;
+        written += self.ledger_url.write_data(writer)?;
+        written += self.generation.write_data(writer)?;
+        written += self.connect.write_data(writer)?;
 
         Ok(written)
     }
@@ -185,10 +213,197 @@ impl DataFormat for PersistStorage {
             } else {
                 IndexMap::new()
             },
+            // ledger urls were added in version 2
+            ledger_url: if header.version > 1 {
+                reader.read_data(&())?
+            } else {
+                None
+            },
+            // generation options were added in version 3
+            generation: if header.version > 2 {
+                reader.read_data(&())?
+            } else {
+                None
+            },
+            connect: if header.version > 2 {
+                reader.read_data(&())?
+            } else {
+                None
+            },
+        })
+    }
+}
+
+impl DataFormat for StorageGeneration {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        Ok(self.genesis.write_data(writer)?
+            + self.accounts.write_data(writer)?
+            + self.transactions.write_data(writer)?)
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        Ok(StorageGeneration {
+            genesis: reader.read_data(&())?,
+            accounts: reader.read_data(&((), ()))?,
+            transactions: reader.read_data(&())?,
+        })
+    }
+}
+
+impl DataFormat for GenesisGeneration {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        let mut written = 0;
+        written += self.private_key.write_data(writer)?;
+        written += self.seed.write_data(writer)?;
+        written += self.additional_accounts.write_data(writer)?;
+        written += self.additional_accounts_balance.write_data(writer)?;
+        written += self.committee_only.write_data(writer)?;
+        written += self.balances.write_data(writer)?;
+        written += self.commissions.write_data(writer)?;
+        written += self.bonded_withdrawal.write_data(writer)?;
+        Ok(written)
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        Ok(GenesisGeneration {
+            private_key: reader.read_data(&())?,
+            seed: reader.read_data(&())?,
+            additional_accounts: reader.read_data(&())?,
+            additional_accounts_balance: reader.read_data(&())?,
+            committee_only: reader.read_data(&())?,
+            balances: reader.read_data(&())?,
+            commissions: reader.read_data(&())?,
+            bonded_withdrawal: reader.read_data(&((), ()))?,
         })
     }
 }
 
+impl DataFormat for GenesisBalances {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        match self {
+            GenesisBalances::File {
+                bonded_balances_file,
+                bonded_balances,
+            } => Ok(0u8.write_data(writer)?
+                + path_to_string(bonded_balances_file).write_data(writer)?
+                + bonded_balances.write_data(writer)?),
+            GenesisBalances::Defined { bonded_balances } => {
+                Ok(1u8.write_data(writer)? + bonded_balances.write_data(writer)?)
+            }
+            GenesisBalances::Generated {
+                committee_size,
+                bonded_balance,
+            } => Ok(2u8.write_data(writer)?
+                + committee_size.write_data(writer)?
+                + bonded_balance.write_data(writer)?),
+        }
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        match reader.read_data(&())? {
+            0u8 => Ok(GenesisBalances::File {
+                bonded_balances_file: PathBuf::from(reader.read_data::<String>(&())?),
+                bonded_balances: reader.read_data(&((), ()))?,
+            }),
+            1u8 => Ok(GenesisBalances::Defined {
+                bonded_balances: reader.read_data(&((), ()))?,
+            }),
+            2u8 => Ok(GenesisBalances::Generated {
+                committee_size: reader.read_data(&())?,
+                bonded_balance: reader.read_data(&())?,
+            }),
+            n => Err(DataReadError::Custom(format!(
+                "invalid GenesisBalances discriminant: {n}"
+            ))),
+        }
+    }
+}
+
+impl DataFormat for GenesisCommissions {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        match self {
+            GenesisCommissions::Defined { bonded_commissions } => {
+                Ok(0u8.write_data(writer)? + bonded_commissions.write_data(writer)?)
+            }
+            GenesisCommissions::Generated { bonded_commission } => {
+                Ok(1u8.write_data(writer)? + bonded_commission.write_data(writer)?)
+            }
+        }
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        match reader.read_data(&())? {
+            0u8 => Ok(GenesisCommissions::Defined {
+                bonded_commissions: reader.read_data(&((), ()))?,
+            }),
+            1u8 => Ok(GenesisCommissions::Generated {
+                bonded_commission: reader.read_data(&())?,
+            }),
+            n => Err(DataReadError::Custom(format!(
+                "invalid GenesisCommissions discriminant: {n}"
+            ))),
+        }
+    }
+}
+
+impl DataFormat for Accounts {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        Ok(self.count.write_data(writer)? + self.seed.write_data(writer)?)
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        Ok(Accounts {
+            count: reader.read_data(&())?,
+            seed: reader.read_data(&())?,
+        })
+    }
+}
+
+impl DataFormat for Transaction {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: Write>(&self, writer: &mut W) -> Result<usize, DataWriteError> {
+        let mut written = 0;
+        written += path_to_string(&self.file).write_data(writer)?;
+        written += self.total.write_data(writer)?;
+        written += self.amount.write_data(writer)?;
+        written += self.sources.write_data(writer)?;
+        written += self.destinations.write_data(writer)?;
+        Ok(written)
+    }
+
+    fn read_data<R: Read>(reader: &mut R, _header: &Self::Header) -> Result<Self, DataReadError> {
+        Ok(Transaction {
+            file: PathBuf::from(reader.read_data::<String>(&())?),
+            total: reader.read_data(&())?,
+            amount: reader.read_data(&())?,
+            sources: reader.read_data(&())?,
+            destinations: reader.read_data(&())?,
+        })
+    }
+}
+
+/// Paths are stored as strings, as specs only contain utf-8 paths
+fn path_to_string(path: &std::path::Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -198,11 +413,17 @@ mod tests {
     use snops_checkpoint::RetentionPolicy;
     use snops_common::{
         binaries::BinaryEntry,
-        format::{DataFormat, read_dataformat, write_dataformat},
+        format::{DataFormat, PackedUint, read_dataformat, write_dataformat},
         state::{InternedId, NetworkId},
     };
 
-    use crate::persist::{PersistStorage, PersistStorageFormatHeader};
+    use crate::{
+        persist::{PersistStorage, PersistStorageFormatHeader},
+        schema::storage::{
+            Accounts, GenesisBalances, GenesisCommissions, GenesisGeneration, StorageGeneration,
+            Transaction,
+        },
+    };
 
     macro_rules! case {
         ($name:ident, $ty:ty, $a:expr_2021, $b:expr_2021) => {
@@ -250,6 +471,13 @@ mod tests {
             retention_policy: None,
             native_genesis: false,
             binaries: IndexMap::new(),
+            ledger_url: Some("file:///mnt/ledger".to_owned()),
+            generation: Some(StorageGeneration {
+                genesis: None,
+                accounts: IndexMap::new(),
+                transactions: vec![],
+            }),
+            connect: None,
         },
         [
             PersistStorageFormatHeader::LATEST_HEADER.to_byte_vec()?,
@@ -262,6 +490,10 @@ mod tests {
             None::<RetentionPolicy>.to_byte_vec()?,
             false.to_byte_vec()?,
             IndexMap::<InternedId, BinaryEntry>::new().to_byte_vec()?,
+            Some("file:///mnt/ledger".to_owned()).to_byte_vec()?,
+            // some generation with no genesis, accounts, or transactions
+            vec![1u8, 0, 0, 0],
+            None::<String>.to_byte_vec()?,
         ]
         .concat()
     );
@@ -278,10 +510,66 @@ mod tests {
             retention_policy: None,
             native_genesis: true,
             binaries: IndexMap::new(),
+            ledger_url: None,
+            generation: None,
+            connect: None,
+        },
+        [
+            3, 3, 1, 1, 1, 1, 1, 4, 98, 97, 115, 101, 0, 0, 0, 0, 1, 1, 1, 8, 97, 99, 99, 111, 117,
+            110, 116, 115, 0, 1, 0, 0, 0, 0
+        ]
+    );
+
+    case!(
+        storage_generation,
+        StorageGeneration,
+        StorageGeneration {
+            genesis: Some(GenesisGeneration {
+                balances: GenesisBalances::File {
+                    bonded_balances_file: "committee.csv".into(),
+                    bonded_balances: IndexMap::new(),
+                },
+                commissions: GenesisCommissions::Defined {
+                    bonded_commissions: [("aleo1".to_owned(), 5)].into_iter().collect(),
+                },
+                ..Default::default()
+            }),
+            accounts: [(
+                InternedId::from_str("accounts")?,
+                Accounts {
+                    count: 2,
+                    seed: None,
+                },
+            )]
+            .into_iter()
+            .collect(),
+            transactions: vec![Transaction {
+                file: "txs.json".into(),
+                total: 1,
+                amount: 2,
+                sources: vec!["a".to_owned()],
+                destinations: vec![],
+            }],
         },
         [
-            3, 1, 1, 1, 1, 1, 1, 4, 98, 97, 115, 101, 0, 0, 0, 0, 1, 1, 1, 8, 97, 99, 99, 111, 117,
-            110, 116, 115, 0, 1, 0
+            // genesis: private key, seed, additional accounts and balance
+            vec![1u8, 0, 0, 0, 0],
+            false.to_byte_vec()?,
+            0u8.to_byte_vec()?,
+            "committee.csv".to_owned().to_byte_vec()?,
+            IndexMap::<String, u64>::new().to_byte_vec()?,
+            0u8.to_byte_vec()?,
+            IndexMap::from([("aleo1".to_owned(), 5u8)]).to_byte_vec()?,
+            None::<IndexMap<String, String>>.to_byte_vec()?,
+            // accounts
+            IndexMap::from([(InternedId::from_str("accounts")?, (2u16, None::<u64>))])
+                .to_byte_vec()?,
+            // transactions
+            PackedUint::from(1).to_byte_vec()?,
+            "txs.json".to_owned().to_byte_vec()?,
+            (1u64, 2u64).to_byte_vec()?,
+            (vec!["a".to_owned()], Vec::<String>::new()).to_byte_vec()?,
         ]
+        .concat()
     );
 }
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use axum::http::StatusCode;
 use serde::{Serialize, Serializer, ser::SerializeStruct};
@@ -13,7 +13,11 @@ use strum_macros::AsRefStr;
 use thiserror::Error;
 use url::Url;
 
-use super::storage::BinarySourceError;
+use super::{
+    include::{MAX_INCLUDE_DEPTH, SpecLocation},
+    storage::BinarySourceError,
+};
+use crate::error::DeserializeError;
 
 #[derive(Debug, Error, AsRefStr)]
 pub enum StorageError {
@@ -39,6 +43,16 @@ pub enum StorageError {
     NoGenerationParams(StorageId),
     #[error("reading balances {0:#?}: {1}")]
     ReadBalances(PathBuf, #[source] std::io::Error),
+    #[error("reading bonded balances {0:#?}: {1}")]
+    ReadBondedBalances(PathBuf, #[source] std::io::Error),
+    #[error("parsing bonded balances {0:#?} line {1}: {2}")]
+    ParseBondedBalances(PathBuf, usize, String),
+    #[error("invalid bonded balance for `{0}`: {1}")]
+    InvalidBondedBalance(String, &'static str),
+    #[error("bonded balances {0:#?}: local files are disabled, set --include-dir")]
+    BondedBalancesDisabled(PathBuf),
+    #[error("bonded balances {0:#?} is outside of the include directory")]
+    BondedBalancesOutsideIncludeDir(PathBuf),
     #[error("reading version {0:#?}: {1}")]
     ReadVersion(PathBuf, #[source] std::io::Error),
     #[error("writing version {0:#?}: {1}")]
@@ -47,6 +61,8 @@ pub enum StorageError {
     WriteCommittee(PathBuf, #[source] std::io::Error),
     #[error("parsing balances {0:#?}: {1}")]
     ParseBalances(PathBuf, #[source] serde_json::Error),
+    #[error("parsing balances {0:#?}: {1}")]
+    ParseBalancesYaml(PathBuf, #[source] serde_yaml::Error),
     #[error("error loading checkpoints: {0}")]
     CheckpointManager(#[from] snops_checkpoint::errors::ManagerLoadError),
     #[error("binary with id `{0}` does not exist for storage id: {1}")]
@@ -71,12 +87,28 @@ pub enum StorageError {
     PermissionError(PathBuf, std::io::Error),
     #[error("failed to parse binary `{0}`: {1}")]
     BinaryParse(InternedId, BinarySourceError),
+    #[error("storage id: `{0}`: {1} did not finish within {2:?}")]
+    GenesisTimeout(StorageId, &'static str, Duration),
+    #[error("storage id: `{0}`: generation was cancelled")]
+    GenerationCancelled(StorageId),
+    #[error("storage id: `{0}` expects native genesis to be {1}, but the document resolves to {2}")]
+    NativeGenesisMismatch(StorageId, bool, bool),
+    #[error("storage id: `{0}`: invalid ledger url `{1}`: {2}")]
+    InvalidLedgerUrl(StorageId, Url, #[source] std::io::Error),
 }
 
 impl_into_status_code!(StorageError, |value| match value {
     Command(e, _) => e.into(),
     FailedToFetchGenesis(_, _, _) => StatusCode::MISDIRECTED_REQUEST,
     NoGenerationParams(_) => StatusCode::BAD_REQUEST,
+    NativeGenesisMismatch(_, _, _) => StatusCode::BAD_REQUEST,
+    InvalidLedgerUrl(_, _, _) => StatusCode::BAD_REQUEST,
+    GenesisTimeout(_, _, _) => StatusCode::GATEWAY_TIMEOUT,
+    GenerationCancelled(_) => StatusCode::CONFLICT,
+    ParseBondedBalances(_, _, _)
+    | InvalidBondedBalance(_, _)
+    | BondedBalancesDisabled(_)
+    | BondedBalancesOutsideIncludeDir(_) => StatusCode::BAD_REQUEST,
     BinaryDoesNotExist(_, _) => StatusCode::NOT_FOUND,
     BinaryFileMissing(_, _) => StatusCode::NOT_FOUND,
     _ => StatusCode::INTERNAL_SERVER_ERROR,
@@ -87,6 +119,35 @@ impl_into_type_str!(StorageError, |value| match value {
     _ => value.as_ref().to_string(),
 });
 
+#[derive(Debug, Error, AsRefStr)]
+pub enum IncludeError {
+    #[error("invalid include url `{0}`: {1}")]
+    InvalidUrl(String, #[source] url::ParseError),
+    #[error("reading included spec {0:#?}: {1}")]
+    Read(PathBuf, #[source] std::io::Error),
+    #[error("included spec {0:#?}: local includes are disabled, set --include-dir")]
+    LocalDisabled(PathBuf),
+    #[error("included spec {0:#?} is outside of the include directory")]
+    OutsideIncludeDir(PathBuf),
+    #[error("fetching included spec `{0}`: {1}")]
+    Fetch(Url, #[source] reqwest::Error),
+    #[error("fetching included spec `{0}`: status {1}")]
+    FetchStatus(Url, StatusCode),
+    #[error("included spec `{0}` exceeds the maximum spec size")]
+    TooLarge(Url),
+    #[error("included spec `{0}` includes itself")]
+    Cycle(SpecLocation),
+    #[error("included spec `{0}` exceeds the maximum include depth of {MAX_INCLUDE_DEPTH}")]
+    TooDeep(SpecLocation),
+    #[error("in included spec `{0}`: {1}")]
+    Deserialize(SpecLocation, #[source] Box<DeserializeError>),
+}
+
+impl_into_status_code!(IncludeError, |value| match value {
+    Fetch(..) | FetchStatus(..) => StatusCode::BAD_GATEWAY,
+    _ => StatusCode::BAD_REQUEST,
+});
+
 #[derive(Debug, Error, AsRefStr)]
 pub enum SchemaError {
     #[error("key source error: {0}")]
@@ -97,6 +158,8 @@ pub enum SchemaError {
     Storage(#[from] StorageError),
     #[error("query parse error: {0}")]
     QueryParse(String),
+    #[error(transparent)]
+    Include(#[from] IncludeError),
 }
 
 impl_into_status_code!(SchemaError, |value| match value {
@@ -104,12 +167,14 @@ impl_into_status_code!(SchemaError, |value| match value {
     NodeTarget(e) => e.into(),
     Storage(e) => e.into(),
     QueryParse(_) => StatusCode::BAD_REQUEST,
+    Include(e) => e.into(),
 });
 
 impl_into_type_str!(SchemaError, |value| match value {
     KeySource(e) => format!("{}.{}", value.as_ref(), e.as_ref()),
     Storage(e) => format!("{}.{}", value.as_ref(), String::from(e)),
     QueryParse(e) => format!("{}.{}", value.as_ref(), e),
+    Include(e) => format!("{}.{}", value.as_ref(), e.as_ref()),
     _ => value.as_ref().to_string(),
 });
 
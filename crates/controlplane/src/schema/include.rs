@@ -0,0 +1,347 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use futures_util::future::BoxFuture;
+use url::Url;
+
+use super::{ItemDocument, error::IncludeError};
+use crate::{env::Environment, state::REST_CLIENT};
+
+/// Maximum number of nested includes
+pub const MAX_INCLUDE_DEPTH: usize = 8;
+/// Largest included spec that will be fetched from a url
+const MAX_REMOTE_SPEC_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Why a local file could not be resolved in the include directory
+pub enum IncludePathError {
+    /// There is no include directory
+    Disabled,
+    Read(PathBuf, std::io::Error),
+    OutsideIncludeDir(PathBuf),
+}
+
+/// Resolve a file in the include directory. Relative paths are resolved
+/// against the directory, and files outside of it are rejected before they
+/// are read, and again once symlinks and `..` are resolved.
+pub async fn resolve_include_path(
+    path: &Path,
+    include_dir: Option<&Path>,
+) -> Result<PathBuf, IncludePathError> {
+    let Some(include_dir) = include_dir else {
+        return Err(IncludePathError::Disabled);
+    };
+    let canonical_dir = tokio::fs::canonicalize(include_dir)
+        .await
+        .map_err(|e| IncludePathError::Read(include_dir.to_path_buf(), e))?;
+
+    let path = canonical_dir.join(path);
+    if !(path.starts_with(include_dir) || path.starts_with(&canonical_dir)) {
+        return Err(IncludePathError::OutsideIncludeDir(path));
+    }
+    let path = tokio::fs::canonicalize(&path)
+        .await
+        .map_err(|e| IncludePathError::Read(path, e))?;
+    if !path.starts_with(&canonical_dir) {
+        return Err(IncludePathError::OutsideIncludeDir(path));
+    }
+    Ok(path)
+}
+
+/// Where a spec was read from. Relative includes are resolved against the
+/// directory of the spec that includes them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecLocation {
+    Path(PathBuf),
+    Url(Url),
+}
+
+impl SpecLocation {
+    /// Parse an http(s) url or a file path
+    pub fn parse(s: &str) -> Result<Self, IncludeError> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Url::parse(s)
+                .map(Self::Url)
+                .map_err(|e| IncludeError::InvalidUrl(s.to_owned(), e));
+        }
+        Ok(Self::Path(PathBuf::from(s)))
+    }
+
+    /// Resolve an include relative to the spec at this location
+    fn join(base: Option<&Self>, include: &str) -> Result<Self, IncludeError> {
+        let location = Self::parse(include)?;
+        Ok(match (base, location) {
+            (Some(Self::Path(base)), Self::Path(path)) if path.is_relative() => {
+                Self::Path(base.parent().map(|dir| dir.join(&path)).unwrap_or(path))
+            }
+            (Some(Self::Url(base)), Self::Path(path)) => base
+                .join(include)
+                .map(Self::Url)
+                .map_err(|e| IncludeError::InvalidUrl(path.display().to_string(), e))?,
+            (_, location) => location,
+        })
+    }
+
+    /// Normalize the location so the same spec is detected when it is
+    /// included by different relative paths. Paths must be in the include
+    /// directory, and relative paths without a base are resolved against it.
+    async fn canonicalize(self, include_dir: Option<&Path>) -> Result<Self, IncludeError> {
+        let path = match self {
+            Self::Path(path) => path,
+            Self::Url(url) => return Ok(Self::Url(url)),
+        };
+
+        let resolved = resolve_include_path(&path, include_dir).await;
+        resolved.map(Self::Path).map_err(|e| match e {
+            IncludePathError::Disabled => IncludeError::LocalDisabled(path),
+            IncludePathError::Read(path, e) => IncludeError::Read(path, e),
+            IncludePathError::OutsideIncludeDir(path) => IncludeError::OutsideIncludeDir(path),
+        })
+    }
+
+    async fn read(&self) -> Result<Vec<u8>, IncludeError> {
+        let url = match self {
+            Self::Path(path) => {
+                return tokio::fs::read(path)
+                    .await
+                    .map_err(|e| IncludeError::Read(path.clone(), e));
+            }
+            Self::Url(url) => url,
+        };
+
+        let res = REST_CLIENT
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|e| IncludeError::Fetch(url.clone(), e))?;
+        if !res.status().is_success() {
+            return Err(IncludeError::FetchStatus(url.clone(), res.status()));
+        }
+        if res
+            .content_length()
+            .is_some_and(|len| len > MAX_REMOTE_SPEC_SIZE)
+        {
+            return Err(IncludeError::TooLarge(url.clone()));
+        }
+
+        let body = res
+            .bytes()
+            .await
+            .map_err(|e| IncludeError::Fetch(url.clone(), e))?;
+        // the content length is not always present
+        if body.len() as u64 > MAX_REMOTE_SPEC_SIZE {
+            return Err(IncludeError::TooLarge(url.clone()));
+        }
+        Ok(body.to_vec())
+    }
+}
+
+impl fmt::Display for SpecLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "{}", path.display()),
+            Self::Url(url) => write!(f, "{url}"),
+        }
+    }
+}
+
+/// Replace the include documents of a spec with the documents of the specs
+/// they refer to, in place. The contents of every included spec are appended
+/// to `contents`, so changes to included specs change the spec's hash.
+///
+/// Local files can only be included from `include_dir`, and are rejected when
+/// it is unset.
+pub async fn resolve_includes(
+    documents: Vec<ItemDocument>,
+    base: Option<SpecLocation>,
+    include_dir: Option<&Path>,
+    contents: &mut Vec<u8>,
+) -> Result<Vec<ItemDocument>, IncludeError> {
+    resolve(documents, base, include_dir, &mut vec![], contents).await
+}
+
+fn resolve<'a>(
+    documents: Vec<ItemDocument>,
+    base: Option<SpecLocation>,
+    include_dir: Option<&'a Path>,
+    chain: &'a mut Vec<SpecLocation>,
+    contents: &'a mut Vec<u8>,
+) -> BoxFuture<'a, Result<Vec<ItemDocument>, IncludeError>> {
+    Box::pin(async move {
+        let mut resolved = Vec::with_capacity(documents.len());
+
+        for document in documents {
+            let ItemDocument::Include { path_or_url } = document else {
+                resolved.push(document);
+                continue;
+            };
+
+            let location = SpecLocation::join(base.as_ref(), &path_or_url)?
+                .canonicalize(include_dir)
+                .await?;
+            if chain.contains(&location) {
+                return Err(IncludeError::Cycle(location));
+            }
+            if chain.len() >= MAX_INCLUDE_DEPTH {
+                return Err(IncludeError::TooDeep(location));
+            }
+
+            let spec = location.read().await?;
+            let included = Environment::deserialize_bytes(&spec)
+                .map_err(|e| IncludeError::Deserialize(location.clone(), Box::new(e)))?;
+            contents.extend_from_slice(&spec);
+
+            chain.push(location.clone());
+            let included = resolve(included, Some(location), include_dir, chain, contents).await?;
+            chain.pop();
+
+            resolved.extend(included);
+        }
+
+        Ok(resolved)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    const STORAGE: &str = "version: storage.snarkos.testing.monadic.us/v1
+id: base
+name: base-ledger
+";
+
+    fn write_spec(dir: &Path, name: &str, spec: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, spec).unwrap();
+        path
+    }
+
+    fn resolve_file(
+        spec: &Path,
+        include_dir: Option<&Path>,
+    ) -> Result<(Vec<ItemDocument>, Vec<u8>), IncludeError> {
+        let documents = Environment::deserialize_bytes(&std::fs::read(spec).unwrap()).unwrap();
+        let base = SpecLocation::Path(spec.to_path_buf());
+        let mut contents = vec![];
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(resolve_includes(
+                documents,
+                Some(base),
+                include_dir,
+                &mut contents,
+            ))
+            .map(|documents| (documents, contents))
+    }
+
+    #[test]
+    fn test_resolve_relative_include() {
+        let dir = std::env::temp_dir().join(format!("snops-include-{}", std::process::id()));
+        write_spec(&dir, "shared/storage.yaml", STORAGE);
+        let include = write_spec(
+            &dir,
+            "shared/include.yaml",
+            "version: include.snarkos.testing.monadic.us/v1
+include: storage.yaml
+",
+        );
+        let spec = write_spec(
+            &dir,
+            "spec.yaml",
+            "version: include.snarkos.testing.monadic.us/v1
+include: shared/include.yaml
+",
+        );
+
+        let (documents, contents) = resolve_file(&spec, Some(&dir)).unwrap();
+        assert_eq!(documents.len(), 1);
+        assert!(matches!(documents[0], ItemDocument::Storage(_)));
+        // included specs are part of the spec's hash
+        assert_eq!(
+            contents,
+            [std::fs::read(include).unwrap(), STORAGE.as_bytes().to_vec()].concat()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_include_cycle() {
+        let dir = std::env::temp_dir().join(format!("snops-include-cycle-{}", std::process::id()));
+        let spec = write_spec(
+            &dir,
+            "a.yaml",
+            "version: include.snarkos.testing.monadic.us/v1
+include: ./b.yaml
+",
+        );
+        write_spec(
+            &dir,
+            "b.yaml",
+            "version: include.snarkos.testing.monadic.us/v1
+include: a.yaml
+",
+        );
+
+        let err = resolve_file(&spec, Some(&dir)).unwrap_err();
+        assert!(matches!(err, IncludeError::Cycle(_)), "{err}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_local_include_requires_include_dir() {
+        let dir = std::env::temp_dir().join(format!("snops-include-off-{}", std::process::id()));
+        write_spec(&dir, "storage.yaml", STORAGE);
+        let spec = write_spec(
+            &dir,
+            "spec.yaml",
+            "version: include.snarkos.testing.monadic.us/v1
+include: storage.yaml
+",
+        );
+
+        let err = resolve_file(&spec, None).unwrap_err();
+        assert!(matches!(err, IncludeError::LocalDisabled(_)), "{err}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_include_outside_include_dir() {
+        let dir = std::env::temp_dir().join(format!("snops-include-dir-{}", std::process::id()));
+        write_spec(&dir, "outside.yaml", STORAGE);
+        let spec = write_spec(
+            &dir,
+            "specs/spec.yaml",
+            "version: include.snarkos.testing.monadic.us/v1
+include: ../outside.yaml
+",
+        );
+
+        let err = resolve_file(&spec, Some(&dir.join("specs"))).unwrap_err();
+        assert!(matches!(err, IncludeError::OutsideIncludeDir(_)), "{err}");
+
+        // absolute paths outside of the directory are rejected without being read
+        let absolute = write_spec(
+            &dir,
+            "specs/absolute.yaml",
+            &format!(
+                "version: include.snarkos.testing.monadic.us/v1
+include: {}
+",
+                dir.join("missing.yaml").display()
+            ),
+        );
+        let err = resolve_file(&absolute, Some(&dir.join("specs"))).unwrap_err();
+        assert!(matches!(err, IncludeError::OutsideIncludeDir(_)), "{err}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
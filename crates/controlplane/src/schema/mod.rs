@@ -3,6 +3,7 @@ use snops_common::state::NodeKey;
 
 pub mod cannon;
 pub mod error;
+pub mod include;
 pub mod infrastructure;
 pub mod nodes;
 pub mod outcomes;
@@ -28,6 +29,35 @@ pub enum ItemDocument {
 
     #[serde(rename = "cannon.snarkos.testing.monadic.us/v1")]
     Cannon(Box<cannon::Document>),
+
+    /// Replaced by the documents of another spec when the spec is applied
+    #[serde(rename = "include.snarkos.testing.monadic.us/v1")]
+    Include {
+        /// A path or http(s) url. Relative paths are resolved against the
+        /// directory of the including spec, and paths must be in the control
+        /// plane's include directory.
+        #[serde(rename = "include")]
+        path_or_url: String,
+    },
+}
+
+impl ItemDocument {
+    /// Deserialize a document as the type of its `version` without buffering
+    /// it, returning the error (with its location) if it fails.
+    pub fn untagged_error(
+        version: &str,
+        doc: serde_yaml::Deserializer,
+    ) -> Option<serde_yaml::Error> {
+        match version {
+            "storage.snarkos.testing.monadic.us/v1" => storage::Document::deserialize(doc).err(),
+            "nodes.snarkos.testing.monadic.us/v1" => nodes::Document::deserialize(doc).err(),
+            "infrastructure.snarkos.testing.monadic.us/v1" => {
+                infrastructure::Document::deserialize(doc).err()
+            }
+            "cannon.snarkos.testing.monadic.us/v1" => cannon::Document::deserialize(doc).err(),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -52,4 +82,26 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn deserialize_error_location() {
+        let spec = "version: storage.snarkos.testing.monadic.us/v1
+id: base
+name: base-ledger
+---
+version: storage.snarkos.testing.monadic.us/v1
+id: broken
+name: broken-ledger
+regen: not-a-number
+";
+
+        let e = Environment::deserialize(spec).expect_err("spec should not deserialize");
+        assert_eq!(e.i, 1);
+        assert_eq!(e.name.as_deref(), Some("broken-ledger"));
+        assert_eq!(
+            e.version.as_deref(),
+            Some("storage.snarkos.testing.monadic.us/v1")
+        );
+        assert_eq!(e.location.map(|(line, _)| line), Some(8));
+    }
 }
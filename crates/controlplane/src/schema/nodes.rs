@@ -1,7 +1,14 @@
-use std::net::{IpAddr, SocketAddr};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+};
 
 use fixedbitset::FixedBitSet;
 use indexmap::{IndexMap, IndexSet};
+use reqwest::{
+    RequestBuilder,
+    header::{HeaderName, HeaderValue},
+};
 use serde::{Deserialize, Deserializer, Serialize, de::Visitor};
 use snops_common::{
     INTERN,
@@ -32,6 +39,10 @@ pub struct Document {
 
     #[serde(default)]
     pub nodes: IndexMap<NodeKey, Node>,
+
+    /// The private key for internal nodes that do not specify a `key`.
+    #[serde(default)]
+    pub default_key: Option<KeySource>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
@@ -42,11 +53,113 @@ pub struct ExternalNode {
     pub bft: Option<SocketAddr>,
     pub node: Option<SocketAddr>,
     pub rest: Option<SocketAddr>,
+    /// Headers attached to REST requests made to the node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rest_headers: Option<RestHeaders>,
+}
+
+impl ExternalNode {
+    /// The node with its REST header values redacted, for API responses
+    pub fn redacted(&self) -> Self {
+        Self {
+            rest_headers: self.rest_headers.as_ref().map(RestHeaders::redacted),
+            ..self.clone()
+        }
+    }
+}
+
+/// Headers for REST requests to a node behind an auth gateway, i.e. a bearer
+/// token or API key. Values are redacted when displayed or debug formatted,
+/// and must be redacted with [`RestHeaders::redacted`] before they are
+/// returned from the API.
+#[derive(Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RestHeaders(pub IndexMap<String, String>);
+
+const REDACTED: &str = "<redacted>";
+
+impl RestHeaders {
+    /// Attach the headers to a request
+    pub fn apply(&self, req: RequestBuilder) -> RequestBuilder {
+        self.0.iter().fold(req, |req, (name, value)| {
+            req.header(name.as_str(), value.as_str())
+        })
+    }
+
+    /// The header names with their values redacted
+    pub fn redacted(&self) -> Self {
+        Self(
+            self.0
+                .keys()
+                .map(|name| (name.clone(), REDACTED.to_owned()))
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for RestHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, name) in self.0.keys().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name}: {REDACTED}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for RestHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.keys().map(|name| (name, REDACTED)))
+            .finish()
+    }
+}
+
+impl<'de> Deserialize<'de> for RestHeaders {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let headers = IndexMap::<String, String>::deserialize(deserializer)?;
+        for (name, value) in &headers {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| D::Error::custom(format!("invalid header name `{name}`: {e}")))?;
+            // the value is left out of the error to avoid leaking it
+            HeaderValue::from_str(value)
+                .map_err(|_| D::Error::custom(format!("invalid value for header `{name}`")))?;
+            // exported specs hold the placeholder until the value is filled in
+            if value == REDACTED {
+                return Err(D::Error::custom(format!(
+                    "header `{name}` is {REDACTED}, replace it with the header's value"
+                )));
+            }
+        }
+        Ok(Self(headers))
+    }
+}
+
+impl DataFormat for RestHeaders {
+    type Header = ();
+    const LATEST_HEADER: Self::Header = ();
+
+    fn write_data<W: std::io::prelude::Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<usize, DataWriteError> {
+        self.0.write_data(writer)
+    }
+
+    fn read_data<R: std::io::prelude::Read>(
+        reader: &mut R,
+        _header: &Self::Header,
+    ) -> Result<Self, DataReadError> {
+        Ok(Self(reader.read_data(&((), ()))?))
+    }
 }
 
 impl DataFormat for ExternalNode {
     type Header = u8;
-    const LATEST_HEADER: Self::Header = 1;
+    const LATEST_HEADER: Self::Header = 2;
 
     fn write_data<W: std::io::prelude::Write>(
         &self,
@@ -56,6 +169,7 @@ impl DataFormat for ExternalNode {
         written += writer.write_data(&self.bft)?;
         written += writer.write_data(&self.node)?;
         written += writer.write_data(&self.rest)?;
+        written += writer.write_data(&self.rest_headers)?;
         Ok(written)
     }
 
@@ -64,11 +178,21 @@ impl DataFormat for ExternalNode {
         header: &Self::Header,
     ) -> Result<Self, DataReadError> {
         match header {
-            1 => {
+            1 | 2 => {
                 let bft = reader.read_data(&())?;
                 let node = reader.read_data(&())?;
                 let rest = reader.read_data(&())?;
-                Ok(ExternalNode { bft, node, rest })
+                let rest_headers = if *header > 1 {
+                    reader.read_data(&())?
+                } else {
+                    None
+                };
+                Ok(ExternalNode {
+                    bft,
+                    node,
+                    rest,
+                    rest_headers,
+                })
             }
             _ => Err(DataReadError::Custom("unsupported version".to_owned())),
         }
@@ -98,6 +222,7 @@ impl<'de> Deserialize<'de> for ExternalNode {
                 let mut bft = None;
                 let mut node = None;
                 let mut rest = None;
+                let mut rest_headers = None;
 
                 while let Some(key) = map.next_key::<String>()? {
                     match key.as_str() {
@@ -110,16 +235,24 @@ impl<'de> Deserialize<'de> for ExternalNode {
                         "rest" => {
                             rest = Some(map.next_value()?);
                         }
+                        "rest_headers" => {
+                            rest_headers = Some(map.next_value()?);
+                        }
                         _ => {
                             return Err(serde::de::Error::unknown_field(
                                 &key,
-                                &["bft", "node", "rest"],
+                                &["bft", "node", "rest", "rest_headers"],
                             ));
                         }
                     }
                 }
 
-                Ok(ExternalNode { bft, node, rest })
+                Ok(ExternalNode {
+                    bft,
+                    node,
+                    rest,
+                    rest_headers,
+                })
             }
 
             fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -131,6 +264,7 @@ impl<'de> Deserialize<'de> for ExternalNode {
                     bft: Some(SocketAddr::new(ip, 5000)),
                     node: Some(SocketAddr::new(ip, 4130)),
                     rest: Some(SocketAddr::new(ip, 3030)),
+                    rest_headers: None,
                 })
             }
         }
@@ -164,6 +298,28 @@ where
     labels.serialize(serializer)
 }
 
+fn deser_label_weights<'de, D>(deserializer: D) -> Result<IndexMap<Spur, u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let weights = IndexMap::<String, u32>::deserialize(deserializer)?;
+    Ok(weights
+        .into_iter()
+        .map(|(label, weight)| (INTERN.get_or_intern(label), weight))
+        .collect())
+}
+
+fn ser_label_weights<S>(weights: &IndexMap<Spur, u32>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_map(
+        weights
+            .iter()
+            .map(|(label, weight)| (INTERN.resolve(label), weight)),
+    )
+}
+
 // TODO: could use some more clarification on some of these fields
 /// A node in the testing infrastructure.
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
@@ -191,6 +347,17 @@ pub struct Node {
     )]
     pub labels: IndexSet<Spur>,
 
+    /// Labels that agents are preferred to have, with a weight for each. Of
+    /// the agents that have every label in `labels`, the node is paired with
+    /// an available agent whose preferred labels add up to the highest weight.
+    #[serde(
+        default,
+        skip_serializing_if = "IndexMap::is_empty",
+        deserialize_with = "deser_label_weights",
+        serialize_with = "ser_label_weights"
+    )]
+    pub preferred_labels: IndexMap<Spur, u32>,
+
     /// When specified, an agent must have this id. Overrides the labels field.
     #[serde(default)]
     pub agent: Option<AgentId>,
@@ -210,6 +377,21 @@ pub struct Node {
     /// The id of the binary for this node to use, uses "default" by default
     #[serde(default)]
     pub binary: Option<InternedId>,
+
+    /// Address to bind the node to, for agents on hosts with multiple
+    /// interfaces. Must be one of the agent's addresses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_addr: Option<IpAddr>,
+
+    /// CPU limit of the node process in millicores (1000 is one core).
+    /// Requires the agent to be started with a cgroup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_limit: Option<u32>,
+
+    /// Memory limit of the node process in MiB. The node is killed when it
+    /// exceeds the limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit: Option<u64>,
 }
 
 impl Node {
@@ -221,6 +403,9 @@ impl Node {
             online: self.online,
             env: self.env.clone(),
             binary: self.binary,
+            bind_addr: self.bind_addr,
+            cpu_limit: self.cpu_limit,
+            memory_limit: self.memory_limit,
 
             // these are resolved later
             validators: Default::default(),
@@ -247,6 +432,19 @@ impl Node {
         }
         mask
     }
+
+    /// The mask bits of the node's preferred labels, with their weights
+    pub fn label_weights(&self, labels: &[Spur]) -> Vec<(usize, u32)> {
+        labels
+            .iter()
+            .enumerate()
+            .filter_map(|(i, label)| {
+                self.preferred_labels
+                    .get(label)
+                    .map(|weight| (i + MASK_PREFIX_LEN, *weight))
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -255,11 +453,14 @@ pub struct NodeFormatHeader {
     pub(crate) height_request: DataHeaderOf<HeightRequest>,
     pub(crate) node_targets: DataHeaderOf<NodeTargets>,
     pub has_binaries: bool,
+    pub has_bind_addr: bool,
+    pub has_limits: bool,
+    pub has_preferred_labels: bool,
 }
 
 impl DataFormat for NodeFormatHeader {
     type Header = u8;
-    const LATEST_HEADER: Self::Header = 2;
+    const LATEST_HEADER: Self::Header = 5;
 
     fn write_data<W: std::io::prelude::Write>(
         &self,
@@ -292,6 +493,9 @@ impl DataFormat for NodeFormatHeader {
             height_request,
             node_targets,
             has_binaries: *header > 1,
+            has_bind_addr: *header > 2,
+            has_limits: *header > 3,
+            has_preferred_labels: *header > 4,
         })
     }
 }
@@ -303,6 +507,9 @@ impl DataFormat for Node {
         height_request: HeightRequest::LATEST_HEADER,
         node_targets: NodeTargets::LATEST_HEADER,
         has_binaries: true,
+        has_bind_addr: true,
+        has_limits: true,
+        has_preferred_labels: true,
     };
 
     fn write_data<W: std::io::prelude::Write>(
@@ -320,6 +527,10 @@ impl DataFormat for Node {
         written += self.peers.write_data(writer)?;
         written += self.env.write_data(writer)?;
         written += self.binary.write_data(writer)?;
+        written += self.bind_addr.write_data(writer)?;
+        written += self.cpu_limit.write_data(writer)?;
+        written += self.memory_limit.write_data(writer)?;
+        written += self.preferred_labels.write_data(writer)?;
         Ok(written)
     }
 
@@ -341,6 +552,21 @@ impl DataFormat for Node {
         } else {
             None
         };
+        let bind_addr = if header.has_bind_addr {
+            reader.read_data(&())?
+        } else {
+            None
+        };
+        let (cpu_limit, memory_limit) = if header.has_limits {
+            (reader.read_data(&())?, reader.read_data(&())?)
+        } else {
+            (None, None)
+        };
+        let preferred_labels = if header.has_preferred_labels {
+            reader.read_data(&((), ()))?
+        } else {
+            IndexMap::new()
+        };
 
         Ok(Node {
             online,
@@ -348,11 +574,15 @@ impl DataFormat for Node {
             key,
             height,
             labels: labels.into_iter().collect(),
+            preferred_labels,
             agent,
             validators,
             peers,
             env: env.into_iter().collect(),
             binary,
+            bind_addr,
+            cpu_limit,
+            memory_limit,
         })
     }
 }
@@ -386,6 +616,12 @@ mod tests {
             serde_yaml::from_str::<KeySource>("accounts.$").unwrap(),
             KeySource::Named(*ACCOUNTS_KEY_ID, None)
         );
+        assert_eq!(
+            serde_yaml::from_str::<KeySource>("accounts.*").unwrap(),
+            KeySource::Random {
+                set: *ACCOUNTS_KEY_ID
+            }
+        );
 
         assert_eq!(
             serde_yaml::from_str::<KeySource>(
@@ -410,6 +646,55 @@ mod tests {
         assert!(serde_yaml::from_str::<KeySource>("committee.-100").is_err(),);
         assert!(serde_yaml::from_str::<KeySource>("accounts.-100").is_err(),);
         assert!(serde_yaml::from_str::<KeySource>("accounts._").is_err(),);
-        assert!(serde_yaml::from_str::<KeySource>("accounts.*").is_err(),);
+        assert!(serde_yaml::from_str::<KeySource>("committee.*").is_err(),);
+    }
+
+    #[test]
+    fn test_external_rest_headers_redacted() {
+        let node = serde_yaml::from_str::<ExternalNode>(
+            "rest: 127.0.0.1:3030\nrest_headers:\n  Authorization: Bearer secret",
+        )
+        .unwrap();
+        let headers = node.rest_headers.as_ref().unwrap();
+        assert_eq!(headers.0["Authorization"], "Bearer secret");
+
+        assert!(!format!("{node:?}").contains("secret"));
+        assert!(!headers.to_string().contains("secret"));
+        assert!(
+            !serde_json::to_string(&node.redacted())
+                .unwrap()
+                .contains("secret")
+        );
+
+        // serde output is lossless so the node can be re-applied
+        let yaml = serde_yaml::to_string(&node).unwrap();
+        assert_eq!(serde_yaml::from_str::<ExternalNode>(&yaml).unwrap(), node);
+
+        let data = node.to_byte_vec().unwrap();
+        let read = ExternalNode::read_data(&mut data.as_slice(), &ExternalNode::LATEST_HEADER);
+        assert_eq!(read.unwrap(), node);
+
+        assert!(
+            serde_yaml::from_str::<ExternalNode>("rest_headers:\n  bad header: value").is_err()
+        );
+    }
+
+    #[test]
+    fn test_preferred_label_weights() {
+        let node: Node = serde_yaml::from_str(
+            "labels: [fleet]\npreferred_labels:\n  tier-high: 10\n  tier-mid: 5",
+        )
+        .unwrap();
+        let labels = ["tier-mid", "fleet", "tier-high"].map(|label| INTERN.get_or_intern(label));
+
+        assert_eq!(
+            node.label_weights(&labels),
+            vec![(MASK_PREFIX_LEN, 5), (MASK_PREFIX_LEN + 2, 10)]
+        );
+        // the weights are kept when the node is re-serialized
+        assert_eq!(
+            serde_yaml::from_str::<Node>(&serde_yaml::to_string(&node).unwrap()).unwrap(),
+            node
+        );
     }
 }
@@ -41,6 +41,7 @@ fn env_or_bin(name: &str, env: &str) -> BinaryEntry {
     let mut entry = BinaryEntry {
         size: None,
         sha256: None,
+        version: None,
         source: source.clone(),
     };
 
@@ -164,6 +165,8 @@ pub struct BinaryEntryInternal {
     pub size: Option<AutoIsDefault<u64>>,
     #[serde(default)]
     pub sha256: Option<AutoIsDefault<String>>,
+    #[serde(default)]
+    pub version: Option<String>,
 }
 
 /// A BinaryEntryDoc can be a shorthand or a full entry
@@ -195,6 +198,7 @@ impl TryFrom<BinaryEntryDoc> for BinaryEntry {
                 source,
                 sha256: None,
                 size: None,
+                version: None,
             }),
             BinaryEntryDoc::Full(entry) => Ok(BinaryEntry {
                 size: match entry.size {
@@ -221,6 +225,7 @@ impl TryFrom<BinaryEntryDoc> for BinaryEntry {
                         ),
                     },
                 },
+                version: entry.version,
                 source: entry.source,
             }),
         }
@@ -1,10 +1,14 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use bech32::{FromBase32, Variant};
 use indexmap::IndexMap;
 use serde::de::DeserializeOwned;
 
 use super::AleoAddrMap;
-use crate::schema::error::StorageError;
+use crate::schema::{
+    error::StorageError,
+    include::{IncludePathError, resolve_include_path},
+};
 
 pub async fn get_version_from_path(path: &PathBuf) -> Result<Option<u16>, StorageError> {
     if !path.exists() {
@@ -28,6 +32,19 @@ pub fn pick_account_addr(entry: String) -> String {
     entry
 }
 
+/// Locate the committee file in a storage directory, preferring
+/// `committee.json` and falling back to `committee.yaml` when only the latter
+/// exists.
+pub fn committee_file_path(base: &Path) -> PathBuf {
+    let json = base.join("committee.json");
+    let yaml = base.join("committee.yaml");
+    if !json.exists() && yaml.exists() {
+        yaml
+    } else {
+        json
+    }
+}
+
 // TODO: function should also take storage id
 // in case of error, the storage id can be used to provide more context
 pub async fn read_to_addrs<T: DeserializeOwned>(
@@ -41,8 +58,169 @@ pub async fn read_to_addrs<T: DeserializeOwned>(
     let data = tokio::fs::read_to_string(file)
         .await
         .map_err(|e| StorageError::ReadBalances(file.clone(), e))?;
-    let parsed: IndexMap<String, T> =
-        serde_json::from_str(&data).map_err(|e| StorageError::ParseBalances(file.clone(), e))?;
+    let parsed: IndexMap<String, T> = match file.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str(&data)
+            .map_err(|e| StorageError::ParseBalancesYaml(file.clone(), e))?,
+        _ => {
+            serde_json::from_str(&data).map_err(|e| StorageError::ParseBalances(file.clone(), e))?
+        }
+    };
 
     Ok(parsed.into_iter().map(|(k, v)| (k, f(v))).collect())
 }
+
+/// Check that an address is a bech32m encoded `aleo` address of 32 bytes.
+/// Every network uses the same prefix.
+pub fn is_valid_address(addr: &str) -> bool {
+    let Ok((hrp, data, variant)) = bech32::decode(addr) else {
+        return false;
+    };
+    hrp == "aleo"
+        && variant == Variant::Bech32m
+        && Vec::<u8>::from_base32(&data).is_ok_and(|bytes| bytes.len() == 32)
+}
+
+/// Resolve a bonded balances file in the include directory
+pub async fn resolve_bonded_balances_path(
+    file: &Path,
+    include_dir: Option<&Path>,
+) -> Result<PathBuf, StorageError> {
+    resolve_include_path(file, include_dir)
+        .await
+        .map_err(|e| match e {
+            IncludePathError::Disabled => StorageError::BondedBalancesDisabled(file.to_path_buf()),
+            IncludePathError::Read(path, e) => StorageError::ReadBondedBalances(path, e),
+            IncludePathError::OutsideIncludeDir(path) => {
+                StorageError::BondedBalancesOutsideIncludeDir(path)
+            }
+        })
+}
+
+/// Read `address,balance` rows from a bonded balances CSV file. Blank lines,
+/// `#` comments, and an `address,balance` header row are skipped. Errors only
+/// refer to the offending line by number, so the file's contents are not
+/// echoed back.
+pub async fn read_bonded_balances_csv(
+    file: &PathBuf,
+) -> Result<IndexMap<String, u64>, StorageError> {
+    let data = tokio::fs::read_to_string(file)
+        .await
+        .map_err(|e| StorageError::ReadBondedBalances(file.clone(), e))?;
+
+    let mut balances = IndexMap::new();
+    for (i, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let err =
+            |msg: &str| StorageError::ParseBondedBalances(file.clone(), i + 1, msg.to_owned());
+        let Some((addr, balance)) = line.split_once(',') else {
+            return Err(err("expected `address,balance`"));
+        };
+        let (addr, balance) = (addr.trim(), balance.trim());
+
+        // allow a header row as the first entry
+        if balances.is_empty() && addr.eq_ignore_ascii_case("address") {
+            continue;
+        }
+
+        if !is_valid_address(addr) {
+            return Err(err("not an aleo address"));
+        }
+        match balance.parse::<u64>() {
+            Ok(0) => return Err(err("balance must be non-zero")),
+            Ok(balance) => balances.insert(addr.to_owned(), balance),
+            Err(_) => return Err(err("invalid balance")),
+        };
+    }
+
+    Ok(balances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: &str = "aleo1yspxekr97q4fu9kkxk88f4874pl96r9zxqwtp7rtn2xc5wqgqggspjljf8";
+
+    #[tokio::test]
+    async fn test_bonded_balances_file_in_include_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let include_dir = dir.path().join("specs");
+        std::fs::create_dir_all(&include_dir).unwrap();
+        std::fs::write(
+            include_dir.join("committee.csv"),
+            format!("address,balance\n{ADDR},10000000000000\n"),
+        )
+        .unwrap();
+        let outside = dir.path().join("secret.csv");
+        std::fs::write(&outside, "secret").unwrap();
+
+        let path = resolve_bonded_balances_path(Path::new("committee.csv"), Some(&include_dir))
+            .await
+            .unwrap();
+        let balances = read_bonded_balances_csv(&path).await.unwrap();
+        assert_eq!(balances.get(ADDR), Some(&10_000_000_000_000));
+
+        // files outside of the include directory are never read
+        for file in [Path::new("../secret.csv"), outside.as_path()] {
+            let err = resolve_bonded_balances_path(file, Some(&include_dir))
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(err, StorageError::BondedBalancesOutsideIncludeDir(_)),
+                "{err}"
+            );
+        }
+
+        let err = resolve_bonded_balances_path(Path::new("committee.csv"), None)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, StorageError::BondedBalancesDisabled(_)),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bonded_balances_csv_parses_addresses() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("committee.csv");
+
+        // a corrupted character fails the address checksum
+        let bad = ADDR.replacen("yspxek", "yspxeq", 1);
+        std::fs::write(&file, format!("{ADDR},10\n{bad},10\n")).unwrap();
+        let err = read_bonded_balances_csv(&file).await.unwrap_err();
+        assert!(
+            matches!(err, StorageError::ParseBondedBalances(_, 2, _)),
+            "{err}"
+        );
+        // the file's contents are not echoed back
+        assert!(!err.to_string().contains(&bad));
+
+        std::fs::write(&file, format!("{ADDR},0\n")).unwrap();
+        let err = read_bonded_balances_csv(&file).await.unwrap_err();
+        assert!(
+            matches!(err, StorageError::ParseBondedBalances(_, 1, _)),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_is_valid_address() {
+        assert!(is_valid_address(ADDR));
+        assert!(!is_valid_address(
+            "APrivateKey1zkp8CZNn3yeCseEtxuVPbDCwSyhGW6yZKUYKfgXmcpoGPWH"
+        ));
+        // a valid checksum with the wrong prefix
+        let other = bech32::encode(
+            "other",
+            bech32::decode(ADDR).unwrap().1,
+            bech32::Variant::Bech32m,
+        )
+        .unwrap();
+        assert!(!is_valid_address(&other));
+    }
+}
@@ -2,7 +2,7 @@ use std::{fs, io::Write, os::unix::fs::PermissionsExt, path::PathBuf};
 
 use futures_util::StreamExt;
 use indexmap::IndexMap;
-use rand::seq::IteratorRandom;
+use rand::{Rng, seq::IteratorRandom};
 use sha2::{Digest, Sha256};
 use snops_checkpoint::RetentionPolicy;
 use snops_common::{
@@ -12,9 +12,12 @@ use snops_common::{
     state::{InternedId, KeyState, NetworkId, StorageId},
 };
 use tracing::{info, trace};
+use url::Url;
 
-use super::{DEFAULT_AOT_BINARY, STORAGE_DIR};
-use crate::{cli::Cli, schema::error::StorageError, state::GlobalState};
+use super::{DEFAULT_AOT_BINARY, STORAGE_DIR, StorageGeneration};
+use crate::{
+    cannon::ledger::LedgerLocation, cli::Cli, schema::error::StorageError, state::GlobalState,
+};
 
 // IndexMap<addr, private_key>
 pub type AleoAddrMap = IndexMap<String, String>;
@@ -40,6 +43,12 @@ pub struct LoadedStorage {
     pub native_genesis: bool,
     /// binaries available for this storage
     pub binaries: IndexMap<InternedId, BinaryEntry>,
+    /// ledger read by cannon query services instead of the storage's own
+    pub ledger_url: Option<Url>,
+    /// generation options the storage was prepared with, for exporting it
+    pub generation: Option<StorageGeneration>,
+    /// url the storage's genesis block was downloaded from
+    pub connect: Option<Url>,
 }
 
 impl LoadedStorage {
@@ -61,6 +70,8 @@ impl LoadedStorage {
                 .and_then(|a| a.get_index(*i).map(|(_, pk)| pk.clone()))
                 .into(),
             KeySource::Named(_name, None) => KeyState::None,
+            // a lookup must resolve to the same key every time
+            KeySource::Random { .. } => KeyState::None,
         }
     }
 
@@ -82,10 +93,17 @@ impl LoadedStorage {
                 .and_then(|a| a.get_index(*i).map(|(addr, _)| addr.clone()))
                 .into(),
             KeySource::Named(_name, None) => KeyState::None,
+            // a lookup must resolve to the same key every time
+            KeySource::Random { .. } => KeyState::None,
         }
     }
 
     pub fn sample_keysource_pk(&self, key: &KeySource) -> KeyState {
+        self.sample_keysource_pk_with(key, &mut rand::thread_rng())
+    }
+
+    /// Sample a private key, picking random keys with the given rng
+    pub fn sample_keysource_pk_with<R: Rng>(&self, key: &KeySource, rng: &mut R) -> KeyState {
         match key {
             KeySource::Local => KeyState::Local,
             KeySource::PrivateKeyLiteral(pk) => KeyState::Literal(pk.clone()),
@@ -96,12 +114,7 @@ impl LoadedStorage {
                 .get_index(*i)
                 .map(|(_, pk)| pk.clone())
                 .into(),
-            KeySource::Committee(None) => self
-                .committee
-                .values()
-                .choose(&mut rand::thread_rng())
-                .cloned()
-                .into(),
+            KeySource::Committee(None) => self.committee.values().choose(rng).cloned().into(),
             KeySource::Named(name, Some(i)) => self
                 .accounts
                 .get(name)
@@ -110,12 +123,20 @@ impl LoadedStorage {
             KeySource::Named(name, None) => self
                 .accounts
                 .get(name)
-                .and_then(|a| a.values().choose(&mut rand::thread_rng()).cloned())
+                .and_then(|a| a.values().choose(rng).cloned())
                 .into(),
+            KeySource::Random { set } => {
+                self.pick_account(set, rng).map(|(_, pk)| pk.clone()).into()
+            }
         }
     }
 
     pub fn sample_keysource_addr(&self, key: &KeySource) -> KeyState {
+        self.sample_keysource_addr_with(key, &mut rand::thread_rng())
+    }
+
+    /// Sample an address, picking random keys with the given rng
+    pub fn sample_keysource_addr_with<R: Rng>(&self, key: &KeySource, rng: &mut R) -> KeyState {
         match key {
             KeySource::Local => KeyState::Local,
             KeySource::PrivateKeyLiteral(_) => KeyState::None,
@@ -126,12 +147,7 @@ impl LoadedStorage {
                 .get_index(*i)
                 .map(|(addr, _)| addr.clone())
                 .into(),
-            KeySource::Committee(None) => self
-                .committee
-                .keys()
-                .choose(&mut rand::thread_rng())
-                .cloned()
-                .into(),
+            KeySource::Committee(None) => self.committee.keys().choose(rng).cloned().into(),
             KeySource::Named(name, Some(i)) => self
                 .accounts
                 .get(name)
@@ -140,11 +156,23 @@ impl LoadedStorage {
             KeySource::Named(name, None) => self
                 .accounts
                 .get(name)
-                .and_then(|a| a.keys().choose(&mut rand::thread_rng()).cloned())
+                .and_then(|a| a.keys().choose(rng).cloned())
+                .into(),
+            KeySource::Random { set } => self
+                .pick_account(set, rng)
+                .map(|(addr, _)| addr.clone())
                 .into(),
         }
     }
 
+    /// Pick a random account from a named account set. The choice is logged
+    /// so a failing transaction can be traced back to its key.
+    fn pick_account<R: Rng>(&self, set: &InternedId, rng: &mut R) -> Option<(&String, &String)> {
+        let (i, (addr, pk)) = self.accounts.get(set)?.iter().enumerate().choose(rng)?;
+        info!("{}: picked {set}.{i} ({addr}) for {set}.*", self.id);
+        Some((addr, pk))
+    }
+
     pub fn info(&self) -> StorageInfo {
         let mut binaries: IndexMap<_, _> = self
             .binaries
@@ -176,6 +204,14 @@ impl LoadedStorage {
         self.path_cli(&state.cli)
     }
 
+    /// Where the query services of cannons read this storage's ledger from
+    pub fn ledger_location(&self, state: &GlobalState) -> LedgerLocation {
+        match &self.ledger_url {
+            Some(url) => LedgerLocation::Remote(url.clone()),
+            None => LedgerLocation::Local(self.path(state)),
+        }
+    }
+
     pub fn path_cli(&self, cli: &Cli) -> PathBuf {
         let mut path = cli.path.join(STORAGE_DIR);
         path.push(self.network.to_string());
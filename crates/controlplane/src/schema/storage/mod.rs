@@ -1,5 +1,12 @@
-use std::{ops::Deref, path::PathBuf, process::Stdio, sync::Arc};
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+    time::Duration,
+};
 
+use futures_util::{StreamExt, stream};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use snops_checkpoint::RetentionPolicy;
@@ -10,14 +17,18 @@ use snops_common::{
     key_source::ACCOUNTS_KEY_ID,
     state::{InternedId, NetworkId, StorageId},
 };
-use tokio::process::Command;
+use tokio::{
+    process::{Child, Command},
+    select,
+    sync::watch,
+};
 use tracing::{error, info, trace, warn};
 
 use super::error::{SchemaError, StorageError};
-use crate::{persist::PersistStorage, state::GlobalState};
+use crate::{cannon::ledger::LedgerLocation, persist::PersistStorage, state::GlobalState};
 
 mod accounts;
-use accounts::*;
+pub use accounts::*;
 mod helpers;
 pub use helpers::*;
 mod loaded;
@@ -26,6 +37,37 @@ mod binaries;
 pub use binaries::*;
 
 pub const STORAGE_DIR: &str = "storage";
+/// Maximum number of account sets generated at once
+const MAX_ACCOUNT_GENERATIONS: usize = 4;
+
+/// Wait for a storage generation command to exit, killing it when it runs
+/// past the timeout or the preparation is cancelled.
+async fn wait_generation(
+    id: StorageId,
+    command: &'static str,
+    mut child: Child,
+    timeout: Duration,
+    cancel: &mut watch::Receiver<bool>,
+) -> Result<ExitStatus, StorageError> {
+    let err = select! {
+        res = tokio::time::timeout(timeout, child.wait()) => match res {
+            Ok(res) => {
+                return res.map_err(|e| {
+                    StorageError::Command(CommandError::action("waiting", command, e), id)
+                });
+            }
+            Err(_) => StorageError::GenesisTimeout(id, command, timeout),
+        },
+        // a closed channel means the preparation can no longer be cancelled
+        Ok(_) = cancel.wait_for(|cancelled| *cancelled) => StorageError::GenerationCancelled(id),
+    };
+
+    error!("{id}: killing {command}: {err}");
+    if let Err(e) = child.kill().await {
+        error!("{id}: failed to kill {command}: {e}");
+    }
+    Err(err)
+}
 
 /// A storage document. Explains how storage for a test should be set up.
 #[derive(Deserialize, Debug, Clone)]
@@ -37,6 +79,12 @@ pub struct Document {
     pub regen: u16,
     pub name: String,
     pub description: Option<String>,
+    /// The network this storage is intended for.
+    ///
+    /// Only required when an env spec contains multiple storage documents, in
+    /// which case the one matching the env's network is selected.
+    #[serde(default)]
+    pub network: Option<NetworkId>,
     /// Tell nodes not to re-download the storage data.
     #[serde(default)]
     pub persist: bool,
@@ -54,6 +102,21 @@ pub struct Document {
     /// compute
     #[serde(default)]
     pub binaries: IndexMap<InternedId, BinaryEntryDoc>,
+    /// Stop preparing the storage after the genesis block is written,
+    /// skipping generation of named account sets.
+    #[serde(default)]
+    pub genesis_only: bool,
+    /// When set, preparing the storage fails unless the document resolves to
+    /// the network's native genesis block (`true`) or a generated/downloaded
+    /// one (`false`).
+    #[serde(default)]
+    pub expect_native_genesis: Option<bool>,
+    /// A `file://` url of a storage directory on a mounted filesystem, i.e.
+    /// NFS, that the query services of cannons read the ledger from instead
+    /// of this storage's directory. The ledger is copied to local disk before
+    /// a query service starts.
+    #[serde(default)]
+    pub ledger_url: Option<url::Url>,
 }
 
 /// Data generation instructions.
@@ -87,6 +150,10 @@ pub struct GenesisGeneration {
     pub seed: Option<u64>,
     pub additional_accounts: Option<u16>,
     pub additional_accounts_balance: Option<u64>,
+    /// Generate a genesis with only the bonded committee, ignoring the
+    /// additional accounts options
+    #[serde(default)]
+    pub committee_only: bool,
     #[serde(flatten)]
     pub balances: GenesisBalances,
     #[serde(flatten)]
@@ -97,6 +164,14 @@ pub struct GenesisGeneration {
 #[derive(Deserialize, Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum GenesisBalances {
+    /// Bonded balances loaded from an `address,balance` CSV file, merged with
+    /// any inline entries. Inline entries win on conflict.
+    #[serde(rename_all = "kebab-case")]
+    File {
+        bonded_balances_file: PathBuf,
+        #[serde(default)]
+        bonded_balances: IndexMap<String, u64>,
+    },
     #[serde(rename_all = "kebab-case")]
     Defined {
         bonded_balances: IndexMap<String, u64>,
@@ -108,6 +183,51 @@ pub enum GenesisBalances {
     },
 }
 
+impl GenesisBalances {
+    /// Resolve the bonded balances for a defined committee, loading them from
+    /// the balances file if one was given. Returns an empty map for generated
+    /// committees.
+    ///
+    /// The balances file is read from the include directory, as env specs are
+    /// resolved on the control plane.
+    pub async fn resolve_bonded_balances(
+        &self,
+        include_dir: Option<&Path>,
+    ) -> Result<IndexMap<String, u64>, StorageError> {
+        let (file, inline) = match self {
+            GenesisBalances::Generated { .. } => return Ok(IndexMap::new()),
+            GenesisBalances::Defined { bonded_balances } => (None, bonded_balances),
+            GenesisBalances::File {
+                bonded_balances_file,
+                bonded_balances,
+            } => (Some(bonded_balances_file), bonded_balances),
+        };
+
+        for (addr, balance) in inline {
+            if !is_valid_address(addr) {
+                return Err(StorageError::InvalidBondedBalance(
+                    addr.clone(),
+                    "not an aleo address",
+                ));
+            }
+            if *balance == 0 {
+                return Err(StorageError::InvalidBondedBalance(
+                    addr.clone(),
+                    "balance must be non-zero",
+                ));
+            }
+        }
+
+        let Some(file) = file else {
+            return Ok(inline.clone());
+        };
+        let path = resolve_bonded_balances_path(file, include_dir).await?;
+        let mut balances = read_bonded_balances_csv(&path).await?;
+        balances.extend(inline.iter().map(|(k, v)| (k.clone(), *v)));
+        Ok(balances)
+    }
+}
+
 #[derive(Deserialize, Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum GenesisCommissions {
@@ -126,6 +246,7 @@ impl Default for GenesisGeneration {
             private_key: None,
             additional_accounts: None,
             additional_accounts_balance: None,
+            committee_only: false,
             balances: GenesisBalances::Generated {
                 committee_size: None,
                 bonded_balance: None,
@@ -139,12 +260,27 @@ impl Default for GenesisGeneration {
 }
 
 impl Document {
+    /// Whether the document uses the network's native genesis block, which is
+    /// the case when it neither downloads nor generates a genesis block
+    pub fn resolves_native_genesis(&self) -> bool {
+        self.connect.is_none()
+            && self
+                .generate
+                .as_ref()
+                .is_some_and(|generation| generation.genesis.is_none())
+    }
+
+    /// Prepare the storage, generating its genesis block and accounts when
+    /// needed. Generation commands are killed when they exceed the configured
+    /// timeout or when `cancel` is set.
     pub async fn prepare(
         self,
         state: &GlobalState,
         network: NetworkId,
+        mut cancel: watch::Receiver<bool>,
     ) -> Result<Arc<LoadedStorage>, SchemaError> {
         let id = self.id;
+        let gen_timeout = Duration::from_secs(state.cli.storage_gen_timeout);
 
         // add the prepared storage to the storage map
 
@@ -154,6 +290,21 @@ impl Document {
             warn!("a storage with the id {id} has already been prepared");
         }
 
+        if let Some(expected) = self.expect_native_genesis {
+            let resolved = self.resolves_native_genesis();
+            if resolved != expected {
+                return Err(StorageError::NativeGenesisMismatch(id, expected, resolved).into());
+            }
+        }
+
+        // the ledger url is checked here so an unsupported url is rejected
+        // with the storage rather than when a cannon starts
+        if let Some(url) = &self.ledger_url {
+            LedgerLocation::Remote(url.clone())
+                .resolve()
+                .map_err(|e| StorageError::InvalidLedgerUrl(id, url.clone(), e))?;
+        }
+
         let base = state.storage_path(network, id);
         let version_file = base.join(VERSION_FILE);
 
@@ -227,7 +378,7 @@ impl Document {
             // generate the genesis block using the aot cli
             let output = base.join(SNARKOS_GENESIS_FILE);
 
-            match (self.connect, generation.genesis.as_ref()) {
+            match (self.connect.clone(), generation.genesis.as_ref()) {
                 (None, None) => {
                     native_genesis = true;
                     info!("{id}: using network native genesis")
@@ -258,6 +409,7 @@ impl Document {
                     command
                         .stdout(Stdio::inherit())
                         .stderr(Stdio::inherit())
+                        .kill_on_drop(true)
                         .env("NETWORK", network.to_string())
                         .arg("genesis")
                         .arg("--output")
@@ -294,7 +446,10 @@ impl Document {
                                     .arg(bonded_balance.to_string());
                             }
                         }
-                        GenesisBalances::Defined { bonded_balances } => {
+                        balances => {
+                            let bonded_balances = balances
+                                .resolve_bonded_balances(state.cli.include_dir.as_deref())
+                                .await?;
                             command
                                 .arg("--bonded-balances")
                                 .arg(serde_json::to_string(&bonded_balances).unwrap());
@@ -324,7 +479,9 @@ impl Document {
                     }
 
                     // conditionally add additional accounts
-                    if let Some(additional_accounts) = genesis.additional_accounts {
+                    if let (Some(additional_accounts), false) =
+                        (genesis.additional_accounts, genesis.committee_only)
+                    {
                         command
                             .arg("--additional-accounts")
                             .arg(additional_accounts.to_string())
@@ -332,7 +489,9 @@ impl Document {
                             .arg(base.join("accounts.json"));
                     }
 
-                    if let Some(balance) = genesis.additional_accounts_balance {
+                    if let (Some(balance), false) =
+                        (genesis.additional_accounts_balance, genesis.committee_only)
+                    {
                         command
                             .arg("--additional-accounts-balance")
                             .arg(balance.to_string());
@@ -340,22 +499,14 @@ impl Document {
 
                     info!("Generating genesis for {id} with command: {command:?}");
 
-                    let res = command
-                        .spawn()
-                        .map_err(|e| {
-                            StorageError::Command(
-                                CommandError::action("spawning", "aot genesis", e),
-                                id,
-                            )
-                        })?
-                        .wait()
-                        .await
-                        .map_err(|e| {
-                            StorageError::Command(
-                                CommandError::action("waiting", "aot genesis", e),
-                                id,
-                            )
-                        })?;
+                    let child = command.spawn().map_err(|e| {
+                        StorageError::Command(
+                            CommandError::action("spawning", "aot genesis", e),
+                            id,
+                        )
+                    })?;
+                    let res =
+                        wait_generation(id, "aot genesis", child, gen_timeout, &mut cancel).await?;
 
                     if !res.success() {
                         warn!("failed to run genesis generation command...");
@@ -375,48 +526,73 @@ impl Document {
             read_to_addrs(pick_additional_addr, &base.join("accounts.json")).await?,
         );
 
-        if let Some(generation) = &self.generate {
-            for (name, account) in &generation.accounts {
-                let path = base.join(format!("{}.json", name));
+        if self.genesis_only {
+            info!("{id}: genesis only, skipping account generation");
+        }
 
-                if !path.exists() {
+        if let (Some(generation), false) = (&self.generate, self.genesis_only) {
+            let missing = generation
+                .accounts
+                .iter()
+                .map(|(name, account)| (name, account, base.join(format!("{name}.json"))))
+                .filter(|(_, _, path)| !path.exists())
+                .collect::<Vec<_>>();
+
+            // split the cores between the generations that run at once so they
+            // do not oversubscribe the machine
+            let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+            let jobs = (cores / missing.len().clamp(1, MAX_ACCOUNT_GENERATIONS)).max(1);
+
+            let mut generations = stream::iter(missing.into_iter().map(|(name, account, path)| {
+                let aot_bin = &aot_bin;
+                let mut cancel = cancel.clone();
+                async move {
                     info!("generating accounts for {name}");
 
-                    let mut command = Command::new(&aot_bin);
+                    let mut command = Command::new(aot_bin);
                     command
                         .stdout(Stdio::inherit())
                         .stderr(Stdio::inherit())
+                        .kill_on_drop(true)
                         .env("NETWORK", network.to_string())
                         .arg("accounts")
                         .arg(account.count.to_string())
                         .arg("--output")
                         .arg(&path);
-                    if let Some(seed) = account.seed {
-                        command.arg("--seed").arg(seed.to_string());
+                    match account.seed {
+                        Some(seed) => {
+                            command.arg("--seed").arg(seed.to_string());
+                        }
+                        // unseeded accounts are random either way, so they can
+                        // be generated on several threads
+                        None => {
+                            command.arg("--jobs").arg(jobs.to_string());
+                        }
                     }
 
-                    let res = command
-                        .spawn()
-                        .map_err(|e| {
-                            StorageError::Command(
-                                CommandError::action("spawning", "aot accounts", e),
-                                id,
-                            )
-                        })?
-                        .wait()
-                        .await
-                        .map_err(|e| {
-                            StorageError::Command(
-                                CommandError::action("waiting", "aot accounts", e),
-                                id,
-                            )
-                        })?;
+                    let child = command.spawn().map_err(|e| {
+                        StorageError::Command(
+                            CommandError::action("spawning", "aot accounts", e),
+                            id,
+                        )
+                    })?;
+                    let res = wait_generation(id, "aot accounts", child, gen_timeout, &mut cancel)
+                        .await?;
 
                     if !res.success() {
                         warn!("failed to run account generation command for {name}...");
                     }
+                    Ok::<_, StorageError>(())
                 }
+            }))
+            .buffer_unordered(MAX_ACCOUNT_GENERATIONS);
 
+            while let Some(res) = generations.next().await {
+                res?;
+            }
+
+            for name in generation.accounts.keys() {
+                let path = base.join(format!("{name}.json"));
                 accounts.insert(*name, read_to_addrs(pick_account_addr, &path).await?);
             }
         }
@@ -426,7 +602,7 @@ impl Document {
             .await
             .map_err(|e| StorageError::WriteVersion(version_file.clone(), e))?;
 
-        let committee_file = base.join("committee.json");
+        let committee_file = committee_file_path(&base);
 
         // if the committee was specified in the generation params, use that
         if let (
@@ -434,7 +610,9 @@ impl Document {
                 genesis:
                     Some(GenesisGeneration {
                         private_key,
-                        balances: GenesisBalances::Defined { bonded_balances },
+                        balances:
+                            genesis_balances @ (GenesisBalances::Defined { .. }
+                            | GenesisBalances::File { .. }),
                         ..
                     }),
                 ..
@@ -443,9 +621,11 @@ impl Document {
         ) = (self.generate.as_ref(), committee_file.exists())
         {
             // TODO: should be possible to get committee from genesis blocks
-            let mut balances: IndexMap<_, _> = bonded_balances
-                .iter()
-                .map(|(addr, bal)| (addr.clone(), (String::new(), *bal)))
+            let mut balances: IndexMap<_, _> = genesis_balances
+                .resolve_bonded_balances(state.cli.include_dir.as_deref())
+                .await?
+                .into_iter()
+                .map(|(addr, bal)| (addr, (String::new(), bal)))
                 .collect();
 
             // derive the committee member 0's key
@@ -458,7 +638,7 @@ impl Document {
                 .await
                 .map_err(|e| StorageError::WriteCommittee(committee_file.clone(), e))?;
         };
-        // otherwise read the committee from the committee.json file
+        // otherwise read the committee from the committee.json or committee.yaml file
         let committee = read_to_addrs(pick_commitee_addr, &committee_file).await?;
 
         let storage = Arc::new(LoadedStorage {
@@ -471,6 +651,9 @@ impl Document {
             persist: self.persist,
             native_genesis,
             binaries,
+            ledger_url: self.ledger_url,
+            generation: self.generate,
+            connect: self.connect,
         });
         if let Err(e) = state
             .db
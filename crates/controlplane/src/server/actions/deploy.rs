@@ -12,7 +12,10 @@ use snops_common::{
     state::{Authorization, KeyState, id_or_none},
 };
 
-use super::{Env, execute::execute_status};
+use super::{
+    Env,
+    execute::{execute_status, resolve_priority_fee},
+};
 use crate::{
     cannon::{error::AuthorizeError, router::AuthQuery},
     env::{Environment, error::ExecutionError},
@@ -94,8 +97,17 @@ pub async fn deploy_inner(
     };
 
     let compute_bin = env.storage.resolve_compute_binary(state).await?;
-    // authorize the transaction
     let aot = AotCmd::new(compute_bin, env.network);
+    let priority_fee = resolve_priority_fee(
+        env,
+        &aot,
+        query.as_ref(),
+        resolved_fee_pk.as_ref().unwrap_or(&resolved_pk),
+        priority_fee,
+    )
+    .await?;
+
+    // authorize the transaction
     let mut auth_str = aot
         .authorize_deploy(
             &resolved_pk,
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use axum::{
     Json,
@@ -8,7 +8,7 @@ use axum::{
 use http::StatusCode;
 use serde_json::json;
 use snops_common::{
-    action_models::{AleoValue, ExecuteAction},
+    action_models::{AleoValue, ExecuteAction, PriorityFee},
     aot_cmds::AotCmd,
     events::{Event, EventKind},
     state::{Authorization, KeyState, id_or_none},
@@ -21,7 +21,7 @@ use crate::{
     env::{Environment, error::ExecutionError},
     events::EventSubscriber,
     server::error::{ActionError, ServerError},
-    state::GlobalState,
+    state::{GlobalState, REST_CLIENT},
 };
 
 pub async fn execute_status(
@@ -30,7 +30,7 @@ pub async fn execute_status(
 ) -> Result<Json<serde_json::Value>, ActionError> {
     use snops_common::events::TransactionEvent::*;
 
-    let mut timeout = Box::pin(tokio::time::sleep(std::time::Duration::from_secs(30)));
+    let mut timeout = Box::pin(tokio::time::sleep(Duration::from_secs(30)));
     let mut agent_id = None;
     let mut retries = 0;
 
@@ -109,6 +109,68 @@ pub async fn execute(
     }
 }
 
+/// Timeout for looking up the fee payer's balance
+const BALANCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Resolve a priority fee. `auto` fees are a fraction of the fee payer's
+/// public balance, which is looked up through the cannon's query path.
+pub async fn resolve_priority_fee(
+    env: &Environment,
+    aot: &AotCmd,
+    query: Option<&String>,
+    fee_pk: &str,
+    priority_fee: Option<PriorityFee>,
+) -> Result<Option<u64>, AuthorizeError> {
+    let fee = match priority_fee {
+        None => return Ok(None),
+        Some(PriorityFee::Amount(amount)) => return Ok(Some(amount)),
+        Some(fee) => fee,
+    };
+
+    // the fee payer may be a storage account or a literal key, so the address
+    // is derived from the resolved key. aot's error is dropped, as it echoes
+    // invalid keys back
+    let Ok(addr) = aot.address(fee_pk).await else {
+        return Err(AuthorizeError::PriorityFee(
+            "fee payer has an invalid private key".to_owned(),
+        ));
+    };
+    let Some(query) = query else {
+        return Err(AuthorizeError::PriorityFee(
+            "cannon has no query path".to_owned(),
+        ));
+    };
+
+    let url = format!(
+        "{query}/{}/program/credits.aleo/mapping/account/{addr}",
+        env.network
+    );
+    let balance = REST_CLIENT
+        .get(&url)
+        .timeout(BALANCE_TIMEOUT)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| AuthorizeError::PriorityFee(format!("fetch balance of {addr}: {e}")))?
+        .json::<Option<String>>()
+        .await
+        .map_err(|e| AuthorizeError::PriorityFee(format!("parse balance of {addr}: {e}")))?;
+
+    let balance = match balance {
+        None => 0,
+        Some(value) => value
+            .strip_suffix("u64")
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| {
+                AuthorizeError::PriorityFee(format!("unexpected balance '{value}' for {addr}"))
+            })?,
+    };
+
+    let resolved = fee.resolve(balance);
+    tracing::debug!("resolved priority fee {fee} to {resolved} from balance {balance} of {addr}");
+    Ok(Some(resolved))
+}
+
 pub async fn execute_inner(
     state: &GlobalState,
     action: ExecuteAction,
@@ -168,9 +230,18 @@ pub async fn execute_inner(
         })
         .collect::<Result<Vec<String>, AuthorizeError>>()?;
 
-    // authorize the transaction
     let compute_bin = env.storage.resolve_compute_binary(state).await?;
     let aot = AotCmd::new(compute_bin, env.network);
+    let priority_fee = resolve_priority_fee(
+        env,
+        &aot,
+        query.as_ref(),
+        resolved_fee_pk.as_ref().unwrap_or(&resolved_pk),
+        priority_fee,
+    )
+    .await?;
+
+    // authorize the transaction
     let mut auth_str = aot
         .authorize_program(
             &resolved_pk,
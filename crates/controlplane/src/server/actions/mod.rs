@@ -40,7 +40,6 @@ struct CommonParams {
 #[derive(Clone)]
 pub struct Env {
     env: Arc<Environment>,
-    #[allow(dead_code)]
     env_id: EnvId,
     state: AppState,
 }
@@ -95,6 +94,8 @@ pub(super) fn routes() -> Router<AppState> {
         .route("/online", post(power::online))
         .route("/offline", post(power::offline))
         .route("/reboot", post(power::reboot))
+        .route("/freeze", post(power::freeze))
+        .route("/thaw", post(power::thaw))
         .route("/config", post(config::config))
         .route("/execute", post(execute::execute))
         .route("/deploy", post(deploy::deploy))
@@ -4,15 +4,20 @@ use axum::{
     Json,
     response::{IntoResponse, Response},
 };
+use futures_util::future::join_all;
 use snops_common::{
     action_models::WithTargets,
     node_targets::NodeTargets,
-    state::{AgentId, AgentState, EnvId, ReconcileOptions},
+    state::{AgentId, AgentState, EnvId, HeightRequest, ReconcileOptions},
 };
-use tracing::info;
+use tracing::{error, info};
 
 use super::Env;
-use crate::state::{GlobalState, pending_reconcile_node_map};
+use crate::{
+    persist::PersistEnv,
+    server::error::{ActionError, ServerError},
+    state::{GlobalState, pending_reconcile_node_map},
+};
 
 async fn wait_for_nodes(
     state: &GlobalState,
@@ -20,8 +25,20 @@ async fn wait_for_nodes(
     nodes: NodeTargets,
     pending: Vec<(AgentId, AgentState)>,
 ) -> Response {
-    let mut awaiting_agents = pending.iter().map(|a| a.0).collect::<HashSet<_>>();
     let node_map = pending_reconcile_node_map(pending.iter());
+    wait_for_reconciles(state, env_id, nodes, pending).await;
+    Json(node_map).into_response()
+}
+
+/// Update the states of agents and wait at most 30 seconds for them to
+/// reconcile. Returns the agents that did not reconcile in time.
+async fn wait_for_reconciles(
+    state: &GlobalState,
+    env_id: EnvId,
+    nodes: NodeTargets,
+    pending: Vec<(AgentId, AgentState)>,
+) -> HashSet<AgentId> {
+    let mut awaiting_agents = pending.iter().map(|a| a.0).collect::<HashSet<_>>();
 
     // create the subscriber before updating agent states in order to
     // avoid missing any events
@@ -47,7 +64,7 @@ async fn wait_for_nodes(
         }
     }
 
-    Json(node_map).into_response()
+    awaiting_agents
 }
 
 pub async fn online(
@@ -135,3 +152,121 @@ pub async fn reboot(
 
     Json(node_map).into_response()
 }
+
+/// Take every node of the environment offline and record the heights they
+/// stopped at. Fails for the nodes that did not go offline, or whose height
+/// could not be read.
+pub async fn freeze(Env { env_id, state, .. }: Env) -> Response {
+    // the env is locked for the whole action so a change made while waiting
+    // for the nodes is not overwritten when the frozen heights are saved
+    let _lock = state.lock_env(env_id).await;
+    let Some(env) = state.get_env(env_id) else {
+        return ServerError::NotFound("environment not found".to_owned()).into_response();
+    };
+
+    info!("env {env_id} invoked freeze action");
+    let pending = env
+        .matching_agents(&NodeTargets::ALL, &state.pool)
+        .filter_map(|a| {
+            a.value().filter_map_to_reconcile(|mut s| {
+                s.online.then(|| {
+                    s.online = false;
+                    s
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let unreconciled = wait_for_reconciles(&state, env_id, NodeTargets::ALL, pending).await;
+
+    let nodes = env
+        .matching_agents(&NodeTargets::ALL, &state.pool)
+        .filter_map(|a| Some((a.node_key()?.clone(), a.id, a.client_owned())))
+        .collect::<Vec<_>>();
+
+    // the block info in an agent's status lags behind its node, so the height
+    // is read from the ledger once the node has stopped
+    let unreconciled = &unreconciled;
+    let results = join_all(nodes.into_iter().map(|(key, agent_id, client)| async move {
+        if unreconciled.contains(&agent_id) {
+            return (key, Err("node did not go offline".to_owned()));
+        }
+        let Some(client) = client else {
+            return (key, Err("agent is not connected".to_owned()));
+        };
+        let res = match client.exec_aot(vec!["height".to_owned()]).await {
+            Ok(output) if output.status == Some(0) => output
+                .stdout
+                .trim()
+                .parse::<u32>()
+                .map_err(|e| format!("invalid height output: {e}")),
+            Ok(output) => Err(output.stderr.trim().to_owned()),
+            Err(e) => Err(e.to_string()),
+        };
+        (key, res)
+    }))
+    .await;
+
+    env.frozen_heights.clear();
+    let mut errors = HashMap::new();
+    for (key, height) in results {
+        match height {
+            Ok(height) => {
+                env.frozen_heights.insert(key, height);
+            }
+            Err(e) => {
+                errors.insert(key, e);
+            }
+        }
+    }
+
+    if let Err(e) = state.db.envs.save(&env_id, &PersistEnv::from(env.as_ref())) {
+        error!("failed to save env {env_id} to persistence: {e}");
+    }
+
+    if !errors.is_empty() {
+        return ActionError::FreezeFailed { errors }.into_response();
+    }
+
+    let heights = env
+        .frozen_heights
+        .iter()
+        .map(|entry| (entry.key().clone(), *entry.value()))
+        .collect::<HashMap<_, _>>();
+    Json(heights).into_response()
+}
+
+/// Bring the nodes of a frozen environment back online at the heights they
+/// were frozen at, rewinding any node that got further
+pub async fn thaw(Env { env_id, state, .. }: Env) -> Response {
+    let _lock = state.lock_env(env_id).await;
+    let Some(env) = state.get_env(env_id) else {
+        return ServerError::NotFound("environment not found".to_owned()).into_response();
+    };
+
+    if env.frozen_heights.is_empty() {
+        return ServerError::BadRequest("environment is not frozen".to_owned()).into_response();
+    }
+
+    info!("env {env_id} invoked thaw action");
+    let pending = env
+        .matching_agents(&NodeTargets::ALL, &state.pool)
+        .filter_map(|a| {
+            let height = env.frozen_heights.get(a.node_key()?).map(|h| *h);
+            a.value().filter_map_to_reconcile(|mut s| {
+                s.online = true;
+                if let Some(height) = height {
+                    s.height = (s.height.0 + 1, HeightRequest::Absolute(height));
+                }
+                Some(s)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    env.frozen_heights.clear();
+    if let Err(e) = state.db.envs.save(&env_id, &PersistEnv::from(env.as_ref())) {
+        error!("failed to save env {env_id} to persistence: {e}");
+    }
+
+    wait_for_nodes(&state, env_id, NodeTargets::ALL, pending).await
+}
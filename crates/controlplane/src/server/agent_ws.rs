@@ -17,9 +17,12 @@ use snops_common::events::AgentEvent;
 use snops_common::{
     constant::HEADER_AGENT_KEY,
     prelude::*,
-    rpc::control::{
-        ControlService,
-        agent::{AgentServiceClient, Handshake},
+    rpc::{
+        codec::{CodecError, PROTOCOL_VERSION},
+        control::{
+            ControlService,
+            agent::{AgentServiceClient, Handshake},
+        },
     },
 };
 use tarpc::{context, server::Channel};
@@ -159,13 +162,17 @@ async fn handle_socket(
                 match agent.env() {
                     Some(env) if !state.envs.contains_key(&env) => {
                         info!("setting agent {id} to Inventory state due to missing env {env}");
-                        agent.set_state(AgentState::Inventory);
+                        let reason = InventoryReason::EnvMissing(env);
+                        AgentEvent::Inventoried { reason }
+                            .with_agent(&agent)
+                            .emit(&state);
+                        agent.set_inventory(reason);
                     }
                     _ => {}
                 }
 
                 // attach the current known agent state to the handshake
-                agent.state().clone_into(&mut handshake.state);
+                agent.state().clone_into(&mut handshake.state.0);
 
                 // mark the agent as connected, update the flags as well
                 agent.mark_connected(client.clone(), query.flags);
@@ -305,6 +312,10 @@ async fn handle_socket(
                     Some(Ok(Message::Binary(bin))) => {
                         let msg = match snops_common::rpc::codec::decode(&bin) {
                             Ok(msg) => msg,
+                            Err(CodecError::VersionMismatch { received }) => {
+                                error!("Agent {id} protocol v{received}, control plane expects v{PROTOCOL_VERSION} - please update the agent");
+                                break;
+                            }
                             Err(e) => {
                                 error!("Agent {id} failed to deserialize a message: {e}");
                                 break;
@@ -1,32 +1,60 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, str::FromStr, time::Duration};
 
 use axum::{
     Json, Router,
-    extract::{self, Path, Query, State},
-    http::StatusCode,
+    extract::{self, Path, Query, Request, State},
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
     routing::{delete, get, post},
 };
-use indexmap::IndexSet;
+use futures_util::future::join_all;
+use indexmap::{IndexMap, IndexSet};
 use serde::Deserialize;
 use serde_json::json;
+use snops_checkpoint::CheckpointManager;
 use snops_common::{
+    INTERN,
+    api::LedgerChecksum,
+    constant::{HEADER_ENV_UNCHANGED, LEDGER_BASE_DIR},
     key_source::KeySource,
     lasso::Spur,
     node_targets::NodeTargets,
     rpc::control::agent::AgentMetric,
-    state::{AgentModeOptions, AgentState, CannonId, EnvId, KeyState, NodeKey, id_or_none},
+    state::{
+        AgentId, AgentModeOptions, AgentState, CannonId, EnvId, InternedId, KeyState, NetworkId,
+        NodeKey, ReconcileOptions, ReconcileStatus, id_or_none,
+    },
 };
 use tarpc::context;
-
-use super::{actions, error::ServerError, event_ws, models::AgentStatusResponse};
+use tower::Service;
+use tower_http::services::ServeFile;
+
+use super::{
+    actions,
+    error::{ActionError, ServerError},
+    event_ws, metrics_ws,
+    models::{
+        AgentStatusResponse, AgentSummaryResponse, EnvSummaryResponse, LedgerChecksumResponse,
+        StorageInspectResponse,
+    },
+};
 use crate::{
-    cannon::{router::redirect_cannon_routes, source::QueryTarget},
+    cannon::{
+        router::{AuthQuery, redirect_cannon_routes},
+        sink::TxSink,
+        source::{QueryTarget, TxSource},
+    },
+    events::EventSubscriber,
     make_env_filter,
-    state::AppState,
+    state::{AppState, TransactionRecorder, scope_query_to_env},
 };
 use crate::{
-    env::{EnvPeer, Environment},
+    env::{EnvNodeState, EnvPeer, Environment},
+    schema::{
+        ItemDocument,
+        error::SchemaError,
+        include::{SpecLocation, resolve_includes},
+    },
     state::AgentFlags,
 };
 
@@ -52,34 +80,55 @@ macro_rules! unwrap_or_bad_request {
 
 pub(super) fn routes() -> Router<AppState> {
     Router::new()
+        .route("/health", get(get_health))
         .route("/events", get(event_ws::event_ws_handler))
         .route("/log/:level", post(set_log_level))
         .route("/agents", get(get_agents))
         .route("/agents/:id", get(get_agent))
         .route("/agents/:id/status", get(get_agent_status))
+        .route("/agents/:id/cmdline", get(get_agent_cmdline))
         .route("/agents/:id/kill", post(kill_agent))
+        .route("/agents/:id/reconcile", post(reconcile_agent))
         .route("/agents/:id/tps", get(get_agent_tps))
+        .route("/agents/:id/ping_rtt", get(get_agent_ping_rtt))
+        .route("/agents/:id/loki_buffered", get(get_agent_loki_buffered))
+        .route(
+            "/agents/:id/metrics/stream",
+            get(metrics_ws::agent_metrics_ws_handler),
+        )
         .route("/agents/:id/log/:level", post(set_agent_log_level))
         .route("/agents/:id/aot/log/:verbosity", post(set_aot_log_level))
         .route("/agents/find", post(find_agents))
+        .route("/agents/resolve", get(resolve_agent_id))
+        .route("/storage/:network/:id", get(get_storage))
         .route("/env/list", get(get_env_list))
+        .route("/env/resolve", get(resolve_env_id))
         .route("/env/:env_id/topology", get(get_env_topology))
         .route(
             "/env/:env_id/topology/resolved",
             get(get_env_topology_resolved),
         )
+        .route("/env/:env_id/topology/graph", get(get_env_topology_graph))
         .route("/env/:env_id/agents", get(get_env_agents))
         .route(
             "/env/:env_id/agents/:node_ty/:node_key",
             get(get_env_agent_key),
         )
+        .route(
+            "/env/:env_id/agents/:node_ty/:node_key/exec",
+            post(post_env_agent_exec),
+        )
         // .route(
         //     "/env/:env_id/agents/:node_ty/:node_key/action/status",
         //     get(get_env_agent_key),
         // )
-        // .route("/env/:env_id/metric/:prom_ql", get())
+        .route("/env/:env_id/ledger/checksum", get(get_env_ledger_checksum))
+        .route("/env/:env_id/metric/:prom_ql", get(get_env_metric))
         .route("/env/:env_id/apply", post(post_env_apply))
+        .route("/env/:env_id/diff", post(post_env_diff))
+        .route("/env/:env_id/scale", post(post_env_scale))
         .route("/env/:env_id/info", get(get_env_info))
+        .route("/env/:env_id/export", get(get_env_export))
         .route("/env/:env_id/height", get(get_latest_height))
         .route("/env/:env_id/block_info", get(get_env_block_info))
         .route("/env/:env_id/balance/:key", get(get_env_balance))
@@ -95,11 +144,51 @@ pub(super) fn routes() -> Router<AppState> {
             get(get_mapping_value),
         )
         .route("/env/:env_id/program/:program/mappings", get(get_mappings))
+        .route(
+            "/env/:env_id/cannons/:cannon",
+            post(post_env_cannon).delete(delete_env_cannon),
+        )
+        .route(
+            "/env/:env_id/cannons/:cannon/restart",
+            post(post_env_cannon_restart),
+        )
         .nest("/env/:env_id/cannons", redirect_cannon_routes())
+        .route(
+            "/env/:env_id/transactions/record",
+            get(get_env_tx_record)
+                .post(post_env_tx_record)
+                .delete(delete_env_tx_record),
+        )
         .route("/env/:id", delete(delete_env))
         .nest("/env/:env_id/action", actions::routes())
 }
 
+async fn get_health(State(state): State<AppState>) -> Response {
+    Json(json!({
+        "prometheus": state.prometheus.as_ref().map(|p| p.status()),
+    }))
+    .into_response()
+}
+
+/// Query a single value from prometheus
+async fn get_env_metric(
+    Path((env_id, prom_ql)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Response {
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    unwrap_or_not_found!("environment not found", state.get_env(env_id));
+    let prometheus = unwrap_or_not_found!("prometheus not configured", state.prometheus.as_ref());
+    let prom_ql = match scope_query_to_env(&prom_ql, env_id) {
+        Ok(prom_ql) => prom_ql,
+        Err(e) => return ServerError::from(e).into_response(),
+    };
+
+    match prometheus.query_value(&prom_ql).await {
+        Ok(value) => Json(value).into_response(),
+        Err(e) => ServerError::from(e).into_response(),
+    }
+}
+
 async fn set_agent_log_level(
     state: State<AppState>,
     Path((id, level)): Path<(String, String)>,
@@ -143,6 +232,38 @@ async fn set_aot_log_level(
     ServerError::from(e).into_response()
 }
 
+async fn get_storage(
+    Path((network, id)): Path<(NetworkId, String)>,
+    state: State<AppState>,
+) -> Response {
+    let id = unwrap_or_not_found!("unknown storage id", id_or_none(&id));
+    let storage = unwrap_or_not_found!("storage not found", state.storage.get(&(network, id)));
+
+    // checkpoints are stored alongside the ledger directory
+    let checkpoints = CheckpointManager::load(
+        storage.path(&state).join(LEDGER_BASE_DIR),
+        storage.retention_policy.clone().unwrap_or_default(),
+    )
+    .map(|manager| manager.checkpoints().count())
+    .unwrap_or_else(|e| {
+        tracing::warn!("failed to load checkpoints for storage {network}/{id}: {e}");
+        0
+    });
+
+    Json(StorageInspectResponse {
+        info: storage.info(),
+        network,
+        committee: storage.committee.len(),
+        accounts: storage
+            .accounts
+            .iter()
+            .map(|(name, keys)| (*name, keys.len()))
+            .collect(),
+        checkpoints,
+    })
+    .into_response()
+}
+
 async fn set_log_level(Path(level): Path<String>, state: State<AppState>) -> Response {
     tracing::debug!("attempting to set log level to {level}");
     let Ok(level) = level.parse() else {
@@ -166,6 +287,16 @@ async fn get_env_info(Path(env_id): Path<String>, state: State<AppState>) -> Res
     Json(env.info(&state)).into_response()
 }
 
+async fn get_env_export(Path(env_id): Path<String>, state: State<AppState>) -> Response {
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    let env = unwrap_or_not_found!("environment not found", state.get_env(env_id));
+
+    match env.export() {
+        Ok(spec) => ([(header::CONTENT_TYPE, "application/yaml")], spec).into_response(),
+        Err(e) => ServerError::Export(e).into_response(),
+    }
+}
+
 async fn get_latest_height(Path(env_id): Path<String>, state: State<AppState>) -> Response {
     let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
     let env = unwrap_or_not_found!("environment not found", state.get_env(env_id));
@@ -330,11 +461,56 @@ async fn get_tx(
     }
 }
 
-async fn get_agents(state: State<AppState>) -> impl IntoResponse {
-    let agents = state
+#[derive(Deserialize)]
+struct AgentListQuery {
+    /// Only list agents with this connection status
+    online: Option<bool>,
+    /// Comma separated labels every listed agent must have
+    labels: Option<String>,
+    /// Only list agents in this environment
+    env: Option<String>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+async fn get_agents(state: State<AppState>, Query(query): Query<AgentListQuery>) -> Response {
+    let env = match query.env.as_deref() {
+        Some(env) => Some(unwrap_or_not_found!("unknown env id", id_or_none(env))),
+        None => None,
+    };
+
+    // a label that was never interned cannot belong to any agent
+    let Some(labels) = query
+        .labels
+        .iter()
+        .flat_map(|l| l.split(','))
+        .filter(|l| !l.is_empty())
+        .map(|l| INTERN.get(l))
+        .collect::<Option<IndexSet<Spur>>>()
+    else {
+        return Json(Vec::<AgentSummaryResponse>::new()).into_response();
+    };
+
+    let mut agents = state
         .pool
         .iter()
-        .map(|agent| AgentStatusResponse::from(agent.value()))
+        .filter(|agent| {
+            query
+                .online
+                .is_none_or(|online| agent.is_connected() == online)
+                && env.is_none_or(|env| agent.env() == Some(env))
+                && agent.has_labels(&labels)
+        })
+        .map(|agent| AgentSummaryResponse::from(agent.value()))
+        .collect::<Vec<_>>();
+
+    // sort so pages are stable between requests
+    agents.sort_unstable_by_key(|agent| agent.agent_id);
+    let agents = agents
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
         .collect::<Vec<_>>();
 
     Json(agents).into_response()
@@ -365,6 +541,21 @@ async fn get_agent_status(state: State<AppState>, Path(id): Path<String>) -> Res
     }
 }
 
+async fn get_agent_cmdline(state: State<AppState>, Path(id): Path<String>) -> Response {
+    let id = unwrap_or_not_found!("unknown agent id", id_or_none(&id));
+    let agent = unwrap_or_not_found!("agent not found", state.pool.get(&id));
+
+    let Some(rpc) = agent.rpc() else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+
+    match rpc.get_node_command(tarpc::context::current()).await {
+        Ok(Some(cmdline)) => Json(cmdline).into_response(),
+        Ok(None) => ServerError::NotFound("agent is not running a node".to_owned()).into_response(),
+        Err(_e) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
 async fn kill_agent(state: State<AppState>, Path(id): Path<String>) -> Response {
     let id = unwrap_or_not_found!("unknown agent id", id_or_none(&id));
     let client = unwrap_or_not_found!(
@@ -384,6 +575,100 @@ async fn kill_agent(state: State<AppState>, Path(id): Path<String>) -> Response
     Json("ok").into_response()
 }
 
+#[derive(Default, Deserialize)]
+struct ReconcileAgentBody {
+    /// Fetch the latest env info before reconciling
+    #[serde(default)]
+    refetch_info: bool,
+    /// Clear the node's last known height before reconciling
+    #[serde(default)]
+    clear_last_height: bool,
+}
+
+/// Time to wait for the outcome of an agent's reconcile, after the request or
+/// after the reconcile requeues
+const RECONCILE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Send an agent its current target state, for when its on-disk state has
+/// drifted from what the control plane expects
+async fn reconcile_agent(
+    state: State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<AuthQuery>,
+    body: Option<Json<ReconcileAgentBody>>,
+) -> Response {
+    let id = unwrap_or_not_found!("unknown agent id", id_or_none(&id));
+    unwrap_or_not_found!(
+        "agent not found",
+        state.pool.get(&id).and_then(|a| a.client_owned())
+    );
+    let body = body.map(|Json(body)| body).unwrap_or_default();
+
+    let opts = ReconcileOptions {
+        refetch_info: body.refetch_info,
+        clear_last_height: body.clear_last_height,
+        ..Default::default()
+    };
+
+    if query.is_async() {
+        state.queue_many_reconciles([id], opts).await;
+        return (StatusCode::ACCEPTED, Json("ok")).into_response();
+    }
+
+    // create the subscriber before requesting the reconcile in order to
+    // avoid missing any events. The agent does not report the outcome of a
+    // reconcile that was in flight when the request arrived.
+    use snops_common::events::prelude::*;
+    let subscriber = state.events.subscribe_on(
+        AgentIs(id) & (AgentReconcile | AgentReconcileComplete | AgentReconcileError),
+    );
+
+    state.queue_many_reconciles([id], opts).await;
+    reconcile_status(id, subscriber).await.into_response()
+}
+
+/// Wait for the outcome of an agent's reconcile. The wait is extended each
+/// time the reconcile requeues, so a long download does not time out.
+async fn reconcile_status(
+    agent_id: AgentId,
+    mut rx: EventSubscriber,
+) -> Result<Json<serde_json::Value>, ActionError> {
+    use snops_common::events::{AgentEvent, Event, EventKind};
+
+    let mut timeout = Box::pin(tokio::time::sleep(RECONCILE_TIMEOUT));
+
+    loop {
+        tokio::select! {
+            _ = &mut timeout => {
+                return Err(ActionError::ReconcileTimeout { agent_id: agent_id.to_string() });
+            },
+            Ok(ev) = rx.next() => {
+                let Event { content: EventKind::Agent(ev), .. } = ev.as_ref() else {
+                    continue;
+                };
+
+                match ev {
+                    AgentEvent::ReconcileComplete => {
+                        return Ok(Json(json!({ "agent_id": agent_id, "status": "complete" })));
+                    }
+                    AgentEvent::ReconcileError(reason) => {
+                        return Err(ActionError::ReconcileFailed {
+                            agent_id: agent_id.to_string(),
+                            reason: reason.clone(),
+                        });
+                    }
+                    AgentEvent::Reconcile(ReconcileStatus { requeue_after: Some(requeue_after), .. }) => {
+                        timeout
+                            .as_mut()
+                            .reset(tokio::time::Instant::now() + *requeue_after + RECONCILE_TIMEOUT);
+                    }
+                    _ => (),
+                }
+            },
+        }
+    }
+}
+
 async fn get_agent_tps(state: State<AppState>, Path(id): Path<String>) -> Response {
     let id = unwrap_or_not_found!("unknown agent id", id_or_none(&id));
     let agent = unwrap_or_not_found!("agent not found", state.pool.get(&id));
@@ -402,6 +687,42 @@ async fn get_agent_tps(state: State<AppState>, Path(id): Path<String>) -> Respon
     }
 }
 
+async fn get_agent_ping_rtt(state: State<AppState>, Path(id): Path<String>) -> Response {
+    let id = unwrap_or_not_found!("unknown agent id", id_or_none(&id));
+    let agent = unwrap_or_not_found!("agent not found", state.pool.get(&id));
+
+    let Some(rpc) = agent.client_owned() else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+
+    match rpc
+        .0
+        .get_metric(tarpc::context::current(), AgentMetric::PingRtt)
+        .await
+    {
+        Ok(rtt) => rtt.to_string().into_response(),
+        Err(_e) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+async fn get_agent_loki_buffered(state: State<AppState>, Path(id): Path<String>) -> Response {
+    let id = unwrap_or_not_found!("unknown agent id", id_or_none(&id));
+    let agent = unwrap_or_not_found!("agent not found", state.pool.get(&id));
+
+    let Some(rpc) = agent.client_owned() else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+
+    match rpc
+        .0
+        .get_metric(tarpc::context::current(), AgentMetric::LokiBuffered)
+        .await
+    {
+        Ok(lines) => lines.to_string().into_response(),
+        Err(_e) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
 async fn get_program(
     Path((env_id, program)): Path<(String, String)>,
     state: State<AppState>,
@@ -547,8 +868,71 @@ async fn find_agents(
     Json(agents).into_response()
 }
 
-async fn get_env_list(State(state): State<AppState>) -> Response {
-    Json(state.envs.iter().map(|e| e.id).collect::<Vec<_>>()).into_response()
+#[derive(Deserialize)]
+struct EnvListQuery {
+    /// List a summary of each env instead of only its id
+    #[serde(default)]
+    summary: bool,
+}
+
+async fn get_env_list(
+    Query(query): Query<EnvListQuery>,
+    State(state): State<AppState>,
+) -> Response {
+    if !query.summary {
+        return Json(state.envs.iter().map(|e| e.id).collect::<Vec<_>>()).into_response();
+    }
+
+    let mut envs = state
+        .envs
+        .iter()
+        .map(|env| EnvSummaryResponse {
+            id: env.id,
+            network: env.network,
+            storage_id: env.storage.id,
+            node_count: env.node_peers.len(),
+            agent_count: env
+                .node_peers
+                .right_values()
+                .filter(|peer| matches!(peer, EnvPeer::Internal(_)))
+                .count(),
+            // the cached block info avoids querying a node per env
+            block_height: state.get_env_block_info(env.id).map(|info| info.height),
+        })
+        .collect::<Vec<_>>();
+    envs.sort_unstable_by_key(|env| env.id);
+
+    Json(envs).into_response()
+}
+
+#[derive(Deserialize)]
+struct ResolveIdQuery {
+    /// A partial or mistyped id
+    id: String,
+}
+
+/// List the env ids a partial env id could refer to, best match first
+async fn resolve_env_id(
+    Query(query): Query<ResolveIdQuery>,
+    State(state): State<AppState>,
+) -> Response {
+    Json(InternedId::fuzzy_candidates(
+        &query.id,
+        state.envs.iter().map(|env| env.id),
+    ))
+    .into_response()
+}
+
+/// List the agent ids a partial agent id could refer to, best match first
+async fn resolve_agent_id(
+    Query(query): Query<ResolveIdQuery>,
+    State(state): State<AppState>,
+) -> Response {
+    Json(InternedId::fuzzy_candidates(
+        &query.id,
+        state.pool.iter().map(|agent| agent.id()),
+    ))
+    .into_response()
 }
 
 async fn get_env_topology(Path(env_id): Path<String>, State(state): State<AppState>) -> Response {
@@ -564,9 +948,14 @@ async fn get_env_topology(Path(env_id): Path<String>, State(state): State<AppSta
         };
         match peer {
             EnvPeer::Internal(id) => {
-                internal.insert(*id, node_state);
+                internal.insert(*id, node_state.clone());
             }
             EnvPeer::External(ip) => {
+                // REST header values are secrets
+                let node_state = match node_state.value() {
+                    EnvNodeState::External(node) => EnvNodeState::External(node.redacted()),
+                    node_state => node_state.clone(),
+                };
                 external.insert(
                     nk.to_string(),
                     json!({"ip": ip.to_string(), "ports": node_state}),
@@ -604,6 +993,17 @@ async fn get_env_topology_resolved(
     Json(resolved).into_response()
 }
 
+/// Get a map of node keys to their configured peer and validator connections
+async fn get_env_topology_graph(
+    Path(env_id): Path<String>,
+    State(state): State<AppState>,
+) -> Response {
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    let env = unwrap_or_not_found!("environment not found", state.get_env(env_id));
+
+    Json(env.topology_graph()).into_response()
+}
+
 /// Get a map of node keys to agent ids
 async fn get_env_agents(Path(env_id): Path<String>, State(state): State<AppState>) -> Response {
     let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
@@ -639,24 +1039,333 @@ async fn get_env_agent_key(
     Json(AgentStatusResponse::from(agent.value())).into_response()
 }
 
+#[derive(Deserialize)]
+struct LedgerChecksumQuery {
+    /// The height of the last block to checksum. Defaults to the lowest latest
+    /// height reported by the nodes, so every node checksums the same blocks.
+    height: Option<u32>,
+}
+
+/// Compare the ledgers of an env's nodes by the checksums of their blocks
+async fn get_env_ledger_checksum(
+    Path(env_id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<LedgerChecksumQuery>,
+) -> Response {
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    let env = unwrap_or_not_found!("environment not found", state.get_env(env_id));
+
+    let mut heights = vec![];
+    let clients = env
+        .node_peers
+        .iter()
+        .filter_map(|(key, peer)| match peer {
+            EnvPeer::Internal(agent_id) => {
+                let agent = state.pool.get(agent_id);
+                let client = agent.as_ref().and_then(|agent| {
+                    heights.extend(agent.status.block_info.as_ref().map(|info| info.height));
+                    agent.client_owned()
+                });
+                Some((key.clone(), client))
+            }
+            EnvPeer::External(_) => None,
+        })
+        .collect::<Vec<_>>();
+    drop(env);
+
+    // nodes on a live chain are a few blocks apart, so they are compared at
+    // the lowest height they all have
+    let mut args = vec!["checksum".to_owned()];
+    if let Some(height) = query.height.or_else(|| heights.into_iter().min()) {
+        args.extend(["--to-height".to_owned(), height.to_string()]);
+    }
+
+    let results = join_all(clients.into_iter().map(|(key, client)| {
+        let args = args.clone();
+        async move {
+            let Some(client) = client else {
+                return (key, Err("agent is not connected".to_owned()));
+            };
+            let res = match client.exec_aot(args).await {
+                Ok(output) if output.status == Some(0) => {
+                    serde_json::from_str::<LedgerChecksum>(output.stdout.trim())
+                        .map_err(|e| format!("invalid checksum output: {e}"))
+                }
+                Ok(output) => Err(output.stderr.trim().to_owned()),
+                Err(e) => Err(e.to_string()),
+            };
+            (key, res)
+        }
+    }))
+    .await;
+
+    let mut res = LedgerChecksumResponse {
+        consistent: true,
+        checksums: IndexMap::new(),
+        errors: IndexMap::new(),
+    };
+    for (key, checksum) in results {
+        match checksum {
+            Ok(checksum) => {
+                res.consistent &= res.checksums.values().all(|c| *c == checksum);
+                res.checksums.insert(key, checksum);
+            }
+            Err(e) => {
+                res.errors.insert(key, e);
+            }
+        }
+    }
+    res.checksums
+        .sort_unstable_by(|a, _, b, _| a.to_string().cmp(&b.to_string()));
+    res.errors
+        .sort_unstable_by(|a, _, b, _| a.to_string().cmp(&b.to_string()));
+
+    Json(res).into_response()
+}
+
+/// Run a read-only aot ledger command against a node's ledger
+async fn post_env_agent_exec(
+    Path((env_id, node_type, node_key)): Path<(String, String, String)>,
+    State(state): State<AppState>,
+    Json(args): Json<Vec<String>>,
+) -> Response {
+    let node_key = unwrap_or_bad_request!(
+        "invalid node key",
+        NodeKey::from_str(&format!("{node_type}/{node_key}")).ok()
+    );
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    let env = unwrap_or_not_found!("environment not found", state.get_env(env_id));
+    let agent_id =
+        unwrap_or_not_found!("node found in environment", env.get_agent_by_key(&node_key));
+    let agent = unwrap_or_not_found!("agent not found", state.pool.get(&agent_id));
+
+    let Some(client) = agent.client_owned() else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    // don't hold the agent across the command
+    drop(agent);
+
+    match client.exec_aot(args).await {
+        Ok(output) => Json(output).into_response(),
+        Err(e) => ServerError::from(e).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct ApplyQuery {
+    /// Reconcile agents even when the spec is unchanged
+    #[serde(default)]
+    force: bool,
+    /// Defer restarting running nodes until they next go offline
+    #[serde(default)]
+    only_if_offline: bool,
+    /// Url of the spec, that its relative includes are resolved against
+    base: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SpecQuery {
+    /// Url of the spec, that its relative includes are resolved against
+    base: Option<String>,
+}
+
+/// Deserialize a spec and splice in the documents of the specs it includes.
+/// Returns the documents and the hash of the spec and its included specs.
+async fn resolve_spec(
+    state: &AppState,
+    body: &str,
+    base: Option<&str>,
+) -> Result<(Vec<ItemDocument>, u64), ServerError> {
+    let documents = Environment::deserialize(body)?;
+    let base = base
+        .map(SpecLocation::parse)
+        .transpose()
+        .map_err(SchemaError::from)?;
+
+    let mut contents = body.as_bytes().to_vec();
+    let documents = resolve_includes(
+        documents,
+        base,
+        state.cli.include_dir.as_deref(),
+        &mut contents,
+    )
+    .await
+    .map_err(SchemaError::from)?;
+
+    Ok((documents, Environment::hash_spec(&contents)))
+}
+
 async fn post_env_apply(
     // This env_id is allowed to be in the Path because it would be allocated
     // anyway
     Path(env_id): Path<EnvId>,
+    Query(query): Query<ApplyQuery>,
     State(state): State<AppState>,
     body: String,
 ) -> Response {
-    let documents = match Environment::deserialize(&body) {
-        Ok(documents) => documents,
-        Err(e) => return ServerError::from(e).into_response(),
+    let (documents, spec_hash) = match resolve_spec(&state, &body, query.base.as_deref()).await {
+        Ok(spec) => spec,
+        Err(e) => return e.into_response(),
+    };
+
+    // skip reconciling agents when the same spec is applied twice
+    if let (Some(node_map), false) = (
+        state
+            .get_env(env_id)
+            .and_then(|env| env.unchanged_node_map(&state, spec_hash)),
+        query.force,
+    ) {
+        tracing::info!("{env_id}: spec unchanged, skipping apply");
+        return ([(HEADER_ENV_UNCHANGED, "true")], Json(json!(node_map))).into_response();
+    }
+
+    match Environment::apply(env_id, documents, spec_hash, query.only_if_offline, state).await {
+        Ok(node_map) => Json(json!(node_map)).into_response(),
+        Err(e) => ServerError::from(e).into_response(),
+    }
+}
+
+async fn post_env_diff(
+    Path(env_id): Path<EnvId>,
+    Query(query): Query<SpecQuery>,
+    State(state): State<AppState>,
+    body: String,
+) -> Response {
+    let (documents, _) = match resolve_spec(&state, &body, query.base.as_deref()).await {
+        Ok(spec) => spec,
+        Err(e) => return e.into_response(),
     };
 
-    match Environment::apply(env_id, documents, state).await {
+    match Environment::diff(env_id, documents, &state) {
+        Ok(diff) => Json(diff).into_response(),
+        Err(e) => ServerError::from(e).into_response(),
+    }
+}
+
+async fn post_env_scale(
+    Path(env_id): Path<String>,
+    Query(query): Query<SpecQuery>,
+    State(state): State<AppState>,
+    body: String,
+) -> Response {
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    let (documents, _) = match resolve_spec(&state, &body, query.base.as_deref()).await {
+        Ok(spec) => spec,
+        Err(e) => return e.into_response(),
+    };
+
+    let mut nodes_docs = documents.into_iter().filter_map(|doc| match doc {
+        ItemDocument::Nodes(nodes) => Some(nodes),
+        _ => None,
+    });
+    let nodes = unwrap_or_bad_request!("missing nodes document", nodes_docs.next());
+    if nodes_docs.next().is_some() {
+        return ServerError::BadRequest("expected a single nodes document".to_owned())
+            .into_response();
+    }
+
+    match Environment::add_nodes(env_id, *nodes, state).await {
         Ok(node_map) => Json(json!(node_map)).into_response(),
         Err(e) => ServerError::from(e).into_response(),
     }
 }
 
+/// The source and sink of a cannon created at runtime
+#[derive(Deserialize)]
+struct CannonBody {
+    source: TxSource,
+    sink: TxSink,
+}
+
+async fn post_env_cannon(
+    Path((env_id, cannon_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    Json(body): Json<CannonBody>,
+) -> Response {
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    let cannon_id =
+        unwrap_or_bad_request!("invalid cannon id", CannonId::from_str(&cannon_id).ok());
+
+    match Environment::add_cannon(env_id, cannon_id, body.source, body.sink, state).await {
+        Ok(_) => status_ok(),
+        Err(e) => ServerError::from(e).into_response(),
+    }
+}
+
+async fn delete_env_cannon(
+    Path((env_id, cannon_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Response {
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    let cannon_id = unwrap_or_not_found!("unknown cannon id", id_or_none(&cannon_id));
+
+    match Environment::remove_cannon(env_id, cannon_id, &state).await {
+        Ok(_) => status_ok(),
+        Err(e) => ServerError::from(e).into_response(),
+    }
+}
+
+async fn post_env_cannon_restart(
+    Path((env_id, cannon_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Response {
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    let cannon_id = unwrap_or_not_found!("unknown cannon id", id_or_none(&cannon_id));
+
+    match Environment::restart_cannon(env_id, cannon_id, state).await {
+        Ok(_) => status_ok(),
+        Err(e) => ServerError::from(e).into_response(),
+    }
+}
+
+async fn post_env_tx_record(Path(env_id): Path<String>, State(state): State<AppState>) -> Response {
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    unwrap_or_not_found!("environment not found", state.get_env(env_id));
+
+    // recording an env that is already being recorded keeps the same file
+    if let Some(recorder) = state.tx_recorders.get(&env_id) {
+        return Json(json!(recorder.path())).into_response();
+    }
+
+    let path = TransactionRecorder::env_path(&state.cli, env_id);
+    match TransactionRecorder::open(&path) {
+        Ok(recorder) => {
+            state.tx_recorders.insert(env_id, recorder);
+            Json(json!(path)).into_response()
+        }
+        Err(e) => ServerError::TransactionRecord(path, e).into_response(),
+    }
+}
+
+async fn delete_env_tx_record(
+    Path(env_id): Path<String>,
+    State(state): State<AppState>,
+) -> Response {
+    let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
+    match state.tx_recorders.remove(&env_id) {
+        Some(_) => status_ok(),
+        None => ServerError::NotFound("environment is not recording transactions".to_owned())
+            .into_response(),
+    }
+}
+
+async fn get_env_tx_record(
+    Path(env_id): Path<String>,
+    State(state): State<AppState>,
+    req: Request,
+) -> Response {
+    let env_id = unwrap_or_bad_request!("invalid environment id", EnvId::from_str(&env_id).ok());
+
+    // the record outlives the env, so it can be downloaded after cleanup
+    let path = TransactionRecorder::env_path(&state.cli, env_id);
+    if !path.exists() {
+        return ServerError::NotFound("no transaction record for environment".to_owned())
+            .into_response();
+    }
+
+    ServeFile::new(path).call(req).await.into_response()
+}
+
 async fn delete_env(Path(env_id): Path<String>, State(state): State<AppState>) -> Response {
     let env_id = unwrap_or_not_found!("unknown environment id", id_or_none(&env_id));
 
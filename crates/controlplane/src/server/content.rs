@@ -1,17 +1,28 @@
-use std::str::FromStr;
+use std::{
+    path::{Path as StdPath, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::SystemTime,
+};
 
 use axum::{
     Router,
+    body::Body,
     extract::{Path, Request, State},
-    middleware,
+    middleware::{self, Next},
     response::{IntoResponse, Redirect, Response},
     routing::get,
 };
-use http::{StatusCode, Uri};
+use dashmap::DashMap;
+use futures_util::StreamExt;
+use http::{HeaderValue, Method, StatusCode, Uri, header};
+use lazy_static::lazy_static;
 use snops_common::{
     binaries::{BinaryEntry, BinarySource},
     state::{InternedId, NetworkId, id_or_none},
+    util::sha256_file,
 };
+use tokio::sync::Semaphore;
 use tower::Service;
 use tower_http::services::ServeFile;
 
@@ -21,10 +32,51 @@ use crate::{
         storage::{DEFAULT_AGENT_BINARY, DEFAULT_AOT_BINARY},
     },
     server::error::ServerError,
-    state::{AppState, GlobalState},
+    state::AppState,
     unwrap_or_bad_request, unwrap_or_not_found,
 };
 
+/// Seconds agents are asked to wait when all download slots are taken
+const DOWNLOAD_RETRY_AFTER_SECS: u64 = 5;
+
+lazy_static! {
+    /// Hashes of served binaries, along with the modification time and size
+    /// of the file when it was hashed
+    static ref BINARY_SHA256: DashMap<PathBuf, (SystemTime, u64, String)> = DashMap::new();
+}
+
+/// Limit the number of concurrent downloads, responding with a 503 and a
+/// `Retry-After` header when the limit is reached. `HEAD` requests have no
+/// body to stream and do not take a download slot.
+async fn limit_downloads(
+    State(limiter): State<Option<Arc<Semaphore>>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = limiter else {
+        return next.run(req).await;
+    };
+    if req.method() == Method::HEAD {
+        return next.run(req).await;
+    }
+
+    let Ok(permit) = limiter.try_acquire_owned() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, DOWNLOAD_RETRY_AFTER_SECS.to_string())],
+        )
+            .into_response();
+    };
+
+    // hold the permit until the body has been fully streamed
+    let (parts, body) = next.run(req).await.into_parts();
+    let body = Body::from_stream(body.into_data_stream().map(move |chunk| {
+        let _permit = &permit;
+        chunk
+    }));
+    Response::from_parts(parts, body)
+}
+
 async fn not_found(uri: Uri, res: Response) -> Response {
     match res.status() {
         StatusCode::NOT_FOUND => {
@@ -36,7 +88,7 @@ async fn not_found(uri: Uri, res: Response) -> Response {
     }
 }
 
-pub(super) async fn init_routes(state: &GlobalState) -> Router<AppState> {
+pub(super) async fn init_routes(state: &AppState) -> Router<AppState> {
     // create storage path
     let storage_path = state.cli.path.join("storage");
     tracing::debug!("storage path: {:?}", storage_path);
@@ -71,6 +123,10 @@ pub(super) async fn init_routes(state: &GlobalState) -> Router<AppState> {
             get(serve_binary).head(serve_binary),
         )
         .layer(middleware::map_response(not_found))
+        .route_layer(middleware::from_fn_with_state(
+            state.download_limiter.clone(),
+            limit_downloads,
+        ))
 }
 
 /// Serve a binary from the storage or a redirect to the binary
@@ -101,10 +157,46 @@ async fn respond_from_entry(id: InternedId, entry: &BinaryEntry, req: Request) -
         BinarySource::Path(file) if !file.exists() => {
             ServerError::from(StorageError::BinaryFileMissing(id, file.clone())).into_response()
         }
-        BinarySource::Path(file) => ServeFile::new(file).call(req).await.into_response(),
+        BinarySource::Path(file) => {
+            let sha256 = match &entry.sha256 {
+                Some(sha256) => Some(sha256.to_ascii_lowercase()),
+                None => binary_sha256(file).await,
+            };
+
+            let mut res = ServeFile::new(file).call(req).await.into_response();
+            // the etag lets agents skip downloads when only the mtime changed
+            if let Some(etag) = sha256.and_then(|s| HeaderValue::from_str(&format!("\"{s}\"")).ok())
+            {
+                res.headers_mut().insert(header::ETAG, etag);
+            }
+            res
+        }
     }
 }
 
+/// Get the sha256 of a binary, only re-hashing the file when its modification
+/// time or size changes
+async fn binary_sha256(file: &StdPath) -> Option<String> {
+    let meta = tokio::fs::metadata(file).await.ok()?;
+    let (modified, len) = (meta.modified().ok()?, meta.len());
+
+    if let Some(entry) = BINARY_SHA256.get(file) {
+        if entry.0 == modified && entry.1 == len {
+            return Some(entry.2.clone());
+        }
+    }
+
+    let path = file.to_path_buf();
+    let sha256 = tokio::task::spawn_blocking(move || sha256_file(&path))
+        .await
+        .ok()?
+        .inspect_err(|e| tracing::error!("failed to hash binary {}: {e}", file.display()))
+        .ok()?;
+
+    BINARY_SHA256.insert(file.to_path_buf(), (modified, len, sha256.clone()));
+    Some(sha256)
+}
+
 async fn serve_file(
     Path((network, storage_id, file)): Path<(NetworkId, String, String)>,
     State(state): State<AppState>,
@@ -139,3 +231,53 @@ async fn serve_file(
     // serve the file
     ServeFile::new(file_path).call(req).await.into_response()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited_router(limiter: Arc<Semaphore>) -> Router {
+        Router::new()
+            .route("/file", get(|| async { "content" }))
+            .route_layer(middleware::from_fn_with_state(
+                Some(limiter),
+                limit_downloads,
+            ))
+    }
+
+    async fn send(router: &mut Router, method: Method) -> Response {
+        let req = http::Request::builder()
+            .method(method)
+            .uri("/file")
+            .body(Body::empty())
+            .unwrap();
+        router.call(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_saturated_limiter_asks_to_retry() {
+        let limiter = Arc::new(Semaphore::new(1));
+        let mut router = limited_router(Arc::clone(&limiter));
+
+        // the permit is held until the first download's body is dropped
+        let first = send(&mut router, Method::GET).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(limiter.available_permits(), 0);
+
+        let busy = send(&mut router, Method::GET).await;
+        assert_eq!(busy.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            busy.headers().get(header::RETRY_AFTER).unwrap(),
+            &DOWNLOAD_RETRY_AFTER_SECS.to_string()
+        );
+
+        // HEAD requests are not limited
+        let head = send(&mut router, Method::HEAD).await;
+        assert_eq!(head.status(), StatusCode::OK);
+
+        drop(first);
+        assert_eq!(limiter.available_permits(), 1);
+        let next = send(&mut router, Method::GET).await;
+        assert_eq!(next.status(), StatusCode::OK);
+    }
+}
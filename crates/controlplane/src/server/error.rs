@@ -1,10 +1,12 @@
+use std::{collections::HashMap, net::IpAddr, path::PathBuf};
+
 use axum::{Json, response::IntoResponse};
 use http::StatusCode;
 use serde::{Serialize, Serializer, ser::SerializeStruct};
 use serde_json::json;
 use snops_common::{
     aot_cmds::AotCmdError, db::error::DatabaseError, events::TransactionAbortReason,
-    impl_into_status_code, impl_into_type_str,
+    impl_into_status_code, impl_into_type_str, rpc::error::ReconcileError, state::NodeKey,
 };
 use thiserror::Error;
 
@@ -12,6 +14,7 @@ use crate::{
     cannon::error::CannonError,
     env::error::{EnvError, EnvRequestError, ExecutionError},
     error::DeserializeError,
+    error::{PrometheusError, StateError},
     schema::error::{SchemaError, StorageError},
 };
 
@@ -45,6 +48,14 @@ pub enum ServerError {
     RpcError(#[from] tarpc::client::RpcError),
     #[error(transparent)]
     Storage(#[from] StorageError),
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error(transparent)]
+    Prometheus(#[from] PrometheusError),
+    #[error("failed to open transaction record `{0}`: {1}")]
+    TransactionRecord(PathBuf, #[source] std::io::Error),
+    #[error("failed to export env: {0}")]
+    Export(#[source] serde_yaml::Error),
 }
 
 impl_into_status_code!(ServerError, |value| match value {
@@ -56,12 +67,16 @@ impl_into_status_code!(ServerError, |value| match value {
     Schema(e) => e.into(),
     EnvRequest(e) => e.into(),
     Storage(e) => e.into(),
+    State(e) => e.into(),
+    Prometheus(e) => e.into(),
     AotCmd(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
     NotFound(_) => axum::http::StatusCode::NOT_FOUND,
     InvalidLogLevel(_) => axum::http::StatusCode::BAD_REQUEST,
     BadRequest(_) => axum::http::StatusCode::BAD_REQUEST,
     FailedToChangeLogLevel => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
     RpcError(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+    TransactionRecord(..) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+    Export(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
 });
 
 impl_into_type_str!(ServerError, |value| match value {
@@ -71,6 +86,7 @@ impl_into_type_str!(ServerError, |value| match value {
     Schema(e) => format!("{}.{}", value.as_ref(), String::from(e)),
     EnvRequest(e) => format!("{}.{}", value.as_ref(), String::from(e)),
     Storage(e) => format!("{}.{}", value.as_ref(), String::from(e)),
+    State(e) => format!("{}.{}", value.as_ref(), String::from(e)),
     _ => value.as_ref().to_string(),
 });
 
@@ -105,6 +121,8 @@ pub enum StartError {
     Serve(#[source] std::io::Error),
     #[error("failed to bind to tcp: {0}")]
     TcpBind(#[source] std::io::Error),
+    #[error("--bind-loopback-only cannot bind to non-loopback address {0}")]
+    NotLoopback(IpAddr),
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -128,11 +146,23 @@ pub enum ActionError {
         tx_id: String,
         retries: i32,
     },
+    #[error("reconcile timed out")]
+    ReconcileTimeout { agent_id: String },
+    #[error("reconcile failed")]
+    ReconcileFailed {
+        agent_id: String,
+        reason: ReconcileError,
+    },
+    #[error("freeze failed")]
+    FreezeFailed { errors: HashMap<NodeKey, String> },
 }
 
 impl_into_status_code!(ActionError, |value| match value {
-    ExecuteStatusTimeout { .. } => StatusCode::REQUEST_TIMEOUT,
-    ExecuteStatusAborted { .. } | ExecuteStatusFailed { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+    ExecuteStatusTimeout { .. } | ReconcileTimeout { .. } => StatusCode::REQUEST_TIMEOUT,
+    ExecuteStatusAborted { .. }
+    | ExecuteStatusFailed { .. }
+    | ReconcileFailed { .. }
+    | FreezeFailed { .. } => StatusCode::INTERNAL_SERVER_ERROR,
 });
 
 impl IntoResponse for ActionError {
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        Path, State, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use snops_common::{
+    api::AgentMetricsUpdate,
+    rpc::control::agent::AgentMetric,
+    state::{AgentId, id_or_none},
+};
+use tokio::select;
+
+use super::error::ServerError;
+use crate::{state::AppState, unwrap_or_not_found};
+
+/// How often the agent is polled for new metrics. Agents refresh their metrics
+/// less frequently than this, so only changed values are forwarded.
+const POLL_RATE: Duration = Duration::from_secs(5);
+
+pub async fn agent_metrics_ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Response {
+    let id = unwrap_or_not_found!("unknown agent id", id_or_none(&id));
+    if !state.pool.contains_key(&id) {
+        return ServerError::NotFound("agent not found".to_owned()).into_response();
+    }
+
+    ws.on_upgrade(move |socket| handle_metrics_ws(state, id, socket))
+}
+
+/// Poll the agent's metrics over its RPC connection and forward any updates
+/// to the websocket until either side disconnects
+async fn handle_metrics_ws(state: AppState, id: AgentId, mut socket: WebSocket) {
+    let mut interval = tokio::time::interval(POLL_RATE);
+    let mut last = None;
+
+    loop {
+        select! {
+            msg = socket.recv() => match msg {
+                Some(Err(_)) | None | Some(Ok(Message::Close(_))) => break,
+                _ => continue,
+            },
+            _ = interval.tick() => {
+                let Some(client) = state.pool.get(&id).map(|agent| agent.client_owned()) else {
+                    // the agent was removed from the pool
+                    break;
+                };
+                // skip this tick if the agent is offline
+                let Some(client) = client else {
+                    continue;
+                };
+
+                let tps = match client
+                    .0
+                    .get_metric(tarpc::context::current(), AgentMetric::Tps)
+                    .await
+                {
+                    Ok(tps) => tps,
+                    Err(e) => {
+                        tracing::debug!("failed to get metrics from agent {id}: {e}");
+                        continue;
+                    }
+                };
+
+                if last == Some(tps) {
+                    continue;
+                }
+                last = Some(tps);
+
+                let update = AgentMetricsUpdate {
+                    timestamp: Utc::now().timestamp(),
+                    tps,
+                };
+                let json = match serde_json::to_string(&update) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        tracing::error!("failed to serialize agent metrics for websocket: {e}");
+                        break;
+                    }
+                };
+                if let Err(e) = socket.send(Message::Text(json)).await {
+                    tracing::error!("failed to send agent metrics to websocket: {e}");
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -15,6 +15,7 @@ mod content;
 pub mod error;
 mod event_ws;
 pub mod jwt;
+mod metrics_ws;
 pub mod models;
 pub mod prometheus;
 mod rpc;
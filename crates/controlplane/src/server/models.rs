@@ -1,8 +1,12 @@
 use std::net::IpAddr;
 
-use snops_common::state::{AgentState, InternedId};
+use indexmap::{IndexMap, IndexSet};
+use snops_common::{
+    api::{LedgerChecksum, StorageInfo},
+    state::{AgentState, EnvId, InternedId, InventoryReason, NetworkId, NodeKey, StorageId},
+};
 
-use crate::state::Agent;
+use crate::state::{Agent, LastReconcileError};
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct AgentStatusResponse {
@@ -12,6 +16,12 @@ pub struct AgentStatusResponse {
     pub external_ip: Option<IpAddr>,
     pub internal_ip: Option<IpAddr>,
     pub state: AgentState,
+    /// Why the agent was last moved to the inventory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inventory_reason: Option<InventoryReason>,
+    /// The last error the agent reported while reconciling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reconcile_error: Option<LastReconcileError>,
 }
 
 impl From<&Agent> for AgentStatusResponse {
@@ -23,6 +33,75 @@ impl From<&Agent> for AgentStatusResponse {
             external_ip: agent.addrs().and_then(|a| a.external),
             internal_ip: agent.addrs().and_then(|a| a.internal.first().cloned()),
             state: agent.state().clone(),
+            inventory_reason: agent.inventory_reason(),
+            reconcile_error: agent.reconcile_error().cloned(),
         }
     }
 }
+
+/// The subset of an agent's status shown when listing many agents
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct AgentSummaryResponse {
+    pub agent_id: InternedId,
+    pub is_connected: bool,
+    pub labels: IndexSet<String>,
+    pub state: AgentState,
+    /// The environment the agent is in, or none when in inventory
+    pub env: Option<EnvId>,
+    pub node_key: Option<NodeKey>,
+    /// Why the agent was last moved to the inventory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inventory_reason: Option<InventoryReason>,
+}
+
+impl From<&Agent> for AgentSummaryResponse {
+    fn from(agent: &Agent) -> Self {
+        Self {
+            agent_id: agent.id(),
+            is_connected: agent.is_connected(),
+            labels: agent.str_labels().into_iter().map(String::from).collect(),
+            state: agent.state().clone(),
+            env: agent.env(),
+            node_key: agent.node_key().cloned(),
+            inventory_reason: agent.inventory_reason(),
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct StorageInspectResponse {
+    #[serde(flatten)]
+    pub info: StorageInfo,
+    pub network: NetworkId,
+    /// Number of keys in the committee
+    pub committee: usize,
+    /// Number of keys in each named account set
+    pub accounts: IndexMap<InternedId, usize>,
+    /// Number of checkpoints available in the control plane's copy of the
+    /// ledger
+    pub checkpoints: usize,
+}
+
+/// The ledger checksums of an environment's nodes
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct LedgerChecksumResponse {
+    /// Whether every checksum that was computed is the same
+    pub consistent: bool,
+    pub checksums: IndexMap<NodeKey, LedgerChecksum>,
+    /// Why the checksums of the other nodes could not be computed
+    pub errors: IndexMap<NodeKey, String>,
+}
+
+/// A summary of an environment shown when listing environments
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct EnvSummaryResponse {
+    pub id: EnvId,
+    pub network: NetworkId,
+    pub storage_id: StorageId,
+    /// Number of nodes in the topology, including external nodes
+    pub node_count: usize,
+    /// Number of agents delegated to the environment's nodes
+    pub agent_count: usize,
+    /// The latest height cached for the environment
+    pub block_height: Option<u32>,
+}
@@ -1,13 +1,30 @@
 use std::collections::HashMap;
 
-use axum::{Json, Router, extract::State, response::IntoResponse, routing::get};
+use axum::{
+    Json, Router,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+};
 use rayon::iter::{ParallelBridge, ParallelIterator};
 use serde::Serialize;
 use snops_common::state::AgentState;
 
 use crate::{cli::PrometheusLocation, state::AppState};
 pub(super) fn routes() -> Router<AppState> {
-    Router::new().route("/httpsd", get(get_httpsd))
+    Router::new()
+        .route("/httpsd", get(get_httpsd))
+        .route("/metrics", get(get_metrics))
+}
+
+/// Metrics of the control plane itself, in the prometheus text format
+async fn get_metrics(State(state): State<AppState>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.tx_latency.render(),
+    )
+        .into_response()
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, net::IpAddr, time::Instant};
+use std::{collections::HashMap, net::IpAddr, sync::Arc, time::Instant};
 
 use chrono::Utc;
 use snops_common::events::AgentEvent;
@@ -21,7 +21,7 @@ use snops_common::{
 use tarpc::context;
 use tracing::warn;
 
-use crate::state::{AgentEventHelpers, EmitEvent};
+use crate::state::{AgentEventHelpers, EmitEvent, transactions::emit_block_summary};
 use crate::{
     error::StateError,
     state::{AddrMap, AgentAddrs, AppState, GetGlobalState, GlobalState},
@@ -176,8 +176,18 @@ impl ControlService for ControlRpcServer {
 
         // make the block request, then update the cache if applicable
         match client.get_snarkos_block_lite(info.block_hash.clone()).await {
-            Ok(Some(block)) => {
+            Ok(Some(mut block)) => {
+                let rejected: Vec<_> = block.rejected.drain(..).map(Arc::from).collect();
+                let aborted: Vec<_> = block.aborted.drain(..).map(Arc::from).collect();
                 let (info, transactions) = block.split();
+                emit_block_summary(
+                    &self.state,
+                    env_id,
+                    info.height,
+                    &transactions,
+                    &rejected,
+                    &aborted,
+                );
                 if let Some(mut c) = self.state.env_network_cache.get_mut(&env_id) {
                     c.add_block(info, transactions);
                 }
@@ -224,13 +234,20 @@ impl ControlService for ControlRpcServer {
 
         agent.status.reconcile = Some((Instant::now(), status.clone()));
 
-        // Emit events for this reconcile
-
-        let ev = AgentEvent::ReconcileComplete.with_agent(&agent);
         let is_complete = status
             .as_ref()
             .is_ok_and(|e| e.requeue_after.is_none() && e.inner.is_some());
 
+        match &status {
+            Err(err) => agent.set_reconcile_error(Some(err.clone())),
+            Ok(_) if is_complete => agent.set_reconcile_error(None),
+            Ok(_) => {}
+        }
+
+        // Emit events for this reconcile
+
+        let ev = AgentEvent::ReconcileComplete.with_agent(&agent);
+
         ev.replace_content(match status {
             Ok(res) => AgentEvent::Reconcile(res),
             Err(err) => AgentEvent::ReconcileError(err),
@@ -244,11 +261,15 @@ impl ControlService for ControlRpcServer {
 }
 
 pub fn resolve_one_addr(src_addrs: &AgentAddrs, target_addrs: &AgentAddrs) -> Option<IpAddr> {
-    match (
-        src_addrs.external,
-        target_addrs.external,
-        target_addrs.internal.first(),
-    ) {
+    // prefer an internal address in a family the source has an address in, so
+    // IPv4-only agents don't resolve the IPv6 addresses of dual stack agents
+    let peer_int = target_addrs
+        .internal
+        .iter()
+        .find(|addr| src_addrs.has_family_of(**addr))
+        .or_else(|| target_addrs.internal.first());
+
+    match (src_addrs.external, target_addrs.external, peer_int) {
         // if peers have the same external address, use the first internal address
         (Some(src_ext), Some(peer_ext), Some(peer_int)) if src_ext == peer_ext => Some(*peer_int),
         // if both peers have only internal addresses, use the internal address
@@ -288,3 +309,57 @@ impl<'a> GetGlobalState<'a> for &'a ControlRpcServer {
         &self.state
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(external: Option<&str>, internal: &[&str]) -> AgentAddrs {
+        AgentAddrs {
+            external: external.map(|a| a.parse().unwrap()),
+            internal: internal.iter().map(|a| a.parse().unwrap()).collect(),
+        }
+    }
+
+    fn ip(addr: &str) -> Option<IpAddr> {
+        Some(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn test_resolve_one_addr_prefers_source_family() {
+        let dual_stack = addrs(None, &["2001:db8::2", "10.0.0.2"]);
+
+        // an IPv4-only source skips the target's IPv6 address
+        let v4_only = addrs(None, &["10.0.0.1"]);
+        assert_eq!(resolve_one_addr(&v4_only, &dual_stack), ip("10.0.0.2"));
+
+        // an IPv6-only source skips the target's IPv4 address
+        let v6_only = addrs(None, &["2001:db8::1"]);
+        let v4_first = addrs(None, &["10.0.0.2", "2001:db8::2"]);
+        assert_eq!(resolve_one_addr(&v6_only, &v4_first), ip("2001:db8::2"));
+
+        // a dual stack source keeps the target's address order
+        let src = addrs(None, &["10.0.0.1", "2001:db8::1"]);
+        assert_eq!(resolve_one_addr(&src, &dual_stack), ip("2001:db8::2"));
+    }
+
+    #[test]
+    fn test_resolve_one_addr_falls_back_to_first_internal() {
+        // without a shared family, the first internal address is still used
+        let v4_only = addrs(None, &["10.0.0.1"]);
+        let v6_only = addrs(None, &["2001:db8::2", "2001:db8::3"]);
+        assert_eq!(resolve_one_addr(&v4_only, &v6_only), ip("2001:db8::2"));
+    }
+
+    #[test]
+    fn test_resolve_one_addr_external_family() {
+        // the source's external address counts towards its families
+        let src = addrs(Some("203.0.113.1"), &["2001:db8::1"]);
+        let target = addrs(Some("203.0.113.1"), &["2001:db8::2", "10.0.0.2"]);
+        assert_eq!(resolve_one_addr(&src, &target), ip("2001:db8::2"));
+
+        // peers behind different external addresses use the external address
+        let target = addrs(Some("203.0.113.2"), &["10.0.0.2"]);
+        assert_eq!(resolve_one_addr(&src, &target), ip("203.0.113.2"));
+    }
+}
@@ -4,6 +4,7 @@ use std::{
     time::Instant,
 };
 
+use chrono::{DateTime, Utc};
 use fixedbitset::FixedBitSet;
 use indexmap::IndexSet;
 use jwt::SignWithKey;
@@ -14,15 +15,24 @@ use snops_common::{
     INTERN,
     events::Event,
     lasso::Spur,
-    rpc::control::agent::AgentServiceClient,
+    rpc::{control::agent::AgentServiceClient, error::ReconcileError},
     state::{
-        AgentId, AgentModeOptions, AgentState, AgentStatus, EnvId, NodeKey, NodeState, PortConfig,
+        AgentId, AgentModeOptions, AgentState, AgentStatus, EnvId, InventoryReason, NodeKey,
+        NodeState, PortConfig,
     },
 };
 
 use super::{AgentClient, AgentFlags, PendingAgentReconcile};
 use crate::server::jwt::{Claims, JWT_SECRET};
 
+/// The last error an agent reported while reconciling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastReconcileError {
+    pub time: DateTime<Utc>,
+    pub message: String,
+    pub error: ReconcileError,
+}
+
 #[derive(Debug)]
 /// Apparently `const* ()` is not send, so this is a workaround
 pub struct Busy;
@@ -35,6 +45,10 @@ pub struct Agent {
     pub(crate) connection: AgentConnection,
     pub(crate) state: AgentState,
     pub(crate) status: AgentStatus,
+    /// Why the agent was last moved to the inventory, if it was
+    pub(crate) inventory_reason: Option<InventoryReason>,
+    /// The last reconcile error, cleared once a reconcile completes
+    pub(crate) reconcile_error: Option<LastReconcileError>,
 
     /// CLI provided information (mode, labels, local private key)
     pub(crate) flags: AgentFlags,
@@ -63,6 +77,8 @@ impl Agent {
             connection: AgentConnection::Online(rpc),
             state: Default::default(),
             status: Default::default(),
+            inventory_reason: None,
+            reconcile_error: None,
             ports: None,
             addrs: None,
         }
@@ -86,6 +102,8 @@ impl Agent {
             },
             status: Default::default(),
             state,
+            inventory_reason: None,
+            reconcile_error: None,
             ports,
             addrs,
         }
@@ -142,9 +160,20 @@ impl Agent {
         matches!(self.state, AgentState::Inventory)
     }
 
+    /// Check if an agent only functions as a compute target
+    pub fn is_compute_only(&self) -> bool {
+        self.flags.mode.compute_only
+    }
+
+    /// Check if an agent is a compute target, whether or not it is working on
+    /// an authorization
+    pub fn is_compute_target(&self) -> bool {
+        self.is_inventory() && self.flags.mode.compute
+    }
+
     /// Check if an agent is available for compute tasks
     pub fn can_compute(&self) -> bool {
-        self.is_inventory() && self.flags.mode.compute && !self.is_compute_claimed()
+        self.is_compute_target() && !self.is_compute_claimed()
     }
 
     /// Check if an agent is working on an authorization
@@ -241,9 +270,37 @@ impl Agent {
     /// Forcibly sets an agent's state. This does **not** reconcile the agent,
     /// and should only be called after an agent is reconciled.
     pub fn set_state(&mut self, state: AgentState) {
+        if !matches!(state, AgentState::Inventory) {
+            self.inventory_reason = None;
+        }
         self.state = state;
     }
 
+    /// Forcibly moves an agent to the inventory, recording why. This does
+    /// **not** reconcile the agent.
+    pub fn set_inventory(&mut self, reason: InventoryReason) {
+        self.state = AgentState::Inventory;
+        self.inventory_reason = Some(reason);
+    }
+
+    pub fn inventory_reason(&self) -> Option<InventoryReason> {
+        self.inventory_reason
+    }
+
+    /// Record the outcome of a reconcile. Errors are kept until a reconcile
+    /// completes so they remain visible while the agent retries.
+    pub fn set_reconcile_error(&mut self, error: Option<ReconcileError>) {
+        self.reconcile_error = error.map(|error| LastReconcileError {
+            time: Utc::now(),
+            message: error.to_string(),
+            error,
+        });
+    }
+
+    pub fn reconcile_error(&self) -> Option<&LastReconcileError> {
+        self.reconcile_error.as_ref()
+    }
+
     /// Set the ports of the agent. This does **not** trigger a reconcile
     pub fn set_ports(&mut self, ports: PortConfig) -> bool {
         let changed = self.ports.as_ref() != Some(&ports);
@@ -348,6 +405,27 @@ impl AgentAddrs {
     pub fn is_some(&self) -> bool {
         self.external.is_some() || !self.internal.is_empty()
     }
+
+    /// Check if the address is one of the agent's addresses
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        self.external == Some(addr) || self.internal.contains(&addr)
+    }
+
+    /// Check if the agent has an address in the same family as the address
+    pub fn has_family_of(&self, addr: IpAddr) -> bool {
+        self.external
+            .iter()
+            .chain(&self.internal)
+            .any(|a| a.is_ipv6() == addr.is_ipv6())
+    }
+
+    /// Move the address to the front of the internal addresses, so it is
+    /// resolved ahead of the others
+    pub fn prefer(&mut self, addr: IpAddr) {
+        if let Some(i) = self.internal.iter().position(|a| *a == addr) {
+            self.internal[..=i].rotate_right(1);
+        }
+    }
 }
 
 pub trait AgentEventHelpers {
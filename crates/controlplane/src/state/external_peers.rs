@@ -1,18 +1,22 @@
-use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 
 use chrono::{TimeDelta, Utc};
 use futures_util::future;
 use serde_json::Value;
-use snops_common::state::{EnvId, LatestBlockInfo, NetworkId, NodeKey};
-use tokio::{sync::mpsc, time::timeout};
+use snops_common::{
+    node_targets::{NodeTarget, NodeTargets},
+    state::{EnvId, LatestBlockInfo, NetworkId, NodeKey},
+};
+use tokio::sync::mpsc;
 
-use super::{AgentClient, GlobalState, snarkos_request};
+use super::{AgentClient, GlobalState, snarkos_request, transactions::emit_block_summary};
 use crate::{
     env::{
         EnvNodeState, EnvPeer,
         cache::{ABlockHash, ATransactionId, MAX_BLOCK_RANGE},
+        error::EnvRequestError,
     },
-    schema::nodes::ExternalNode,
+    schema::nodes::{ExternalNode, RestHeaders},
 };
 
 type ExtPeerPair = (NodeKey, SocketAddr);
@@ -41,26 +45,18 @@ pub async fn block_info_task(state: Arc<GlobalState>) {
         // environment
         let peers_with_block_hashes = future::join_all(external_rest_peers.into_iter().map(
             |((env, network), peers)| {
+                let state = &state;
                 let req_ok_tx = req_ok_tx.clone();
                 async move {
-                    let peers = future::join_all(peers.into_iter().map(|(key, addr)| {
-                        let req_ok_tx = req_ok_tx.clone();
-                        async move {
-                            let res = timeout(
-                                // short timeout for block hash requests as not much is being
-                                // serialized on snarkOS side
-                                Duration::from_secs(1),
-                                get_block_hash_for_peer(network, addr),
-                            )
-                            .await
-                            .ok()
-                            .and_then(|hash| hash.map(|h| (key.clone(), addr, h)));
+                    let peers = get_block_hashes_for_peers(state, env, peers)
+                        .await
+                        .into_iter()
+                        .map(|(key, addr, res)| {
                             // mark down a successful request
-                            let _ = req_ok_tx.send((env, key, res.is_some()));
-                            res
-                        }
-                    }))
-                    .await;
+                            let _ = req_ok_tx.send((env, key.clone(), res.is_some()));
+                            res.map(|h| (key, addr, h))
+                        })
+                        .collect::<Vec<_>>();
                     ((env, network), peers)
                 }
             },
@@ -142,6 +138,7 @@ pub async fn block_info_task(state: Arc<GlobalState>) {
                 // list of agents that could fulfil this request (rather than making slow rest &
                 // deserialize requests)
                 let agents = Arc::new(online_agents_above_height(&state, env, max_height));
+                let env_ref = state.get_env(env);
                 let req_ok_tx = req_ok_tx.clone();
 
                 async move {
@@ -151,6 +148,7 @@ pub async fn block_info_task(state: Arc<GlobalState>) {
                             |(hash, BlockRequestPeers { peers, .. })| {
                                 let req_ok_tx = req_ok_tx.clone();
                                 let agents = agents.clone();
+                                let env_ref = env_ref.clone();
 
                                 // peer keys to update (or request)
                                 let keys =
@@ -167,8 +165,11 @@ pub async fn block_info_task(state: Arc<GlobalState>) {
                                     // if agents failed, fallback on external peers
                                     let mut failures = 0u8;
                                     for (key, addr) in peers {
+                                        let headers = env_ref
+                                            .as_ref()
+                                            .and_then(|env| env.external_rest_headers(addr));
                                         if let Some(res) =
-                                            get_block_info_for_peer(network, addr).await
+                                            get_block_info_for_peer(network, addr, headers).await
                                         {
                                             let _ = req_ok_tx.send((env, key, true));
                                             return Some((res, keys));
@@ -210,6 +211,10 @@ pub async fn block_info_task(state: Arc<GlobalState>) {
             // update the cache with the block info and transaction ids
             // then update each peer's info
             for ((info, txs), keys) in responses.into_iter().flatten() {
+                // rejected and aborted transactions are not tracked for these blocks
+                if !cache.has_transactions_for_block(&info.block_hash) {
+                    emit_block_summary(&state, env, info.height, &txs, &[], &[]);
+                }
                 cache.add_block(info.clone(), txs);
                 for key in keys {
                     cache.update_latest_info(&info);
@@ -251,19 +256,50 @@ pub fn online_agents_above_height(
         .collect()
 }
 
-/// Obtain a peer's latest block hash and height
-/// We do not assume the hash and height are related, and they are used for
-/// separate purposes.
-async fn get_block_hash_for_peer(network: NetworkId, addr: SocketAddr) -> Option<(Arc<str>, u32)> {
-    // make a request to the external peer for the latest block hash
-    let hash_res = snarkos_request::get_on_addr::<Value>(network, "/block/hash/latest", addr)
-        .await
-        .ok()?;
-    let height_res = snarkos_request::get_on_addr::<Value>(network, "/block/hash/latest", addr)
-        .await
-        .ok()?;
-
-    Some((hash_res.as_str()?.into(), height_res.as_u64()? as u32))
+/// Obtain the latest block hash and height of each peer, querying the peers
+/// concurrently. We do not assume the hash and height are related, and they
+/// are used for separate purposes.
+async fn get_block_hashes_for_peers(
+    state: &GlobalState,
+    env: EnvId,
+    peers: Vec<ExtPeerPair>,
+) -> Vec<(NodeKey, SocketAddr, Option<(Arc<str>, u32)>)> {
+    if peers.is_empty() {
+        return Vec::new();
+    }
+
+    let targets = NodeTargets::from(
+        peers
+            .iter()
+            .map(|(key, _)| NodeTarget::from(key.clone()))
+            .collect::<Vec<_>>(),
+    );
+    let (hashes, heights) = tokio::join!(
+        state.snarkos_get_all::<Value>(env, "/block/hash/latest", &targets),
+        state.snarkos_get_all::<Value>(env, "/block/height/latest", &targets),
+    );
+
+    let mut hashes = collect_responses(hashes, |v| v.as_str().map(Arc::from));
+    let mut heights = collect_responses(heights, |v| v.as_u64().map(|h| h as u32));
+
+    peers
+        .into_iter()
+        .map(|(key, addr)| {
+            let res = hashes.remove(&key).zip(heights.remove(&key));
+            (key, addr, res)
+        })
+        .collect()
+}
+
+/// Map the successful responses of a `snarkos_get_all` by node key
+fn collect_responses<T>(
+    res: Result<Vec<(NodeKey, Result<Value, EnvRequestError>)>, EnvRequestError>,
+    parse: impl Fn(Value) -> Option<T>,
+) -> HashMap<NodeKey, T> {
+    res.unwrap_or_default()
+        .into_iter()
+        .filter_map(|(key, res)| Some((key, parse(res.ok()?)?)))
+        .collect()
 }
 
 const MAX_BLOCK_REQUEST_FAILURES: u8 = 3;
@@ -290,10 +326,13 @@ async fn get_block_from_agents(
 async fn get_block_info_for_peer(
     network: NetworkId,
     addr: SocketAddr,
+    headers: Option<RestHeaders>,
 ) -> Option<(LatestBlockInfo, Vec<ATransactionId>)> {
     // make a request to the external peer for the latest block
     // TODO: make this a RawValue to prevent unnecessarily parsing the response
-    let Ok(block_raw) = snarkos_request::get_on_addr::<Value>(network, "/block/latest", addr).await
+    let Ok(block_raw) =
+        snarkos_request::get_on_addr::<Value>(network, "/block/latest", addr, headers.as_ref())
+            .await
     else {
         tracing::trace!("failed to get latest block for peer: {addr:?}");
         return None;
@@ -329,7 +368,9 @@ async fn get_block_info_for_peer(
 
     // fetch the state root (because it's missing from the block)
     let route = format!("/stateRoot/{height}");
-    let Ok(state_root) = snarkos_request::get_on_addr::<String>(network, &route, addr).await else {
+    let Ok(state_root) =
+        snarkos_request::get_on_addr::<String>(network, &route, addr, headers.as_ref()).await
+    else {
         tracing::trace!("failed to get state root for peer: {addr:?}");
         return None;
     };
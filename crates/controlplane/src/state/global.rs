@@ -1,37 +1,51 @@
-use std::{fmt::Display, net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{fmt::Display, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use chrono::Utc;
 use dashmap::DashMap;
+use futures_util::{StreamExt, stream::FuturesUnordered};
 use lazysort::SortedBy;
-use prometheus_http_query::Client as PrometheusClient;
 use serde::de::DeserializeOwned;
 use snops_common::{
     constant::ENV_AGENT_KEY,
-    events::Event,
+    events::{Event, TransactionRecord},
     node_targets::NodeTargets,
+    rpc::error::SnarkosRequestError,
     state::{
-        AgentId, AgentPeer, AgentState, EnvId, LatestBlockInfo, NetworkId, NodeType, StorageId,
+        AgentId, AgentPeer, AgentState, EnvId, InventoryReason, LatestBlockInfo, NetworkId,
+        NodeKey, NodeType, ReconcileOptions, StorageId,
     },
     util::OpaqueDebug,
 };
-use tokio::sync::Semaphore;
+use tokio::{
+    sync::{Mutex, OwnedMutexGuard, Semaphore, watch},
+    time::timeout,
+};
 use tracing::info;
 
 use super::{
-    AddrMap, AgentClient, AgentPool, EnvMap, StorageMap,
+    AddrMap, AgentClient, AgentPool, EnvMap, PrometheusBreaker, StorageMap, TransactionRecorder,
     snarkos_request::{self, reparse_json_env},
 };
 use crate::{
     ReloadHandler,
+    cannon::{self, latency::ConfirmationLatency},
     cli::Cli,
     db::Database,
     env::{Environment, PortType, cache::NetworkCache, error::EnvRequestError},
     error::StateError,
     events::Events,
-    schema::storage::{LoadedStorage, STORAGE_DIR},
+    schema::{
+        nodes::RestHeaders,
+        storage::{LoadedStorage, STORAGE_DIR},
+    },
     server::error::StartError,
 };
 
+/// Maximum number of in-flight requests for a `snarkos_get_all`
+const MAX_CONCURRENT_GETS: usize = 32;
+/// Timeout for each node's request in a `snarkos_get_all`
+const GET_ALL_TIMEOUT: Duration = Duration::from_secs(2);
+
 lazy_static::lazy_static! {
     pub(crate) static ref REST_CLIENT: reqwest::Client = reqwest::Client::new();
 }
@@ -46,9 +60,26 @@ pub struct GlobalState {
     pub storage: StorageMap,
     pub envs: EnvMap,
     pub env_network_cache: OpaqueDebug<DashMap<EnvId, NetworkCache>>,
+    /// Number of authorizations each compute agent is currently executing
+    pub compute_load: DashMap<AgentId, usize>,
+    /// Limits concurrent content downloads when configured
+    pub download_limiter: Option<Arc<Semaphore>>,
+    /// Agents with an in-flight reconcile request, and the merged options of
+    /// any requests coalesced into a follow-up
+    pub pending_reconciles: DashMap<AgentId, Option<ReconcileOptions>>,
+    /// Cancels the storage preparation of envs that are being applied, keyed
+    /// by env and apply
+    pub pending_prepares: DashMap<(EnvId, u64), watch::Sender<bool>>,
+    /// Serializes the changes that replace an env, such as applying its spec
+    /// or adding, removing, and restarting its cannons
+    pub env_locks: DashMap<EnvId, Arc<Mutex<()>>>,
+    /// Envs that record their transaction status transitions to a file
+    pub tx_recorders: DashMap<EnvId, TransactionRecorder>,
+    /// Authorization to confirmation latencies of each cannon
+    pub tx_latency: ConfirmationLatency,
     pub events: Events,
 
-    pub prometheus: OpaqueDebug<Option<PrometheusClient>>,
+    pub prometheus: OpaqueDebug<Option<PrometheusBreaker>>,
 
     pub log_level_handler: ReloadHandler,
 }
@@ -72,7 +103,7 @@ impl GlobalState {
     pub async fn load(
         cli: Cli,
         db: Database,
-        prometheus: Option<PrometheusClient>,
+        prometheus: Option<PrometheusBreaker>,
         log_level_handler: ReloadHandler,
     ) -> Result<Arc<Self>, StartError> {
         // Load storage meta from persistence, then read the storage data from FS
@@ -91,6 +122,10 @@ impl GlobalState {
 
         let pool: DashMap<_, _> = db.agents.read_all().collect();
 
+        let download_limiter = cli
+            .max_concurrent_downloads
+            .map(|n| Arc::new(Semaphore::new(n)));
+
         let state = Arc::new(Self {
             cli,
             agent_key: std::env::var(ENV_AGENT_KEY).ok(),
@@ -101,9 +136,19 @@ impl GlobalState {
             prometheus: OpaqueDebug(prometheus),
             db: OpaqueDebug(db),
             env_network_cache: Default::default(),
+            compute_load: Default::default(),
+            pending_reconciles: Default::default(),
+            pending_prepares: Default::default(),
+            env_locks: Default::default(),
+            tx_recorders: Default::default(),
+            tx_latency: Default::default(),
+            download_limiter,
             log_level_handler,
         });
 
+        // snapshots are only removed by the cannons that created them
+        cannon::remove_stale_snapshots(&state.cli).await;
+
         let env_meta = state.db.envs.read_all().collect::<Vec<_>>();
 
         let num_cannons = env_meta.iter().map(|(_, e)| e.cannons.len()).sum();
@@ -134,11 +179,11 @@ impl GlobalState {
 
         // For all agents not in envs, set their state to Inventory
         for mut entry in state.pool.iter_mut() {
-            let AgentState::Node(env, _) = entry.value().state() else {
+            let &AgentState::Node(env, _) = entry.value().state() else {
                 continue;
             };
 
-            if state.envs.contains_key(env) {
+            if state.envs.contains_key(&env) {
                 continue;
             }
 
@@ -146,13 +191,27 @@ impl GlobalState {
                 "setting agent {} to Inventory state due to missing env {env}",
                 entry.key()
             );
-            entry.set_state(AgentState::Inventory);
+            entry.set_inventory(InventoryReason::EnvMissing(env));
             let _ = state.db.agents.save(entry.key(), entry.value());
         }
 
         Ok(state)
     }
 
+    /// Load an empty state backed by a new database in a temporary directory
+    #[cfg(test)]
+    pub(crate) async fn load_for_test() -> Arc<Self> {
+        use clap::Parser;
+        use snops_common::db::Database as _;
+        use tracing_subscriber::{EnvFilter, reload};
+
+        let path = std::env::temp_dir().join(format!("snops-test-{}", uuid::Uuid::new_v4()));
+        let cli = Cli::parse_from(["snops", "--path", &path.to_string_lossy()]);
+        let db = Database::open_with(&path.join("store"), &cli.db).unwrap();
+        let (_, log_level_handler) = reload::Layer::new(EnvFilter::default());
+        Self::load(cli, db, None, log_level_handler).await.unwrap()
+    }
+
     pub fn storage_path(&self, network: NetworkId, storage_id: StorageId) -> PathBuf {
         self.cli
             .path
@@ -168,11 +227,17 @@ impl GlobalState {
             .iter()
             .filter_map(|id| self.pool.get(id))
             .map(|agent| {
-                let addrs = agent
+                let mut addrs = agent
                     .addrs
-                    .as_ref()
+                    .clone()
                     .ok_or_else(|| StateError::NoAddress(agent.id()))?;
-                Ok((agent.id(), addrs.clone()))
+                // advertise the address the node is bound to
+                if let AgentState::Node(_, node) = agent.state() {
+                    if let Some(bind_addr) = node.bind_addr {
+                        addrs.prefer(bind_addr);
+                    }
+                }
+                Ok((agent.id(), addrs))
             })
             .collect()
     }
@@ -195,6 +260,18 @@ impl GlobalState {
         }
     }
 
+    /// Get the number of authorizations an agent is currently executing
+    pub fn get_compute_load(&self, id: AgentId) -> usize {
+        self.compute_load.get(&id).map(|v| *v).unwrap_or_default()
+    }
+
+    /// Mark an agent as executing an authorization until the returned guard is
+    /// dropped
+    pub fn track_compute(&self, id: AgentId) -> ComputeLoadGuard<'_> {
+        *self.compute_load.entry(id).or_default() += 1;
+        ComputeLoadGuard { state: self, id }
+    }
+
     pub fn try_unload_storage(
         &self,
         network: NetworkId,
@@ -226,6 +303,22 @@ impl GlobalState {
         self.envs.remove(&env_id).map(|(_, env)| env)
     }
 
+    /// Wait for exclusive access to change an env. The env must be read after
+    /// the lock is acquired for the change to be based on its latest version.
+    pub async fn lock_env(&self, env_id: EnvId) -> OwnedMutexGuard<()> {
+        let lock = Arc::clone(self.env_locks.entry(env_id).or_default().value());
+        lock.lock_owned().await
+    }
+
+    /// Release the lock of a removed env, forgetting it unless another change
+    /// is waiting on it
+    pub fn release_env_lock(&self, env_id: EnvId, guard: OwnedMutexGuard<()>) {
+        // only the map and the guard hold the lock when nobody is waiting
+        self.env_locks
+            .remove_if(&env_id, |_, lock| Arc::strong_count(lock) == 2);
+        drop(guard);
+    }
+
     pub fn get_env(&self, id: EnvId) -> Option<Arc<Environment>> {
         Some(Arc::clone(self.envs.get(&id)?.value()))
     }
@@ -264,6 +357,11 @@ impl GlobalState {
                 let agent_id = match peer {
                     AgentPeer::Internal(id, _) => id,
                     AgentPeer::External(addr) => {
+                        // skip external peers that have stopped responding
+                        if cache.as_ref().is_some_and(|c| c.is_peer_penalized(key)) {
+                            return None;
+                        }
+
                         // lookup the external peer info from the cache
                         return Some(if let Some(info) = ext_infos.and_then(|c| c.get(key)) {
                             (info.score(&now), Some(info.clone()), None, None)
@@ -346,7 +444,10 @@ impl GlobalState {
             };
 
             // attempt to make the request from the node via REST
-            match snarkos_request::get_on_addr(env.network, &route_str, addr).await {
+            let headers = env.external_rest_headers(addr);
+            match snarkos_request::get_on_addr(env.network, &route_str, addr, headers.as_ref())
+                .await
+            {
                 Ok(res) => return Ok(res),
                 Err(e) => {
                     tracing::error!("env {env_id} request to `{addr}{route_str}`: {e}");
@@ -357,6 +458,102 @@ impl GlobalState {
 
         Err(EnvRequestError::NoResponsiveNodes)
     }
+
+    /// Make a request to every node matching the target concurrently. Unlike
+    /// `snarkos_get`, a node that fails or times out does not fail the whole
+    /// request; its error is returned alongside the other nodes' responses.
+    /// Provers are included, so callers that only want nodes serving chain
+    /// data should exclude them from the target.
+    pub async fn snarkos_get_all<T: DeserializeOwned>(
+        &self,
+        env_id: EnvId,
+        route: impl Display,
+        target: &NodeTargets,
+    ) -> Result<Vec<(NodeKey, Result<T, EnvRequestError>)>, EnvRequestError> {
+        let Some(env) = self.get_env(env_id) else {
+            return Err(EnvRequestError::MissingEnv(env_id));
+        };
+        let network = env.network;
+        let route = route.to_string();
+
+        // collect the peers so the pool is not locked during the requests
+        let peers = env
+            .matching_peers(target, &self.pool, PortType::Rest)
+            .map(|(key, peer)| (key.clone(), peer))
+            .collect::<Vec<_>>();
+
+        let mut requests = FuturesUnordered::new();
+        let mut results = Vec::with_capacity(peers.len());
+        for (key, peer) in peers {
+            let headers = match peer {
+                AgentPeer::External(addr) => env.external_rest_headers(addr),
+                AgentPeer::Internal(..) => None,
+            };
+            // wait for a request to complete before starting another
+            if requests.len() >= MAX_CONCURRENT_GETS {
+                results.extend(requests.next().await);
+            }
+
+            let route = &route;
+            requests.push(async move {
+                let res = timeout(
+                    GET_ALL_TIMEOUT,
+                    self.snarkos_get_peer(network, route, peer, headers),
+                )
+                .await
+                .unwrap_or(Err(EnvRequestError::AgentRequestError(
+                    SnarkosRequestError::TimedOut,
+                )));
+                (key, res)
+            });
+        }
+
+        while let Some(res) = requests.next().await {
+            results.push(res);
+        }
+
+        Ok(results)
+    }
+
+    /// Make a request to a single node, through its agent if it has one
+    async fn snarkos_get_peer<T: DeserializeOwned>(
+        &self,
+        network: NetworkId,
+        route: &str,
+        peer: AgentPeer,
+        headers: Option<RestHeaders>,
+    ) -> Result<T, EnvRequestError> {
+        match peer {
+            AgentPeer::Internal(agent_id, _) => self
+                .get_client(agent_id)
+                .ok_or(SnarkosRequestError::OfflineNode)
+                .map_err(EnvRequestError::AgentRequestError)?
+                .snarkos_get(route)
+                .await
+                .map_err(EnvRequestError::AgentRequestError),
+            AgentPeer::External(addr) => {
+                snarkos_request::get_on_addr(network, route, addr, headers.as_ref())
+                    .await
+                    .map_err(EnvRequestError::AgentRequestError)
+            }
+        }
+    }
+}
+
+/// Decrements an agent's compute load when dropped
+pub struct ComputeLoadGuard<'a> {
+    state: &'a GlobalState,
+    id: AgentId,
+}
+
+impl Drop for ComputeLoadGuard<'_> {
+    fn drop(&mut self) {
+        // remove the entry once the agent is idle to keep the map small
+        self.state.compute_load.remove_if_mut(&self.id, |_, load| {
+            *load = load.saturating_sub(1);
+            *load == 0
+        });
+    }
 }
 
 pub trait GetGlobalState<'a> {
@@ -383,6 +580,19 @@ pub trait EmitEvent {
 impl EmitEvent for Event {
     #[inline]
     fn emit<'a>(self, state: impl GetGlobalState<'a>) {
-        state.global_state().events.emit(self);
+        let state = state.global_state();
+        state.tx_latency.observe(&self);
+        if let Some(env) = self.env {
+            // only transaction events of recorded envs look up the env's height
+            if state.tx_recorders.contains_key(&env) {
+                if let Some(mut record) = TransactionRecord::from_event(&self, None) {
+                    record.height = state.get_env_block_info(env).map(|info| info.height);
+                    if let Some(recorder) = state.tx_recorders.get(&env) {
+                        recorder.record(record);
+                    }
+                }
+            }
+        }
+        state.events.emit(self);
     }
 }
@@ -7,16 +7,20 @@ mod agent;
 mod agent_flags;
 pub mod external_peers;
 mod global;
+mod prometheus;
 mod reconcile;
 mod rpc;
 pub mod snarkos_request;
 pub mod transactions;
+mod tx_record;
 
 pub use agent::*;
 pub use agent_flags::*;
 pub use global::*;
+pub use prometheus::*;
 pub use reconcile::*;
 pub use rpc::*;
+pub use tx_record::*;
 
 use crate::{env::Environment, schema::storage::LoadedStorage};
 
@@ -0,0 +1,246 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use prometheus_http_query::{Client as PrometheusClient, response::Data};
+use promql_parser::{
+    label::{MatchOp, Matcher},
+    parser::{Expr, VectorSelector},
+};
+use serde::Serialize;
+use snops_common::state::EnvId;
+use tracing::warn;
+
+use crate::error::PrometheusError;
+
+/// Number of consecutive failed queries before queries are short-circuited
+const FAILURE_THRESHOLD: u32 = 3;
+/// How long queries are short-circuited before prometheus is probed again
+const COOLDOWN: Duration = Duration::from_secs(30);
+/// How long a single query may take before it counts as a failure
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Number of distinct queries whose last value is kept
+const MAX_CACHED_QUERIES: usize = 256;
+
+/// State of the prometheus circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BreakerStatus {
+    /// Queries are sent to prometheus
+    Closed,
+    /// Queries are short-circuited until the cooldown elapses
+    Open,
+    /// The cooldown elapsed and the next query probes prometheus
+    HalfOpen,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// A prometheus client that stops querying prometheus after consecutive
+/// failures, answering from the last known values until a probe succeeds.
+pub struct PrometheusBreaker {
+    client: PrometheusClient,
+    state: Mutex<BreakerState>,
+    /// Last successful value of each query
+    cache: DashMap<String, Option<f64>>,
+}
+
+impl PrometheusBreaker {
+    pub fn new(client: PrometheusClient) -> Self {
+        Self {
+            client,
+            state: Default::default(),
+            cache: Default::default(),
+        }
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        match self.state.lock().unwrap().open_until {
+            None => BreakerStatus::Closed,
+            Some(until) if until > Instant::now() => BreakerStatus::Open,
+            Some(_) => BreakerStatus::HalfOpen,
+        }
+    }
+
+    /// Query prometheus for a single value. While the breaker is open, the
+    /// last value of the query is returned instead, or none if there is no
+    /// previous value.
+    pub async fn query_value(&self, query: &str) -> Result<Option<f64>, PrometheusError> {
+        // malformed queries are rejected without reaching prometheus, so they
+        // do not count towards opening the breaker
+        promql_parser::parser::parse(query).map_err(PrometheusError::InvalidQuery)?;
+
+        {
+            let mut state = self.state.lock().unwrap();
+            match state.open_until {
+                Some(until) if until > Instant::now() => {
+                    return Ok(self.cache.get(query).and_then(|v| *v));
+                }
+                // let this query probe prometheus, and short-circuit the others
+                // until it completes
+                Some(_) => state.open_until = Some(Instant::now() + COOLDOWN),
+                None => {}
+            }
+        }
+
+        let res = match tokio::time::timeout(QUERY_TIMEOUT, self.client.query(query).get()).await {
+            Ok(Ok(res)) => res,
+            // prometheus answered, but could not evaluate the query
+            Ok(Err(prometheus_http_query::Error::Prometheus(e))) => {
+                *self.state.lock().unwrap() = BreakerState::default();
+                return Err(PrometheusError::InvalidQuery(e.to_string()));
+            }
+            Ok(Err(e)) => {
+                self.record_failure();
+                return Err(PrometheusError::Query(e));
+            }
+            Err(_) => {
+                self.record_failure();
+                return Err(PrometheusError::TimedOut);
+            }
+        };
+        *self.state.lock().unwrap() = BreakerState::default();
+
+        let value = match res.data() {
+            Data::Scalar(sample) => Some(sample.value()),
+            Data::Vector(vector) => vector.last().map(|item| item.sample().value()),
+            _ => return Err(PrometheusError::UnsupportedResponse),
+        };
+        self.cache_value(query, value);
+        Ok(value)
+    }
+
+    /// Store the last value of a query, evicting another query's value once
+    /// the cache is full
+    fn cache_value(&self, query: &str, value: Option<f64>) {
+        if self.cache.len() >= MAX_CACHED_QUERIES && !self.cache.contains_key(query) {
+            // the key is cloned so the iterator's shard lock is released
+            // before removing
+            let evicted = self.cache.iter().next().map(|e| e.key().clone());
+            if let Some(evicted) = evicted {
+                self.cache.remove(&evicted);
+            }
+        }
+        self.cache.insert(query.to_owned(), value);
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures += 1;
+        if state.failures >= FAILURE_THRESHOLD {
+            if state.failures == FAILURE_THRESHOLD {
+                warn!("prometheus failed {FAILURE_THRESHOLD} queries in a row, pausing queries");
+            }
+            state.open_until = Some(Instant::now() + COOLDOWN);
+        }
+    }
+}
+
+/// Rewrite a query so every series selector in it only matches the series
+/// scraped from the given environment's agents
+pub fn scope_query_to_env(query: &str, env_id: EnvId) -> Result<String, PrometheusError> {
+    let mut expr = promql_parser::parser::parse(query).map_err(PrometheusError::InvalidQuery)?;
+    add_env_matcher(&mut expr, &env_id.to_string());
+    Ok(expr.to_string())
+}
+
+fn add_env_matcher(expr: &mut Expr, env_id: &str) {
+    match expr {
+        Expr::VectorSelector(vs) => add_selector_matcher(vs, env_id),
+        Expr::MatrixSelector(ms) => add_selector_matcher(&mut ms.vs, env_id),
+        Expr::Aggregate(agg) => {
+            add_env_matcher(&mut agg.expr, env_id);
+            if let Some(param) = agg.param.as_mut() {
+                add_env_matcher(param, env_id);
+            }
+        }
+        Expr::Unary(unary) => add_env_matcher(&mut unary.expr, env_id),
+        Expr::Binary(binary) => {
+            add_env_matcher(&mut binary.lhs, env_id);
+            add_env_matcher(&mut binary.rhs, env_id);
+        }
+        Expr::Paren(paren) => add_env_matcher(&mut paren.expr, env_id),
+        Expr::Subquery(subquery) => add_env_matcher(&mut subquery.expr, env_id),
+        Expr::Call(call) => {
+            for arg in call.args.args.iter_mut() {
+                add_env_matcher(arg, env_id);
+            }
+        }
+        Expr::NumberLiteral(_) | Expr::StringLiteral(_) | Expr::Extension(_) => {}
+    }
+}
+
+fn add_selector_matcher(vs: &mut VectorSelector, env_id: &str) {
+    let matcher = || Matcher::new(MatchOp::Equal, "env_id", env_id);
+    // drop any env_id matchers from the query so it cannot select another
+    // environment's series
+    vs.matchers.matchers.retain(|m| m.name != "env_id");
+    vs.matchers.matchers.push(matcher());
+    for group in vs.matchers.or_matchers.iter_mut() {
+        group.retain(|m| m.name != "env_id");
+        group.push(matcher());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn breaker() -> PrometheusBreaker {
+        PrometheusBreaker::new(PrometheusClient::try_from("http://127.0.0.1:9").unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_query_does_not_open_breaker() {
+        let breaker = breaker();
+        for _ in 0..FAILURE_THRESHOLD {
+            assert!(matches!(
+                breaker.query_value("sum(").await,
+                Err(PrometheusError::InvalidQuery(_))
+            ));
+        }
+        assert_eq!(breaker.status(), BreakerStatus::Closed);
+    }
+
+    #[test]
+    fn test_scope_query_to_env() {
+        let env_id = EnvId::from_str("test-env").unwrap();
+        let scoped = scope_query_to_env(
+            r#"sum(rate(snarkos_blocks_height_total{env_id="other"}[1m])) / count(up)"#,
+            env_id,
+        )
+        .unwrap();
+        assert_eq!(scoped.matches(r#"env_id="test-env""#).count(), 2);
+        assert!(!scoped.contains("other"));
+        // the rewritten query is still valid
+        promql_parser::parser::parse(&scoped).unwrap();
+
+        assert!(matches!(
+            scope_query_to_env("sum(", env_id),
+            Err(PrometheusError::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        let breaker = breaker();
+        for i in 0..MAX_CACHED_QUERIES * 2 {
+            breaker.cache_value(&format!("up{{job=\"{i}\"}}"), Some(i as f64));
+        }
+        assert_eq!(breaker.cache.len(), MAX_CACHED_QUERIES);
+
+        // replacing an existing query's value does not evict another
+        let existing = breaker.cache.iter().next().unwrap().key().clone();
+        breaker.cache_value(&existing, None);
+        assert_eq!(breaker.cache.len(), MAX_CACHED_QUERIES);
+        assert_eq!(breaker.cache.get(&existing).as_deref(), Some(&None));
+    }
+}
@@ -1,10 +1,15 @@
 use std::collections::HashMap;
 
+use dashmap::mapref::entry::Entry;
 use futures_util::future::join_all;
-use snops_common::state::{AgentId, AgentState, NodeKey, ReconcileOptions};
+use snops_common::{
+    events::AgentEvent,
+    state::{AgentId, AgentState, InventoryReason, NodeKey, ReconcileOptions},
+};
+use tarpc::client::RpcError;
 use tracing::{error, info};
 
-use super::GlobalState;
+use super::{AgentEventHelpers, EmitEvent, GlobalState};
 
 /// The tuple to pass into `reconcile_agents`.
 pub type PendingAgentReconcile = (AgentId, AgentState);
@@ -48,6 +53,38 @@ impl GlobalState {
         self.queue_many_reconciles(agent_ids, opts).await;
     }
 
+    /// Move many agents to the inventory at once, recording why, and
+    /// reconcile them.
+    pub async fn inventory_agents(
+        &self,
+        iter: impl IntoIterator<Item = AgentId>,
+        reason: InventoryReason,
+    ) {
+        let mut agent_ids = vec![];
+
+        for id in iter {
+            if let Some(mut agent) = self.pool.get_mut(&id) {
+                agent_ids.push(id);
+                // emitted before the state changes so the event carries the
+                // agent's env and node key
+                AgentEvent::Inventoried { reason }
+                    .with_agent(&agent)
+                    .emit(self);
+                agent.set_inventory(reason);
+                if let Err(e) = self.db.agents.save(&id, &agent) {
+                    error!("failed to save agent {id} to the database: {e}");
+                }
+            }
+        }
+
+        self.queue_many_reconciles(agent_ids, Default::default())
+            .await;
+    }
+
+    /// Request a reconcile from many agents at once. Agents with a request
+    /// already in flight are not sent a duplicate; instead their options are
+    /// merged into a single follow-up request sent once the current one
+    /// completes.
     pub async fn queue_many_reconciles(
         &self,
         iter: impl IntoIterator<Item = AgentId>,
@@ -55,22 +92,34 @@ impl GlobalState {
     ) -> (usize, usize) {
         let mut handles = vec![];
         let mut agent_ids = vec![];
+        let mut coalesced = 0;
 
         for id in iter {
-            let agent = self.pool.get(&id);
-            let Some(agent) = agent else {
+            let Some(agent) = self.pool.get(&id) else {
                 continue;
             };
-            let Some(client) = agent.client_owned() else {
+            if agent.client_owned().is_none() {
                 continue;
-            };
+            }
+
+            match self.pending_reconciles.entry(id) {
+                Entry::Occupied(mut pending) => {
+                    let merged = pending.get().map_or(opts, |prev| prev.union(opts));
+                    pending.insert(Some(merged));
+                    coalesced += 1;
+                    continue;
+                }
+                Entry::Vacant(pending) => {
+                    pending.insert(None);
+                }
+            }
 
             agent_ids.push(id);
-            let target = agent.state.clone();
+            handles.push(self.dispatch_reconcile(id, opts));
+        }
 
-            handles.push(tokio::spawn(async move {
-                client.set_agent_state(target, opts).await
-            }));
+        if coalesced > 0 {
+            info!("Coalesced {coalesced} reconcile requests into in-flight requests");
         }
 
         if handles.is_empty() {
@@ -85,11 +134,10 @@ impl GlobalState {
         let mut success = 0;
         for (agent_id, result) in agent_ids.into_iter().zip(reconciliations) {
             match result {
-                Ok(Ok(())) => {
+                Ok(()) => {
                     success += 1;
                 }
-                Ok(Err(e)) => error!("agent {agent_id} experienced a rpc error: {e}"),
-                Err(e) => error!("join error during agent {agent_id} reconcile request: {e}"),
+                Err(e) => error!("agent {agent_id} experienced a rpc error: {e}"),
             }
         }
 
@@ -97,4 +145,69 @@ impl GlobalState {
 
         (success, num_reqs)
     }
+
+    /// Send an agent its current target state, repeating the request while
+    /// follow-up requests were coalesced into it.
+    async fn dispatch_reconcile(
+        &self,
+        id: AgentId,
+        mut opts: ReconcileOptions,
+    ) -> Result<(), RpcError> {
+        // clears the pending entry if this future is dropped mid-request so
+        // later reconciles for the agent are not coalesced into nothing
+        let mut guard = PendingReconcileGuard {
+            state: self,
+            id,
+            armed: true,
+        };
+
+        loop {
+            // the target state is read for each request so follow-ups observe
+            // any state changes made while the previous request was in flight
+            let Some((client, target)) = self
+                .pool
+                .get(&id)
+                .and_then(|agent| Some((agent.client_owned()?, agent.state.clone())))
+            else {
+                return Ok(());
+            };
+
+            let res = client.set_agent_state(target, opts).await;
+
+            // nothing was coalesced while this request was in flight
+            if self
+                .pending_reconciles
+                .remove_if(&id, |_, next| next.is_none())
+                .is_some()
+            {
+                guard.armed = false;
+                return res;
+            }
+
+            let next = self
+                .pending_reconciles
+                .get_mut(&id)
+                .and_then(|mut next| next.take());
+            match next {
+                Some(next) => opts = next,
+                None => return res,
+            }
+        }
+    }
+}
+
+/// Removes an agent's pending reconcile entry when dropped, unless the entry
+/// was already removed by the dispatch that owns it.
+struct PendingReconcileGuard<'a> {
+    state: &'a GlobalState,
+    id: AgentId,
+    armed: bool,
+}
+
+impl Drop for PendingReconcileGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            self.state.pending_reconciles.remove(&self.id);
+        }
+    }
 }
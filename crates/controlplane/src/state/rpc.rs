@@ -2,7 +2,11 @@ use std::{fmt::Display, time::Duration};
 
 use serde::de::DeserializeOwned;
 use snops_common::{
-    rpc::{control::agent::AgentServiceClient, error::SnarkosRequestError},
+    format::Compact,
+    rpc::{
+        control::agent::{AgentServiceClient, AotExecOutput},
+        error::SnarkosRequestError,
+    },
     state::{
         AgentId, AgentState, EnvId, NetworkId, ReconcileOptions, snarkos_status::SnarkOSLiteBlock,
     },
@@ -20,7 +24,9 @@ impl AgentClient {
         to: AgentState,
         opts: ReconcileOptions,
     ) -> Result<(), RpcError> {
-        self.0.set_agent_state(context::current(), to, opts).await
+        self.0
+            .set_agent_state(context::current(), Compact(to), opts)
+            .await
     }
 
     pub async fn clear_peer_addr(&self, peer: AgentId) -> Result<(), RpcError> {
@@ -42,18 +48,23 @@ impl AgentClient {
         }
     }
 
+    /// Execute an authorization on the agent. When a timeout is given, the
+    /// agent kills the execution once it runs for longer.
     pub async fn execute_authorization(
         &self,
         env_id: EnvId,
         network: NetworkId,
         query: String,
         auth: String,
+        timeout: Option<Duration>,
     ) -> Result<String, StateError> {
         let mut ctx = context::current();
-        ctx.deadline += Duration::from_secs(30);
+        // leave the agent time to prepare the execution and report a timeout
+        // before the request expires
+        ctx.deadline += timeout.unwrap_or_default() + Duration::from_secs(30);
         Ok(self
             .0
-            .execute_authorization(ctx, env_id, network, query, auth)
+            .execute_authorization(ctx, env_id, network, query, auth, timeout)
             .await??)
     }
 
@@ -74,4 +85,11 @@ impl AgentClient {
     pub async fn find_transaction(&self, tx_id: String) -> Result<Option<String>, StateError> {
         Ok(self.0.find_transaction(context::current(), tx_id).await??)
     }
+
+    /// Run a read-only aot ledger command against the agent's node ledger
+    pub async fn exec_aot(&self, args: Vec<String>) -> Result<AotExecOutput, StateError> {
+        let mut ctx = context::current();
+        ctx.deadline += Duration::from_secs(60);
+        Ok(self.0.exec_aot(ctx, args).await??)
+    }
 }
@@ -5,7 +5,7 @@ use serde_json::json;
 use snops_common::{rpc::error::SnarkosRequestError, state::NetworkId};
 
 use super::REST_CLIENT;
-use crate::env::error::EnvRequestError;
+use crate::{env::error::EnvRequestError, schema::nodes::RestHeaders};
 
 /// I would rather reparse a string than use unsafe/dyn any here
 /// because we would be making a request anyway and it's not a big deal.
@@ -44,9 +44,14 @@ pub async fn get_on_addr<T: DeserializeOwned>(
     network: NetworkId,
     route: &str,
     addr: SocketAddr,
+    headers: Option<&RestHeaders>,
 ) -> Result<T, SnarkosRequestError> {
     let url = format!("http://{addr}/{network}{route}");
-    let request = REST_CLIENT.get(&url).send();
+    let mut request = REST_CLIENT.get(&url);
+    if let Some(headers) = headers {
+        request = headers.apply(request);
+    }
+    let request = request.send();
 
     // make the request with a 1 second timeout, then parse the response as json
     tokio::time::timeout(std::time::Duration::from_secs(5), request)
@@ -1,22 +1,108 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use chrono::{TimeDelta, Utc};
 use futures_util::future;
 use snops_common::{
     events::{EventHelpers, TransactionEvent},
+    node_targets::NodeTargets,
     state::{CannonId, EnvId, TransactionSendState},
 };
 use tokio::time::timeout;
 use tracing::{info, trace};
 
 use super::{EmitEvent, GlobalState};
-use crate::cannon::tracker::TransactionTracker;
+use crate::{
+    cannon::{CannonInstance, tracker::TransactionTracker},
+    env::cache::ATransactionId,
+};
+
+/// Emit a summary of the transactions each active cannon in the environment
+/// had included in a new block
+pub fn emit_block_summary(
+    state: &GlobalState,
+    env_id: EnvId,
+    height: u32,
+    confirmed: &[ATransactionId],
+    rejected: &[ATransactionId],
+    aborted: &[ATransactionId],
+) {
+    let Some(env) = state.get_env(env_id) else {
+        return;
+    };
+
+    for (cannon_id, cannon) in &env.cannons {
+        if cannon.transactions.is_empty() {
+            continue;
+        }
+
+        let count = |ids: &[ATransactionId]| {
+            ids.iter()
+                .filter(|id| cannon.transactions.contains_key(&id.to_string()))
+                .count() as u32
+        };
+
+        TransactionEvent::BlockSummary {
+            height,
+            confirmed: count(confirmed),
+            rejected: count(rejected),
+            aborted: count(aborted),
+        }
+        .with_cannon(*cannon_id)
+        .with_env_id(env_id)
+        .emit(state);
+    }
+}
+
+/// How long a confirmed transaction is remembered to prevent re-broadcasts
+const CONFIRMED_TX_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+/// Most confirmed transactions remembered for each env
+const MAX_CONFIRMED_TXS: usize = 100_000;
+/// How often expired confirmed transactions are removed
+const CONFIRMED_PRUNE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Forget confirmed transactions older than the retention, and the oldest
+/// confirmed transactions of envs over the limit
+fn prune_confirmed_transactions(state: &GlobalState) {
+    let cutoff = Utc::now().timestamp() - CONFIRMED_TX_RETENTION.as_secs() as i64;
+
+    let mut by_env = HashMap::<EnvId, Vec<(i64, Arc<String>)>>::new();
+    for ((env_id, tx_id), confirmed_at) in state.db.tx_confirmed.read_all() {
+        by_env
+            .entry(env_id)
+            .or_default()
+            .push((confirmed_at, tx_id));
+    }
+
+    for (env_id, mut txs) in by_env {
+        // newest first, so the transactions over the limit are the oldest
+        txs.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+        let expired = txs
+            .into_iter()
+            .enumerate()
+            .filter(|(i, (confirmed_at, _))| *i >= MAX_CONFIRMED_TXS || *confirmed_at < cutoff);
+        for (_, (_, tx_id)) in expired {
+            if let Err(e) = state.db.tx_confirmed.delete(&(env_id, tx_id)) {
+                tracing::error!("{env_id}: failed to prune a confirmed transaction: {e:?}");
+            }
+        }
+    }
+}
 
 /// This task re-sends all transactions that have not been confirmed,
 /// re-computes all transactions that have not been computed, and removes
 /// transactions that are confirmed.
 pub async fn tracking_task(state: Arc<GlobalState>) {
+    let mut last_prune = Instant::now();
     loop {
+        if last_prune.elapsed() >= CONFIRMED_PRUNE_INTERVAL {
+            prune_confirmed_transactions(&state);
+            last_prune = Instant::now();
+        }
+
         let pending_txs = get_pending_transactions(&state);
 
         future::join_all(pending_txs.into_iter().map(|((env_id, cannon_id), pending)| {
@@ -44,6 +130,8 @@ pub async fn tracking_task(state: Arc<GlobalState>) {
                 // queue up all the transactions that need to be confirmed
                 for tx_id in pending.to_broadcast {
                     trace!("cannon {env_id}.{cannon_id} queueing transaction {tx_id} for re-broadcast");
+                    // the transaction is checked for in the mempool again after it is re-broadcasted
+                    cannon.mempool_accepted.remove(&tx_id);
                     if let Err(e) = cannon.tx_sender.send(tx_id.clone()) {
                         tracing::error!(
                             "cannon {env_id}.{cannon_id} failed to send broadcast {tx_id} to cannon: {e:?}"
@@ -74,6 +162,10 @@ pub async fn tracking_task(state: Arc<GlobalState>) {
                             return None;
                         }};
 
+                        if let Err(e) = state.db.tx_confirmed.save(&(env_id, Arc::clone(&tx_id)), &Utc::now().timestamp()) {
+                            tracing::error!("cannon {env_id}.{cannon_id} failed to save confirmed {tx_id}: {e:?}");
+                        }
+
                         // Emit a confirmed event
                         TransactionEvent::Confirmed { hash }
                             .with_cannon(cannon_id)
@@ -83,9 +175,17 @@ pub async fn tracking_task(state: Arc<GlobalState>) {
                         Some(tx_id)
                 }})).await;
 
+                // look for the broadcasted transactions in the target nodes' mempools
+                if let Some(target) = cannon.sink.target.as_ref() {
+                    if !pending.to_verify.is_empty() {
+                        verify_mempool(&state, env_id, &cannon, target, pending.to_verify).await;
+                    }
+                }
+
                 // remove all the transactions that are confirmed or expired
                 for tx_id in pending.to_remove.into_iter().chain(confirmed.into_iter().flatten()) {
                     cannon.transactions.remove(&tx_id);
+                    cannon.mempool_accepted.remove(&tx_id);
                     if let Err(e) =
                         TransactionTracker::delete(&state, &(env_id, cannon_id, tx_id.clone()))
                     {
@@ -99,15 +199,70 @@ pub async fn tracking_task(state: Arc<GlobalState>) {
     }
 }
 
+/// Emit a mempool accepted event for each broadcasted transaction that is in
+/// the mempool of one of the cannon's target nodes. Transactions that are not
+/// found are checked again on the next update.
+async fn verify_mempool(
+    state: &GlobalState,
+    env_id: EnvId,
+    cannon: &CannonInstance,
+    target: &NodeTargets,
+    tx_ids: Vec<Arc<String>>,
+) {
+    let mempools = match state
+        .snarkos_get_all::<Vec<serde_json::Value>>(env_id, "/memoryPool/transactions", target)
+        .await
+    {
+        Ok(mempools) => mempools,
+        Err(e) => {
+            trace!(
+                "cannon {env_id}.{} failed to get target mempools: {e}",
+                cannon.id
+            );
+            return;
+        }
+    };
+
+    let mempools = mempools
+        .into_iter()
+        .filter_map(|(key, txs)| {
+            let ids = txs
+                .ok()?
+                .iter()
+                .filter_map(|tx| tx.get("id")?.as_str().map(str::to_owned))
+                .collect::<HashSet<_>>();
+            Some((key, ids))
+        })
+        .collect::<Vec<_>>();
+
+    for tx_id in tx_ids {
+        let Some((node, _)) = mempools
+            .iter()
+            .find(|(_, ids)| ids.contains(tx_id.as_str()))
+        else {
+            continue;
+        };
+
+        cannon.mempool_accepted.insert(Arc::clone(&tx_id));
+        TransactionEvent::MempoolAccepted { node: node.clone() }
+            .with_cannon(cannon.id)
+            .with_env_id(env_id)
+            .with_transaction(tx_id)
+            .emit(state);
+    }
+}
+
 struct PendingTransactions {
     to_execute: Vec<Arc<String>>,
     to_broadcast: Vec<Arc<String>>,
     to_remove: Vec<Arc<String>>,
     to_confirm: Vec<(Arc<String>, Option<u32>)>,
+    /// Broadcasted transactions that have not been found in a mempool
+    to_verify: Vec<Arc<String>>,
 }
 
 /// Get a list of transactions that need to be executed, broadcasted, removed,
-/// or confirmed
+/// confirmed, or found in a mempool
 fn get_pending_transactions(state: &GlobalState) -> Vec<((EnvId, CannonId), PendingTransactions)> {
     let now = Utc::now();
     let mut pending = vec![];
@@ -122,6 +277,7 @@ fn get_pending_transactions(state: &GlobalState) -> Vec<((EnvId, CannonId), Pend
             let mut to_broadcast = vec![];
             let mut to_remove = vec![];
             let mut to_confirm = vec![];
+            let mut to_verify = vec![];
 
             for tx in cannon.transactions.iter() {
                 let tx_id = tx.key().to_owned();
@@ -189,6 +345,11 @@ fn get_pending_transactions(state: &GlobalState) -> Vec<((EnvId, CannonId), Pend
                             _ => true,
                         };
 
+                        if cannon.sink.target.is_some() && !cannon.mempool_accepted.contains(&tx_id)
+                        {
+                            to_verify.push((tx_id.clone(), tx.index));
+                        }
+
                         if !height_changed {
                             continue;
                         }
@@ -230,6 +391,7 @@ fn get_pending_transactions(state: &GlobalState) -> Vec<((EnvId, CannonId), Pend
                     to_broadcast: sorted_by_index(to_broadcast),
                     to_remove,
                     to_confirm: sorted_by_index(to_confirm),
+                    to_verify: sorted_by_index(to_verify),
                 },
             ));
         }
@@ -243,3 +405,34 @@ fn sorted_by_index<T>(mut vec: Vec<(T, u64)>) -> Vec<T> {
     vec.sort_by_key(|(_, index)| *index);
     vec.into_iter().map(|(first, _)| first).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_prune_confirmed_transactions() {
+        let state = GlobalState::load_for_test().await;
+        let env_id = EnvId::from_str("env").unwrap();
+        let now = Utc::now().timestamp();
+        let expired = now - CONFIRMED_TX_RETENTION.as_secs() as i64 - 1;
+
+        let tx = |id: &str| (env_id, Arc::new(id.to_owned()));
+        state.db.tx_confirmed.save(&tx("fresh"), &now).unwrap();
+        state
+            .db
+            .tx_confirmed
+            .save(&tx("expired"), &expired)
+            .unwrap();
+
+        prune_confirmed_transactions(&state);
+
+        assert_eq!(
+            state.db.tx_confirmed.restore(&tx("fresh")).unwrap(),
+            Some(now)
+        );
+        assert_eq!(state.db.tx_confirmed.restore(&tx("expired")).unwrap(), None);
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, LineWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+use snops_common::{events::TransactionRecord, state::EnvId};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{error, warn};
+
+use crate::cli::Cli;
+
+/// Directory under the control plane path that holds transaction records
+pub const TX_RECORD_DIR: &str = "tx_records";
+/// Most records waiting to be written before new records are dropped
+const MAX_QUEUED_RECORDS: usize = 16 * 1024;
+
+/// Appends the transaction status transitions of an environment to a JSON
+/// lines file, to be replayed and analyzed offline.
+///
+/// Records are queued and written by a short-lived task, which is started
+/// when records arrive and exits once the queue is empty. Records queued
+/// before the recorder is dropped are still written.
+#[derive(Debug)]
+pub struct TransactionRecorder {
+    path: PathBuf,
+    tx: mpsc::Sender<TransactionRecord>,
+    writer: Arc<Mutex<RecordWriter>>,
+    /// Whether a task is writing the queued records
+    draining: Arc<AtomicBool>,
+    /// Records dropped because the queue was full
+    dropped: Arc<AtomicU64>,
+}
+
+#[derive(Debug)]
+struct RecordWriter {
+    path: PathBuf,
+    writer: LineWriter<File>,
+    rx: mpsc::Receiver<TransactionRecord>,
+}
+
+impl TransactionRecorder {
+    /// Path of an environment's transaction record
+    pub fn env_path(cli: &Cli, env_id: EnvId) -> PathBuf {
+        cli.path.join(TX_RECORD_DIR).join(format!("{env_id}.jsonl"))
+    }
+
+    /// Open a record file, appending to it if it already exists
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        let (tx, rx) = mpsc::channel(MAX_QUEUED_RECORDS);
+        let writer = RecordWriter {
+            path: path.clone(),
+            writer: LineWriter::new(file),
+            rx,
+        };
+
+        Ok(Self {
+            path,
+            tx,
+            writer: Arc::new(Mutex::new(writer)),
+            draining: Default::default(),
+            dropped: Default::default(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Queue a record to be written as a single line, starting a task to
+    /// write it if none is running
+    pub fn record(&self, record: TransactionRecord) {
+        match self.tx.try_send(record) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Err(TrySendError::Closed(_)) => return,
+        }
+
+        if !self.draining.swap(true, Ordering::AcqRel) {
+            let writer = Arc::clone(&self.writer);
+            let draining = Arc::clone(&self.draining);
+            let dropped = Arc::clone(&self.dropped);
+            tokio::task::spawn_blocking(move || drain_records(&writer, &draining, &dropped));
+        }
+    }
+}
+
+/// Write the queued records until the queue is empty
+fn drain_records(writer: &Mutex<RecordWriter>, draining: &AtomicBool, dropped: &AtomicU64) {
+    let Ok(mut writer) = writer.lock() else {
+        return;
+    };
+
+    loop {
+        while let Ok(record) = writer.rx.try_recv() {
+            writer.write(&record);
+        }
+
+        // a record queued after the queue was emptied, but before the flag
+        // was cleared, did not start a task of its own
+        draining.store(false, Ordering::Release);
+        if writer.rx.is_empty() || draining.swap(true, Ordering::AcqRel) {
+            break;
+        }
+    }
+
+    let dropped = dropped.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        warn!(
+            "dropped {dropped} transaction records for {}, the queue was full",
+            writer.path.display()
+        );
+    }
+}
+
+impl RecordWriter {
+    fn write(&mut self, record: &TransactionRecord) {
+        let line = match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("failed to serialize transaction record: {e}");
+                return;
+            }
+        };
+
+        if let Err(e) = writeln!(self.writer, "{line}") {
+            error!(
+                "failed to write transaction record to {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc, time::Duration};
+
+    use chrono::Utc;
+    use snops_common::state::CannonId;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_records_are_written_in_order() {
+        let path = std::env::temp_dir()
+            .join(format!("snops-tx-record-{}", uuid::Uuid::new_v4()))
+            .join("env.jsonl");
+        let recorder = TransactionRecorder::open(&path).unwrap();
+
+        let records = (0..3)
+            .map(|i| TransactionRecord {
+                time: Utc::now(),
+                env: EnvId::from_str("env").unwrap(),
+                cannon: CannonId::default(),
+                transaction: Arc::new(format!("at1tx{i}")),
+                height: Some(i),
+                status: "transaction-confirmed".to_owned(),
+            })
+            .collect::<Vec<_>>();
+        for record in &records {
+            recorder.record(record.clone());
+        }
+        drop(recorder);
+
+        // the writer finishes the queued records after the recorder is dropped
+        let read = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let lines = std::fs::read_to_string(&path).unwrap_or_default();
+                if lines.lines().count() == records.len() {
+                    return lines;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("transaction records were not written");
+
+        let written = read
+            .lines()
+            .map(|line| serde_json::from_str::<TransactionRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(written, records);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}